@@ -0,0 +1,43 @@
+//! Attestation of settled results by the ZK nodes. The nodes sign the encoded result record, so
+//! third parties and other chains can verify an outcome from the signatures alone, without
+//! trusting a reading of this contract's state.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use pbc_contract_common::signature::Signature;
+use pbc_contract_common::Hash;
+use pbc_traits::ReadWriteState;
+use read_write_rpc_derive::ReadWriteRPC;
+use read_write_state_derive::ReadWriteState;
+
+/// Outcome of a settled game as attested by the nodes.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+pub struct ResultRecord {
+    pub player: Address,
+    /// Id of the game, see `compute_game_id`.
+    pub game_id: Hash,
+    pub player_won: bool,
+    /// Amount owed to the player, zero for a lost game.
+    pub payout: u64,
+}
+
+impl ResultRecord {
+    /// The attested bytes: the record in state serialization.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        self.state_write_to(&mut data).unwrap();
+        data
+    }
+
+    pub fn decode(mut data: &[u8]) -> Self {
+        ResultRecord::state_read_from(&mut data)
+    }
+}
+
+/// A result record together with the nodes' signatures on its encoding.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct AttestedResult {
+    pub record: ResultRecord,
+    /// Signature of each node, in node order; `None` for a node that did not sign.
+    pub signatures: Vec<Option<Signature>>,
+}
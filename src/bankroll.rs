@@ -0,0 +1,191 @@
+//! The house bankroll: its capital in every token, the potential payouts of open bets counted
+//! against it, and the deployment of idle bankroll to a whitelisted yield contract.
+//!
+//! Only the house bankroll is ever deployed; player escrow and deposited balances are accounted
+//! separately and never leave the contract through the adapter.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use read_write_state_derive::ReadWriteState;
+
+use crate::contract::CoinFlipState;
+use crate::engine::winnings_for;
+use crate::errors::{ensure, GameError, OrFail};
+use crate::zk_compute;
+
+/// The whitelisted yield contract and the bankroll moved to it.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct YieldAdapter {
+    /// The yield contract bankroll is deployed to.
+    pub contract: Address,
+    /// Bankroll kept in the contract for payouts, never deployed.
+    pub buffer: u64,
+    /// Bankroll confirmed deployed to the yield contract.
+    pub deployed: u64,
+    /// Bankroll sent to the yield contract, awaiting confirmation.
+    pub pending_deposit: u64,
+    /// Deployed bankroll being recalled, awaiting confirmation.
+    pub pending_recall: u64,
+}
+
+impl YieldAdapter {
+    pub fn new(contract: Address, buffer: u64) -> Self {
+        YieldAdapter {
+            contract,
+            buffer,
+            deployed: 0,
+            pending_deposit: 0,
+            pending_recall: 0,
+        }
+    }
+
+    /// Bankroll that is neither deployed nor on its way to the yield contract.
+    pub fn idle(&self, house_bankroll: u64) -> u64 {
+        house_bankroll.saturating_sub(self.deployed + self.pending_deposit)
+    }
+
+    /// Idle bankroll above the buffer, which may be deployed.
+    pub fn deployable(&self, house_bankroll: u64) -> u64 {
+        self.idle(house_bankroll).saturating_sub(self.buffer)
+    }
+
+    /// Deployed bankroll that is not already being recalled.
+    pub fn recallable(&self) -> u64 {
+        self.deployed - self.pending_recall
+    }
+
+    /// Whether any bankroll is deployed or moving.
+    pub fn is_in_use(&self) -> bool {
+        self.deployed > 0 || self.pending_deposit > 0 || self.pending_recall > 0
+    }
+}
+
+impl CoinFlipState {
+    /// House capital in the given token.
+    pub(crate) fn bankroll_of(&self, token: &Address) -> u64 {
+        if *token == self.token_address {
+            self.house_bankroll
+        } else {
+            self.token_registry.get(token).map(|config| config.bankroll).unwrap_or(0)
+        }
+    }
+
+    /// Count the potential payout of the player's new bet towards the exposure of its token,
+    /// rejecting the bet if it would exceed the exposure limit or, while winnings are reserved,
+    /// the free bankroll.
+    pub(crate) fn reserve_exposure(
+        &mut self,
+        player: &Address,
+        token: &Address,
+        potential_payout: u64,
+    ) {
+        let outstanding = self.outstanding_payouts.get(token).copied().unwrap_or(0);
+        let exposure = outstanding.checked_add(potential_payout).or_fail(
+            GameError::BetRejected,
+            "The bet would overflow the house's exposure.",
+        );
+        if self.max_exposure_bps > 0 {
+            let limit = self.bankroll_of(token) as u128 * self.max_exposure_bps as u128 / 10_000;
+            ensure!(
+                exposure as u128 <= limit,
+                GameError::BetRejected,
+                "The bet would exceed the house's exposure limit."
+            );
+        }
+        // The free bankroll already excludes the payouts reserved for other open bets
+        ensure!(
+            !self.reserve_winnings || potential_payout <= self.free_bankroll(token),
+            GameError::InsufficientFunds,
+            "The house bankroll cannot reserve the potential payout of the bet."
+        );
+        self.outstanding_payouts.insert(*token, exposure);
+        self.bet_exposures.insert(*player, potential_payout);
+    }
+
+    /// Reserve the bankroll for the largest multiplier a bonus round of the player's won bet may
+    /// draw, if bonus rounds are enabled and the free bankroll covers it.
+    ///
+    /// ### Returns:
+    ///
+    /// Whether the bonus round may be played.
+    pub(crate) fn reserve_bonus_round(&mut self, player: &Address) -> bool {
+        let Some(bet) = self.player_bets.get(player) else {
+            return false;
+        };
+        // Winnings converted to another token are priced against another bankroll
+        if !self.bonus_round_enabled || self.payout_tokens.get(player).is_some() {
+            return false;
+        }
+        let token = self.payout_token_of(player);
+        let extra = winnings_for(&bet, self.dice_house_edge_bps)
+            .saturating_mul(zk_compute::BONUS_MAX_MULTIPLIER as u64 - 1);
+        if self.free_bankroll(&token) < extra {
+            return false;
+        }
+        let outstanding = self.outstanding_payouts.get(&token).copied().unwrap_or(0);
+        self.outstanding_payouts.insert(token, outstanding + extra);
+        let exposure = self.bet_exposures.get(player).unwrap_or(0);
+        self.bet_exposures.insert(*player, exposure + extra);
+        true
+    }
+
+    /// Stop counting the potential payout of the player's bet, once it is settled or discarded.
+    pub(crate) fn release_exposure(&mut self, player: &Address) {
+        if let Some(exposure) = self.bet_exposures.get(player) {
+            self.bet_exposures.remove(player);
+            let token = self.payout_token_of(player);
+            let outstanding = self.outstanding_payouts.get(&token).copied().unwrap_or(0);
+            self.outstanding_payouts.insert(token, outstanding.saturating_sub(exposure));
+        }
+    }
+
+    /// Add to the house capital in the given token.
+    pub(crate) fn credit_bankroll(&mut self, token: &Address, amount: u64) {
+        if *token == self.token_address {
+            self.house_bankroll += amount;
+        } else if let Some(config) = self.token_registry.get_mut(token) {
+            config.bankroll += amount;
+        }
+    }
+
+    /// Add a fee earned in the default token to the house bankroll. While a treasury is set, the
+    /// fee is owed to it until swept.
+    pub(crate) fn collect_fee(&mut self, fee: u64) {
+        self.house_bankroll += fee;
+        if self.treasury.is_some() {
+            self.fee_sweeps.unswept += fee;
+        }
+    }
+
+    /// Take from the house capital in the given token. Fails rather than hiding an insolvent
+    /// bankroll.
+    pub(crate) fn debit_bankroll(&mut self, token: &Address, amount: u64) {
+        let bankroll = if *token == self.token_address {
+            &mut self.house_bankroll
+        } else if let Some(config) = self.token_registry.get_mut(token) {
+            &mut config.bankroll
+        } else {
+            return;
+        };
+        *bankroll = bankroll.checked_sub(amount).or_fail(
+            GameError::InsufficientFunds,
+            "The house bankroll cannot cover the amount.",
+        );
+    }
+
+    /// Bankroll in the given token that is not deployed to the yield contract.
+    pub(crate) fn idle_bankroll(&self, token: &Address) -> u64 {
+        let bankroll = self.bankroll_of(token);
+        match &self.yield_adapter {
+            Some(adapter) if *token == self.token_address => adapter.idle(bankroll),
+            _ => bankroll,
+        }
+    }
+
+    /// Bankroll in the given token that may leave the contract: neither backing the potential
+    /// payouts of open bets nor deployed to the yield contract.
+    pub(crate) fn free_bankroll(&self, token: &Address) -> u64 {
+        let outstanding = self.outstanding_payouts.get(token).copied().unwrap_or(0);
+        self.idle_bankroll(token).saturating_sub(outstanding)
+    }
+}
@@ -0,0 +1,167 @@
+//! Single-elimination brackets between players.
+//!
+//! A bracket of `2^k` players is filled by registrations escrowing the entry fee. Once full, the
+//! players meet in pairs, round after round: each player of a match inputs secret randomness and
+//! the ZK coin flip over both inputs decides who advances. A player who has not contributed by
+//! the round deadline loses by walkover, and when neither player of a match shows up the higher
+//! seed advances. The last player standing wins the pot minus the rake, which goes to the house.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use pbc_contract_common::sorted_vec_map::SortedVecMap;
+use read_write_rpc_derive::ReadWriteRPC;
+use read_write_state_derive::ReadWriteState;
+
+/// Maximum number of rounds of a bracket, so at most 64 players.
+pub const MAX_BRACKET_ROUNDS: u8 = 6;
+
+/// Maximum rake of a bracket, in basis points of the pot.
+pub const MAX_BRACKET_RAKE_BPS: u32 = 1_000;
+
+/// Progress of a bracket.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, PartialEq, Copy, Clone)]
+#[repr(u8)]
+pub enum BracketStatus {
+    /// Players register until the bracket is full or registration closes.
+    #[discriminant(0)]
+    Registering {},
+    /// The matches of the current round are being played.
+    #[discriminant(1)]
+    Playing {},
+    /// Won by `winner`, who was paid the pot minus the rake.
+    #[discriminant(2)]
+    Finished { winner: Address },
+    /// Registration closed before the bracket was full and the entry fees were refunded.
+    #[discriminant(3)]
+    Cancelled {},
+}
+
+/// A single-elimination bracket.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct Bracket {
+    pub entry_fee: u64,
+    /// Number of players, a power of two.
+    pub size: u32,
+    pub rake_bps: u32,
+    pub registration_closes_at: i64,
+    /// Time the players of a round have to contribute their randomness.
+    pub round_duration_millis: i64,
+    /// Players still in, in bracket order; players `2i` and `2i + 1` meet in match `i` of the
+    /// current round. Holds the registered players while registering.
+    pub players: Vec<Address>,
+    /// Current round, counting from 0.
+    pub round: u8,
+    /// Deadline of the current round, after which matches are decided by walkover.
+    pub round_deadline: i64,
+    /// Winners of the matches of the current round decided so far, by match index.
+    pub match_winners: SortedVecMap<u32, Address>,
+    /// Matches of the current round whose flip is being computed.
+    pub computing: Vec<u32>,
+    /// Entry fees escrowed.
+    pub pot: u64,
+    pub status: BracketStatus,
+}
+
+impl Bracket {
+    /// Open a bracket of `2^rounds` players.
+    pub fn new(
+        entry_fee: u64,
+        rounds: u8,
+        rake_bps: u32,
+        registration_closes_at: i64,
+        round_duration_millis: i64,
+    ) -> Self {
+        Bracket {
+            entry_fee,
+            size: 1 << rounds,
+            rake_bps,
+            registration_closes_at,
+            round_duration_millis,
+            players: vec![],
+            round: 0,
+            round_deadline: 0,
+            match_winners: SortedVecMap::new(),
+            computing: vec![],
+            pot: 0,
+            status: BracketStatus::Registering {},
+        }
+    }
+
+    /// Whether players can register at the given time.
+    pub fn is_registering(&self, now: i64) -> bool {
+        self.status == BracketStatus::Registering {}
+            && now < self.registration_closes_at
+            && (self.players.len() as u32) < self.size
+    }
+
+    pub fn is_registered(&self, player: &Address) -> bool {
+        self.players.contains(player)
+    }
+
+    /// Register a player whose entry fee was escrowed, starting the first round at `now` once
+    /// the bracket is full.
+    pub fn register(&mut self, player: Address, now: i64) {
+        self.players.push(player);
+        self.pot += self.entry_fee;
+        if self.players.len() as u32 == self.size {
+            self.status = BracketStatus::Playing {};
+            self.round_deadline = now.saturating_add(self.round_duration_millis);
+        }
+    }
+
+    /// Number of matches in the current round.
+    pub fn match_count(&self) -> u32 {
+        self.players.len() as u32 / 2
+    }
+
+    /// Index of the match of the current round the player plays in, if they are still in.
+    pub fn match_of(&self, player: &Address) -> Option<u32> {
+        self.players
+            .iter()
+            .position(|candidate| candidate == player)
+            .map(|position| position as u32 / 2)
+    }
+
+    /// The two players of a match of the current round, higher seed first.
+    pub fn pairing(&self, match_index: u32) -> (Address, Address) {
+        let first = 2 * match_index as usize;
+        (self.players[first], self.players[first + 1])
+    }
+
+    /// Whether the match of the current round still waits for its winner.
+    pub fn is_open(&self, match_index: u32) -> bool {
+        self.status == BracketStatus::Playing {}
+            && match_index < self.match_count()
+            && self.match_winners.get(&match_index).is_none()
+            && !self.computing.contains(&match_index)
+    }
+
+    /// Rake the house takes from the pot.
+    pub fn rake(&self) -> u64 {
+        (self.pot as u128 * self.rake_bps as u128 / 10_000) as u64
+    }
+
+    /// Record the winner of a match of the current round. Once every match is decided the
+    /// winners advance to the next round starting at `now`, and the last one wins the bracket.
+    ///
+    /// ### Returns:
+    ///
+    /// The winner of the bracket, once it is decided.
+    pub fn decide(&mut self, match_index: u32, winner: Address, now: i64) -> Option<Address> {
+        self.computing.retain(|computing| *computing != match_index);
+        self.match_winners.insert(match_index, winner);
+        if (self.match_winners.len() as u32) < self.match_count() {
+            return None;
+        }
+
+        self.players = self.match_winners.iter().map(|(_, winner)| *winner).collect();
+        self.match_winners = SortedVecMap::new();
+        self.round += 1;
+        self.round_deadline = now.saturating_add(self.round_duration_millis);
+        if self.players.len() > 1 {
+            return None;
+        }
+        self.status = BracketStatus::Finished { winner };
+        Some(winner)
+    }
+}
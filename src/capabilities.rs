@@ -0,0 +1,134 @@
+//! Self-describing view of a deployment, so generic casino front-ends can configure themselves
+//! against any deployment of this contract.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use read_write_rpc_derive::ReadWriteRPC;
+use read_write_state_derive::ReadWriteState;
+
+use crate::contract::CoinFlipState;
+use crate::lottery::MAX_LOTTERY_TICKETS;
+use crate::zk_compute::MAX_MULTI_FLIPS;
+
+/// Version of the contract's client-facing API. Bumped on every change that requires clients to
+/// be updated.
+pub const API_VERSION: u32 = 1;
+
+/// Games offered by the contract.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, PartialEq, Copy, Clone)]
+#[repr(u8)]
+pub enum GameType {
+    #[discriminant(0)]
+    CoinFlip {},
+    #[discriminant(1)]
+    MultiFlip {},
+    #[discriminant(2)]
+    Dice {},
+    #[discriminant(3)]
+    Roulette {},
+    #[discriminant(4)]
+    RockPaperScissors {},
+    #[discriminant(5)]
+    Practice {},
+    #[discriminant(6)]
+    Lottery {},
+    #[discriminant(7)]
+    Tournament {},
+}
+
+/// Optional features and whether the operator has enabled them.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+pub struct FeatureFlags {
+    pub strict_fairness: bool,
+    pub allowlist: bool,
+    pub burn_on_loss: bool,
+    pub streak_cover: bool,
+    pub referrals: bool,
+    pub free_bets: bool,
+    pub notarization: bool,
+    pub jackpot: bool,
+}
+
+/// Limits applied to bets.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+pub struct Limits {
+    pub max_multi_flips: u8,
+    pub max_lottery_tickets: u32,
+    /// Zero when potential payouts are not limited.
+    pub max_exposure_bps: u32,
+    pub dice_house_edge_bps: u32,
+    pub max_round_contributions: u32,
+}
+
+/// A token accepted for bets and its bet limits.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+pub struct TokenCapabilities {
+    pub token: Address,
+    pub min_bet: u64,
+    pub max_bet: u64,
+}
+
+/// Everything a front-end needs to know to offer the games of a deployment.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+pub struct Capabilities {
+    pub api_version: u32,
+    /// Games that can currently be played.
+    pub games: Vec<GameType>,
+    pub features: FeatureFlags,
+    pub limits: Limits,
+    /// The contract's default token, in which deposits, jackpots and lotteries are held.
+    pub default_token: Address,
+    pub tokens: Vec<TokenCapabilities>,
+}
+
+impl CoinFlipState {
+    /// Describe the games, features, limits and tokens of this deployment.
+    pub fn get_capabilities(&self) -> Capabilities {
+        let mut games = vec![
+            GameType::CoinFlip {},
+            GameType::MultiFlip {},
+            GameType::Dice {},
+            GameType::Roulette {},
+            GameType::RockPaperScissors {},
+            GameType::Practice {},
+        ];
+        if self.lottery.as_ref().map_or(false, |lottery| !lottery.is_finished()) {
+            games.push(GameType::Lottery {});
+        }
+        if self.tournament.as_ref().map_or(false, |tournament| !tournament.settled) {
+            games.push(GameType::Tournament {});
+        }
+
+        Capabilities {
+            api_version: API_VERSION,
+            games,
+            features: FeatureFlags {
+                strict_fairness: self.strict_fairness,
+                allowlist: self.allowlist_enabled,
+                burn_on_loss: self.burn.is_some(),
+                streak_cover: self.streak_cover.is_some(),
+                referrals: self.referral_commission_bps > 0,
+                free_bets: self.points_per_free_bet_credit > 0,
+                notarization: self.notary.is_some(),
+                jackpot: self.jackpot_rake_bps > 0,
+            },
+            limits: Limits {
+                max_multi_flips: MAX_MULTI_FLIPS,
+                max_lottery_tickets: MAX_LOTTERY_TICKETS,
+                max_exposure_bps: self.max_exposure_bps,
+                dice_house_edge_bps: self.dice_house_edge_bps,
+                max_round_contributions: self.max_round_contributions,
+            },
+            default_token: self.token_address,
+            tokens: self
+                .token_registry
+                .iter()
+                .map(|(token, config)| TokenCapabilities {
+                    token: *token,
+                    min_bet: config.min_bet,
+                    max_bet: config.max_bet,
+                })
+                .collect(),
+        }
+    }
+}
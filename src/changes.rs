@@ -0,0 +1,80 @@
+//! Bounded log of recent per-player changes, so push-style UIs can poll for what happened since
+//! their last update instead of diffing the whole state.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use read_write_rpc_derive::ReadWriteRPC;
+use read_write_state_derive::ReadWriteState;
+
+use crate::contract::CoinFlipState;
+use crate::engine::GamePhase;
+
+/// Number of changes kept; older changes are overwritten.
+pub const CHANGE_LOG_CAPACITY: usize = 128;
+
+/// What changed for a player.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, PartialEq, Copy, Clone)]
+#[repr(u8)]
+pub enum ChangeKind {
+    /// The player's game moved to a new phase.
+    #[discriminant(0)]
+    PhaseChanged { phase: GamePhase },
+    /// The player's game was settled.
+    #[discriminant(1)]
+    Settled { player_won: bool },
+}
+
+/// A single change, stamped with the block production time it happened at.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Copy, Clone)]
+pub struct ChangeRecord {
+    pub time: i64,
+    pub player: Address,
+    pub kind: ChangeKind,
+}
+
+/// Ring buffer of the most recent `CHANGE_LOG_CAPACITY` changes.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct ChangeLog {
+    records: Vec<ChangeRecord>,
+    /// Index of the oldest record once the buffer is full.
+    next: u32,
+}
+
+impl ChangeLog {
+    pub fn new() -> Self {
+        ChangeLog {
+            records: vec![],
+            next: 0,
+        }
+    }
+
+    /// Append a change, overwriting the oldest one if the buffer is full.
+    pub fn push(&mut self, record: ChangeRecord) {
+        if self.records.len() < CHANGE_LOG_CAPACITY {
+            self.records.push(record);
+        } else {
+            self.records[self.next as usize] = record;
+            self.next = ((self.next as usize + 1) % CHANGE_LOG_CAPACITY) as u32;
+        }
+    }
+
+    /// The kept records, oldest first.
+    fn chronological(&self) -> impl Iterator<Item = &ChangeRecord> {
+        let (newer, older) = self.records.split_at(self.next as usize);
+        older.iter().chain(newer.iter())
+    }
+}
+
+impl CoinFlipState {
+    /// Changes made after `block_time`, oldest first.
+    ///
+    /// Only the most recent `CHANGE_LOG_CAPACITY` changes are kept, so a client that polls too
+    /// rarely should reload the full state instead.
+    pub fn get_changes_since(&self, block_time: i64) -> Vec<ChangeRecord> {
+        self.recent_changes
+            .chronological()
+            .filter(|record| record.time > block_time)
+            .copied()
+            .collect()
+    }
+}
@@ -0,0 +1,83 @@
+//! Garbage collection of settled games. Anyone may call `cleanup` to remove games that settled
+//! longer than the retention window ago, optionally earning a bounty from the house bankroll for
+//! every game removed.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use pbc_contract_common::avl_tree_map::AvlTreeMap;
+use read_write_rpc_derive::ReadWriteRPC;
+use read_write_state_derive::ReadWriteState;
+
+/// Retention window of settled games when none is configured (7 days).
+pub const DEFAULT_SETTLED_GAME_RETENTION_MILLIS: i64 = 7 * 24 * 60 * 60 * 1000;
+
+/// How settled games are cleaned up.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+pub struct CleanupConfig {
+    /// How long a settled game is kept before `cleanup` may remove it.
+    pub retention_millis: i64,
+    /// Paid to the caller of `cleanup` from the house bankroll per game removed. Zero disables
+    /// the bounty.
+    pub bounty_per_game: u64,
+}
+
+impl CleanupConfig {
+    pub fn new() -> Self {
+        CleanupConfig {
+            retention_millis: DEFAULT_SETTLED_GAME_RETENTION_MILLIS,
+            bounty_per_game: 0,
+        }
+    }
+
+    /// Whether a game settled at `settled_at` may be removed at `now`.
+    pub fn is_expired(&self, settled_at: i64, now: i64) -> bool {
+        now.saturating_sub(settled_at) > self.retention_millis
+    }
+}
+
+/// A game as it was queued when it settled.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct SettledGame {
+    pub player: Address,
+    pub settled_at: i64,
+}
+
+/// Settled games in the order they settled, so pruning examines the oldest first rather than
+/// every settled game. A game settling again is queued again, and its earlier entry goes stale.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct SettlementQueue {
+    /// Position of the oldest queued game.
+    pub head: u64,
+    /// Position the next settled game is queued at.
+    pub tail: u64,
+    pub games: AvlTreeMap<u64, SettledGame>,
+}
+
+impl SettlementQueue {
+    pub fn new() -> Self {
+        SettlementQueue {
+            head: 0,
+            tail: 0,
+            games: AvlTreeMap::new(),
+        }
+    }
+
+    /// Queue a game settled at `settled_at`.
+    pub fn push(&mut self, player: Address, settled_at: i64) {
+        self.games.insert(self.tail, SettledGame { player, settled_at });
+        self.tail += 1;
+    }
+
+    /// The oldest queued game, if any.
+    pub fn front(&self) -> Option<SettledGame> {
+        self.games.get(&self.head)
+    }
+
+    /// Remove the oldest queued game.
+    pub fn pop(&mut self) {
+        if self.head < self.tail {
+            self.games.remove(&self.head);
+            self.head += 1;
+        }
+    }
+}
@@ -0,0 +1,10589 @@
+//! The coin flip contract: its state, actions, callbacks and zk hooks.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use pbc_contract_common::avl_tree_map::AvlTreeMap;
+use pbc_contract_common::context::ContractContext;
+use pbc_contract_common::events::EventGroup;
+use pbc_contract_common::sorted_vec_map::SortedVecMap;
+use pbc_contract_common::zk::{
+    AttestationId, CalculationStatus, SecretVarId, ZkClosed, ZkInputDef, ZkState, ZkStateChange,
+};
+use pbc_contract_common::signature::Signature;
+use pbc_contract_common::{Hash, PublicKey};
+
+use read_write_rpc_derive::ReadWriteRPC;
+use pbc_zk::{Sbi1, Sbi128, Sbi32, Sbi64, Sbi8, SecretBinary};
+use read_write_state_derive::ReadWriteState;
+
+use crate::attestation::{AttestedResult, ResultRecord};
+use crate::bankroll::YieldAdapter;
+use crate::bracket::{Bracket, BracketStatus, MAX_BRACKET_RAKE_BPS, MAX_BRACKET_ROUNDS};
+use crate::errors::{ensure, fail, GameError, OrFail};
+use crate::engine::{
+    compute_game_id, encode_sequence, fold_randomness, lands_heads, parlay_winnings,
+    winnings_for, BetKind, DicePrediction, GamePhase, MultiFlipPrediction, PlayerBet,
+    PlayerChoice, WeightedOdds,
+};
+use crate::{
+    notary, pipeline, price_oracle, token_client, upgrade, validation, webhooks, zk_compute,
+};
+use crate::changes::{ChangeKind, ChangeLog, ChangeRecord};
+use crate::cleanup::{CleanupConfig, SettlementQueue, DEFAULT_SETTLED_GAME_RETENTION_MILLIS};
+use crate::custody::{end_user_address, CustodyAccount};
+use crate::deadlines::{PhaseTimeouts, WaitingStage};
+use crate::history::{GameHistory, GameRecord, DEFAULT_HISTORY_RETENTION};
+use crate::house_seed::{SeedCommitment, NO_SEED_EPOCH};
+use crate::import::{ImportStats, ImportedAccount};
+use crate::epoch::{EpochArchiveConfig, EpochState, PoolSizes};
+use crate::event_sink::{EventSinkConfig, GameEvent};
+use crate::export::ExportedGame;
+use crate::friends::{FriendsTable, TableStatus, MAX_TABLE_PARTICIPANTS};
+use crate::gambling_limits::{GamblingLimits, PlayerLimits};
+use crate::games::GameKind;
+use crate::instant::{InstantBet, InstantConfig, InstantEntropy};
+use crate::integrators::Integrator;
+use crate::intents::BetIntent;
+use crate::ledger::EscrowLedger;
+use crate::insurance::{InsuranceFund, InsuranceSummary, MAX_INSURANCE_SHARE_BPS};
+use crate::lottery::{Lottery, LotteryStatus, MAX_LOTTERY_TICKETS};
+use crate::loss_insurance::{InsuredBet, LossInsuranceConfig, LossInsuranceTotals};
+use crate::notary::NotaryConfig;
+use crate::pause::PauseState;
+use crate::pipeline::PipelineConfig;
+use crate::price_oracle::{PriceOracleConfig, UsdPrice};
+use crate::promo::{PromoConfig, Promotion};
+use crate::providers::ProviderRegistry;
+use crate::queue::{ComputeLane, ComputeQueue, DEFAULT_MAX_VIP_STREAK};
+use crate::roles::Role;
+use crate::staking::{StakeSummary, StakingPool};
+use crate::streak_cover::{StreakCover, StreakCoverConfig, StreakCoverPool};
+use crate::sunset::Sunset;
+use crate::test_mode::MAX_FAUCET_AMOUNT;
+use crate::throttle::{BetThrottle, ThrottleConfig};
+use crate::token_client::GasCosts;
+use crate::tournament::Tournament;
+use crate::unclaimed::UnclaimedVaultConfig;
+use crate::void::BetCharges;
+use crate::treasury::{FeeSweeps, TreasuryConfig};
+use crate::upgrade::{CoinFlipStateV1, UpgradeSchedule, STATE_VERSION};
+use crate::webhooks::{Notification, WebhookTarget};
+use crate::tokens::{BurnConfig, ConversionRate, TokenConfig};
+use crate::rps::{
+    RpsGame, RpsStatus, RPS_CREATOR_WINS, RPS_DRAW, RPS_OPPONENT_WINS, RPS_THROW_TIMEOUT_MILLIS,
+};
+use crate::seasons::{SeasonRecord, SeasonState};
+use crate::sessions::BotSession;
+use crate::side_bets::{SideBet, SideBetPool, MAX_SIDE_BETS_PER_GAME};
+use crate::sponsorship::Sponsorship;
+use pbc_traits::ReadRPC;
+use pbc_traits::ReadWriteState;
+use pbc_contract_common::context::CallbackContext;
+use pbc_contract_common::upgrade::ContractHashes;
+
+
+/// Metadata information associated with each individual variable.
+#[derive(ReadWriteState, ReadWriteRPC, Debug)]
+#[repr(u8)]
+pub enum SecretVarType {
+    /// Randomness contributed to the round of `player`.
+    #[discriminant(0)]
+    Randomness {player: Address},
+    #[discriminant(1)]
+    FlipResult {player: Address},
+    /// Heads/tails choice of a player in secret choice mode.
+    #[discriminant(2)]
+    Choice {player: Address},
+    /// Win/lose outcome of a secret choice game.
+    #[discriminant(3)]
+    Outcome {player: Address},
+    /// Secret stake of a player in secret bet mode.
+    #[discriminant(4)]
+    SecretBet {player: Address},
+    /// Amount to return to a player in secret bet mode.
+    #[discriminant(5)]
+    SecretPayout {player: Address},
+    /// Bitmask of flips of a multi-flip bet.
+    #[discriminant(6)]
+    MultiFlipResult {player: Address},
+    /// Roll of a dice game.
+    #[discriminant(7)]
+    DiceRoll {player: Address},
+    /// Spin of a roulette game.
+    #[discriminant(8)]
+    RouletteSpin {player: Address},
+    /// Randomness contributed to the draw of a lottery.
+    #[discriminant(9)]
+    LotteryRandomness {lottery_id: u32},
+    /// Winning ticket index of a lottery.
+    #[discriminant(10)]
+    LotteryDraw {lottery_id: u32},
+    /// Coin flip of round `round` (counting from 0) of a best-of-N match.
+    #[discriminant(11)]
+    MatchRound {player: Address, round: u8},
+    /// Secret throw of a player in a rock-paper-scissors game.
+    #[discriminant(12)]
+    RpsThrow {game_id: u64, player: Address},
+    /// Outcome of a rock-paper-scissors game, see `rps::RPS_DRAW`.
+    #[discriminant(13)]
+    RpsOutcome {game_id: u64},
+    /// Coin flip of a zero-stake practice round.
+    #[discriminant(14)]
+    PracticeFlip {player: Address},
+    /// Coin flip of leg `leg` (counting from 0) of a parlay.
+    #[discriminant(15)]
+    ParlayLeg {player: Address, leg: u8},
+    /// Outcome of a weighted coin flip, see `zk_compute::compute_weighted_flip`.
+    #[discriminant(16)]
+    WeightedFlip {player: Address},
+    /// Private winnings balance of a player, see `claim_winnings_privately`.
+    #[discriminant(17)]
+    PrivateBalance {player: Address},
+    /// Amount withdrawn from a private balance, the only value of the balance that is opened.
+    #[discriminant(18)]
+    PrivateWithdrawal {player: Address},
+    /// Multiplier drawn in a bonus round, see `zk_compute::compute_bonus_multiplier`.
+    #[discriminant(19)]
+    BonusMultiplier {player: Address},
+    /// Randomness contributed by `player` to their match in round `round` of a bracket.
+    #[discriminant(20)]
+    BracketRandomness {bracket_id: u32, round: u8, player: Address},
+    /// Coin flip of match `match_index` in round `round` of a bracket.
+    #[discriminant(21)]
+    BracketMatch {bracket_id: u32, round: u8, match_index: u32},
+    /// Randomness contributed by `player` to the flip of a friends table.
+    #[discriminant(22)]
+    TableRandomness {table_id: u32, player: Address},
+    /// Coin flip of a friends table.
+    #[discriminant(23)]
+    TableFlip {table_id: u32},
+    /// Sealed stake of a player in secret bet mode, see `add_sealed_bet`.
+    #[discriminant(24)]
+    SealedStake {player: Address},
+    /// Amount payable to a player for a sealed stake, zero on a loss.
+    #[discriminant(25)]
+    SealedPayout {player: Address},
+    /// Sealed take of the house from the lost sealed stakes, last added to by `player`'s game.
+    #[discriminant(26)]
+    SealedHouseTake {player: Address},
+    /// Sealed credit of a player from the unstaked covers of their lost sealed bets.
+    #[discriminant(27)]
+    SealedCredit {player: Address},
+    /// Sealed take or credit released to `recipient`, the contract itself for the house.
+    #[discriminant(28)]
+    SealedRelease {recipient: Address},
+    /// House seed committed for `epoch`, folded into every round computed during the epoch.
+    #[discriminant(29)]
+    HouseSeed {epoch: u32},
+}
+
+
+/// Marker left behind when a player's game record is cleared, so audits can tell a
+/// cleaned-up record apart from one that never existed.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct Tombstone {
+    /// Block production time at which the record was cleared.
+    pub removed_at: i64,
+    /// Hash of the serialized bet, flip result and winner as they were when cleared.
+    pub final_state_hash: Hash,
+}
+
+/// Escrow transfer of a bet that has not yet called back, binding the callback to the bet.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone, PartialEq)]
+pub struct PendingEscrow {
+    pub amount: u64,
+    /// Nonce the bet was placed with.
+    pub nonce: u64,
+}
+
+/// Escrow of a bet that expired before its transfer called back. The stake is returned if the
+/// transfer still succeeds.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct ExpiredEscrow {
+    pub escrow: PendingEscrow,
+    pub token: Address,
+}
+
+/// How long tombstones are kept before `purge_tombstones` may remove them (30 days).
+const TOMBSTONE_RETENTION_MILLIS: i64 = 30 * 24 * 60 * 60 * 1000;
+
+/// Milliseconds in a day, the period storage rent is priced in.
+const MILLIS_PER_DAY: i64 = 24 * 60 * 60 * 1000;
+
+/// Storage rent charged from the internal balances of players with large state footprints.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+pub struct StorageRentConfig {
+    /// Bytes of state each player may use without paying rent.
+    pub free_bytes: u32,
+    /// Rent charged per started KiB above the free allowance, per day.
+    pub rent_per_kib_per_day: u64,
+}
+
+/// Weighted coin mode, where the coin lands on the player's side with configurable odds.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+pub struct WeightedCoinConfig {
+    /// Number of equally likely outcomes the randomness is reduced to.
+    pub denominator: u8,
+    /// House edge in basis points of the payout multiplier.
+    pub house_edge_bps: u32,
+}
+
+/// Storage usage attributed to a single player.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct StorageAccount {
+    /// Approximate bytes of state attributable to the player at the last collection.
+    pub bytes: u32,
+    /// Block production time at which rent was last collected.
+    pub last_collected_at: i64,
+}
+
+/// Score of a best-of-N match, where a single escrowed stake covers all rounds.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct MatchScore {
+    /// Number of rounds in the match, always odd.
+    pub rounds: u8,
+    pub player_wins: u8,
+    pub house_wins: u8,
+}
+
+impl MatchScore {
+    /// Number of rounds played so far, which is also the index of the next round.
+    pub fn rounds_played(&self) -> u8 {
+        self.player_wins + self.house_wins
+    }
+
+    /// Whether either side has won a majority of the rounds.
+    pub fn is_decided(&self) -> bool {
+        let majority = self.rounds / 2 + 1;
+        self.player_wins >= majority || self.house_wins >= majority
+    }
+}
+
+/// Maximum number of rounds in a best-of-N match.
+const MAX_MATCH_ROUNDS: u8 = 15;
+
+/// Predictions of a parlay, where the stake rides across a flip per prediction and is only won
+/// if every prediction hits.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct Parlay {
+    pub choices: Vec<PlayerChoice>,
+    /// Number of legs won so far, which is also the index of the next leg.
+    pub legs_won: u8,
+}
+
+impl Parlay {
+    /// The prediction for the next leg.
+    pub fn current_choice(&self) -> PlayerChoice {
+        self.choices[self.legs_won as usize]
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.legs_won as usize == self.choices.len()
+    }
+}
+
+/// Maximum number of legs in a parlay.
+const MAX_PARLAY_LEGS: usize = 10;
+
+/// Escalating penalties for players who abandon games mid-flow.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+pub struct AbandonPenaltyConfig {
+    /// Abandonments after which each further abandonment starts a betting cooldown.
+    pub cooldown_threshold: u32,
+    pub cooldown_millis: i64,
+    /// Abandonments after which a fee is taken from every subsequent stake.
+    pub fee_threshold: u32,
+    pub fee_bps: u32,
+}
+
+/// Abandonment history of a player.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct AbandonRecord {
+    pub abandon_count: u32,
+    /// Block production time until which the player cannot bet.
+    pub cooldown_until: i64,
+}
+
+/// Results of a player's practice rounds, kept apart from real games.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct PracticeStats {
+    pub rounds: u32,
+    pub wins: u32,
+    pub last_played_at: i64,
+}
+
+/// Lifetime statistics of a player's settled games.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone, Default)]
+pub struct PlayerStats {
+    pub games_played: u64,
+    pub wins: u64,
+    pub losses: u64,
+    pub total_wagered: u64,
+    /// Everything transferred to the player by `payout_winner`.
+    pub total_paid_out: u64,
+    pub current_win_streak: u32,
+    pub best_win_streak: u32,
+}
+
+impl PlayerStats {
+    /// Record a settled game.
+    fn record_game(&mut self, amount: u64, player_won: bool) {
+        self.games_played += 1;
+        self.total_wagered += amount;
+        if player_won {
+            self.wins += 1;
+            self.current_win_streak += 1;
+            self.best_win_streak = self.best_win_streak.max(self.current_win_streak);
+        } else {
+            self.losses += 1;
+            self.current_win_streak = 0;
+        }
+    }
+
+    /// Net profit of the player over all games, negative when they lost more than they won.
+    pub fn net_pnl(&self) -> i128 {
+        self.total_paid_out as i128 - self.total_wagered as i128
+    }
+}
+
+/// Maximum commission paid to referrers on the bets of their referees, in basis points.
+const MAX_REFERRAL_COMMISSION_BPS: u32 = 1_000;
+
+/// Randomness contributions accepted per round unless the owner configures otherwise.
+const DEFAULT_MAX_ROUND_CONTRIBUTIONS: u32 = 8;
+
+/// Randomness contributions a sender may have in open rounds at once unless the owner configures
+/// otherwise.
+pub(crate) const DEFAULT_MAX_SENDER_CONTRIBUTIONS: u32 = 16;
+
+/// Minimum time between two practice rounds of the same player.
+const PRACTICE_COOLDOWN_MILLIS: i64 = 60_000;
+
+/// Lost sealed bets a sealed credit or the house's sealed take must total before it is released,
+/// so the opened total never reveals the stake of a single bet.
+const MIN_SEALED_LOSSES_PER_RELEASE: u32 = 5;
+
+/// Balance of a user, see `CoinFlipState::balance_of`.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+pub struct BalanceView {
+    pub balance: u64,
+    pub pending_withdrawal: u64,
+    pub available: u64,
+}
+
+/// Summary of a player's game and funds, kept in `CoinFlipState::player_views` so clients can
+/// show a player's status from a single entry.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct PlayerView {
+    pub phase: GamePhase,
+    /// Bet of the game in play, until the game is done.
+    pub active_bet: Option<PlayerBet>,
+    /// Whether the player won their most recently concluded game, if any.
+    pub last_won: Option<bool>,
+    /// Winnings waiting to be claimed with `payout`, in the payout token of the bet.
+    pub pending_payout: u64,
+    pub balance: BalanceView,
+}
+
+/// Settings of a new deployment, so it needs no administrative transactions before taking bets.
+#[derive(ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+pub struct InitConfig {
+    /// Default token, in which balances and the house bankroll are held.
+    pub token: Address,
+    /// Owner of the contract, the deployer when absent.
+    pub owner: Option<Address>,
+    /// Bet limits of the default token.
+    pub min_bet: u64,
+    pub max_bet: u64,
+    /// Maximum potential payouts of open bets, in basis points of the bankroll. Zero disables the
+    /// limit.
+    pub max_exposure_bps: u32,
+    pub dice_house_edge_bps: u32,
+    pub parlay_house_edge_bps: u32,
+    /// Whether every round also needs randomness from its bettor. Randomness from a non-bettor
+    /// is always required.
+    pub strict_fairness: bool,
+    pub max_round_contributions: u32,
+    pub dispute_window_millis: i64,
+    /// How long settled games are kept before `cleanup` may remove them.
+    pub settled_game_retention_millis: i64,
+    pub phase_timeouts: PhaseTimeouts,
+    /// Whether the testnet-only actions of `test_mode` are available.
+    pub is_test_mode: bool,
+}
+
+impl InitConfig {
+    /// Settings of a deployment accepting any bet in `token`, with every optional limit disabled.
+    pub fn new(token: Address) -> Self {
+        InitConfig {
+            token,
+            owner: None,
+            min_bet: 1,
+            max_bet: u64::MAX,
+            max_exposure_bps: 0,
+            dice_house_edge_bps: 0,
+            parlay_house_edge_bps: 0,
+            strict_fairness: false,
+            max_round_contributions: DEFAULT_MAX_ROUND_CONTRIBUTIONS,
+            dispute_window_millis: 0,
+            settled_game_retention_millis: DEFAULT_SETTLED_GAME_RETENTION_MILLIS,
+            phase_timeouts: PhaseTimeouts::new(),
+            is_test_mode: false,
+        }
+    }
+
+    fn validate(&self) {
+        ensure!(
+            self.min_bet > 0 && self.min_bet <= self.max_bet,
+            GameError::InvalidArgument,
+            "The minimum bet must be positive and at most the maximum bet."
+        );
+        ensure!(
+            self.dice_house_edge_bps < 10_000 && self.parlay_house_edge_bps < 10_000,
+            GameError::InvalidArgument,
+            "The house edge must be less than one times the stake."
+        );
+        ensure!(
+            self.max_round_contributions >= 2,
+            GameError::InvalidArgument,
+            "A round must accept at least two contributions."
+        );
+        ensure!(
+            self.dispute_window_millis >= 0,
+            GameError::InvalidArgument,
+            "The dispute window cannot be negative."
+        );
+        ensure!(
+            self.settled_game_retention_millis >= 0,
+            GameError::InvalidArgument,
+            "The retention window cannot be negative."
+        );
+        ensure!(
+            self.phase_timeouts.is_valid(),
+            GameError::InvalidArgument,
+            "Phase timeouts cannot be negative."
+        );
+    }
+}
+
+/// A double-or-nothing streak of a player letting their winnings ride.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct RideStreak {
+    /// Number of consecutive rides so far.
+    pub rides: u32,
+    /// Stake of the bet the streak started from.
+    pub original_stake: u64,
+}
+
+impl RideStreak {
+    /// Current stake as a multiple of the original stake.
+    pub fn multiplier(&self, current_stake: u64) -> u64 {
+        current_stake / self.original_stake.max(1)
+    }
+}
+
+/// The state of the coin flip game, now supporting multiple players.
+#[state]
+pub struct CoinFlipState {
+    // Per-player maps grow with the number of players, so they are kept in AVL trees that only
+    // load the entries an action touches.
+    pub(crate) player_bets: AvlTreeMap<Address, PlayerBet>,
+    pub(crate) flip_results: AvlTreeMap<Address, bool>,
+    pub(crate) winners: AvlTreeMap<Address, Address>,
+    pub(crate) user_balances: AvlTreeMap<Address, u64>,
+    pub(crate) game_phases: AvlTreeMap<Address, GamePhase>,
+    pub(crate) token_address: Address, // New field to store the token contract address
+    pub(crate) tombstones: AvlTreeMap<Address, Tombstone>,
+    pub(crate) owner: Address,
+    /// Owner nominated by `transfer_ownership`, until they accept.
+    pub(crate) pending_owner: Option<Address>,
+    /// Roles granted by the owner, see `Role`.
+    pub(crate) role_grants: SortedVecMap<Address, Vec<Role>>,
+    pub(crate) storage_rent: Option<StorageRentConfig>,
+    pub(crate) storage_accounts: AvlTreeMap<Address, StorageAccount>,
+    pub(crate) collected_storage_rent: u64,
+    pub(crate) epoch: EpochState,
+    pub(crate) epoch_archive: Option<EpochArchiveConfig>,
+    /// Unstaked part of the cover of settled secret bets, returned at payout.
+    pub(crate) secret_bet_refunds: AvlTreeMap<Address, u64>,
+    /// Opened flips of multi-flip bets, bit `i` set meaning flip `i` landed heads.
+    pub(crate) multi_flip_results: AvlTreeMap<Address, u32>,
+    /// Opened rolls of dice games, counting from 1.
+    pub(crate) dice_rolls: AvlTreeMap<Address, u8>,
+    /// Opened pockets of roulette games.
+    pub(crate) roulette_spins: AvlTreeMap<Address, u8>,
+    /// House edge of dice games in basis points of the payout multiplier.
+    pub(crate) dice_house_edge_bps: u32,
+    /// When set, a round is only computed once its bettor contributed as well as someone else.
+    pub(crate) strict_fairness: bool,
+    /// Addresses whose randomness contributions to each player's round have been confirmed.
+    pub(crate) round_contributors: AvlTreeMap<Address, Vec<Address>>,
+    /// The current or most recent lottery.
+    pub(crate) lottery: Option<Lottery>,
+    /// Running double-or-nothing streaks of players who let their winnings ride.
+    pub(crate) ride_streaks: AvlTreeMap<Address, RideStreak>,
+    /// Maximum number of consecutive rides; zero disables `let_it_ride`.
+    pub(crate) max_consecutive_rides: u32,
+    /// Number of rounds started so far, used to derive the salts of practice rounds.
+    pub(crate) rounds_started: u64,
+    /// Public salt of each player's current round, tagging its computations with the round.
+    pub(crate) round_salts: AvlTreeMap<Address, Hash>,
+    /// Scores of running and finished best-of-N matches.
+    pub(crate) matches: AvlTreeMap<Address, MatchScore>,
+    pub(crate) abandon_penalty: Option<AbandonPenaltyConfig>,
+    pub(crate) abandonments: AvlTreeMap<Address, AbandonRecord>,
+    /// Fees taken from the stakes of players penalized for abandoning games.
+    pub(crate) collected_penalty_fees: u64,
+    pub(crate) rps_games: SortedVecMap<u64, RpsGame>,
+    pub(crate) next_rps_game_id: u64,
+    /// Players whose rounds are computed in the VIP lane.
+    pub(crate) vip_players: Vec<Address>,
+    /// Rounds waiting for the computation engine to become available.
+    pub(crate) compute_queue: ComputeQueue,
+    /// Share of every bet, in basis points, that accrues to the jackpot.
+    pub(crate) jackpot_rake_bps: u32,
+    /// Progressive jackpot, awarded when a coin flip hits the jackpot pattern.
+    pub(crate) jackpot_pool: u64,
+    /// Recent phase transitions and settlements, see `get_changes_since`.
+    pub(crate) recent_changes: ChangeLog,
+    /// Withdrawals from `user_balances` awaiting their token transfer.
+    pub(crate) pending_withdrawals: AvlTreeMap<Address, u64>,
+    /// Whether each player's current round has been computed or queued and not settled yet.
+    pub(crate) computation_pending: AvlTreeMap<Address, bool>,
+    /// House capital backing payouts, kept apart from player escrow and balances.
+    pub(crate) house_bankroll: u64,
+    /// Whitelisted yield contract idle bankroll may be deployed to.
+    pub(crate) yield_adapter: Option<YieldAdapter>,
+    /// Tokens accepted for bets. The default token at `token_address` is always registered.
+    pub(crate) token_registry: SortedVecMap<Address, TokenConfig>,
+    /// Token of each player's current bet, when it is not the default token.
+    pub(crate) bet_tokens: AvlTreeMap<Address, Address>,
+    /// Fund for compensating players after incidents, fed by a share of fees.
+    pub(crate) insurance: InsuranceFund,
+    /// Choices of practice rounds waiting for their flip.
+    pub(crate) practice_choices: AvlTreeMap<Address, PlayerChoice>,
+    pub(crate) practice_stats: AvlTreeMap<Address, PracticeStats>,
+    /// Log contract settled rounds are notarized to, if any.
+    pub(crate) notary: Option<NotaryConfig>,
+    /// Hashes of rounds settled in the current transaction, waiting to be sent to the notary.
+    pub(crate) notarization_outbox: Vec<Hash>,
+    /// Maximum potential payouts of open bets, in basis points of the bankroll of their token.
+    /// Zero disables the limit.
+    pub(crate) max_exposure_bps: u32,
+    /// Potential payouts of open bets, per token.
+    pub(crate) outstanding_payouts: SortedVecMap<Address, u64>,
+    /// Potential payout of each player's open bet.
+    pub(crate) bet_exposures: AvlTreeMap<Address, u64>,
+    /// Conversion rates from each stake token to the payout tokens it can be paid out in.
+    pub(crate) conversion_rates: SortedVecMap<Address, Vec<ConversionRate>>,
+    /// Payout token of each player's current bet, when it differs from the stake token.
+    pub(crate) payout_tokens: AvlTreeMap<Address, Address>,
+    /// Winnings of settled dual-token bets, converted into the payout token at settlement.
+    pub(crate) converted_winnings: AvlTreeMap<Address, u64>,
+    /// Completed games, see `get_game_history`.
+    pub(crate) game_history: GameHistory,
+    /// Burning of losing stakes, disabled when absent.
+    pub(crate) burn: Option<BurnConfig>,
+    /// Burned stakes not yet sent to the token contract's burn action.
+    pub(crate) pending_burn: u64,
+    /// Lifetime statistics of every player that has settled a game.
+    pub(crate) player_stats: AvlTreeMap<Address, PlayerStats>,
+    /// Pricing of streak cover, not sold when absent.
+    pub(crate) streak_cover: Option<StreakCoverConfig>,
+    pub(crate) streak_cover_pool: StreakCoverPool,
+    /// Streak cover bought for each player's current bet.
+    pub(crate) covered_bets: AvlTreeMap<Address, StreakCover>,
+    /// Accounts of the custodians allowed to play on behalf of their end users.
+    pub(crate) custody_accounts: SortedVecMap<Address, CustodyAccount>,
+    /// The current or most recent tournament, if any has been opened.
+    pub(crate) tournament: Option<Tournament>,
+    /// Referrer of each referred player.
+    pub(crate) referrers: AvlTreeMap<Address, Address>,
+    /// Commission paid by the house to referrers on every bet of their referees, in basis points.
+    pub(crate) referral_commission_bps: u32,
+    /// Commissions earned by referrers and not yet claimed.
+    pub(crate) referral_rewards: AvlTreeMap<Address, u64>,
+    /// Loyalty points of every player, earned one per token wagered.
+    pub(crate) loyalty_points: AvlTreeMap<Address, u64>,
+    /// Loyalty points redeemed for each token of free-bet credit. Zero disables redemption.
+    pub(crate) points_per_free_bet_credit: u64,
+    /// Free-bet credits of every player, spent on bets instead of escrowed tokens.
+    pub(crate) free_bet_credits: AvlTreeMap<Address, u64>,
+    /// Players whose current bet is a free bet.
+    pub(crate) free_bets: AvlTreeMap<Address, bool>,
+    /// Randomness contributions accepted per round; later ones are deleted to bound the cost of
+    /// the round's computation. One of them is kept for the bettor.
+    pub(crate) max_round_contributions: u32,
+    /// Whether only allowlisted players may bet.
+    pub(crate) allowlist_enabled: bool,
+    pub(crate) allowlist: AvlTreeMap<Address, bool>,
+    /// Address allowed to manage the allowlist besides the owner, e.g. a KYC provider.
+    pub(crate) allowlist_registrar: Option<Address>,
+    /// Contracts notified of operational events.
+    pub(crate) webhook_targets: Vec<WebhookTarget>,
+    /// Smallest winnings reported as a large win. Zero disables large-win notifications.
+    pub(crate) large_win_threshold: u64,
+    /// Notifications raised in the current transaction, waiting to be sent to the targets.
+    pub(crate) notification_outbox: Vec<Notification>,
+    /// Addresses blocked from betting by the operator.
+    pub(crate) blocked_addresses: AvlTreeMap<Address, bool>,
+    /// End of the self-exclusion of every player that requested one.
+    pub(crate) self_exclusions: AvlTreeMap<Address, i64>,
+    /// Which actions are paused by the operator.
+    pub(crate) pause_state: PauseState,
+    /// Configuration of instant mode, disabled when absent.
+    pub(crate) instant_config: Option<InstantConfig>,
+    /// Committed instant entropy, oldest first.
+    pub(crate) instant_entropy: Vec<InstantEntropy>,
+    /// Shares of stakers and the house in the default token's bankroll.
+    pub(crate) bankroll_stakes: StakingPool,
+    /// Version of the state layout, see `upgrade::STATE_VERSION`.
+    pub(crate) state_version: u32,
+    pub(crate) upgrade_schedule: UpgradeSchedule,
+    /// Shutdown of the contract, once announced.
+    pub(crate) sunset: Option<Sunset>,
+    /// When each game in the `Done` phase settled, see `cleanup`.
+    pub(crate) settled_at: AvlTreeMap<Address, i64>,
+    pub(crate) cleanup_config: CleanupConfig,
+    pub(crate) randomness_providers: ProviderRegistry,
+    /// Whether settled results are attested by the ZK nodes.
+    pub(crate) attest_results: bool,
+    /// Results settled in the current transaction, waiting to be attested.
+    pub(crate) attestation_outbox: Vec<ResultRecord>,
+    /// The latest attested result of each player.
+    pub(crate) result_attestations: AvlTreeMap<Address, AttestedResult>,
+    /// How long an opened result may be disputed before it is settled. Zero settles results as
+    /// soon as they are opened.
+    pub(crate) dispute_window_millis: i64,
+    /// Address allowed to void disputed games besides the owner.
+    pub(crate) arbiter: Option<Address>,
+    /// End of the dispute window of every game in the `Challengeable` phase.
+    pub(crate) challenge_deadlines: AvlTreeMap<Address, i64>,
+    /// Pricing of loss insurance, not sold when absent.
+    pub(crate) loss_insurance: Option<LossInsuranceConfig>,
+    pub(crate) insured_bets: AvlTreeMap<Address, InsuredBet>,
+    pub(crate) loss_insurance_totals: LossInsuranceTotals,
+    /// Spectators' side bets on the flip of each player's open game.
+    pub(crate) side_bets: AvlTreeMap<Address, Vec<SideBet>>,
+    pub(crate) side_bet_pool: SideBetPool,
+    /// Parlays of players whose current bet is one.
+    pub(crate) parlays: AvlTreeMap<Address, Parlay>,
+    pub(crate) parlay_house_edge_bps: u32,
+    /// Weighted coin mode, disabled when `None`.
+    pub(crate) weighted_coin: Option<WeightedCoinConfig>,
+    /// Odds of players whose current bet is on a weighted coin.
+    pub(crate) weighted_bets: AvlTreeMap<Address, WeightedOdds>,
+    /// Next bet nonce of each player, see `place_bet`.
+    pub(crate) bet_nonces: AvlTreeMap<Address, u64>,
+    /// Escrow transfers of bets awaiting their callback.
+    pub(crate) pending_escrows: AvlTreeMap<Address, PendingEscrow>,
+    /// Fee kept from the stake of a cancelled bet, in basis points.
+    pub(crate) cancellation_fee_bps: u32,
+    /// Promotional matching of bets and its budget.
+    pub(crate) promotion: Promotion,
+    /// Paid to the caller of `keeper_tick` from the house bankroll for a tick that did work.
+    pub(crate) keeper_bounty: u64,
+    /// Predicted sequences of players whose current bet is on an exact multi-flip sequence,
+    /// encoded as by `engine::encode_sequence`.
+    pub(crate) sequence_bets: AvlTreeMap<Address, u32>,
+    /// Players whose private balance is being updated by a computation.
+    pub(crate) private_updates_pending: AvlTreeMap<Address, bool>,
+    /// Shortname of the token's allowance query. When set, escrowed bets of
+    /// `start_game_and_place_bet` are only placed once the allowance is known to cover them.
+    pub(crate) allowance_shortname: Option<u32>,
+    /// Status of every player that has played or held a balance, refreshed by each transition.
+    pub(crate) player_views: AvlTreeMap<Address, PlayerView>,
+    /// Timeouts of the stages games wait in, see `expire_game`.
+    pub(crate) phase_timeouts: PhaseTimeouts,
+    /// When each player's game entered its current stage.
+    pub(crate) stage_entered_at: AvlTreeMap<Address, i64>,
+    /// Escrows of bets that expired while their transfer was in flight.
+    pub(crate) expired_escrows: AvlTreeMap<Address, ExpiredEscrow>,
+    /// Gas sent with interactions and reserved for the callbacks registered on them.
+    pub(crate) gas_costs: GasCosts,
+    /// Contracts allowed to bet on behalf of players, see `integrator_bet`.
+    pub(crate) integrators: SortedVecMap<Address, Integrator>,
+    /// Integrator that placed, and paid the stake of, each player's current bet.
+    pub(crate) bet_integrators: AvlTreeMap<Address, Address>,
+    /// Sponsorship budgets of sponsors, see `start_sponsored_game`.
+    pub(crate) sponsorships: SortedVecMap<Address, Sponsorship>,
+    /// Sponsor whose budget funded each player's current bet.
+    pub(crate) sponsored_bets: AvlTreeMap<Address, Address>,
+    /// Treasury contract receiving the house's fees, see `sweep_fees`.
+    pub(crate) treasury: Option<TreasuryConfig>,
+    pub(crate) fee_sweeps: FeeSweeps,
+    /// Standings of the running season.
+    pub(crate) season: SeasonState,
+    /// Records of closed seasons by season number.
+    pub(crate) season_archive: AvlTreeMap<u32, SeasonRecord>,
+    /// Limits on how often bets are accepted, and the latest bets they are checked against.
+    pub(crate) bet_throttle: BetThrottle,
+    /// Responsible-gambling limits players set on themselves, see `set_gambling_limits`.
+    pub(crate) gambling_limits: AvlTreeMap<Address, PlayerLimits>,
+    /// Default token held for games, apart from deposited balances.
+    pub(crate) ledger: EscrowLedger,
+    /// Whether won flips may trigger a bonus round, see `set_bonus_round`.
+    pub(crate) bonus_round_enabled: bool,
+    /// Multipliers drawn in the bonus rounds of won games, applied to their winnings.
+    pub(crate) bonus_multipliers: AvlTreeMap<Address, u8>,
+    /// Running and finished elimination brackets, by id.
+    pub(crate) brackets: SortedVecMap<u32, Bracket>,
+    pub(crate) next_bracket_id: u32,
+    /// House seeds committed for each epoch, see `commit_house_seed`.
+    pub(crate) house_seeds: SortedVecMap<u32, SeedCommitment>,
+    /// Contract pushed the transitions of players' games, see `set_event_sink`.
+    pub(crate) event_sink: Option<EventSinkConfig>,
+    /// Game events raised but not sent to the event sink yet.
+    pub(crate) game_event_outbox: Vec<GameEvent>,
+    /// Whether bets are only accepted while the free bankroll can reserve their potential payout,
+    /// see `set_winnings_reserve`.
+    pub(crate) reserve_winnings: bool,
+    /// Randomness contributions a sender may have in open rounds at once, so a single party
+    /// cannot flood the zk state with contributions to many rounds.
+    pub(crate) max_sender_contributions: u32,
+    /// Public key each player signs their bet intents with, see `submit_signed_bet`.
+    pub(crate) intent_keys: AvlTreeMap<Address, PublicKey>,
+    /// Claim window of won games and where their unclaimed winnings go, see `set_unclaimed_vault`.
+    pub(crate) unclaimed_vault: Option<UnclaimedVaultConfig>,
+    /// Time after which the winnings of each player's won game can be swept.
+    pub(crate) claim_deadlines: AvlTreeMap<Address, i64>,
+    /// Unclaimed winnings swept so far.
+    pub(crate) unclaimed_swept_total: u64,
+    /// Oracle quoting the USD prices of bet tokens, see `set_price_oracle`.
+    pub(crate) price_oracle: Option<PriceOracleConfig>,
+    /// Last USD price quoted for each token.
+    pub(crate) usd_prices: SortedVecMap<Address, UsdPrice>,
+    /// Bots approved for sessions, with the flips a session of each may run.
+    pub(crate) session_bots: SortedVecMap<Address, u32>,
+    /// Open sessions, by bot.
+    pub(crate) bot_sessions: SortedVecMap<Address, BotSession>,
+    /// Whether the current bet of a bot was staked from its session.
+    pub(crate) session_bets: SortedVecMap<Address, bool>,
+    /// Friends tables, by id.
+    pub(crate) friends_tables: SortedVecMap<u32, FriendsTable>,
+    pub(crate) next_table_id: u32,
+    /// Id of the open friends table of each invite code hash.
+    pub(crate) table_invites: SortedVecMap<Hash, u32>,
+    /// Chaining of escrow, randomness requests and the flip, see `set_pipeline`.
+    pub(crate) pipeline: Option<PipelineConfig>,
+    /// Whether the testnet-only actions are available, fixed at initialization.
+    pub(crate) is_test_mode: bool,
+    /// Id of each player's current game, see `compute_game_id`.
+    pub(crate) game_ids: AvlTreeMap<Address, Hash>,
+    /// Resolved games by resolution sequence number, see `export_games`.
+    pub(crate) resolved_games: AvlTreeMap<u64, ExportedGame>,
+    /// Number of games resolved since deployment, the sequence number of the next one.
+    pub(crate) resolved_game_count: u64,
+    /// Charges taken on account of each player's current bet, reversed if its game is voided.
+    pub(crate) bet_charges: AvlTreeMap<Address, BetCharges>,
+    /// Cover of each lost sealed bet whose split between the house and the player is being
+    /// computed.
+    pub(crate) sealed_refunds: AvlTreeMap<Address, u64>,
+    /// Progress of the import of balances from a previous deployment, see `import_state`.
+    pub(crate) import: ImportStats,
+    /// Time by which the players of each rock-paper-scissors game accepting throws must throw.
+    pub(crate) rps_throw_deadlines: SortedVecMap<u64, i64>,
+    /// Instant bets waiting for their entropy, the first for the first value of
+    /// `instant_entropy`.
+    pub(crate) instant_bets: Vec<InstantBet>,
+    /// Settled games in the order they settled, for `prune_expired_games`.
+    pub(crate) settlement_queue: SettlementQueue,
+    /// Accounts credited by the faucet, whose balances and winnings are play money.
+    pub(crate) play_money_accounts: AvlTreeMap<Address, bool>,
+    /// Lost sealed bets credited to each player's sealed credit since its last withdrawal.
+    pub(crate) sealed_credit_losses: AvlTreeMap<Address, u32>,
+    /// Lost sealed bets added to the house's sealed take since its last release.
+    pub(crate) sealed_house_losses: u32,
+    /// Accounts imported by `import_state`.
+    pub(crate) imported_users: AvlTreeMap<Address, bool>,
+    /// Jackpots won by each player's current game and not paid out yet, in the default token.
+    pub(crate) won_jackpots: AvlTreeMap<Address, u64>,
+    /// Sum of `user_balances`, kept up to date so it is known without reading every balance.
+    pub(crate) user_balance_total: u64,
+}
+
+#[allow(dead_code)]
+impl CoinFlipState {
+    /// Check if the game is finished for the given player.
+    fn is_game_finished(&self, player: &Address) -> bool {
+        self.flip_results.contains_key(player)
+    }
+
+    /// Get the winner of the game for a given player.
+    fn get_winner(&self, player: &Address) -> Option<Address> {
+        self.winners.get(player)
+    }
+
+    /// Nonce the next bet of the player must be placed with.
+    fn next_bet_nonce(&self, player: &Address) -> u64 {
+        self.bet_nonces.get(player).unwrap_or(0)
+    }
+
+    /// Consume the bet nonce of the player, which must be the next one.
+    fn use_bet_nonce(&mut self, player: &Address, nonce: u64) {
+        let expected = self.next_bet_nonce(player);
+        ensure!(
+            nonce >= expected,
+            GameError::InvalidNonce,
+            "The bet nonce has already been used."
+        );
+        ensure!(
+            nonce == expected,
+            GameError::InvalidNonce,
+            "The bet nonce must be the next nonce of the player."
+        );
+        self.bet_nonces.insert(*player, expected + 1);
+    }
+
+    /// Match the player's bet with a promotional stake if a promotion is running.
+    fn apply_promo(&mut self, player: &Address, now: i64) {
+        let Some(bet) = self.player_bets.get(player) else {
+            return;
+        };
+        let stake = self.promotion.offer(player, bet.amount, now);
+        if stake > 0 {
+            let winnings = self.winnings_of(player, &bet);
+            let payout = (winnings as u128 * stake as u128 / bet.amount as u128) as u64;
+            self.promotion.grant(*player, stake, payout);
+        }
+    }
+
+    /// Reserve the potential payout of the player's bet again after its odds changed.
+    fn reprice_bet(&mut self, player: &Address, now: i64) {
+        let bet = self.player_bets.get(player).unwrap();
+        let potential_payout = self.winnings_of(player, &bet);
+        self.release_exposure(player);
+        let token = self.bet_token_of(player);
+        self.reserve_exposure(player, &token, potential_payout);
+        if self.promotion.stakes.get(player).is_some() {
+            self.promotion.revoke(player);
+            self.apply_promo(player, now);
+        }
+    }
+
+    /// Amount paid out if the player's bet wins, which for a parlay pays its every leg.
+    fn winnings_of(&self, player: &Address, bet: &PlayerBet) -> u64 {
+        if let Some(odds) = self.weighted_bets.get(player) {
+            let house_edge_bps = self
+                .weighted_coin
+                .as_ref()
+                .map_or(0, |config| config.house_edge_bps);
+            return odds.winnings(bet.amount, house_edge_bps);
+        }
+        match self.parlays.get(player) {
+            Some(parlay) => parlay_winnings(
+                bet.amount,
+                parlay.choices.len() as u32,
+                self.parlay_house_edge_bps,
+            ),
+            None => {
+                // A bonus round multiplies the winnings of a plain bet
+                let multiplier = self.bonus_multipliers.get(player).unwrap_or(1);
+                winnings_for(bet, self.dice_house_edge_bps) * multiplier as u64
+            }
+        }
+    }
+
+    /// Adjust the balance of a given user.
+    pub(crate) fn adjust_balance(&mut self, user: Address, amount: u64) {
+        let balance = self.user_balances.get(&user).unwrap_or(0);
+        self.set_balance(user, balance + amount);
+    }
+
+    fn set_balance(&mut self, user: Address, balance: u64) {
+        let previous = self.user_balances.get(&user).unwrap_or(0);
+        self.user_balance_total = self.user_balance_total - previous + balance;
+        self.user_balances.insert(user, balance);
+        self.refresh_player_view(&user);
+    }
+
+    /// Token the player's current bet is escrowed and paid out in.
+    fn bet_token_of(&self, player: &Address) -> Address {
+        self.bet_tokens.get(player).unwrap_or(self.token_address)
+    }
+
+    /// Whether the player may bet, which is always the case unless allowlist mode is enabled.
+    pub(crate) fn is_allowlisted(&self, player: &Address) -> bool {
+        !self.allowlist_enabled || self.allowlist.contains_key(player)
+    }
+
+    /// Whether the player is blocked or self-excluded at the given time.
+    pub(crate) fn is_banned(&self, player: &Address, now: i64) -> bool {
+        self.blocked_addresses.contains_key(player)
+            || self
+                .self_exclusions
+                .get(player)
+                .map_or(false, |excluded_until| now < excluded_until)
+    }
+
+    fn assert_accepting_bets(&self) {
+        ensure!(
+            self.pause_state.accepts_bets(),
+            GameError::Unavailable,
+            "New bets are paused."
+        );
+    }
+
+    fn assert_not_paused(&self) {
+        ensure!(
+            self.pause_state.allows_play(),
+            GameError::Unavailable,
+            "The contract is paused."
+        );
+    }
+
+    /// At most `limit` players whose game holds an escrowed or escrowing stake that is not yet
+    /// settled.
+    fn outstanding_games(&self, limit: u32) -> Vec<Address> {
+        self.player_bets
+            .iter()
+            .map(|(player, _)| player)
+            .filter(|player| self.game_phases.get(player) != Some(GamePhase::Done {}))
+            .take(limit as usize)
+            .collect()
+    }
+
+    fn assert_not_banned(&self, player: &Address, now: i64) {
+        ensure!(
+            !self.is_banned(player, now),
+            GameError::BetRejected,
+            "The player is banned from betting."
+        );
+    }
+
+    fn assert_allowlisted(&self, player: &Address) {
+        ensure!(
+            self.is_allowlisted(player),
+            GameError::BetRejected,
+            "The player is not on the allowlist."
+        );
+    }
+
+    /// Whether a bet of `amount` in `token` would be escrowed by a transfer, rather than paid from
+    /// free-bet credits or the deposited balance.
+    fn needs_escrow(&self, player: &Address, amount: u64, token: &Address) -> bool {
+        if *token != self.token_address {
+            return true;
+        }
+        let free_bet_credits = self.free_bet_credits.get(player).unwrap_or(0);
+        free_bet_credits < amount && self.available_balance(player) < amount
+    }
+
+    /// Whether the player's current bet is a free bet.
+    fn is_free_bet(&self, player: &Address) -> bool {
+        self.free_bets.get(player) == Some(true)
+    }
+
+    /// Token the player's current bet pays out in.
+    pub(crate) fn payout_token_of(&self, player: &Address) -> Address {
+        self.payout_tokens
+            .get(player)
+            .unwrap_or_else(|| self.bet_token_of(player))
+    }
+
+    /// Convert an amount of the stake token into the payout token at the current rate.
+    pub(crate) fn convert_payout(
+        &self,
+        stake_token: &Address,
+        payout_token: &Address,
+        amount: u64,
+    ) -> u64 {
+        if stake_token == payout_token {
+            return amount;
+        }
+        self.conversion_rates
+            .get(stake_token)
+            .and_then(|rates| rates.iter().find(|rate| rate.payout_token == *payout_token))
+            .or_fail(
+                GameError::NotFound,
+                "No conversion rate between the stake and payout token.",
+            )
+            .convert(amount)
+    }
+
+    /// Remove and return the unstaked part of the cover of the player's secret bet.
+    fn take_secret_bet_refund(&mut self, player: &Address) -> u64 {
+        let refund = self.secret_bet_refunds.get(player).unwrap_or(0);
+        self.secret_bet_refunds.remove(player);
+        refund
+    }
+
+    /// Credit the referrer of the player their commission on an escrowed bet, paid from the house
+    /// bankroll. Commissions are paid in the default token, so only its bets earn them.
+    fn credit_referral(&mut self, player: &Address, bet_amount: u64) {
+        if let Some(referrer) = self.referrers.get(player) {
+            let commission =
+                (bet_amount as u128 * self.referral_commission_bps as u128 / 10_000) as u64;
+            let commission = commission.min(self.house_bankroll);
+            self.house_bankroll -= commission;
+            let rewards = self.referral_rewards.get(&referrer).unwrap_or(0);
+            self.referral_rewards.insert(referrer, rewards + commission);
+            self.record_referral_commission(player, commission);
+        }
+    }
+
+    /// Pay every provider that contributed to the round of the player its fee for the flip, as far
+    /// as the free house bankroll allows.
+    fn pay_provider_fees(&mut self, player: &Address) {
+        let fee = self.randomness_providers.fee_per_flip;
+        if fee == 0 {
+            return;
+        }
+        let contributors = self.round_contributors.get(player).unwrap_or_default();
+        for contributor in contributors {
+            if contributor == *player || !self.randomness_providers.is_provider(&contributor) {
+                continue;
+            }
+            let fee = fee.min(self.free_bankroll(&self.token_address));
+            self.house_bankroll -= fee;
+            self.randomness_providers.credit(contributor, fee);
+        }
+    }
+
+    /// Deposited balance of a user that is not tied up in a pending withdrawal.
+    pub(crate) fn available_balance(&self, user: &Address) -> u64 {
+        let balance = self.user_balances.get(user).unwrap_or(0);
+        let pending = self.pending_withdrawals.get(user).unwrap_or(0);
+        balance.saturating_sub(pending)
+    }
+
+    /// The latest attested result of a player, with the nodes' signatures.
+    pub fn get_result_attestation(&self, player: &Address) -> Option<AttestedResult> {
+        self.result_attestations.get(player)
+    }
+
+    /// Shares of a staker in the bankroll and what they are currently worth.
+    pub fn get_stake(&self, staker: &Address) -> StakeSummary {
+        self.bankroll_stakes.summary(staker, self.house_bankroll)
+    }
+
+    /// Balance of a user as shown to clients: the deposited total, the part being withdrawn and
+    /// the part that can still be bet or withdrawn.
+    pub fn balance_of(&self, user: &Address) -> BalanceView {
+        BalanceView {
+            balance: self.user_balances.get(user).unwrap_or(0),
+            pending_withdrawal: self.pending_withdrawals.get(user).unwrap_or(0),
+            available: self.available_balance(user),
+        }
+    }
+
+    /// Recompute the view of the player from the current state of their game and funds.
+    fn refresh_player_view(&mut self, player: &Address) {
+        let phase = self.game_phases.get(player).unwrap_or(GamePhase::Start {});
+        let bet = self.player_bets.get(player);
+        let last_won = match self.get_winner(player) {
+            Some(winner) => Some(winner == *player),
+            None => self.player_views.get(player).and_then(|view| view.last_won),
+        };
+        let pending_payout = match &bet {
+            Some(bet) if phase == GamePhase::Done {} && last_won == Some(true) => {
+                match self.converted_winnings.get(player) {
+                    Some(converted) => converted,
+                    // The stake of a free bet was never escrowed, so only the profit is paid
+                    None if self.is_free_bet(player) => self.winnings_of(player, bet) - bet.amount,
+                    None => self.winnings_of(player, bet),
+                }
+            }
+            _ => 0,
+        };
+        let view = PlayerView {
+            phase,
+            active_bet: bet.filter(|_| phase != GamePhase::Done {}),
+            last_won,
+            pending_payout,
+            balance: self.balance_of(player),
+        };
+        self.player_views.insert(*player, view);
+    }
+
+    /// Accounting of the insurance fund.
+    pub fn insurance_summary(&self) -> InsuranceSummary {
+        self.insurance.summary()
+    }
+
+    /// Clear the bet, flip result and winner of a player, leaving a tombstone behind.
+    fn clear_player_record(&mut self, player: &Address, now: i64) {
+        // Released first, as it needs the token of the bet being cleared
+        self.release_exposure(player);
+        // Cover and insurance of a bet discarded before settlement are refunded
+        if let Some(cover) = self.covered_bets.get(player) {
+            self.covered_bets.remove(player);
+            let refunded = self.streak_cover_pool.refund(cover.premium);
+            self.adjust_balance(*player, refunded);
+        }
+        self.refund_side_bets(player);
+        self.promotion.release(player);
+        // A jackpot that was not paid out goes to the deposited balance
+        if let Some(jackpot) = self.won_jackpots.get(player) {
+            self.won_jackpots.remove(player);
+            self.adjust_balance(*player, jackpot);
+        }
+        if let Some(insured) = self.insured_bets.get(player) {
+            self.insured_bets.remove(player);
+            let refunded = insured.premium.min(self.house_bankroll);
+            self.house_bankroll -= refunded;
+            self.loss_insurance_totals.premiums_collected -= refunded;
+            self.adjust_balance(*player, refunded);
+        }
+        self.payout_tokens.remove(player);
+        self.converted_winnings.remove(player);
+        self.free_bets.remove(player);
+        self.round_contributors.remove(player);
+        self.bet_tokens.remove(player);
+        self.computation_pending.remove(player);
+        self.matches.remove(player);
+        self.parlays.remove(player);
+        self.weighted_bets.remove(player);
+        self.sequence_bets.remove(player);
+        self.pending_escrows.remove(player);
+        self.bet_integrators.remove(player);
+        self.sponsored_bets.remove(player);
+        self.session_bets.remove(player);
+        self.bonus_multipliers.remove(player);
+        self.claim_deadlines.remove(player);
+        self.game_ids.remove(player);
+        self.bet_charges.remove(player);
+        // Stakes and winnings that were not refunded or paid out are the house's
+        self.ledger.forfeit(player);
+        let bet = self.player_bets.get(player);
+        let flip_result = self.flip_results.get(player);
+        let winner = self.winners.get(player);
+        self.player_bets.remove(player);
+        self.flip_results.remove(player);
+        self.winners.remove(player);
+        self.refresh_player_view(player);
+
+        if bet.is_none() && flip_result.is_none() && winner.is_none() {
+            return;
+        }
+
+        let mut record = Vec::new();
+        bet.state_write_to(&mut record).unwrap();
+        flip_result.state_write_to(&mut record).unwrap();
+        winner.state_write_to(&mut record).unwrap();
+
+        self.tombstones.insert(
+            *player,
+            Tombstone {
+                removed_at: now,
+                final_state_hash: Hash::digest(&record),
+            },
+        );
+    }
+
+    /// Whether the player's game still owes them winnings or a jackpot that `take_payout` would
+    /// pay out.
+    pub(crate) fn has_unclaimed_payout(&self, player: &Address) -> bool {
+        self.get_winner(player) == Some(*player) || self.won_jackpots.contains_key(player)
+    }
+
+    /// Remove the game of a player if it is settled and holds no unclaimed winnings.
+    ///
+    /// ### Returns:
+    ///
+    /// Whether the game was removed.
+    fn prune_settled_game(&mut self, player: &Address, now: i64) -> bool {
+        let phase = self.game_phases.get(player).unwrap_or(GamePhase::Start {});
+        if !phase.is_settled() || self.has_unclaimed_payout(player) {
+            return false;
+        }
+        self.clear_player_record(player, now);
+        self.game_phases.remove(player);
+        self.settled_at.remove(player);
+        self.stage_entered_at.remove(player);
+        self.refresh_player_view(player);
+        true
+    }
+
+    /// Move the player's game to a new phase, as allowed by `GamePhase::can_move_to`.
+    fn set_phase(&mut self, player: Address, phase: GamePhase, now: i64) {
+        let current = self.game_phases.get(&player).unwrap_or(GamePhase::Start {});
+        ensure!(
+            current.can_move_to(phase),
+            GameError::InvalidPhase,
+            "A game cannot move from the {:?} phase to the {:?} phase.",
+            current,
+            phase
+        );
+        self.game_phases.insert(player, phase);
+        self.stage_entered_at.insert(player, now);
+        if phase == (GamePhase::Done {}) {
+            self.settled_at.insert(player, now);
+            self.settlement_queue.push(player, now);
+        } else {
+            self.settled_at.remove(&player);
+        }
+        self.recent_changes.push(ChangeRecord {
+            time: now,
+            player,
+            kind: ChangeKind::PhaseChanged { phase },
+        });
+        if phase == (GamePhase::FlipCoin {}) {
+            let amount = self.player_bets.get(&player).map_or(0, |bet| bet.amount);
+            self.raise_game_event(GameEvent::BetEscrowed { player, amount });
+        }
+        self.refresh_player_view(&player);
+    }
+
+    /// Record the opened result of the player's game. With a dispute window the game becomes
+    /// challengeable and is only settled once the window passes; otherwise it is settled now.
+    fn conclude_game(&mut self, player: Address, player_won: bool, house: Address, now: i64) {
+        let winner = if player_won { player } else { house };
+        self.winners.insert(player, winner);
+        if self.dispute_window_millis > 0 {
+            self.set_phase(player, GamePhase::Challengeable {}, now);
+            self.challenge_deadlines
+                .insert(player, now.saturating_add(self.dispute_window_millis));
+        } else {
+            self.record_settlement(&player, player_won, now);
+        }
+    }
+
+    /// Settle a challengeable game of the player as it was opened.
+    fn finalize_game(&mut self, player: &Address, now: i64) {
+        self.challenge_deadlines.remove(player);
+        let player_won = self.get_winner(player) == Some(*player);
+        self.set_phase(*player, GamePhase::Done {}, now);
+        self.record_settlement(player, player_won, now);
+    }
+
+    /// Settle the player's game if it is challengeable and its dispute window has passed.
+    fn finalize_expired_challenge(&mut self, player: &Address, now: i64) {
+        let expired = self
+            .challenge_deadlines
+            .get(player)
+            .is_some_and(|deadline| now >= deadline);
+        if expired {
+            self.finalize_game(player, now);
+        }
+    }
+
+    /// Record a settled game of the given player in the running epoch.
+    fn record_settlement(&mut self, player: &Address, player_won: bool, now: i64) {
+        self.release_exposure(player);
+        self.settle_side_bets(player);
+        if !player_won {
+            self.promotion.release(player);
+        }
+        if self.notary.is_some() {
+            let mut record = Vec::new();
+            player.state_write_to(&mut record).unwrap();
+            self.player_bets.get(player).state_write_to(&mut record).unwrap();
+            self.round_salts.get(player).state_write_to(&mut record).unwrap();
+            player_won.state_write_to(&mut record).unwrap();
+            now.state_write_to(&mut record).unwrap();
+            self.notarization_outbox.push(Hash::digest(&record));
+        }
+        self.fold_into_house_seed(player, player_won);
+        self.raise_game_event(GameEvent::ResultOpened {
+            player: *player,
+            player_won,
+        });
+        self.recent_changes.push(ChangeRecord {
+            time: now,
+            player: *player,
+            kind: ChangeKind::Settled { player_won },
+        });
+        if let Some(bet) = self.player_bets.get(player) {
+            let winnings = if player_won {
+                Some(self.winnings_of(player, &bet))
+            } else {
+                None
+            };
+            if self.attest_results {
+                let game_id = self.game_ids.get(player);
+                self.attestation_outbox.push(ResultRecord {
+                    player: *player,
+                    game_id: game_id.unwrap_or(Hash { bytes: [0; 32] }),
+                    player_won,
+                    payout: winnings.unwrap_or(0),
+                });
+            }
+            // Epochs and seasons rank players in USD while an oracle is configured
+            let token = self.bet_token_of(player);
+            let recorded_stake = self.recorded_value(&token, bet.amount);
+            let recorded_winnings = winnings.map(|winnings| self.recorded_value(&token, winnings));
+            self.epoch.record_settlement(*player, recorded_stake, recorded_winnings);
+            self.season.record_settlement(*player, recorded_stake, recorded_winnings);
+            self.record_limited_result(*player, bet.amount, winnings, now);
+            self.ledger.resolve(*player, winnings);
+            if let Some(winnings) = winnings {
+                if self.large_win_threshold > 0 && winnings >= self.large_win_threshold {
+                    self.notification_outbox.push(Notification::LargeWin {
+                        player: *player,
+                        winnings,
+                    });
+                }
+            }
+            let free_bet = self.is_free_bet(player);
+            if !free_bet {
+                let points = self.loyalty_points.get(player).unwrap_or(0);
+                self.loyalty_points.insert(*player, points + bet.amount);
+            }
+            let mut stats = self.player_stats.get(player).unwrap_or_default();
+            let broken_streak = if player_won { 0 } else { stats.current_win_streak };
+            stats.record_game(bet.amount, player_won);
+            self.player_stats.insert(*player, stats);
+            let mut history_record = GameRecord {
+                time: now,
+                player: *player,
+                amount: bet.amount,
+                choice: bet.choice,
+                flip_result: self.flip_results.get(player),
+                player_won,
+                payout: winnings.unwrap_or(0),
+            };
+            let stake = bet.amount;
+            let burned = match &self.burn {
+                Some(burn) if !player_won && !free_bet && token == self.token_address => {
+                    (stake as u128 * burn.share_bps as u128 / 10_000) as u64
+                }
+                _ => 0,
+            };
+            self.pending_burn += burned;
+            self.epoch.total_burned += burned;
+            // Nothing was escrowed for a free bet, so the house has no stake to keep
+            let retained = if free_bet { 0 } else { stake - burned };
+            match self.payout_tokens.get(player) {
+                // The house keeps the stake and pays won winnings in the payout token, at the
+                // rate of the moment of settlement
+                Some(payout_token) => {
+                    self.credit_bankroll(&token, retained);
+                    if let Some(winnings) = winnings {
+                        let converted = self.convert_payout(&token, &payout_token, winnings);
+                        self.debit_bankroll(&payout_token, converted);
+                        self.converted_winnings.insert(*player, converted);
+                        history_record.payout = converted;
+                    }
+                }
+                // The house keeps a lost stake and covers the profit of a won bet
+                None => match winnings {
+                    Some(winnings) => self.debit_bankroll(&token, winnings.saturating_sub(stake)),
+                    None if free_bet => {}
+                    None => self.credit_bankroll(&token, retained),
+                },
+            }
+            let game_id = self.game_ids.get(player).unwrap_or(Hash { bytes: [0; 32] });
+            self.index_resolved_game(ExportedGame {
+                game_id,
+                record: history_record.clone(),
+            });
+            self.game_history.push(history_record);
+            if let Some(cover) = self.covered_bets.get(player) {
+                self.covered_bets.remove(player);
+                let min_streak = self.streak_cover.as_ref().map_or(0, |config| config.min_streak);
+                if broken_streak > 0 && broken_streak >= min_streak {
+                    let paid = self.streak_cover_pool.pay(cover.consolation);
+                    self.adjust_balance(*player, paid);
+                }
+            }
+            if let Some(insured) = self.insured_bets.get(player) {
+                self.insured_bets.remove(player);
+                if !player_won {
+                    let refund = insured.refund.min(self.house_bankroll);
+                    self.house_bankroll -= refund;
+                    self.loss_insurance_totals.refunds_paid += refund;
+                    self.adjust_balance(*player, refund);
+                }
+            }
+        }
+        if let Some(tournament) = self.tournament.as_mut() {
+            if player_won && tournament.is_running(now) {
+                tournament.record_win(player);
+            }
+        }
+        if player_won {
+            self.start_claim_window(player, now);
+        } else {
+            self.ride_streaks.remove(player);
+        }
+        self.refresh_player_view(player);
+    }
+
+    /// Token amounts currently held on behalf of players.
+    fn pool_sizes(&self) -> PoolSizes {
+        PoolSizes {
+            escrowed_bets: self.ledger.escrowed_total,
+            user_balances: self.user_balance_total,
+        }
+    }
+
+    /// Derive the public salt of a new round of the player's game from the id of the game,
+    /// chained through the salt of the game's previous round, so every round of every game has a
+    /// salt of its own. The salt is public, so it only tags the computations with the round they
+    /// belong to: the randomness of the result comes from the contributions alone.
+    fn assign_round_salt(&mut self, player: &Address) {
+        let mut seed = Vec::new();
+        self.game_ids.get(player).state_write_to(&mut seed).unwrap();
+        self.round_salts.get(player).state_write_to(&mut seed).unwrap();
+        self.round_salts.insert(*player, Hash::digest(&seed));
+        self.rounds_started += 1;
+    }
+
+    /// The salt word passed into the computations of the player's current round.
+    fn round_salt_word(&self, player: &Address) -> i32 {
+        self.round_salts
+            .get(player)
+            .map(|salt| salt_word(&salt))
+            .unwrap_or(0)
+    }
+
+    /// Whether `contributor` has contributed randomness to the current round of `player`.
+    fn has_contributed(&self, player: &Address, contributor: &Address) -> bool {
+        self.round_contributors
+            .get(player)
+            .is_some_and(|contributors| contributors.contains(contributor))
+    }
+
+    /// Whether the address has a stake in the outcome of the player's round: the player, the
+    /// integrator that placed the bet or the beneficiary of its winnings.
+    fn is_party_to(&self, player: &Address, address: &Address) -> bool {
+        address == player
+            || self.bet_integrators.get(player) == Some(*address)
+            || self.payee_of(player) == *address
+    }
+
+    /// Remove games that settled longer than the retention window ago, oldest first, examining
+    /// at most `max_entries` queued settlements. Games with unclaimed winnings are queued again
+    /// to be examined after the others.
+    ///
+    /// ### Returns:
+    ///
+    /// The number of games removed.
+    fn prune_expired_games(&mut self, now: i64, max_entries: u32) -> u32 {
+        let mut removed = 0;
+        for _ in 0..max_entries {
+            let Some(game) = self.settlement_queue.front() else {
+                break;
+            };
+            if !self.cleanup_config.is_expired(game.settled_at, now) {
+                break;
+            }
+            self.settlement_queue.pop();
+            // Entries of games that were pruned or settled again since are stale
+            if self.settled_at.get(&game.player) != Some(game.settled_at) {
+                continue;
+            }
+            if self.prune_settled_game(&game.player, now) {
+                removed += 1;
+            } else {
+                self.settlement_queue.push(game.player, game.settled_at);
+            }
+        }
+        removed
+    }
+
+    /// Stage the player's game is waiting in, if any.
+    fn waiting_stage_of(&self, player: &Address) -> Option<WaitingStage> {
+        if self.pending_escrows.get(player).is_some() {
+            return Some(WaitingStage::Escrow);
+        }
+        if self.game_phases.get(player) == Some(GamePhase::BonusRound {}) {
+            return Some(WaitingStage::Computation);
+        }
+        if self.game_phases.get(player) != Some(GamePhase::FlipCoin {}) {
+            return None;
+        }
+        if self.computation_pending.get(player) == Some(true) {
+            Some(WaitingStage::Computation)
+        } else {
+            Some(WaitingStage::Randomness)
+        }
+    }
+
+    /// Whether the player's game is past the deadline of the stage it waits in.
+    fn is_overdue(&self, player: &Address, now: i64) -> bool {
+        let deadline = self.waiting_stage_of(player).and_then(|stage| {
+            let entered_at = self.stage_entered_at.get(player)?;
+            self.phase_timeouts.deadline(stage, entered_at)
+        });
+        deadline.is_some_and(|deadline| now >= deadline)
+    }
+
+    fn assert_not_overdue(&self, player: &Address, now: i64) {
+        ensure!(
+            !self.is_overdue(player, now),
+            GameError::DeadlinePassed,
+            "The game is past the deadline of its current stage."
+        );
+    }
+
+    /// What the randomness of the player's round lacks before it may be flipped, if anything.
+    fn missing_randomness(&self, player: &Address) -> Option<&'static str> {
+        let game_id = self.game_ids.get(player);
+        if self
+            .instant_bets
+            .iter()
+            .any(|bet| bet.player == *player && Some(bet.game_id) == game_id)
+        {
+            return Some("An instant bet is settled by its entropy value, not by a flip.");
+        }
+        let contributors = self.round_contributors.get(player).unwrap_or_default();
+        // Without an outside contribution the bettor, who sees the public salt, would choose the
+        // outcome of the round.
+        if !contributors.iter().any(|contributor| !self.is_party_to(player, contributor)) {
+            return Some("The round needs randomness from someone other than the bettor.");
+        }
+        if self.strict_fairness && !contributors.contains(player) {
+            return Some(
+                "Strict fairness requires randomness from both the bettor and a non-bettor.",
+            );
+        }
+        if self.randomness_providers.is_required() {
+            if !contributors.contains(player) {
+                return Some("The player must contribute randomness to the round.");
+            }
+            let provided = contributors.iter().any(|contributor| {
+                !self.is_party_to(player, contributor)
+                    && self.randomness_providers.is_provider(contributor)
+            });
+            if !provided {
+                return Some("A randomness provider must contribute to the round.");
+            }
+        }
+        None
+    }
+
+    /// Each party contributes randomness at most once per round, so no single party can fill the
+    /// round's contribution cap.
+    fn assert_first_contribution(&self, player: &Address, contributor: &Address) {
+        ensure!(
+            !self.has_contributed(player, contributor),
+            GameError::AlreadyDone,
+            "Randomness was already contributed to this round."
+        );
+    }
+
+    /// Count an abandoned game of the player, starting a cooldown once past the threshold.
+    fn record_abandonment(&mut self, player: &Address, now: i64) {
+        let mut record = self.abandonments.get(player).unwrap_or(AbandonRecord {
+            abandon_count: 0,
+            cooldown_until: 0,
+        });
+        record.abandon_count += 1;
+        if let Some(config) = &self.abandon_penalty {
+            if record.abandon_count > config.cooldown_threshold {
+                record.cooldown_until = now + config.cooldown_millis;
+            }
+        }
+        self.abandonments.insert(*player, record);
+    }
+
+    /// Penalty fee taken from a stake of the player, if they abandoned too many games.
+    pub(crate) fn abandonment_fee(&self, player: &Address, amount: u64) -> u64 {
+        match (&self.abandon_penalty, self.abandonments.get(player)) {
+            (Some(config), Some(record)) if record.abandon_count > config.fee_threshold => {
+                (amount as u128 * config.fee_bps as u128 / 10_000) as u64
+            }
+            _ => 0,
+        }
+    }
+
+    /// Lane the player's rounds are computed in.
+    fn compute_lane_of(&self, player: &Address) -> ComputeLane {
+        if self.vip_players.contains(player) {
+            ComputeLane::Vip {}
+        } else {
+            ComputeLane::Standard {}
+        }
+    }
+
+    /// Assert that the given address is the owner of the contract.
+    fn assert_owner(&self, address: &Address) {
+        ensure!(
+            *address == self.owner,
+            GameError::Unauthorized,
+            "Only the owner can perform this action."
+        );
+    }
+
+    /// Approximate number of bytes of state attributable to the given player.
+    fn storage_bytes_of(&self, player: &Address) -> u32 {
+        let mut bytes = Vec::new();
+        self.player_bets.get(player).state_write_to(&mut bytes).unwrap();
+        self.flip_results.get(player).state_write_to(&mut bytes).unwrap();
+        self.winners.get(player).state_write_to(&mut bytes).unwrap();
+        self.user_balances.get(player).state_write_to(&mut bytes).unwrap();
+        self.game_phases.get(player).state_write_to(&mut bytes).unwrap();
+        self.tombstones.get(player).state_write_to(&mut bytes).unwrap();
+        self.player_views.get(player).state_write_to(&mut bytes).unwrap();
+        bytes.len() as u32
+    }
+
+    /// Charge the storage rent accrued by a player since the last collection, from the part of
+    /// their balance not tied up in a pending withdrawal. Players who cannot pay have their
+    /// settled records pruned instead.
+    fn collect_storage_rent_of(&mut self, player: &Address, config: &StorageRentConfig, now: i64) {
+        let bytes = self.storage_bytes_of(player);
+        let last_collected_at = self
+            .storage_accounts
+            .get(player)
+            .map(|account| account.last_collected_at)
+            .unwrap_or(now);
+        let rent = storage_rent_due(config, bytes, now - last_collected_at);
+
+        if rent <= self.available_balance(player) {
+            let balance = self.user_balances.get(player).unwrap_or(0);
+            self.set_balance(*player, balance - rent);
+            // The operator's part of the rent accrues to the bankroll and so to its stakers
+            let fee = self.insurance.allocate(rent);
+            self.collected_storage_rent += fee;
+            self.collect_fee(fee);
+        } else {
+            self.prune_settled_game(player, now);
+        }
+
+        self.storage_accounts.insert(
+            *player,
+            StorageAccount {
+                bytes: self.storage_bytes_of(player),
+                last_collected_at: now,
+            },
+        );
+    }
+}
+
+/// The first four bytes of a hash as the salt word of a computation.
+fn salt_word(hash: &Hash) -> i32 {
+    i32::from_le_bytes(hash.bytes[..4].try_into().unwrap())
+}
+
+/// Rent owed for keeping `bytes` of state for `elapsed_millis`.
+fn storage_rent_due(config: &StorageRentConfig, bytes: u32, elapsed_millis: i64) -> u64 {
+    let billable_bytes = bytes.saturating_sub(config.free_bytes) as u128;
+    let billable_kib = (billable_bytes + 1023) / 1024;
+    let rent = billable_kib * config.rent_per_kib_per_day as u128 * elapsed_millis.max(0) as u128
+        / MILLIS_PER_DAY as u128;
+    rent.min(u64::MAX as u128) as u64
+}
+
+/// Randomness contribution to a coin flip, XOR-folded as a whole word.
+#[derive(CreateTypeSpec, SecretBinary)]
+pub struct RandomContribution {
+    result: Sbi32,
+}
+
+/// Secret rock-paper-scissors throw: 0 for rock, 1 for paper and 2 for scissors.
+#[derive(CreateTypeSpec, SecretBinary)]
+pub struct RpsThrow {
+    throw: Sbi8,
+}
+
+/// Randomness contribution to a lottery draw.
+#[derive(CreateTypeSpec, SecretBinary)]
+pub struct LotteryContribution {
+    value: Sbi64,
+}
+
+/// Secret stake, at most the escrowed cover amount.
+#[derive(CreateTypeSpec, SecretBinary)]
+pub struct SecretBetAmount {
+    amount: Sbi64,
+}
+
+/// Sealed stake, at most the escrowed cover amount, see `add_sealed_bet`.
+#[derive(CreateTypeSpec, SecretBinary)]
+pub struct SealedBetAmount {
+    amount: Sbi128,
+}
+
+/// Secret heads/tails choice, where `true` means heads.
+#[derive(CreateTypeSpec, SecretBinary)]
+pub struct SecretChoice {
+    choice: Sbi1,
+}
+
+/// Initialize a new coin flip game with the given settings, which must all be valid.
+#[init(zk = true)]
+pub fn initialize(
+    context: ContractContext,
+    zk_state: ZkState<SecretVarType>,
+    config: InitConfig,
+) -> (CoinFlipState, Vec<EventGroup>) {
+    config.validate();
+    let token_address = config.token;
+    let mut token_registry = SortedVecMap::new();
+    token_registry.insert(token_address, TokenConfig::new(config.min_bet, config.max_bet));
+    let state = CoinFlipState {
+        player_bets: AvlTreeMap::new(),
+        flip_results: AvlTreeMap::new(),
+        winners: AvlTreeMap::new(),
+        user_balances: AvlTreeMap::new(),
+        game_phases: AvlTreeMap::new(),
+        token_address, // Store the token address in the state
+        tombstones: AvlTreeMap::new(),
+        owner: config.owner.unwrap_or(context.sender),
+        pending_owner: None,
+        role_grants: SortedVecMap::new(),
+        storage_rent: None,
+        storage_accounts: AvlTreeMap::new(),
+        collected_storage_rent: 0,
+        epoch: EpochState::new(0, context.block_production_time),
+        epoch_archive: None,
+        secret_bet_refunds: AvlTreeMap::new(),
+        multi_flip_results: AvlTreeMap::new(),
+        dice_rolls: AvlTreeMap::new(),
+        roulette_spins: AvlTreeMap::new(),
+        dice_house_edge_bps: config.dice_house_edge_bps,
+        strict_fairness: config.strict_fairness,
+        round_contributors: AvlTreeMap::new(),
+        lottery: None,
+        ride_streaks: AvlTreeMap::new(),
+        max_consecutive_rides: 0,
+        rounds_started: 0,
+        round_salts: AvlTreeMap::new(),
+        matches: AvlTreeMap::new(),
+        abandon_penalty: None,
+        abandonments: AvlTreeMap::new(),
+        collected_penalty_fees: 0,
+        rps_games: SortedVecMap::new(),
+        next_rps_game_id: 0,
+        vip_players: vec![],
+        compute_queue: ComputeQueue::new(DEFAULT_MAX_VIP_STREAK),
+        jackpot_rake_bps: 0,
+        jackpot_pool: 0,
+        recent_changes: ChangeLog::new(),
+        pending_withdrawals: AvlTreeMap::new(),
+        computation_pending: AvlTreeMap::new(),
+        house_bankroll: 0,
+        yield_adapter: None,
+        token_registry,
+        bet_tokens: AvlTreeMap::new(),
+        insurance: InsuranceFund::new(),
+        practice_choices: AvlTreeMap::new(),
+        practice_stats: AvlTreeMap::new(),
+        notary: None,
+        notarization_outbox: vec![],
+        max_exposure_bps: config.max_exposure_bps,
+        outstanding_payouts: SortedVecMap::new(),
+        bet_exposures: AvlTreeMap::new(),
+        conversion_rates: SortedVecMap::new(),
+        payout_tokens: AvlTreeMap::new(),
+        converted_winnings: AvlTreeMap::new(),
+        game_history: GameHistory::new(DEFAULT_HISTORY_RETENTION),
+        burn: None,
+        pending_burn: 0,
+        player_stats: AvlTreeMap::new(),
+        streak_cover: None,
+        streak_cover_pool: StreakCoverPool::new(),
+        covered_bets: AvlTreeMap::new(),
+        custody_accounts: SortedVecMap::new(),
+        tournament: None,
+        referrers: AvlTreeMap::new(),
+        referral_commission_bps: 0,
+        referral_rewards: AvlTreeMap::new(),
+        loyalty_points: AvlTreeMap::new(),
+        points_per_free_bet_credit: 0,
+        free_bet_credits: AvlTreeMap::new(),
+        free_bets: AvlTreeMap::new(),
+        max_round_contributions: config.max_round_contributions,
+        allowlist_enabled: false,
+        allowlist: AvlTreeMap::new(),
+        allowlist_registrar: None,
+        webhook_targets: vec![],
+        large_win_threshold: 0,
+        notification_outbox: vec![],
+        blocked_addresses: AvlTreeMap::new(),
+        self_exclusions: AvlTreeMap::new(),
+        pause_state: PauseState::Running {},
+        instant_config: None,
+        instant_entropy: vec![],
+        bankroll_stakes: StakingPool::new(),
+        state_version: STATE_VERSION,
+        upgrade_schedule: UpgradeSchedule::new(),
+        sunset: None,
+        settled_at: AvlTreeMap::new(),
+        cleanup_config: CleanupConfig {
+            retention_millis: config.settled_game_retention_millis,
+            bounty_per_game: 0,
+        },
+        randomness_providers: ProviderRegistry::new(),
+        attest_results: false,
+        attestation_outbox: vec![],
+        result_attestations: AvlTreeMap::new(),
+        dispute_window_millis: config.dispute_window_millis,
+        arbiter: None,
+        challenge_deadlines: AvlTreeMap::new(),
+        loss_insurance: None,
+        insured_bets: AvlTreeMap::new(),
+        loss_insurance_totals: LossInsuranceTotals::new(),
+        side_bets: AvlTreeMap::new(),
+        side_bet_pool: SideBetPool::new(),
+        parlays: AvlTreeMap::new(),
+        parlay_house_edge_bps: config.parlay_house_edge_bps,
+        weighted_coin: None,
+        weighted_bets: AvlTreeMap::new(),
+        bet_nonces: AvlTreeMap::new(),
+        pending_escrows: AvlTreeMap::new(),
+        cancellation_fee_bps: 0,
+        promotion: Promotion::new(),
+        keeper_bounty: 0,
+        sequence_bets: AvlTreeMap::new(),
+        private_updates_pending: AvlTreeMap::new(),
+        allowance_shortname: None,
+        player_views: AvlTreeMap::new(),
+        phase_timeouts: config.phase_timeouts,
+        stage_entered_at: AvlTreeMap::new(),
+        expired_escrows: AvlTreeMap::new(),
+        gas_costs: GasCosts::new(),
+        integrators: SortedVecMap::new(),
+        bet_integrators: AvlTreeMap::new(),
+        sponsorships: SortedVecMap::new(),
+        sponsored_bets: AvlTreeMap::new(),
+        treasury: None,
+        fee_sweeps: FeeSweeps::new(),
+        season: SeasonState::new(0, context.block_production_time),
+        season_archive: AvlTreeMap::new(),
+        bet_throttle: BetThrottle::new(),
+        gambling_limits: AvlTreeMap::new(),
+        ledger: EscrowLedger::new(),
+        bonus_round_enabled: false,
+        bonus_multipliers: AvlTreeMap::new(),
+        brackets: SortedVecMap::new(),
+        next_bracket_id: 0,
+        house_seeds: SortedVecMap::new(),
+        event_sink: None,
+        game_event_outbox: vec![],
+        reserve_winnings: false,
+        max_sender_contributions: DEFAULT_MAX_SENDER_CONTRIBUTIONS,
+        intent_keys: AvlTreeMap::new(),
+        unclaimed_vault: None,
+        claim_deadlines: AvlTreeMap::new(),
+        unclaimed_swept_total: 0,
+        price_oracle: None,
+        usd_prices: SortedVecMap::new(),
+        session_bots: SortedVecMap::new(),
+        bot_sessions: SortedVecMap::new(),
+        session_bets: SortedVecMap::new(),
+        friends_tables: SortedVecMap::new(),
+        next_table_id: 0,
+        table_invites: SortedVecMap::new(),
+        pipeline: None,
+        is_test_mode: config.is_test_mode,
+        game_ids: AvlTreeMap::new(),
+        resolved_games: AvlTreeMap::new(),
+        resolved_game_count: 0,
+        bet_charges: AvlTreeMap::new(),
+        sealed_refunds: AvlTreeMap::new(),
+        import: ImportStats::new(true),
+        rps_throw_deadlines: SortedVecMap::new(),
+        instant_bets: vec![],
+        settlement_queue: SettlementQueue::new(),
+        play_money_accounts: AvlTreeMap::new(),
+        sealed_credit_losses: AvlTreeMap::new(),
+        sealed_house_losses: 0,
+        imported_users: AvlTreeMap::new(),
+        won_jackpots: AvlTreeMap::new(),
+        user_balance_total: 0,
+    };
+
+    (state, vec![])
+}
+
+/// Start the game, place the bet, and choose Heads or Tails for multiple players.
+/// Before starting, check if the player left the game in an inconsistent state and reset it to `Start` if needed.
+/// The bet is escrowed and paid out in `token`, which must be registered.
+#[action(shortname = 0x01, zk = true)]
+pub fn start_game_and_place_bet(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    bet_amount: u64,
+    choice: PlayerChoice,
+    token: Address,
+    nonce: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    if let Some(oracle) = &state.price_oracle {
+        // Rejected bets fail now rather than after the query
+        assert_placeable(&context, &state, bet_amount, choice, &token, nonce);
+        let mut event_group = EventGroup::builder();
+        price_oracle::query_price(&mut event_group, &state.gas_costs, oracle, token);
+        token_client::register_callback(&mut event_group, &state.gas_costs, 0x16)
+            .argument(context.sender)
+            .argument(bet_amount)
+            .argument(choice)
+            .argument(token)
+            .argument(nonce)
+            .done();
+        return (state, vec![event_group.build()], vec![]);
+    }
+
+    let event_groups = place_checked_bet(&context, &mut state, bet_amount, choice, token, nonce);
+
+    // Returning the event group and leaving the game in the current phase (Start) until callback
+    (state, event_groups, vec![])
+}
+
+/// Check that the bet of `start_game_and_place_bet` would be accepted, so a bet waiting for a
+/// query is rejected before the query is sent.
+fn assert_placeable(
+    context: &ContractContext,
+    state: &CoinFlipState,
+    bet_amount: u64,
+    choice: PlayerChoice,
+    token: &Address,
+    nonce: u64,
+) {
+    let player_bet = PlayerBet {
+        amount: bet_amount,
+        choice: Some(choice),
+        secret_amount: false,
+        multi_flip: None,
+        dice: None,
+        roulette: None,
+        beneficiary: None,
+    };
+    let violations = state.validate_bet(
+        &context.sender,
+        &player_bet,
+        token,
+        token,
+        context.block_production_time,
+    );
+    // The USD limits are checked once the query has quoted the token's current price
+    let violations: Vec<_> = violations
+        .into_iter()
+        .filter(|violation| !violation.is_usd_limit())
+        .collect();
+    validation::assert_no_violations(&violations);
+    ensure!(
+        nonce == state.next_bet_nonce(&context.sender),
+        GameError::InvalidNonce,
+        "The bet nonce must be the next nonce of the player."
+    );
+}
+
+/// Place the bet of `start_game_and_place_bet`, first querying the allowance of its escrow if the
+/// allowance precheck is enabled.
+fn place_checked_bet(
+    context: &ContractContext,
+    state: &mut CoinFlipState,
+    bet_amount: u64,
+    choice: PlayerChoice,
+    token: Address,
+    nonce: u64,
+) -> Vec<EventGroup> {
+    if let Some(allowance_shortname) = state.allowance_shortname {
+        if state.needs_escrow(&context.sender, bet_amount, &token) {
+            // Rejected bets fail now rather than after the query
+            assert_placeable(context, state, bet_amount, choice, &token, nonce);
+            let mut event_group = EventGroup::builder();
+            token_client::query_allowance(
+                &mut event_group,
+                &state.gas_costs,
+                token,
+                allowance_shortname,
+                context.sender,
+                context.contract_address,
+            );
+            token_client::register_callback(&mut event_group, &state.gas_costs, 0x11)
+                .argument(context.sender)
+                .argument(bet_amount)
+                .argument(choice)
+                .argument(token)
+                .argument(nonce)
+                .done();
+            return vec![event_group.build()];
+        }
+    }
+
+    let player_bet = PlayerBet {
+        amount: bet_amount,
+        choice: Some(choice),
+        secret_amount: false,
+        multi_flip: None,
+        dice: None,
+        roulette: None,
+        beneficiary: None,
+    };
+    place_bet(context, state, nonce, player_bet, token, None)
+}
+
+/// Start the game and place a bet without revealing the choice.
+/// The choice must afterwards be given with `add_secret_choice` before the coin is flipped.
+#[action(shortname = 0x06, zk = true)]
+pub fn start_game_with_secret_choice(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    bet_amount: u64,
+    nonce: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let player_bet = PlayerBet {
+        amount: bet_amount,
+        choice: None,
+        secret_amount: false,
+        multi_flip: None,
+        dice: None,
+        roulette: None,
+        beneficiary: None,
+    };
+    let event_groups =
+        place_bet(&context, &mut state, nonce, player_bet, state.token_address, None);
+
+    (state, event_groups, vec![])
+}
+
+/// Start the game with a secret stake. `cover_amount` is escrowed publicly and the actual stake,
+/// at most the cover, must afterwards be given with `add_secret_bet`. The unstaked part of the
+/// cover is returned at payout.
+#[action(shortname = 0x0B, zk = true)]
+pub fn start_game_with_secret_bet(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    cover_amount: u64,
+    choice: PlayerChoice,
+    nonce: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let player_bet = PlayerBet {
+        amount: cover_amount,
+        choice: Some(choice),
+        secret_amount: true,
+        multi_flip: None,
+        dice: None,
+        roulette: None,
+        beneficiary: None,
+    };
+    let event_groups =
+        place_bet(&context, &mut state, nonce, player_bet, state.token_address, None);
+
+    (state, event_groups, vec![])
+}
+
+/// Start a game betting that at least `min_matches` of `flips` coins land on `choice`.
+/// All flips are drawn from a single ZK computation and the win pays the fair odds.
+#[action(shortname = 0x0C, zk = true)]
+pub fn place_multi_bet(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    bet_amount: u64,
+    choice: PlayerChoice,
+    flips: u8,
+    min_matches: u8,
+    nonce: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let prediction = MultiFlipPrediction { flips, min_matches };
+    validation::assert_no_violations(&validation::validate_multi_flip(&prediction));
+
+    let player_bet = PlayerBet {
+        amount: bet_amount,
+        choice: Some(choice),
+        secret_amount: false,
+        multi_flip: Some(prediction),
+        dice: None,
+        roulette: None,
+        beneficiary: None,
+    };
+    let event_groups =
+        place_bet(&context, &mut state, nonce, player_bet, state.token_address, None);
+
+    (state, event_groups, vec![])
+}
+
+/// Start a dice game betting that a die with `faces` faces shows `predicted`, counting from 1.
+/// A correct guess pays `faces` times the stake minus the house edge.
+#[action(shortname = 0x0D, zk = true)]
+pub fn roll_dice(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    bet_amount: u64,
+    faces: u8,
+    predicted: u8,
+    nonce: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let prediction = DicePrediction { faces, predicted };
+    validation::assert_no_violations(&validation::validate_dice(
+        &prediction,
+        state.dice_house_edge_bps,
+    ));
+
+    let player_bet = PlayerBet {
+        amount: bet_amount,
+        choice: None,
+        secret_amount: false,
+        multi_flip: None,
+        dice: Some(prediction),
+        roulette: None,
+        beneficiary: None,
+    };
+    let event_groups =
+        place_bet(&context, &mut state, nonce, player_bet, state.token_address, None);
+
+    (state, event_groups, vec![])
+}
+
+/// Start a roulette game with a bet of the given kind.
+#[action(shortname = 0x10, zk = true)]
+pub fn place_roulette_bet(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    bet_amount: u64,
+    kind: BetKind,
+    nonce: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    validation::assert_no_violations(&validation::validate_roulette(&kind));
+
+    let player_bet = PlayerBet {
+        amount: bet_amount,
+        choice: None,
+        secret_amount: false,
+        multi_flip: None,
+        dice: None,
+        roulette: Some(kind),
+        beneficiary: None,
+    };
+    let event_groups =
+        place_bet(&context, &mut state, nonce, player_bet, state.token_address, None);
+
+    (state, event_groups, vec![])
+}
+
+/// Set the house edge of dice games in basis points of the payout multiplier.
+/// Only callable by the owner.
+#[action(shortname = 0x0E, zk = true)]
+pub fn set_dice_house_edge(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    house_edge_bps: u32,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    ensure!(
+        house_edge_bps < 10_000,
+        GameError::InvalidArgument,
+        "The house edge must be less than one times the stake."
+    );
+    state.dice_house_edge_bps = house_edge_bps;
+    (state, vec![], vec![])
+}
+
+/// Enable or disable strict fairness. Only callable by the owner.
+#[action(shortname = 0x0F, zk = true)]
+pub fn set_strict_fairness(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    enabled: bool,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    state.strict_fairness = enabled;
+    (state, vec![], vec![])
+}
+
+/// Record the bet of the sender and fund it, from the deposited balance or an escrow transfer.
+/// `nonce` must be the sender's next bet nonce, so a resubmitted or duplicated transaction is
+/// rejected rather than replacing the live bet.
+fn place_bet(
+    context: &ContractContext,
+    state: &mut CoinFlipState,
+    nonce: u64,
+    player_bet: PlayerBet,
+    token: Address,
+    payout_token: Option<Address>,
+) -> Vec<EventGroup> {
+    let source = StakeSource::Player;
+    place_funded_bet(context, state, nonce, player_bet, token, payout_token, source)
+}
+
+/// Who pays the stake of a bet being placed.
+#[derive(PartialEq, Copy, Clone)]
+enum StakeSource {
+    /// The player, from free-bet credits, their balance or an escrow transfer.
+    Player,
+    /// The integrator placing the bet, by escrow transfer.
+    Integrator(Address),
+    /// A sponsor, from its sponsorship budget.
+    Sponsor(Address),
+    /// The player's bot session, from its balance.
+    Session,
+}
+
+/// Record the bet of the sender like `place_bet`, funded by `source`. A bet funded by an
+/// integrator or sponsor is attributed to it until the bet is cleared.
+fn place_funded_bet(
+    context: &ContractContext,
+    state: &mut CoinFlipState,
+    nonce: u64,
+    player_bet: PlayerBet,
+    token: Address,
+    payout_token: Option<Address>,
+    source: StakeSource,
+) -> Vec<EventGroup> {
+    state.use_bet_nonce(&context.sender, nonce);
+    state.close_import();
+    ensure!(
+        !state.is_play_money_account(&context.sender)
+            || (token == state.token_address && payout_token.is_none()),
+        GameError::Ineligible,
+        "Play money is only bet and paid out in the default token."
+    );
+    let bet_amount = player_bet.amount;
+    validation::assert_no_violations(&state.validate_bet(
+        &context.sender,
+        &player_bet,
+        &token,
+        payout_token.as_ref().unwrap_or(&token),
+        context.block_production_time,
+    ));
+    state.bet_throttle.record(context.sender, context.block_production_time);
+    state.record_limited_wager(context.sender, bet_amount, context.block_production_time);
+    let mut player_bet = player_bet;
+
+    // Free-bet credits are spent before anything is escrowed, and carry no fees. Integrators and
+    // sponsors pay the stakes of their bets, never from the player's credits or balance
+    let free_bet_credits = state.free_bet_credits.get(&context.sender).unwrap_or(0);
+    let free_bet = source == StakeSource::Player
+        && token == state.token_address
+        && payout_token.is_none()
+        && free_bet_credits >= bet_amount;
+    let penalty_fee = if free_bet {
+        0
+    } else {
+        state.abandonment_fee(&context.sender, bet_amount)
+    };
+    player_bet.amount -= penalty_fee;
+    let mut charges = BetCharges::default();
+    if free_bet {
+        // No fees or rake on a free bet
+    } else if token == state.token_address {
+        let fee = state.insurance.allocate(penalty_fee);
+        state.collected_penalty_fees += fee;
+        state.collect_fee(fee);
+        // The jackpot is paid in the default token, so only its bets are raked
+        let rake = (player_bet.amount as u128 * state.jackpot_rake_bps as u128 / 10_000) as u64;
+        player_bet.amount -= rake;
+        state.jackpot_pool += rake;
+        charges.insurance_share = penalty_fee - fee;
+        charges.house_fee = fee;
+        charges.jackpot_rake = rake;
+    } else {
+        state.credit_bankroll(&token, penalty_fee);
+        charges.house_fee = penalty_fee;
+    }
+
+    // Check the current phase of the player. The bet was validated to be past the dispute
+    // window of a challengeable game, and to have no unclaimed payout that clearing the previous
+    // game would give to the house
+    state.finalize_expired_challenge(&context.sender, context.block_production_time);
+    let player_phase = state
+        .game_phases
+        .get(&context.sender)
+        .unwrap_or(GamePhase::Start {});
+
+    if let GamePhase::Start {} = player_phase {
+        // Player is in the Start phase, no need to reset.
+    } else {
+        if !player_phase.is_settled() {
+            state.record_abandonment(&context.sender, context.block_production_time);
+        }
+        // Reset the player's state if the game was left in an inconsistent phase
+        state.clear_player_record(&context.sender, context.block_production_time);
+        state.set_phase(context.sender, GamePhase::Start {}, context.block_production_time); // Set phase to Start
+    }
+
+    // Ensure that the game is now in the `Start` phase before placing a bet
+    let player_phase = state
+        .game_phases
+        .get(&context.sender)
+        .unwrap_or(GamePhase::Start {});
+    ensure!(
+        player_phase == GamePhase::Start {},
+        GameError::InvalidPhase,
+        "The game must be in the Start phase to place a bet."
+    );
+
+    // A fresh bet ends any double-or-nothing streak or match
+    state.ride_streaks.remove(&context.sender);
+    state.matches.remove(&context.sender);
+    state.parlays.remove(&context.sender);
+    state.weighted_bets.remove(&context.sender);
+    state.sequence_bets.remove(&context.sender);
+
+    // **Place the bet:**
+    let payout_token = payout_token.unwrap_or(token);
+    let potential_payout = state.convert_payout(
+        &token,
+        &payout_token,
+        winnings_for(&player_bet, state.dice_house_edge_bps),
+    );
+    state.player_bets.insert(context.sender, player_bet);
+    let game_id = compute_game_id(&context.sender, nonce, context.block_production_time);
+    state.game_ids.insert(context.sender, game_id);
+    state.round_salts.remove(&context.sender);
+    state.assign_round_salt(&context.sender);
+    state.bet_charges.insert(context.sender, charges);
+    if token != state.token_address {
+        state.bet_tokens.insert(context.sender, token);
+    }
+    if let StakeSource::Integrator(integrator) = source {
+        state.bet_integrators.insert(context.sender, integrator);
+    }
+    if payout_token != token {
+        state.payout_tokens.insert(context.sender, payout_token);
+    }
+    state.reserve_exposure(&context.sender, &payout_token, potential_payout);
+    if !free_bet && token == state.token_address && payout_token == token {
+        state.apply_promo(&context.sender, context.block_production_time);
+    }
+
+    if free_bet {
+        state
+            .free_bet_credits
+            .insert(context.sender, free_bet_credits - bet_amount);
+        state.free_bets.insert(context.sender, true);
+        state.set_phase(context.sender, GamePhase::FlipCoin {}, context.block_production_time);
+        return settlement_events(state);
+    }
+
+    if let StakeSource::Sponsor(sponsor) = source {
+        state.draw_sponsored_stake(sponsor, context.sender, bet_amount);
+        state.escrow_stake(&context.sender);
+        state.set_phase(context.sender, GamePhase::FlipCoin {}, context.block_production_time);
+        return settlement_events(state);
+    }
+
+    if source == StakeSource::Session {
+        state.draw_session_stake(context.sender, bet_amount);
+        state.escrow_stake(&context.sender);
+        state.set_phase(context.sender, GamePhase::FlipCoin {}, context.block_production_time);
+        return settlement_events(state);
+    }
+
+    // Deposited balances are held in the default token
+    if source == StakeSource::Player
+        && token == state.token_address
+        && state.available_balance(&context.sender) >= bet_amount
+    {
+        let balance = state.user_balances.get(&context.sender).unwrap_or(0);
+        state.set_balance(context.sender, balance - bet_amount);
+        state.credit_referral(&context.sender, bet_amount);
+        state.escrow_stake(&context.sender);
+        state.set_phase(context.sender, GamePhase::FlipCoin {}, context.block_production_time);
+        return settlement_events(state);
+    }
+
+    // **Transfer tokens before proceeding**:
+    // Initiating token transfer and registering a callback
+    let mut event_group = EventGroup::builder();
+
+    token_client::transfer_from(
+        &mut event_group,
+        &state.gas_costs,
+        token,
+        match source {
+            StakeSource::Integrator(integrator) => integrator,
+            _ => context.sender,
+        },
+        context.contract_address,
+        bet_amount,
+    );
+
+    // Registering a callback to proceed only if the transfer is successful
+    state.pending_escrows.insert(
+        context.sender,
+        PendingEscrow {
+            amount: bet_amount,
+            nonce,
+        },
+    );
+    state
+        .stage_entered_at
+        .insert(context.sender, context.block_production_time);
+    state.refresh_player_view(&context.sender);
+    token_client::register_callback(&mut event_group, &state.gas_costs, 0x01)
+        .argument(context.sender)
+        .argument(bet_amount)
+        .argument(nonce)
+        .done();
+
+    let mut events = vec![event_group.build()];
+    // Settling the previous game may have queued notarizations, notifications or burns
+    events.extend(settlement_events(state));
+    events
+}
+
+/// Callback action to be triggered when the token transfer is successful. The callback must match
+/// the pending escrow of the player's current bet, which it consumes.
+#[callback(shortname = 0x01, zk = true)]
+pub fn transfer_success_callback(
+    context: ContractContext,
+    callback_ctx: CallbackContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    player: Address,
+    amount: u64,
+    nonce: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    // The bet expired while its stake was in flight, so an arriving stake goes back to the player
+    if let Some(expired) = state.expired_escrows.get(&player) {
+        if expired.escrow == (PendingEscrow { amount, nonce }) {
+            state.expired_escrows.remove(&player);
+            let mut events = vec![];
+            if !callback_ctx.results[0].succeeded {
+                // Nothing arrived, so nothing is owed
+            } else if expired.token == state.token_address {
+                state.adjust_balance(player, amount);
+            } else {
+                let mut event_group = EventGroup::builder();
+                token_client::transfer(
+                    &mut event_group,
+                    &state.gas_costs,
+                    expired.token,
+                    player,
+                    amount,
+                );
+                events.push(event_group.build());
+            }
+            return (state, events, vec![]);
+        }
+    }
+
+    // Check if the transfer succeeded using the callback context
+    ensure!(
+        callback_ctx.results[0].succeeded,
+        GameError::TransferFailed,
+        "Token transfer failed, cannot proceed to the next phase."
+    );
+    ensure!(
+        state.pending_escrows.get(&player) == Some(PendingEscrow { amount, nonce }),
+        GameError::TransferFailed,
+        "The callback does not match the pending escrow of the player."
+    );
+    state.pending_escrows.remove(&player);
+    if state.bet_token_of(&player) == state.token_address {
+        let bet_amount = state.player_bets.get(&player).map_or(0, |bet| bet.amount);
+        state.credit_referral(&player, bet_amount);
+    }
+    state.escrow_stake(&player);
+
+    // Now move the player to the next phase after a successful transfer
+    state.set_phase(player, GamePhase::FlipCoin {}, context.block_production_time); // Move the player to the next phase
+    
+    let mut events: Vec<EventGroup> = state.take_game_events().into_iter().collect();
+    if let Some(config) = &state.pipeline {
+        let providers = &state.randomness_providers.providers;
+        events.extend(pipeline::randomness_request_event(
+            providers,
+            &state.gas_costs,
+            config,
+            player,
+        ));
+    }
+    (state, events, vec![])
+}
+
+
+/// Remove tombstones that are older than the retention window. Callable by anyone.
+#[action(shortname = 0x05, zk = true)]
+pub fn purge_tombstones(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let expired: Vec<Address> = state
+        .tombstones
+        .iter()
+        .filter(|(_, tombstone)| {
+            context.block_production_time - tombstone.removed_at > TOMBSTONE_RETENTION_MILLIS
+        })
+        .map(|(player, _)| player)
+        .collect();
+
+    for player in expired {
+        state.tombstones.remove(&player);
+    }
+
+    (state, vec![], vec![])
+}
+
+/// Configure storage rent, or disable it by passing `None`. Only callable by the owner.
+#[action(shortname = 0x07, zk = true)]
+pub fn set_storage_rent(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    config: Option<StorageRentConfig>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    state.storage_rent = config;
+    (state, vec![], vec![])
+}
+
+/// Collect storage rent from the given players, pruning the settled records of those who cannot pay.
+/// Callable by anyone.
+#[action(shortname = 0x08, zk = true)]
+pub fn collect_storage_rent(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    players: Vec<Address>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let config = state
+        .storage_rent
+        .clone()
+        .or_fail(GameError::Unavailable, "Storage rent is not enabled.");
+    for player in players {
+        state.collect_storage_rent_of(&player, &config, context.block_production_time);
+    }
+    (state, vec![], vec![])
+}
+
+/// Configure the archive contract receiving epoch summaries, or disable archiving with `None`.
+/// Only callable by the owner.
+#[action(shortname = 0x09, zk = true)]
+pub fn set_epoch_archive(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    config: Option<EpochArchiveConfig>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    state.epoch_archive = config;
+    (state, vec![], vec![])
+}
+
+/// Configure the log contract settled rounds are notarized to, or stop notarizing with `None`.
+/// Only callable by the owner.
+#[action(shortname = 0x2B, zk = true)]
+pub fn set_notary(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    config: Option<NotaryConfig>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    state.notary = config;
+    (state, vec![], vec![])
+}
+
+/// Close the running epoch once it has lasted long enough: push its summary to the archive
+/// contract and start a new epoch with empty statistics. Callable by anyone.
+#[action(shortname = 0x0A, zk = true)]
+pub fn close_epoch(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let config = state
+        .epoch_archive
+        .clone()
+        .or_fail(GameError::Unavailable, "Epoch archiving is not configured.");
+    ensure!(
+        state.epoch.is_over(&config, context.block_production_time),
+        GameError::InvalidPhase,
+        "The current epoch has not ended yet."
+    );
+
+    let summary = state
+        .epoch
+        .summarize(context.block_production_time, state.pool_sizes());
+
+    let mut event_group = EventGroup::builder();
+    let (archive, shortname) = (config.archive_address, config.archive_shortname);
+    token_client::interaction(&mut event_group, &state.gas_costs, archive, shortname)
+        .argument(summary)
+        .done();
+
+    state.epoch = EpochState::new(state.epoch.epoch + 1, context.block_production_time);
+
+    (state, vec![event_group.build()], vec![])
+}
+
+/// Add randomness for the coin flip for a specific player.
+#[zk_on_secret_input(shortname = 0x40, secret_type = "RandomContribution")]
+pub fn add_randomness_to_flip(
+    context: ContractContext,
+    state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+) -> (
+    CoinFlipState,
+    Vec<EventGroup>,
+    ZkInputDef<SecretVarType, RandomContribution>,
+) {
+    state.assert_not_paused();
+    let player_phase = state
+        .game_phases
+        .get(&context.sender)
+        .unwrap_or(GamePhase::Start {});
+    ensure!(
+        player_phase == GamePhase::FlipCoin {},
+        GameError::InvalidPhase,
+        "Must be in the FlipCoin phase to input secret randomness."
+    );
+    state.assert_not_overdue(&context.sender, context.block_production_time);
+    state.assert_not_banned(&context.sender, context.block_production_time);
+    state.assert_first_contribution(&context.sender, &context.sender);
+    assert_no_pending_contribution(&zk_state, &context.sender, &context.sender);
+    assert_within_contribution_caps(&state, &zk_state, &context.sender, &context.sender);
+
+    let input_def = ZkInputDef::with_metadata(
+        Some(SHORTNAME_INPUTTED_VARIABLE),
+        SecretVarType::Randomness {player: context.sender},
+    );
+
+    (state, vec![], input_def)
+}
+
+/// Add randomness to the round of another player, e.g. as a house randomness provider.
+/// Required at least once per round when strict fairness is enabled.
+#[zk_on_secret_input(shortname = 0x43, secret_type = "RandomContribution")]
+pub fn add_randomness_for_player(
+    context: ContractContext,
+    state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    player: Address,
+) -> (
+    CoinFlipState,
+    Vec<EventGroup>,
+    ZkInputDef<SecretVarType, RandomContribution>,
+) {
+    state.assert_not_paused();
+    let player_phase = state
+        .game_phases
+        .get(&player)
+        .unwrap_or(GamePhase::Start {});
+    ensure!(
+        player_phase == GamePhase::FlipCoin {},
+        GameError::InvalidPhase,
+        "The player's game must be in the FlipCoin phase to receive randomness."
+    );
+    state.assert_not_overdue(&player, context.block_production_time);
+    state.assert_first_contribution(&player, &context.sender);
+    assert_no_pending_contribution(&zk_state, &player, &context.sender);
+    assert_within_contribution_caps(&state, &zk_state, &player, &context.sender);
+    // Parties to the bet contributing as its other side would defeat fairness
+    ensure!(
+        !state.is_party_to(&player, &context.sender),
+        GameError::Unauthorized,
+        "Parties to a bet cannot contribute randomness to it as a non-bettor."
+    );
+
+    let input_def = ZkInputDef::with_metadata(
+        Some(SHORTNAME_INPUTTED_VARIABLE),
+        SecretVarType::Randomness {player},
+    );
+
+    (state, vec![], input_def)
+}
+
+/// Reject a contribution to the round of the player while an earlier one of the same contributor
+/// is still being input.
+fn assert_no_pending_contribution(
+    zk_state: &ZkState<SecretVarType>,
+    player: &Address,
+    contributor: &Address,
+) {
+    let pending = zk_state.pending_inputs.iter().any(|(_, variable)| {
+        variable.owner == *contributor
+            && matches!(variable.metadata, SecretVarType::Randomness { player: round } if round == *player)
+    });
+    ensure!(
+        !pending,
+        GameError::AlreadyDone,
+        "Randomness was already contributed to this round."
+    );
+}
+
+/// Number of randomness contributions among the variables, given by owner and metadata, for
+/// which `filter` holds of the round's player and the contributor.
+fn count_contributions<'a>(
+    variables: impl Iterator<Item = (&'a Address, &'a SecretVarType)>,
+    filter: impl Fn(&Address, &Address) -> bool,
+) -> u32 {
+    variables
+        .filter(|(owner, metadata)| match metadata {
+            SecretVarType::Randomness { player } => filter(player, owner),
+            _ => false,
+        })
+        .count() as u32
+}
+
+/// Whether the confirmed variable is a randomness contribution to the current round of its
+/// player, whose contributor was recorded for the round. Contributions left over from an
+/// abandoned round are not.
+fn is_current_contribution(state: &CoinFlipState, variable: &ZkClosed<SecretVarType>) -> bool {
+    match variable.metadata {
+        SecretVarType::Randomness { player } => state.has_contributed(&player, &variable.owner),
+        _ => false,
+    }
+}
+
+/// Randomness contributions, pending or confirmed to a current round, for which `filter` holds
+/// of the round's player and the contributor.
+fn count_open_contributions(
+    state: &CoinFlipState,
+    zk_state: &ZkState<SecretVarType>,
+    filter: impl Fn(&Address, &Address) -> bool,
+) -> u32 {
+    let pending = zk_state
+        .pending_inputs
+        .iter()
+        .map(|(_, variable)| (&variable.owner, &variable.metadata));
+    let confirmed = zk_state
+        .secret_variables
+        .iter()
+        .filter(|(_, variable)| is_current_contribution(state, variable))
+        .map(|(_, variable)| (&variable.owner, &variable.metadata));
+    count_contributions(pending.chain(confirmed), filter)
+}
+
+/// Assert that neither the round of `player` nor `contributor` reached their cap of randomness
+/// contributions.
+fn assert_within_contribution_caps(
+    state: &CoinFlipState,
+    zk_state: &ZkState<SecretVarType>,
+    player: &Address,
+    contributor: &Address,
+) {
+    // One slot of every round is kept for the bettor, so other parties cannot crowd them out
+    let (filled, cap) = if contributor == player {
+        let all = count_open_contributions(state, zk_state, |round, _| round == player);
+        (all, state.max_round_contributions)
+    } else {
+        let others = count_open_contributions(state, zk_state, |round, owner| {
+            round == player && owner != player
+        });
+        (others, state.max_round_contributions - 1)
+    };
+    ensure!(
+        filled < cap,
+        GameError::Unavailable,
+        "The round accepts no more randomness contributions."
+    );
+    ensure!(
+        count_open_contributions(state, zk_state, |_, owner| owner == contributor)
+            < state.max_sender_contributions,
+        GameError::Unavailable,
+        "The sender has too many randomness contributions in open rounds."
+    );
+}
+
+/// Add the secret heads/tails choice for a game started with `start_game_with_secret_choice`.
+#[zk_on_secret_input(shortname = 0x41, secret_type = "SecretChoice")]
+pub fn add_secret_choice(
+    context: ContractContext,
+    state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+) -> (
+    CoinFlipState,
+    Vec<EventGroup>,
+    ZkInputDef<SecretVarType, SecretChoice>,
+) {
+    state.assert_not_paused();
+    let player_phase = state
+        .game_phases
+        .get(&context.sender)
+        .unwrap_or(GamePhase::Start {});
+    ensure!(
+        player_phase == GamePhase::FlipCoin {},
+        GameError::InvalidPhase,
+        "Must be in the FlipCoin phase to input a secret choice."
+    );
+    state.assert_not_overdue(&context.sender, context.block_production_time);
+    ensure!(
+        matches!(
+            state.player_bets.get(&context.sender),
+            Some(bet) if bet.has_secret_choice()
+        ) && state.weighted_bets.get(&context.sender).is_none(),
+        GameError::InvalidPhase,
+        "The game was not started with a secret choice."
+    );
+    ensure!(
+        choice_variable_of(&zk_state, &context.sender).is_none()
+            && !zk_state.pending_inputs.iter().any(|(_, variable)| {
+                matches!(variable.metadata, SecretVarType::Choice { player } if player == context.sender)
+            }),
+        GameError::AlreadyDone,
+        "The secret choice has already been given."
+    );
+
+    let input_def = ZkInputDef::with_metadata(
+        Some(SHORTNAME_INPUTTED_VARIABLE),
+        SecretVarType::Choice {player: context.sender},
+    );
+
+    (state, vec![], input_def)
+}
+
+/// Add the secret stake for a game started with `start_game_with_secret_bet`.
+#[zk_on_secret_input(shortname = 0x42, secret_type = "SecretBetAmount")]
+pub fn add_secret_bet(
+    context: ContractContext,
+    state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+) -> (
+    CoinFlipState,
+    Vec<EventGroup>,
+    ZkInputDef<SecretVarType, SecretBetAmount>,
+) {
+    assert_secret_stake_awaited(&context, &state, &zk_state);
+
+    let input_def = ZkInputDef::with_metadata(
+        Some(SHORTNAME_INPUTTED_VARIABLE),
+        SecretVarType::SecretBet {player: context.sender},
+    );
+
+    (state, vec![], input_def)
+}
+
+/// Add the stake for a game started with `start_game_with_secret_bet` as a sealed stake, in
+/// place of `add_secret_bet`. The sealed stake is settled by opening only the amount payable to
+/// the player, so a lost stake is not opened on its own: the cover is split between the house's
+/// sealed take and the player's sealed credit in secret, and the player is recorded as having lost
+/// a zero stake. Credits and takes are only released once they total
+/// `MIN_SEALED_LOSSES_PER_RELEASE` lost bets, revealing the sum of those stakes but none of them.
+#[zk_on_secret_input(shortname = 0x48, secret_type = "SealedBetAmount")]
+pub fn add_sealed_bet(
+    context: ContractContext,
+    state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+) -> (
+    CoinFlipState,
+    Vec<EventGroup>,
+    ZkInputDef<SecretVarType, SealedBetAmount>,
+) {
+    assert_secret_stake_awaited(&context, &state, &zk_state);
+
+    let input_def = ZkInputDef::with_metadata(
+        Some(SHORTNAME_INPUTTED_VARIABLE),
+        SecretVarType::SealedStake {player: context.sender},
+    );
+
+    (state, vec![], input_def)
+}
+
+/// Assert that the sender's game waits for its secret or sealed stake.
+fn assert_secret_stake_awaited(
+    context: &ContractContext,
+    state: &CoinFlipState,
+    zk_state: &ZkState<SecretVarType>,
+) {
+    state.assert_not_paused();
+    let player_phase = state
+        .game_phases
+        .get(&context.sender)
+        .unwrap_or(GamePhase::Start {});
+    ensure!(
+        player_phase == GamePhase::FlipCoin {},
+        GameError::InvalidPhase,
+        "Must be in the FlipCoin phase to input a secret bet."
+    );
+    state.assert_not_overdue(&context.sender, context.block_production_time);
+    ensure!(
+        matches!(
+            state.player_bets.get(&context.sender),
+            Some(PlayerBet { secret_amount: true, .. })
+        ),
+        GameError::InvalidPhase,
+        "The game was not started with a secret bet."
+    );
+    ensure!(
+        state.sealed_refunds.get(&context.sender).is_none(),
+        GameError::Unavailable,
+        "The split of the last lost sealed bet is still being computed."
+    );
+    let is_stake_of_sender = |metadata: &SecretVarType| match *metadata {
+        SecretVarType::SecretBet {player} | SecretVarType::SealedStake {player} => {
+            player == context.sender
+        }
+        _ => false,
+    };
+    ensure!(
+        !zk_state
+            .secret_variables
+            .iter()
+            .any(|(_, variable)| is_stake_of_sender(&variable.metadata))
+            && !zk_state
+                .pending_inputs
+                .iter()
+                .any(|(_, variable)| is_stake_of_sender(&variable.metadata)),
+        GameError::AlreadyDone,
+        "The secret bet has already been given."
+    );
+}
+
+/// Automatically called when a variable is confirmed on chain.
+#[zk_on_variable_inputted(shortname = 0x01)]
+pub fn inputted_variable(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    variable_id: SecretVarId,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let variable = zk_state.get_variable(variable_id).unwrap();
+    let mut stale_randomness = vec![];
+    if let SecretVarType::Randomness {player} = variable.metadata {
+        // Contributions arriving once the round stopped accepting randomness, repeating an
+        // earlier contribution of the same party, beyond the round's or the sender's cap, or
+        // malformed are deleted so they never enter a computation. The input definition fixes
+        // the size of a contribution to one word, any value of which is valid, so a well-formed
+        // contribution is one that is still secret.
+        let accepting = state.game_phases.get(&player) == Some(GamePhase::FlipCoin {})
+            && state.computation_pending.get(&player) != Some(true)
+            && !state.has_contributed(&player, &variable.owner)
+            && variable.data.is_none();
+        let confirmed = || {
+            zk_state
+                .secret_variables
+                .iter()
+                .filter(|(id, variable)| {
+                    **id == variable_id || is_current_contribution(&state, variable)
+                })
+                .map(|(_, variable)| (&variable.owner, &variable.metadata))
+        };
+        // Other parties only fill the slots not kept for the bettor
+        let (round_contributions, round_cap) = if variable.owner == player {
+            let all = count_contributions(confirmed(), |round, _| *round == player);
+            (all, state.max_round_contributions)
+        } else {
+            let others = count_contributions(confirmed(), |round, owner| {
+                *round == player && *owner != player
+            });
+            (others, state.max_round_contributions - 1)
+        };
+        let sender_contributions =
+            count_contributions(confirmed(), |_, owner| *owner == variable.owner);
+        if !accepting
+            || round_contributions > round_cap
+            || sender_contributions > state.max_sender_contributions
+        {
+            return (
+                state,
+                vec![],
+                vec![ZkStateChange::DeleteVariables {
+                    variables_to_delete: vec![variable_id],
+                }],
+            );
+        }
+
+        let mut contributors = state.round_contributors.get(&player).unwrap_or_default();
+        // Contributions left over from an abandoned round of the player are deleted once the
+        // next round receives its first contribution, so they never enter its computation
+        if contributors.is_empty() {
+            stale_randomness = randomness_variables_of(&zk_state, &player);
+            stale_randomness.retain(|id| *id != variable_id);
+        }
+        contributors.push(variable.owner);
+        state.round_contributors.insert(player, contributors);
+    }
+    if let SecretVarType::BracketRandomness {bracket_id, round, player} = variable.metadata {
+        // Contributions confirmed once their match was decided or its flip started are deleted
+        let accepting = state.brackets.get(&bracket_id).is_some_and(|bracket| {
+            bracket.round == round
+                && bracket
+                    .match_of(&player)
+                    .is_some_and(|match_index| bracket.is_open(match_index))
+        });
+        if !accepting {
+            return (
+                state,
+                vec![],
+                vec![ZkStateChange::DeleteVariables {
+                    variables_to_delete: vec![variable_id],
+                }],
+            );
+        }
+    }
+    if let SecretVarType::TableRandomness {table_id, ..} = variable.metadata {
+        // Contributions confirmed once the table's flip started or it was called off are deleted
+        let accepting = state
+            .friends_tables
+            .get(&table_id)
+            .is_some_and(|table| table.status == TableStatus::Open {});
+        if !accepting {
+            return (
+                state,
+                vec![],
+                vec![ZkStateChange::DeleteVariables {
+                    variables_to_delete: vec![variable_id],
+                }],
+            );
+        }
+    }
+    if let SecretVarType::HouseSeed {epoch} = variable.metadata {
+        // A seed confirmed once its epoch started could have been chosen after bets of the epoch
+        if epoch <= state.epoch.epoch || state.house_seeds.get(&epoch).is_some() {
+            return (
+                state,
+                vec![],
+                vec![ZkStateChange::DeleteVariables {
+                    variables_to_delete: vec![variable_id],
+                }],
+            );
+        }
+        state
+            .house_seeds
+            .insert(epoch, SeedCommitment::new(context.block_production_time));
+        return (state, vec![], vec![]);
+    }
+
+    // The pipeline flips a round as soon as its last input is confirmed
+    let round = match variable.metadata {
+        SecretVarType::Randomness {player} => player,
+        _ => variable.owner,
+    };
+    let now = context.block_production_time;
+    let mut changes = vec![];
+    if !stale_randomness.is_empty() {
+        changes.push(ZkStateChange::DeleteVariables {
+            variables_to_delete: stale_randomness,
+        });
+    }
+    if state.pipeline.is_some()
+        && state.pause_state.allows_play()
+        && is_ready_to_flip(&state, &zk_state, &round, now)
+    {
+        let (events, round_changes) = start_round(&mut state, &zk_state, round, now);
+        changes.extend(round_changes);
+        return (state, events, changes);
+    }
+    (state, vec![], changes)
+}
+
+/// Start the computation to compute the coin flip result for a specific player.
+///
+/// The round is queued in the player's lane if the computation engine is busy, and started once
+/// it is the round's turn. Flipping again before the round is settled does nothing.
+#[action(shortname = 0x03, zk = true)]
+pub fn flip_coin(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_not_paused();
+    let player_phase = state
+        .game_phases
+        .get(&context.sender)
+        .unwrap_or(GamePhase::Start {});
+    ensure!(
+        player_phase == GamePhase::FlipCoin {},
+        GameError::InvalidPhase,
+        "The coin can only be flipped in the FlipCoin phase"
+    );
+    state.assert_not_overdue(&context.sender, context.block_production_time);
+    state.assert_not_banned(&context.sender, context.block_production_time);
+    if let Some(missing) = state.missing_randomness(&context.sender) {
+        fail!(GameError::MissingRandomness, "{missing}");
+    }
+    if state.computation_pending.get(&context.sender) == Some(true) {
+        return (state, vec![], vec![]);
+    }
+
+    let (events, changes) =
+        start_round(&mut state, &zk_state, context.sender, context.block_production_time);
+    (state, events, changes)
+}
+
+/// Start the computation of the player's round, or queue it in the player's lane if the
+/// computation engine is busy.
+fn start_round(
+    state: &mut CoinFlipState,
+    zk_state: &ZkState<SecretVarType>,
+    player: Address,
+    now: i64,
+) -> (Vec<EventGroup>, Vec<ZkStateChange>) {
+    // Build the computation up front so a round that cannot be computed is rejected now rather
+    // than when it leaves the queue.
+    let computation = round_computation(state, zk_state, &player);
+    state.computation_pending.insert(player, true);
+    state.raise_game_event(GameEvent::RandomnessComplete { player });
+    state.stage_entered_at.insert(player, now);
+    state.pay_provider_fees(&player);
+    let events = state.take_game_events().into_iter().collect();
+    if zk_state.calculation_state != CalculationStatus::Waiting || !state.compute_queue.is_empty() {
+        let lane = state.compute_lane_of(&player);
+        state.compute_queue.push(player, lane);
+        let next_round = next_queued_computation(state, zk_state);
+        return (events, next_round.into_iter().collect());
+    }
+
+    (events, vec![computation])
+}
+
+/// Build the computation of the player's current round, routed by the kind of game.
+fn round_computation(
+    state: &CoinFlipState,
+    zk_state: &ZkState<SecretVarType>,
+    player: &Address,
+) -> ZkStateChange {
+    let salt = state.round_salt_word(player);
+    let seed_epoch = state.epoch.epoch;
+    let bet = state.player_bets.get(player);
+
+    match state.game_kind_of(player) {
+        GameKind::WeightedFlip {} => {
+            let odds = state.weighted_bets.get(player).unwrap();
+            zk_compute::compute_weighted_flip_start(
+                odds.denominator,
+                odds.winning_outcomes,
+                salt,
+                seed_epoch,
+                *player,
+                Some(SHORTNAME_FLIP_COMPUTE_COMPLETE),
+                &SecretVarType::WeightedFlip {player: *player},
+            )
+        }
+        GameKind::Roulette {} => zk_compute::compute_roulette_spin_start(
+            salt,
+            seed_epoch,
+            *player,
+            Some(SHORTNAME_FLIP_COMPUTE_COMPLETE),
+            &SecretVarType::RouletteSpin {player: *player},
+        ),
+        GameKind::Dice {} => zk_compute::compute_dice_roll_start(
+            bet.unwrap().dice.unwrap().faces,
+            salt,
+            seed_epoch,
+            *player,
+            Some(SHORTNAME_FLIP_COMPUTE_COMPLETE),
+            &SecretVarType::DiceRoll {player: *player},
+        ),
+        GameKind::MultiFlip {} => zk_compute::compute_multi_flip_start(
+            bet.unwrap().multi_flip.unwrap().flips,
+            salt,
+            seed_epoch,
+            *player,
+            Some(SHORTNAME_FLIP_COMPUTE_COMPLETE),
+            &SecretVarType::MultiFlipResult {player: *player},
+        ),
+        GameKind::SecretBet {} => {
+            let bet = bet.unwrap();
+            // A lost sealed stake is kept until its split is computed, and never plays again
+            let sealed_stake = sealed_stake_variable_of(zk_state, player)
+                .filter(|_| state.sealed_refunds.get(player).is_none());
+            if let Some(stake_variable) = sealed_stake {
+                return zk_compute::compute_sealed_payout_start(
+                    stake_variable,
+                    bet.amount as i64,
+                    bet.choice == Some(PlayerChoice::Heads {}),
+                    salt,
+                    seed_epoch,
+                    *player,
+                    Some(SHORTNAME_FLIP_COMPUTE_COMPLETE),
+                    &SecretVarType::SealedPayout {player: *player},
+                );
+            }
+            let bet_variable = secret_bet_variable_of(zk_state, player)
+                .or_fail(
+                    GameError::InvalidPhase,
+                    "The secret bet must be given before the coin is flipped",
+                );
+            zk_compute::compute_secret_bet_payout_start(
+                bet_variable,
+                bet.amount as i64,
+                bet.choice == Some(PlayerChoice::Heads {}),
+                salt,
+                seed_epoch,
+                *player,
+                Some(SHORTNAME_FLIP_COMPUTE_COMPLETE),
+                &SecretVarType::SecretPayout {player: *player},
+            )
+        }
+        GameKind::SecretChoice {} => {
+            let choice_variable = choice_variable_of(zk_state, player)
+                .or_fail(
+                    GameError::InvalidPhase,
+                    "The secret choice must be given before the coin is flipped",
+                );
+            zk_compute::compute_secret_choice_outcome_start(
+                choice_variable,
+                salt,
+                seed_epoch,
+                *player,
+                Some(SHORTNAME_FLIP_COMPUTE_COMPLETE),
+                &SecretVarType::Outcome {player: *player},
+            )
+        }
+        GameKind::Parlay {} => {
+            let parlay = state.parlays.get(player).unwrap();
+            zk_compute::compute_coin_flip_start(
+                salt,
+                seed_epoch,
+                *player,
+                parlay.current_choice() == PlayerChoice::Heads {},
+                Some(SHORTNAME_FLIP_COMPUTE_COMPLETE),
+                &SecretVarType::ParlayLeg {
+                    player: *player,
+                    leg: parlay.legs_won,
+                },
+            )
+        }
+        GameKind::BestOfMatch {} | GameKind::CoinFlip {} => {
+            let metadata = match state.matches.get(player) {
+                Some(score) => SecretVarType::MatchRound {
+                    player: *player,
+                    round: score.rounds_played(),
+                },
+                None => SecretVarType::FlipResult {player: *player},
+            };
+            let choice = bet.and_then(|bet| bet.choice);
+            zk_compute::compute_coin_flip_start(
+                salt,
+                seed_epoch,
+                *player,
+                choice == Some(PlayerChoice::Heads {}),
+                Some(SHORTNAME_FLIP_COMPUTE_COMPLETE),
+                &metadata,
+            )
+        }
+    }
+}
+
+/// Whether the player's round waits for its flip with all the randomness and secret inputs it
+/// needs, so a keeper may start the flip.
+fn is_ready_to_flip(
+    state: &CoinFlipState,
+    zk_state: &ZkState<SecretVarType>,
+    player: &Address,
+    now: i64,
+) -> bool {
+    let Some(bet) = state.player_bets.get(player) else {
+        return false;
+    };
+    let has_secret_inputs = if bet.secret_amount {
+        secret_bet_variable_of(zk_state, player).is_some()
+            || sealed_stake_variable_of(zk_state, player).is_some()
+    } else if bet.has_secret_choice() && state.weighted_bets.get(player).is_none() {
+        choice_variable_of(zk_state, player).is_some()
+    } else {
+        true
+    };
+    state.game_phases.get(player) == Some(GamePhase::FlipCoin {})
+        && state.computation_pending.get(player) != Some(true)
+        && state.round_contributors.get(player).is_some()
+        && state.missing_randomness(player).is_none()
+        && !state.is_banned(player, now)
+        && has_secret_inputs
+}
+
+/// Take the next queued round that is still waiting to be flipped and build its computation,
+/// if the computation engine is available.
+fn next_queued_computation(
+    state: &mut CoinFlipState,
+    zk_state: &ZkState<SecretVarType>,
+) -> Option<ZkStateChange> {
+    if zk_state.calculation_state != CalculationStatus::Waiting {
+        return None;
+    }
+    while let Some(player) = state.compute_queue.pop() {
+        if state.game_phases.get(&player) == Some(GamePhase::FlipCoin {}) {
+            return Some(round_computation(state, zk_state, &player));
+        }
+    }
+    None
+}
+
+/// Set whether a player's rounds are computed in the VIP lane. Only callable by the owner.
+#[action(shortname = 0x1C, zk = true)]
+pub fn set_vip_player(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    player: Address,
+    vip: bool,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    state.vip_players.retain(|vip_player| *vip_player != player);
+    if vip {
+        state.vip_players.push(player);
+    }
+    (state, vec![], vec![])
+}
+
+/// Set how many VIP rounds may be started in a row while standard rounds are waiting. Only
+/// callable by the owner or an operator.
+#[action(shortname = 0x1D, zk = true)]
+pub fn set_max_vip_streak(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    max_vip_streak: u32,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_operator(&context.sender);
+    ensure!(
+        max_vip_streak > 0,
+        GameError::InvalidArgument,
+        "At least one VIP round must be allowed ahead of standard rounds."
+    );
+    state.compute_queue.max_vip_streak = max_vip_streak;
+    (state, vec![], vec![])
+}
+
+
+
+/// Automatically called when the coin flip computation is completed.
+#[zk_on_compute_complete(shortname = 0x01)]
+fn flip_compute_complete(
+    context: ContractContext,
+    state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    output_variables: Vec<SecretVarId>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    (
+        state,
+        vec![],
+        vec![ZkStateChange::OpenVariables {
+            variables: output_variables,
+        }],
+    )
+}
+
+/// Automatically called when a private balance is updated. The new balance replaces the old one
+/// and is handed to the player, and the next queued round is started.
+#[zk_on_compute_complete(shortname = 0x02)]
+fn private_balance_computed(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    output_variables: Vec<SecretVarId>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let new_balance = output_variables[0];
+    let metadata = zk_state.get_variable(new_balance).unwrap().metadata;
+    let SecretVarType::PrivateBalance {player} = metadata else {
+        fail!(
+            GameError::UnexpectedOutput,
+            "Expected a private balance as the output."
+        );
+    };
+    let old_balances: Vec<SecretVarId> = zk_state
+        .secret_variables
+        .iter()
+        .filter(|(variable_id, variable)| {
+            let is_balance = matches!(
+                variable.metadata,
+                SecretVarType::PrivateBalance { player: owner } if owner == player
+            );
+            is_balance && *variable_id != new_balance
+        })
+        .map(|(variable_id, _)| *variable_id)
+        .collect();
+    state.private_updates_pending.remove(&player);
+
+    let mut changes = vec![
+        ZkStateChange::DeleteVariables {
+            variables_to_delete: old_balances,
+        },
+        ZkStateChange::TransferVariable {
+            variable: new_balance,
+            new_owner: player,
+        },
+    ];
+    changes.extend(next_queued_computation(&mut state, &zk_state));
+    (state, vec![], changes)
+}
+
+/// Automatically called when part of a lost sealed bet's cover is credited in secret, or a sealed
+/// total is released. The house's take is credited first and the player's credit next, each
+/// replacing the variables it added to, and the lost stake is deleted once both are credited. A
+/// released total replaces the variables it totalled and is opened to be paid out.
+#[zk_on_compute_complete(shortname = 0x03)]
+fn sealed_split_computed(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    output_variables: Vec<SecretVarId>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let output = output_variables[0];
+    let metadata = zk_state.get_variable(output).unwrap().metadata;
+    let replaced = |is_replaced: &dyn Fn(&SecretVarType) -> bool| -> Vec<SecretVarId> {
+        zk_state
+            .secret_variables
+            .iter()
+            .filter(|(variable_id, variable)| {
+                *variable_id != output && is_replaced(&variable.metadata)
+            })
+            .map(|(variable_id, _)| *variable_id)
+            .collect()
+    };
+    let is_house_take = |metadata: &SecretVarType| {
+        matches!(metadata, SecretVarType::SealedHouseTake { .. })
+    };
+    let is_credit_of = |player: Address| {
+        move |metadata: &SecretVarType| {
+            matches!(metadata, SecretVarType::SealedCredit { player: owner } if *owner == player)
+        }
+    };
+    match metadata {
+        SecretVarType::SealedHouseTake {player} => {
+            let cover = state.sealed_refunds.get(&player).unwrap_or(0);
+            let stake_variable = sealed_stake_variable_of(&zk_state, &player)
+                .or_fail(GameError::UnexpectedOutput, "The lost sealed stake is missing.");
+            let changes = vec![
+                ZkStateChange::DeleteVariables {
+                    variables_to_delete: replaced(&is_house_take),
+                },
+                zk_compute::compute_sealed_credit_start(
+                    stake_variable,
+                    cover as i64,
+                    player,
+                    Some(SHORTNAME_SEALED_SPLIT_COMPUTED),
+                    &SecretVarType::SealedCredit {player},
+                ),
+            ];
+            (state, vec![], changes)
+        }
+        SecretVarType::SealedCredit {player} => {
+            state.sealed_refunds.remove(&player);
+            let losses = state.sealed_credit_losses.get(&player).unwrap_or(0);
+            state.sealed_credit_losses.insert(player, losses + 1);
+            state.sealed_house_losses += 1;
+            let mut variables_to_delete = replaced(&is_credit_of(player));
+            variables_to_delete.extend(sealed_stake_variable_of(&zk_state, &player));
+            let mut changes = vec![
+                ZkStateChange::DeleteVariables {
+                    variables_to_delete,
+                },
+                ZkStateChange::TransferVariable {
+                    variable: output,
+                    new_owner: player,
+                },
+            ];
+            changes.extend(next_queued_computation(&mut state, &zk_state));
+            (state, vec![], changes)
+        }
+        SecretVarType::SealedRelease {recipient} => {
+            let variables_to_delete = if recipient == context.contract_address {
+                replaced(&is_house_take)
+            } else {
+                replaced(&is_credit_of(recipient))
+            };
+            let changes = vec![
+                ZkStateChange::DeleteVariables {
+                    variables_to_delete,
+                },
+                ZkStateChange::OpenVariables {
+                    variables: vec![output],
+                },
+            ];
+            (state, vec![], changes)
+        }
+        _ => fail!(
+            GameError::UnexpectedOutput,
+            "Expected a sealed take, credit or release as the output."
+        ),
+    }
+}
+
+/// Automatically called when output variables are opened. Each opened variable is settled by
+/// the routine its metadata selects. The computation engine is available again, so the next
+/// queued round is started, and settled rounds are notarized if a notary is configured.
+#[zk_on_variables_opened]
+fn open_flip_result_variable(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    opened_variables: Vec<SecretVarId>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let mut events = vec![];
+    let mut changes = vec![];
+    for variable_id in opened_variables {
+        let (settled, settled_events, settled_changes) =
+            settle_opened_variable(&context, state, &zk_state, variable_id);
+        state = settled;
+        events.extend(settled_events);
+        changes.extend(settled_changes);
+    }
+    let started = changes
+        .iter()
+        .any(|change| matches!(change, ZkStateChange::StartComputation { .. }));
+    if !started {
+        changes.extend(next_queued_computation(&mut state, &zk_state));
+    }
+    events.extend(settlement_events(&mut state));
+    changes.extend(settlement_attestations(&mut state));
+    (state, events, changes)
+}
+
+/// Request attestation of the results settled in this transaction.
+fn settlement_attestations(state: &mut CoinFlipState) -> Vec<ZkStateChange> {
+    std::mem::take(&mut state.attestation_outbox)
+        .iter()
+        .map(|record| ZkStateChange::Attest {
+            data_to_attest: record.encode(),
+        })
+        .collect()
+}
+
+/// Send out what settling games queued up: round hashes to the notary, notifications to the
+/// webhook targets, game events to the event sink and burned stakes to the token contract.
+fn settlement_events(state: &mut CoinFlipState) -> Vec<EventGroup> {
+    let mut events = vec![];
+    let record_hashes = std::mem::take(&mut state.notarization_outbox);
+    if let (Some(config), false) = (&state.notary, record_hashes.is_empty()) {
+        events.push(notary::notarization_event(config, &state.gas_costs, record_hashes));
+    }
+
+    let notifications = std::mem::take(&mut state.notification_outbox);
+    events.extend(webhooks::notification_event(
+        &state.webhook_targets,
+        &state.gas_costs,
+        notifications,
+    ));
+    events.extend(state.take_game_events());
+
+    let burn_amount = std::mem::take(&mut state.pending_burn);
+    if let (Some(config), true) = (&state.burn, burn_amount > 0) {
+        let mut event_group = EventGroup::builder();
+        token_client::burn(
+            &mut event_group,
+            &state.gas_costs,
+            state.token_address,
+            config.burn_shortname,
+            burn_amount,
+        );
+        events.push(event_group.build());
+    }
+    events
+}
+
+/// Forward the unswept fees to the treasury, as far as the free bankroll covers them.
+fn fee_sweep(state: &mut CoinFlipState) -> Option<EventGroup> {
+    let treasury = state.treasury.as_ref()?.treasury_address;
+    let amount = state
+        .fee_sweeps
+        .unswept
+        .min(state.free_bankroll(&state.token_address));
+    if amount == 0 {
+        return None;
+    }
+    state.bankroll_stakes.withdraw_house(amount, state.house_bankroll);
+    state.house_bankroll -= amount;
+    state.fee_sweeps.unswept -= amount;
+    state.fee_sweeps.in_flight += amount;
+
+    let mut event_group = EventGroup::builder();
+    token_client::transfer(
+        &mut event_group,
+        &state.gas_costs,
+        state.token_address,
+        treasury,
+        amount,
+    );
+    token_client::register_callback(&mut event_group, &state.gas_costs, 0x14)
+        .argument(amount)
+        .done();
+    Some(event_group.build())
+}
+
+/// Settle whatever the opened output variable belongs to, dispatching on its metadata. Outputs
+/// of unknown kinds are left alone.
+fn settle_opened_variable(
+    context: &ContractContext,
+    mut state: CoinFlipState,
+    zk_state: &ZkState<SecretVarType>,
+    variable_id: SecretVarId,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let opened_variable = zk_state.get_variable(variable_id).unwrap();
+    if let Some((player, kind)) = opened_variable.metadata.settled_round() {
+        ensure!(
+            state.player_bets.get(&player).is_none() || state.game_kind_of(&player) == kind,
+            GameError::UnexpectedOutput,
+            "The output does not settle the kind of game the player is playing."
+        );
+    }
+
+        if let SecretVarType::FlipResult {player} = opened_variable.metadata {
+        if let Some(data) = &opened_variable.data {
+            let flip = zk_compute::parse_compute_output_coin_flip(data);
+            let flip_result = flip.is_heads;
+
+            // Insert the result into the state
+            state.flip_results.insert(player, flip_result);
+
+            // A won flip may trigger a bonus round, which concludes the game once its multiplier
+            // is drawn
+            let bonus_round = state.player_bets.get(&player).is_some()
+                && flip.player_won
+                && flip.bonus
+                && state.reserve_bonus_round(&player);
+
+            if bonus_round {
+                state.set_phase(player, GamePhase::BonusRound {}, context.block_production_time);
+            } else {
+                // **Change:** Ensure the game phase transitions to 'Done' for the player who
+                // started the game only
+                state.set_phase(player, GamePhase::Done {}, context.block_production_time);
+            }
+
+            // The computation compared the flip with the choice of the bet it was started for
+            if state.player_bets.get(&player).is_some() && !bonus_round {
+                state.conclude_game(
+                    player,
+                    flip.player_won,
+                    context.contract_address,
+                    context.block_production_time,
+                );
+            }
+
+            // **Change:** No phase update for the winner, keep it only for the player who started the game.
+
+            // The jackpot is paid in the default token, so only its bets can win it. It is paid
+            // out with the winnings of the game.
+            if flip.jackpot
+                && state.jackpot_pool > 0
+                && state.bet_token_of(&player) == state.token_address
+            {
+                state.won_jackpots.insert(player, state.jackpot_pool);
+                state.jackpot_pool = 0;
+            }
+
+            // The multiplier is drawn from the randomness of the round, which is kept until then
+            if bonus_round {
+                let zk_changes = vec![
+                    ZkStateChange::DeleteVariables {
+                        variables_to_delete: vec![opened_variable.variable_id],
+                    },
+                    zk_compute::compute_bonus_multiplier_start(
+                        state.round_salt_word(&player),
+                        state.epoch.epoch,
+                        player,
+                        Some(SHORTNAME_FLIP_COMPUTE_COMPLETE),
+                        &SecretVarType::BonusMultiplier {player},
+                    ),
+                ];
+                return (state, vec![], zk_changes);
+            }
+
+            // The result has been consumed, so drop it together with the randomness that produced it.
+            let variables_to_delete = vec![opened_variable.variable_id];
+            let zk_changes = finish_round(&mut state, &zk_state, &player, variables_to_delete);
+            return (state, vec![], zk_changes);
+        } else {
+            fail!(
+                GameError::UnexpectedOutput,
+                "Expected data in the opened variable, but found None."
+            );
+        }
+    }
+
+    if let SecretVarType::BonusMultiplier {player} = opened_variable.metadata {
+        let data = opened_variable
+            .data
+            .as_ref()
+            .or_fail(
+                GameError::UnexpectedOutput,
+                "Expected data in the opened variable, but found None.",
+            );
+        let multiplier = zk_compute::parse_compute_output_bonus_multiplier(data);
+
+        // Multipliers of games that left their bonus round, e.g. by expiring, are discarded
+        if state.game_phases.get(&player) == Some(GamePhase::BonusRound {}) {
+            state.bonus_multipliers.insert(player, multiplier);
+            state.set_phase(player, GamePhase::Done {}, context.block_production_time);
+            state.conclude_game(
+                player,
+                true,
+                context.contract_address,
+                context.block_production_time,
+            );
+        }
+
+        let variables_to_delete = vec![opened_variable.variable_id];
+        let zk_changes = finish_round(&mut state, &zk_state, &player, variables_to_delete);
+        return (state, vec![], zk_changes);
+    }
+
+    if let SecretVarType::MatchRound {player, round} = opened_variable.metadata {
+        let data = opened_variable
+            .data
+            .as_ref()
+            .or_fail(
+                GameError::UnexpectedOutput,
+                "Expected data in the opened variable, but found None.",
+            );
+        let player_won_round = zk_compute::parse_compute_output_coin_flip(data).player_won;
+
+        let bet = state.player_bets.get(&player);
+        if let (Some(mut score), Some(_)) = (state.matches.get(&player), bet) {
+            // Results of stale rounds, e.g. from a duplicate flip, are discarded
+            if round == score.rounds_played() && !score.is_decided() {
+                if player_won_round {
+                    score.player_wins += 1;
+                } else {
+                    score.house_wins += 1;
+                }
+
+                if score.is_decided() {
+                    let player_won = score.player_wins > score.house_wins;
+                    state.set_phase(player, GamePhase::Done {}, context.block_production_time);
+                    state.conclude_game(
+                        player,
+                        player_won,
+                        context.contract_address,
+                        context.block_production_time,
+                    );
+                } else {
+                    // The next round needs fresh randomness under a fresh salt
+                    state.assign_round_salt(&player);
+                }
+                state.matches.insert(player, score);
+            }
+        }
+
+        let variables_to_delete = vec![opened_variable.variable_id];
+        let zk_changes = finish_round(&mut state, &zk_state, &player, variables_to_delete);
+        return (state, vec![], zk_changes);
+    }
+
+    if let SecretVarType::ParlayLeg {player, leg} = opened_variable.metadata {
+        let data = opened_variable
+            .data
+            .as_ref()
+            .or_fail(
+                GameError::UnexpectedOutput,
+                "Expected data in the opened variable, but found None.",
+            );
+        let flip = zk_compute::parse_compute_output_coin_flip(data);
+        let leg_won = flip.player_won;
+
+        let bet = state.player_bets.get(&player);
+        if let (Some(mut parlay), Some(_)) = (state.parlays.get(&player), bet) {
+            // Results of stale legs, e.g. from a duplicate flip, are discarded
+            if leg == parlay.legs_won && !parlay.is_complete() {
+                state.flip_results.insert(player, flip.is_heads);
+                if leg_won {
+                    parlay.legs_won += 1;
+                }
+                state.parlays.insert(player, parlay.clone());
+
+                // A lost leg ends the parlay at once
+                if !leg_won || parlay.is_complete() {
+                    state.set_phase(player, GamePhase::Done {}, context.block_production_time);
+                    state.conclude_game(
+                        player,
+                        leg_won,
+                        context.contract_address,
+                        context.block_production_time,
+                    );
+                } else {
+                    // The next leg needs fresh randomness under a fresh salt
+                    state.assign_round_salt(&player);
+                }
+            }
+        }
+
+        let variables_to_delete = vec![opened_variable.variable_id];
+        let zk_changes = finish_round(&mut state, &zk_state, &player, variables_to_delete);
+        return (state, vec![], zk_changes);
+    }
+
+    if let SecretVarType::Outcome {player} = opened_variable.metadata {
+        let data = opened_variable
+            .data
+            .as_ref()
+            .or_fail(
+                GameError::UnexpectedOutput,
+                "Expected data in the opened variable, but found None.",
+            );
+        let player_won = zk_compute::parse_compute_output_secret_choice(data);
+
+        state.set_phase(player, GamePhase::Done {}, context.block_production_time);
+        state.conclude_game(
+            player,
+            player_won,
+            context.contract_address,
+            context.block_production_time,
+        );
+
+        // The choice stays secret; only the outcome was revealed.
+        let mut variables_to_delete = vec![opened_variable.variable_id];
+        variables_to_delete.extend(choice_variable_of(&zk_state, &player));
+        let zk_changes = finish_round(&mut state, &zk_state, &player, variables_to_delete);
+        return (state, vec![], zk_changes);
+    }
+
+    if let SecretVarType::SecretPayout {player} = opened_variable.metadata {
+        let data = opened_variable
+            .data
+            .as_ref()
+            .or_fail(
+                GameError::UnexpectedOutput,
+                "Expected data in the opened variable, but found None.",
+            );
+        let returned = zk_compute::parse_compute_output_secret_payout(data);
+
+        state.set_phase(player, GamePhase::Done {}, context.block_production_time);
+        if let Some(mut bet) = state.player_bets.get(&player) {
+            // The returned amount is the cover plus the stake on a win, or minus it on a loss.
+            let cover = bet.amount;
+            let player_won = returned > cover;
+            let stake = if player_won { returned - cover } else { cover - returned };
+            bet.amount = stake;
+            state.player_bets.insert(player, bet);
+            state.secret_bet_refunds.insert(player, cover - stake);
+
+            state.conclude_game(
+                player,
+                player_won,
+                context.contract_address,
+                context.block_production_time,
+            );
+        }
+
+        let mut variables_to_delete = vec![opened_variable.variable_id];
+        variables_to_delete.extend(secret_bet_variable_of(&zk_state, &player));
+        let zk_changes = finish_round(&mut state, &zk_state, &player, variables_to_delete);
+        return (state, vec![], zk_changes);
+    }
+
+    if let SecretVarType::SealedPayout {player} = opened_variable.metadata {
+        let data = opened_variable
+            .data
+            .as_ref()
+            .or_fail(
+                GameError::UnexpectedOutput,
+                "Expected data in the opened variable, but found None.",
+            );
+        let payable = zk_compute::parse_compute_output_sealed_amount(data);
+
+        state.set_phase(player, GamePhase::Done {}, context.block_production_time);
+        let mut variables_to_delete = vec![opened_variable.variable_id];
+        let stake_variable = sealed_stake_variable_of(zk_state, &player);
+        let mut split = None;
+        if let Some(mut bet) = state.player_bets.get(&player) {
+            // The payable amount is the cover plus the stake on a win, and zero on a loss
+            let cover = bet.amount;
+            let player_won = payable > 0;
+            if player_won {
+                let stake = payable - cover;
+                bet.amount = stake;
+                state.secret_bet_refunds.insert(player, cover - stake);
+                variables_to_delete.extend(stake_variable);
+            } else if let Some(stake_variable) = stake_variable {
+                // The cover leaves the ledger to be split in secret, and the game settles as
+                // lost with a zero stake, so the stake is not opened on its own
+                state.ledger.refund(&player, 0);
+                bet.amount = 0;
+                state.sealed_refunds.insert(player, cover);
+                split = Some(zk_compute::compute_sealed_house_take_start(
+                    stake_variable,
+                    cover as i64,
+                    Some(SHORTNAME_SEALED_SPLIT_COMPUTED),
+                    &SecretVarType::SealedHouseTake {player},
+                ));
+            }
+            state.player_bets.insert(player, bet);
+
+            state.conclude_game(
+                player,
+                player_won,
+                context.contract_address,
+                context.block_production_time,
+            );
+        }
+
+        let mut zk_changes = finish_round(&mut state, zk_state, &player, variables_to_delete);
+        zk_changes.extend(split);
+        return (state, vec![], zk_changes);
+    }
+
+    if let SecretVarType::HouseSeed {epoch} = opened_variable.metadata {
+        let data = opened_variable
+            .data
+            .as_ref()
+            .or_fail(
+                GameError::UnexpectedOutput,
+                "Expected data in the opened variable, but found None.",
+            );
+        if let Some(commitment) = state.house_seeds.get_mut(&epoch) {
+            commitment.reveal(zk_compute::decode_output::<u32>(data));
+        }
+        let changes = vec![ZkStateChange::DeleteVariables {
+            variables_to_delete: vec![opened_variable.variable_id],
+        }];
+        return (state, vec![], changes);
+    }
+
+    if let SecretVarType::SealedRelease {recipient} = opened_variable.metadata {
+        let data = opened_variable
+            .data
+            .as_ref()
+            .or_fail(
+                GameError::UnexpectedOutput,
+                "Expected data in the opened variable, but found None.",
+            );
+        let released = zk_compute::parse_compute_output_sealed_amount(data);
+
+        let changes = vec![ZkStateChange::DeleteVariables {
+            variables_to_delete: vec![opened_variable.variable_id],
+        }];
+        if recipient == context.contract_address {
+            state.house_bankroll += released;
+            state.ledger.house_balance += released as i128;
+            return (state, vec![], changes);
+        }
+        let mut event_group = EventGroup::builder();
+        token_client::transfer(
+            &mut event_group,
+            &state.gas_costs,
+            state.token_address,
+            recipient,
+            released,
+        );
+        return (state, vec![event_group.build()], changes);
+    }
+
+    if let SecretVarType::MultiFlipResult {player} = opened_variable.metadata {
+        let data = opened_variable
+            .data
+            .as_ref()
+            .or_fail(
+                GameError::UnexpectedOutput,
+                "Expected data in the opened variable, but found None.",
+            );
+
+        state.set_phase(player, GamePhase::Done {}, context.block_production_time);
+        if let Some(PlayerBet { choice: Some(choice), multi_flip: Some(prediction), .. }) =
+            state.player_bets.get(&player)
+        {
+            let mask = zk_compute::parse_compute_output_multi(data, prediction.flips);
+            state.multi_flip_results.insert(player, mask);
+
+            let player_won = match state.sequence_bets.get(&player) {
+                Some(sequence) => mask == sequence,
+                None => prediction.is_satisfied_by(mask, choice),
+            };
+            state.conclude_game(
+                player,
+                player_won,
+                context.contract_address,
+                context.block_production_time,
+            );
+        }
+
+        let variables_to_delete = vec![opened_variable.variable_id];
+        let zk_changes = finish_round(&mut state, &zk_state, &player, variables_to_delete);
+        return (state, vec![], zk_changes);
+    }
+
+    if let SecretVarType::DiceRoll {player} = opened_variable.metadata {
+        let data = opened_variable
+            .data
+            .as_ref()
+            .or_fail(
+                GameError::UnexpectedOutput,
+                "Expected data in the opened variable, but found None.",
+            );
+        let roll = zk_compute::parse_compute_output_dice(data);
+
+        state.set_phase(player, GamePhase::Done {}, context.block_production_time);
+        state.dice_rolls.insert(player, roll);
+        if let Some(PlayerBet { dice: Some(prediction), .. }) = state.player_bets.get(&player) {
+            let player_won = roll == prediction.predicted;
+            state.conclude_game(
+                player,
+                player_won,
+                context.contract_address,
+                context.block_production_time,
+            );
+        }
+
+        let variables_to_delete = vec![opened_variable.variable_id];
+        let zk_changes = finish_round(&mut state, &zk_state, &player, variables_to_delete);
+        return (state, vec![], zk_changes);
+    }
+
+    if let SecretVarType::PrivateWithdrawal {player} = opened_variable.metadata {
+        let data = opened_variable
+            .data
+            .as_ref()
+            .or_fail(
+                GameError::UnexpectedOutput,
+                "Expected data in the opened variable, but found None.",
+            );
+        let withdrawn = zk_compute::parse_compute_output_withdrawable(data);
+
+        let mut changes = vec![ZkStateChange::DeleteVariables {
+            variables_to_delete: vec![opened_variable.variable_id],
+        }];
+        let balance_variable = private_balance_variable_of(zk_state, &player);
+        match (withdrawn, balance_variable) {
+            (0, _) | (_, None) => {
+                state.private_updates_pending.remove(&player);
+                return (state, vec![], changes);
+            }
+            (withdrawn, Some(balance_variable)) => {
+                changes.push(zk_compute::compute_private_credit_start(
+                    balance_variable,
+                    -(withdrawn as i64),
+                    Some(SHORTNAME_PRIVATE_BALANCE_COMPUTED),
+                    &SecretVarType::PrivateBalance {player},
+                ));
+                let mut event_group = EventGroup::builder();
+                token_client::transfer(
+                    &mut event_group,
+                    &state.gas_costs,
+                    state.token_address,
+                    player,
+                    withdrawn,
+                );
+                return (state, vec![event_group.build()], changes);
+            }
+        }
+    }
+
+    if let SecretVarType::WeightedFlip {player} = opened_variable.metadata {
+        let data = opened_variable
+            .data
+            .as_ref()
+            .or_fail(
+                GameError::UnexpectedOutput,
+                "Expected data in the opened variable, but found None.",
+            );
+        let player_won = zk_compute::parse_compute_output_weighted(data);
+
+        state.set_phase(player, GamePhase::Done {}, context.block_production_time);
+        if state.player_bets.get(&player).is_some() {
+            state.conclude_game(
+                player,
+                player_won,
+                context.contract_address,
+                context.block_production_time,
+            );
+        }
+
+        let variables_to_delete = vec![opened_variable.variable_id];
+        let zk_changes = finish_round(&mut state, &zk_state, &player, variables_to_delete);
+        return (state, vec![], zk_changes);
+    }
+
+    if let SecretVarType::RouletteSpin {player} = opened_variable.metadata {
+        let data = opened_variable
+            .data
+            .as_ref()
+            .or_fail(
+                GameError::UnexpectedOutput,
+                "Expected data in the opened variable, but found None.",
+            );
+        let number = zk_compute::parse_compute_output_roulette(data);
+
+        state.set_phase(player, GamePhase::Done {}, context.block_production_time);
+        state.roulette_spins.insert(player, number);
+        if let Some(PlayerBet { roulette: Some(kind), .. }) = state.player_bets.get(&player) {
+            let player_won = kind.wins_on(number);
+            state.conclude_game(
+                player,
+                player_won,
+                context.contract_address,
+                context.block_production_time,
+            );
+        }
+
+        let variables_to_delete = vec![opened_variable.variable_id];
+        let zk_changes = finish_round(&mut state, &zk_state, &player, variables_to_delete);
+        return (state, vec![], zk_changes);
+    }
+
+    if let SecretVarType::LotteryDraw {lottery_id} = opened_variable.metadata {
+        let data = opened_variable
+            .data
+            .as_ref()
+            .or_fail(
+                GameError::UnexpectedOutput,
+                "Expected data in the opened variable, but found None.",
+            );
+        let ticket = zk_compute::parse_compute_output_lottery(data);
+
+        let lottery = state.lottery.as_mut().unwrap();
+        ensure!(
+            lottery.id == lottery_id,
+            GameError::UnexpectedOutput,
+            "Drawn lottery is not the current lottery"
+        );
+        let winner = lottery.tickets.get(ticket as usize).copied();
+        lottery.status = LotteryStatus::Drawn { winner };
+
+        let mut event_groups = vec![];
+        if let Some(winner) = winner {
+            let mut event_group = EventGroup::builder();
+            token_client::transfer(
+                &mut event_group,
+                &state.gas_costs,
+                state.token_address,
+                winner,
+                lottery.pot,
+            );
+            event_groups.push(event_group.build());
+        }
+
+        let mut variables_to_delete = lottery_randomness_variables_of(&zk_state, lottery_id);
+        variables_to_delete.push(opened_variable.variable_id);
+        return (
+            state,
+            event_groups,
+            vec![ZkStateChange::DeleteVariables {
+                variables_to_delete,
+            }],
+        );
+    }
+
+    if let SecretVarType::PracticeFlip {player} = opened_variable.metadata {
+        let data = opened_variable
+            .data
+            .as_ref()
+            .or_fail(
+                GameError::UnexpectedOutput,
+                "Expected data in the opened variable, but found None.",
+            );
+        let player_won = zk_compute::parse_compute_output_coin_flip(data).player_won;
+
+        if state.practice_choices.contains_key(&player) {
+            state.practice_choices.remove(&player);
+            let mut stats = state.practice_stats.get(&player).unwrap();
+            stats.rounds += 1;
+            if player_won {
+                stats.wins += 1;
+            }
+            state.practice_stats.insert(player, stats);
+        }
+
+        // The randomness belongs to the real rounds, so only the practice result is deleted
+        return (
+            state,
+            vec![],
+            vec![ZkStateChange::DeleteVariables {
+                variables_to_delete: vec![opened_variable.variable_id],
+            }],
+        );
+    }
+
+    if let SecretVarType::RpsOutcome {game_id} = opened_variable.metadata {
+        let data = opened_variable
+            .data
+            .as_ref()
+            .or_fail(
+                GameError::UnexpectedOutput,
+                "Expected data in the opened variable, but found None.",
+            );
+
+        let mut game = state.rps_games.get(&game_id).cloned().unwrap();
+        let opponent = game.opponent.unwrap();
+        let winner = match zk_compute::parse_compute_output_rps(data) {
+            RPS_CREATOR_WINS => Some(game.creator),
+            RPS_OPPONENT_WINS => Some(opponent),
+            _ => None,
+        };
+        game.status = RpsStatus::Finished { winner };
+
+        let mut event_group = EventGroup::builder();
+        match winner {
+            Some(winner) => {
+                token_client::transfer(
+                    &mut event_group,
+                    &state.gas_costs,
+                    state.token_address,
+                    winner,
+                    game.stake * 2,
+                );
+            }
+            None => {
+                for player in [game.creator, opponent] {
+                    token_client::transfer(
+                        &mut event_group,
+                        &state.gas_costs,
+                        state.token_address,
+                        player,
+                        game.stake,
+                    );
+                }
+            }
+        }
+        state.rps_games.insert(game_id, game);
+
+        let mut variables_to_delete = rps_throw_variables_of(&zk_state, game_id);
+        variables_to_delete.push(opened_variable.variable_id);
+        return (
+            state,
+            vec![event_group.build()],
+            vec![ZkStateChange::DeleteVariables {
+                variables_to_delete,
+            }],
+        );
+    }
+
+    if let SecretVarType::BracketMatch {bracket_id, round, match_index} = opened_variable.metadata {
+        let data = opened_variable
+            .data
+            .as_ref()
+            .or_fail(
+                GameError::UnexpectedOutput,
+                "Expected data in the opened variable, but found None.",
+            );
+        let (first, second) = state.brackets.get(&bracket_id).unwrap().pairing(match_index);
+        let winner = if zk_compute::parse_compute_output_bracket_match(data) {
+            first
+        } else {
+            second
+        };
+
+        let mut variables_to_delete: Vec<SecretVarId> = [first, second]
+            .iter()
+            .filter_map(|player| bracket_randomness_of(zk_state, bracket_id, round, player))
+            .collect();
+        variables_to_delete.push(opened_variable.variable_id);
+        let event_groups = decide_bracket_match(
+            &mut state,
+            bracket_id,
+            match_index,
+            winner,
+            context.block_production_time,
+        );
+        return (
+            state,
+            event_groups,
+            vec![ZkStateChange::DeleteVariables {
+                variables_to_delete,
+            }],
+        );
+    }
+
+    if let SecretVarType::TableFlip {table_id} = opened_variable.metadata {
+        let data = opened_variable
+            .data
+            .as_ref()
+            .or_fail(
+                GameError::UnexpectedOutput,
+                "Expected data in the opened variable, but found None.",
+            );
+        let heads = zk_compute::parse_compute_output_table_flip(data);
+        let table = state.friends_tables.get_mut(&table_id).unwrap();
+        table.status = TableStatus::Settled { heads };
+        let payouts = table.payouts(heads);
+        let invite_code_hash = table.invite_code_hash;
+        state.table_invites.remove(&invite_code_hash);
+
+        let mut variables_to_delete = table_randomness_of(zk_state, table_id);
+        variables_to_delete.push(opened_variable.variable_id);
+        let mut event_group = EventGroup::builder();
+        for (participant, amount) in payouts {
+            token_client::transfer(
+                &mut event_group,
+                &state.gas_costs,
+                state.token_address,
+                participant,
+                amount,
+            );
+        }
+        return (
+            state,
+            vec![event_group.build()],
+            vec![ZkStateChange::DeleteVariables {
+                variables_to_delete,
+            }],
+        );
+    }
+
+    (state, vec![], vec![])
+}
+
+/// Collect the ids of all throws in the given rock-paper-scissors game.
+fn rps_throw_variables_of(zk_state: &ZkState<SecretVarType>, game_id: u64) -> Vec<SecretVarId> {
+    zk_state
+        .secret_variables
+        .iter()
+        .filter(|(_, variable)| {
+            matches!(variable.metadata, SecretVarType::RpsThrow { game_id: id, .. } if id == game_id)
+        })
+        .map(|(variable_id, _)| *variable_id)
+        .collect()
+}
+
+/// Find the confirmed throw of a player in a rock-paper-scissors game, if any.
+fn rps_throw_variable_of(
+    zk_state: &ZkState<SecretVarType>,
+    game_id: u64,
+    player: &Address,
+) -> Option<SecretVarId> {
+    zk_state
+        .secret_variables
+        .iter()
+        .find(|(_, variable)| {
+            matches!(variable.metadata, SecretVarType::RpsThrow { game_id: id, player: thrower } if id == game_id && thrower == *player)
+        })
+        .map(|(variable_id, _)| *variable_id)
+}
+
+/// Collect the ids of all randomness contributions to the given lottery.
+fn lottery_randomness_variables_of(
+    zk_state: &ZkState<SecretVarType>,
+    lottery_id: u32,
+) -> Vec<SecretVarId> {
+    zk_state
+        .secret_variables
+        .iter()
+        .filter(|(_, variable)| {
+            matches!(variable.metadata, SecretVarType::LotteryRandomness { lottery_id: id } if id == lottery_id)
+        })
+        .map(|(variable_id, _)| *variable_id)
+        .collect()
+}
+
+/// Find the secret stake inputted by the given player, if any.
+fn secret_bet_variable_of(
+    zk_state: &ZkState<SecretVarType>,
+    player: &Address,
+) -> Option<SecretVarId> {
+    zk_state
+        .secret_variables
+        .iter()
+        .find(|(_, variable)| {
+            matches!(variable.metadata, SecretVarType::SecretBet { player: owner } if owner == *player)
+        })
+        .map(|(variable_id, _)| *variable_id)
+}
+
+/// Find the house seed committed for the given epoch, if it was confirmed.
+fn house_seed_variable_of(zk_state: &ZkState<SecretVarType>, epoch: u32) -> Option<SecretVarId> {
+    zk_state
+        .secret_variables
+        .iter()
+        .find(|(_, variable)| {
+            matches!(variable.metadata, SecretVarType::HouseSeed { epoch: other } if other == epoch)
+        })
+        .map(|(variable_id, _)| *variable_id)
+}
+
+/// Find the sealed stake inputted by the given player, if any.
+fn sealed_stake_variable_of(
+    zk_state: &ZkState<SecretVarType>,
+    player: &Address,
+) -> Option<SecretVarId> {
+    zk_state
+        .secret_variables
+        .iter()
+        .find(|(_, variable)| {
+            matches!(variable.metadata, SecretVarType::SealedStake { player: owner } if owner == *player)
+        })
+        .map(|(variable_id, _)| *variable_id)
+}
+
+/// Find the variable holding the sealed credit of the given player.
+fn sealed_credit_variable_of(
+    zk_state: &ZkState<SecretVarType>,
+    player: &Address,
+) -> Option<SecretVarId> {
+    zk_state
+        .secret_variables
+        .iter()
+        .find(|(_, variable)| {
+            matches!(variable.metadata, SecretVarType::SealedCredit { player: owner } if owner == *player)
+        })
+        .map(|(variable_id, _)| *variable_id)
+}
+
+/// Find the secret choice inputted by the given player, if any.
+fn choice_variable_of(
+    zk_state: &ZkState<SecretVarType>,
+    player: &Address,
+) -> Option<SecretVarId> {
+    zk_state
+        .secret_variables
+        .iter()
+        .find(|(_, variable)| {
+            matches!(variable.metadata, SecretVarType::Choice { player: owner } if owner == *player)
+        })
+        .map(|(variable_id, _)| *variable_id)
+}
+
+/// Find the variable holding the private balance of the given player.
+fn private_balance_variable_of(
+    zk_state: &ZkState<SecretVarType>,
+    player: &Address,
+) -> Option<SecretVarId> {
+    zk_state
+        .secret_variables
+        .iter()
+        .find(|(_, variable)| {
+            matches!(variable.metadata, SecretVarType::PrivateBalance { player: owner } if owner == *player)
+        })
+        .map(|(variable_id, _)| *variable_id)
+}
+
+/// Clean up after a settled round of the given player: forget its contributors and delete the
+/// given variables together with the randomness contributed to the round.
+fn finish_round(
+    state: &mut CoinFlipState,
+    zk_state: &ZkState<SecretVarType>,
+    player: &Address,
+    mut variables_to_delete: Vec<SecretVarId>,
+) -> Vec<ZkStateChange> {
+    state.round_contributors.remove(player);
+    state.computation_pending.remove(player);
+    variables_to_delete.extend(randomness_variables_of(zk_state, player));
+    vec![ZkStateChange::DeleteVariables {
+        variables_to_delete,
+    }]
+}
+
+/// Collect the ids of all randomness contributions to the rounds of the given player, selected by
+/// their metadata whoever contributed them.
+fn randomness_variables_of(
+    zk_state: &ZkState<SecretVarType>,
+    player: &Address,
+) -> Vec<SecretVarId> {
+    zk_state
+        .secret_variables
+        .iter()
+        .filter(|(_, variable)| {
+            matches!(variable.metadata, SecretVarType::Randomness { player: round } if round == *player)
+        })
+        .map(|(variable_id, _)| *variable_id)
+        .collect()
+}
+
+/// Payout the winner for a specific player.
+#[action(shortname = 0x04, zk = true)]
+pub fn payout_winner(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_not_paused();
+    state.finalize_expired_challenge(&context.sender, context.block_production_time);
+    let player_phase = state
+        .game_phases
+        .get(&context.sender)
+        .unwrap_or(GamePhase::Start {});
+    ensure!(
+        player_phase == GamePhase::Done {},
+        GameError::InvalidPhase,
+        "Payout can only occur after the game has completed."
+    );
+
+    // Winnings go to the beneficiary named with the bet, if any
+    let payee = state.payee_of(&context.sender);
+    let (payout, converted_payout) = take_payout(&mut state, &context.sender);
+
+    if payout > 0 || converted_payout > 0 {
+        // Create an event group to transfer tokens to the player
+        let mut event_group = EventGroup::builder();
+
+        // Call the token contract's `transfer` method
+        if payout > 0 {
+            token_client::transfer(
+                &mut event_group,
+                &state.gas_costs,
+                state.bet_token_of(&context.sender),
+                payee,
+                payout,
+            );
+        }
+        if converted_payout > 0 {
+            token_client::transfer(
+                &mut event_group,
+                &state.gas_costs,
+                state.payout_token_of(&context.sender),
+                payee,
+                converted_payout,
+            );
+        }
+
+        let mut events = vec![event_group.build()];
+        events.extend(settlement_events(&mut state));
+        let changes = settlement_attestations(&mut state);
+        return (state, events, changes);
+    }
+
+    // No payout is needed, but settling the game may have queued events
+    let events = settlement_events(&mut state);
+    let changes = settlement_attestations(&mut state);
+    (state, events, changes)
+}
+
+/// Take everything owed to the player for their completed game, so it can be claimed only once.
+///
+/// ### Returns:
+///
+/// The amount owed in the token of the bet and the amount owed in its payout token.
+fn take_payout(state: &mut CoinFlipState, player: &Address) -> (u64, u64) {
+    // The unstaked part of a secret bet's cover and a won jackpot are paid whatever the outcome
+    let mut payout = state.take_secret_bet_refund(player);
+    if let Some(jackpot) = state.won_jackpots.get(player) {
+        state.won_jackpots.remove(player);
+        payout += jackpot;
+    }
+
+    // Winnings of dual-token bets are paid separately, in their payout token
+    let mut converted_payout = 0;
+
+    if let Some(winner) = state.get_winner(player) {
+        // If the winner is the player themselves
+        if winner == *player {
+            if let Some(bet) = state.player_bets.get(player) {
+                let converted_winnings = state.converted_winnings.get(player);
+                state.converted_winnings.remove(player);
+                match converted_winnings {
+                    Some(converted) => converted_payout = converted,
+                    // The stake of a free bet was never escrowed, so only the profit is paid
+                    None if state.free_bets.get(player) == Some(true) => {
+                        payout += state.winnings_of(player, &bet) - bet.amount
+                    }
+                    // Calculate the winnings (double the bet)
+                    None => payout += state.winnings_of(player, &bet),
+                }
+                // Winnings of a promotional stake are paid from the promo budget
+                payout += state.promotion.pay(player);
+
+                // Forget the winner so the winnings can be neither claimed twice nor ridden after payout
+                state.winners.remove(player);
+                state.claim_deadlines.remove(player);
+                state.ledger.pay(player);
+
+                // // After the payout, reset the player's state
+                // state.player_bets.remove(&context.sender);
+                // state.flip_results.remove(&context.sender);
+                // state.winners.remove(&context.sender);
+                // state.game_phases.insert(context.sender, GamePhase::Start {}); // Reset phase to Start
+            }
+        }
+    }
+
+    if payout > 0 || converted_payout > 0 {
+        let mut stats = state.player_stats.get(player).unwrap_or_default();
+        stats.total_paid_out += payout + converted_payout;
+        state.player_stats.insert(*player, stats);
+        state.raise_game_event(GameEvent::PaidOut {
+            player: *player,
+            amount: payout + converted_payout,
+        });
+    }
+    // Winnings of play money stay in the contract as play money, in the default token only
+    if state.is_play_money_account(player) {
+        state.adjust_balance(*player, payout);
+        payout = 0;
+    }
+    state.refresh_player_view(player);
+    (payout, converted_payout)
+}
+
+/// Take the winnings of the player's completed game once its claim window has passed, so they
+/// can be swept instead of paid out. Only games staked and paid in the default token are swept.
+///
+/// ### Returns:
+///
+/// The amount swept, zero if the player has no winnings to sweep.
+fn take_unclaimed(state: &mut CoinFlipState, player: &Address, now: i64) -> u64 {
+    let sweepable = state.game_phases.get(player) == Some(GamePhase::Done {})
+        && state.get_winner(player) == Some(*player)
+        && state.claim_window_passed(player, now)
+        && state.bet_token_of(player) == state.token_address
+        && state.payout_tokens.get(player).is_none();
+    let Some(bet) = state.player_bets.get(player).filter(|_| sweepable) else {
+        return 0;
+    };
+    let mut amount = state.take_secret_bet_refund(player);
+    if let Some(jackpot) = state.won_jackpots.get(player) {
+        state.won_jackpots.remove(player);
+        amount += jackpot;
+    }
+    amount += if state.is_free_bet(player) {
+        // The stake of a free bet was never escrowed, so only the profit is owed
+        state.winnings_of(player, &bet) - bet.amount
+    } else {
+        state.winnings_of(player, &bet)
+    };
+    // Winnings of a promotional stake never leave the promo budget
+    state.promotion.release(player);
+    state.winners.remove(player);
+    state.claim_deadlines.remove(player);
+    state.unclaimed_swept_total += amount;
+    state.refresh_player_view(player);
+    amount
+}
+
+/// Open a new lottery selling tickets at `ticket_price` for `duration_millis`.
+/// Only callable by the owner, once the previous lottery has been drawn.
+#[action(shortname = 0x11, zk = true)]
+pub fn open_lottery(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    ticket_price: u64,
+    duration_millis: i64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    ensure!(
+        ticket_price > 0,
+        GameError::InvalidArgument,
+        "The ticket price must be positive."
+    );
+    let next_id = match &state.lottery {
+        None => 0,
+        Some(lottery) => {
+            ensure!(
+                lottery.is_finished(),
+                GameError::InvalidPhase,
+                "The previous lottery has not been drawn yet."
+            );
+            lottery.id + 1
+        }
+    };
+
+    state.lottery = Some(Lottery::new(
+        next_id,
+        ticket_price,
+        context.block_production_time + duration_millis,
+    ));
+    (state, vec![], vec![])
+}
+
+/// Buy `count` tickets in the open lottery. The tickets are escrowed through the token contract
+/// and only added once the transfer succeeds.
+#[action(shortname = 0x12, zk = true)]
+pub fn buy_lottery_tickets(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    count: u32,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_accepting_bets();
+    state.close_import();
+    state.assert_allowlisted(&context.sender);
+    let lottery = state
+        .lottery
+        .as_ref()
+        .or_fail(GameError::NotFound, "No lottery has been opened.");
+    ensure!(
+        lottery.is_selling(context.block_production_time),
+        GameError::InvalidPhase,
+        "The lottery is not selling tickets."
+    );
+    ensure!(
+        count > 0,
+        GameError::InvalidArgument,
+        "At least one ticket must be bought."
+    );
+    ensure!(
+        lottery.ticket_count() + count <= MAX_LOTTERY_TICKETS,
+        GameError::Unavailable,
+        "The lottery is sold out."
+    );
+
+    let amount = lottery.ticket_price * count as u64;
+    let mut event_group = EventGroup::builder();
+    token_client::transfer_from(
+        &mut event_group,
+        &state.gas_costs,
+        state.token_address,
+        context.sender,
+        context.contract_address,
+        amount,
+    );
+    token_client::register_callback(&mut event_group, &state.gas_costs, 0x02)
+        .argument(context.sender)
+        .argument(lottery.id)
+        .argument(count)
+        .done();
+
+    (state, vec![event_group.build()], vec![])
+}
+
+/// Callback adding escrowed lottery tickets. Tickets arriving after the draw has started are
+/// refunded instead.
+#[callback(shortname = 0x02, zk = true)]
+pub fn lottery_tickets_callback(
+    context: ContractContext,
+    callback_ctx: CallbackContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    buyer: Address,
+    lottery_id: u32,
+    count: u32,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    ensure!(
+        callback_ctx.results[0].succeeded,
+        GameError::TransferFailed,
+        "Token transfer failed, no tickets were bought."
+    );
+
+    let lottery = state.lottery.as_mut().unwrap();
+    if lottery.id == lottery_id && lottery.status == (LotteryStatus::Open {}) {
+        lottery.add_tickets(buyer, count);
+        return (state, vec![], vec![]);
+    }
+
+    let refund = lottery.ticket_price * count as u64;
+    let mut event_group = EventGroup::builder();
+    token_client::transfer(
+        &mut event_group,
+        &state.gas_costs,
+        state.token_address,
+        buyer,
+        refund,
+    );
+    (state, vec![event_group.build()], vec![])
+}
+
+/// Add randomness to the draw of the open lottery. Callable by anyone until the draw starts.
+#[zk_on_secret_input(shortname = 0x44, secret_type = "LotteryContribution")]
+pub fn add_lottery_randomness(
+    context: ContractContext,
+    state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+) -> (
+    CoinFlipState,
+    Vec<EventGroup>,
+    ZkInputDef<SecretVarType, LotteryContribution>,
+) {
+    state.assert_not_paused();
+    let lottery = state
+        .lottery
+        .as_ref()
+        .or_fail(GameError::NotFound, "No lottery has been opened.");
+    ensure!(
+        lottery.status == (LotteryStatus::Open {}),
+        GameError::InvalidPhase,
+        "Randomness can only be added until the lottery is drawn."
+    );
+
+    let input_def = ZkInputDef::with_metadata(
+        Some(SHORTNAME_INPUTTED_VARIABLE),
+        SecretVarType::LotteryRandomness {lottery_id: lottery.id},
+    );
+
+    (state, vec![], input_def)
+}
+
+/// Draw the winning ticket once the lottery has closed. Callable by anyone, once an address
+/// holding no ticket has contributed randomness, since contributions of ticket holders alone could
+/// be chosen to pick their own ticket, and no contribution at all always picks the first ticket.
+#[action(shortname = 0x13, zk = true)]
+pub fn draw_lottery(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_not_paused();
+    let lottery = state
+        .lottery
+        .as_mut()
+        .or_fail(GameError::NotFound, "No lottery has been opened.");
+    ensure!(
+        lottery.status == LotteryStatus::Open {},
+        GameError::InvalidPhase,
+        "The lottery is not open."
+    );
+    ensure!(
+        context.block_production_time >= lottery.closes_at,
+        GameError::InvalidPhase,
+        "The lottery has not closed yet."
+    );
+
+    if lottery.tickets.is_empty() {
+        lottery.status = LotteryStatus::Drawn { winner: None };
+        let variables_to_delete = lottery_randomness_variables_of(&zk_state, lottery.id);
+        return (
+            state,
+            vec![],
+            vec![ZkStateChange::DeleteVariables {
+                variables_to_delete,
+            }],
+        );
+    }
+
+    let has_outside_contribution = lottery_randomness_variables_of(&zk_state, lottery.id)
+        .iter()
+        .any(|variable_id| {
+            let owner = zk_state.get_variable(*variable_id).unwrap().owner;
+            !lottery.tickets.contains(&owner)
+        });
+    ensure!(
+        has_outside_contribution,
+        GameError::MissingRandomness,
+        "The draw needs randomness from an address holding no ticket."
+    );
+
+    lottery.status = LotteryStatus::Drawing {};
+    let lottery_id = lottery.id;
+    let ticket_count = lottery.ticket_count();
+    (
+        state,
+        vec![],
+        vec![zk_compute::compute_lottery_draw_start(
+            ticket_count,
+            Some(SHORTNAME_FLIP_COMPUTE_COMPLETE),
+            &SecretVarType::LotteryDraw {lottery_id},
+        )],
+    )
+}
+
+/// Set the maximum number of consecutive rides, zero disabling `let_it_ride`.
+/// Only callable by the owner or an operator.
+#[action(shortname = 0x14, zk = true)]
+pub fn set_max_consecutive_rides(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    max_rides: u32,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_operator(&context.sender);
+    state.max_consecutive_rides = max_rides;
+    (state, vec![], vec![])
+}
+
+/// Re-stake the unclaimed winnings of a won coin flip on another flip, double or nothing.
+/// The winnings stay escrowed in the contract, so the game moves straight to the FlipCoin phase.
+#[action(shortname = 0x15, zk = true)]
+pub fn let_it_ride(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    choice: PlayerChoice,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_accepting_bets();
+    state.finalize_expired_challenge(&context.sender, context.block_production_time);
+    let player_phase = state
+        .game_phases
+        .get(&context.sender)
+        .unwrap_or(GamePhase::Start {});
+    ensure!(
+        player_phase == GamePhase::Done {},
+        GameError::InvalidPhase,
+        "Winnings can only ride once the game has completed."
+    );
+    ensure!(
+        state.get_winner(&context.sender) == Some(context.sender),
+        GameError::InvalidPhase,
+        "Only unclaimed winnings can ride."
+    );
+
+    let bet = state.player_bets.get(&context.sender).unwrap();
+    ensure!(
+        bet.choice.is_some()
+            && !bet.secret_amount
+            && bet.multi_flip.is_none()
+            && bet.dice.is_none()
+            && bet.roulette.is_none(),
+        GameError::Ineligible,
+        "Only single coin flips with a public stake can ride."
+    );
+    ensure!(
+        state.payout_tokens.get(&context.sender).is_none(),
+        GameError::Ineligible,
+        "Winnings paid in another token than the stake cannot ride."
+    );
+    ensure!(
+        !state.is_free_bet(&context.sender),
+        GameError::Ineligible,
+        "Winnings of a free bet cannot ride."
+    );
+
+    let streak = state
+        .ride_streaks
+        .get(&context.sender)
+        .unwrap_or(RideStreak {
+            rides: 0,
+            original_stake: bet.amount,
+        });
+    ensure!(
+        streak.rides < state.max_consecutive_rides,
+        GameError::Unavailable,
+        "The maximum number of consecutive rides has been reached."
+    );
+
+    let stake = state.winnings_of(&context.sender, &bet);
+    state.bonus_multipliers.remove(&context.sender);
+    let promo_winnings = state.promotion.pay(&context.sender);
+    state.adjust_balance(context.sender, promo_winnings);
+    state.matches.remove(&context.sender);
+    state.parlays.remove(&context.sender);
+    state.flip_results.remove(&context.sender);
+    state.winners.remove(&context.sender);
+    state.ledger.ride(context.sender);
+    let ridden_bet = PlayerBet {
+        amount: stake,
+        choice: Some(choice),
+        ..bet
+    };
+    let token = state.bet_token_of(&context.sender);
+    state.reserve_exposure(
+        &context.sender,
+        &token,
+        winnings_for(&ridden_bet, state.dice_house_edge_bps),
+    );
+    state.player_bets.insert(context.sender, ridden_bet);
+    state.ride_streaks.insert(
+        context.sender,
+        RideStreak {
+            rides: streak.rides + 1,
+            ..streak
+        },
+    );
+    state.assign_round_salt(&context.sender);
+    state.set_phase(context.sender, GamePhase::FlipCoin {}, context.block_production_time);
+
+    let events = settlement_events(&mut state);
+    let changes = settlement_attestations(&mut state);
+    (state, events, changes)
+}
+
+/// Start a best-of-`rounds` match on `choice`: a single escrowed stake covers every round, a
+/// coin is flipped per round, and the stake is settled once either side wins the majority.
+#[action(shortname = 0x16, zk = true)]
+pub fn start_match(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    bet_amount: u64,
+    choice: PlayerChoice,
+    rounds: u8,
+    nonce: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    ensure!(
+        rounds % 2 == 1 && rounds <= MAX_MATCH_ROUNDS,
+        GameError::InvalidArgument,
+        "A match needs an odd number of rounds, at most 15."
+    );
+
+    let player_bet = PlayerBet {
+        amount: bet_amount,
+        choice: Some(choice),
+        secret_amount: false,
+        multi_flip: None,
+        dice: None,
+        roulette: None,
+        beneficiary: None,
+    };
+    let event_groups =
+        place_bet(&context, &mut state, nonce, player_bet, state.token_address, None);
+    state.matches.insert(
+        context.sender,
+        MatchScore {
+            rounds,
+            player_wins: 0,
+            house_wins: 0,
+        },
+    );
+
+    (state, event_groups, vec![])
+}
+
+/// Configure the penalties for abandoning games, or disable them with `None`.
+/// Only callable by the owner or an operator.
+#[action(shortname = 0x17, zk = true)]
+pub fn set_abandon_penalty(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    config: Option<AbandonPenaltyConfig>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_operator(&context.sender);
+    if let Some(config) = &config {
+        ensure!(
+            config.fee_bps <= 10_000,
+            GameError::InvalidArgument,
+            "The fee cannot exceed the stake."
+        );
+    }
+    state.abandon_penalty = config;
+    (state, vec![], vec![])
+}
+
+/// Create a rock-paper-scissors challenge staking `stake`. The challenge opens for opponents
+/// once the stake has been escrowed.
+#[action(shortname = 0x18, zk = true)]
+pub fn create_rps_challenge(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    stake: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_accepting_bets();
+    state.close_import();
+    state.assert_allowlisted(&context.sender);
+    ensure!(
+        stake > 0,
+        GameError::InvalidArgument,
+        "The stake must be positive."
+    );
+    let game_id = state.next_rps_game_id;
+    state.next_rps_game_id += 1;
+    state.rps_games.insert(
+        game_id,
+        RpsGame {
+            creator: context.sender,
+            opponent: None,
+            opponent_escrowed: false,
+            stake,
+            status: RpsStatus::AwaitingEscrow {},
+        },
+    );
+
+    let event_group = rps_escrow(&context, &state, game_id, stake);
+    (state, vec![event_group], vec![])
+}
+
+/// Join an open rock-paper-scissors challenge, escrowing the same stake as its creator.
+#[action(shortname = 0x19, zk = true)]
+pub fn join_rps_challenge(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    game_id: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_accepting_bets();
+    state.close_import();
+    state.assert_allowlisted(&context.sender);
+    let mut game = state
+        .rps_games
+        .get(&game_id)
+        .cloned()
+        .or_fail(GameError::NotFound, "Unknown rock-paper-scissors game.");
+    ensure!(
+        game.status == RpsStatus::WaitingForOpponent {},
+        GameError::InvalidPhase,
+        "The challenge is not open for opponents."
+    );
+    ensure!(
+        game.opponent.is_none(),
+        GameError::InvalidPhase,
+        "The challenge already has an opponent."
+    );
+    ensure!(
+        game.creator != context.sender,
+        GameError::InvalidArgument,
+        "Cannot join your own challenge."
+    );
+
+    game.opponent = Some(context.sender);
+    let stake = game.stake;
+    state.rps_games.insert(game_id, game);
+
+    let event_group = rps_escrow(&context, &state, game_id, stake);
+    (state, vec![event_group], vec![])
+}
+
+/// Cancel a challenge nobody has joined yet and refund the creator's stake.
+#[action(shortname = 0x1A, zk = true)]
+pub fn cancel_rps_challenge(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    game_id: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_not_paused();
+    let mut game = state
+        .rps_games
+        .get(&game_id)
+        .cloned()
+        .or_fail(GameError::NotFound, "Unknown rock-paper-scissors game.");
+    ensure!(
+        game.creator == context.sender,
+        GameError::Unauthorized,
+        "Only the creator can cancel the challenge."
+    );
+    ensure!(
+        game.status == RpsStatus::WaitingForOpponent {} && game.opponent.is_none(),
+        GameError::InvalidPhase,
+        "Only challenges without an opponent can be cancelled."
+    );
+
+    game.status = RpsStatus::Cancelled {};
+    let stake = game.stake;
+    state.rps_games.insert(game_id, game);
+
+    let mut event_group = EventGroup::builder();
+    token_client::transfer(
+        &mut event_group,
+        &state.gas_costs,
+        state.token_address,
+        context.sender,
+        stake,
+    );
+    (state, vec![event_group.build()], vec![])
+}
+
+/// Build the escrow transfer of a rock-paper-scissors stake from the sender.
+fn rps_escrow(context: &ContractContext, state: &CoinFlipState, game_id: u64, stake: u64) -> EventGroup {
+    let mut event_group = EventGroup::builder();
+    token_client::transfer_from(
+        &mut event_group,
+        &state.gas_costs,
+        state.token_address,
+        context.sender,
+        context.contract_address,
+        stake,
+    );
+    token_client::register_callback(&mut event_group, &state.gas_costs, 0x03)
+        .argument(game_id)
+        .argument(context.sender)
+        .done();
+    event_group.build()
+}
+
+/// Callback of a rock-paper-scissors stake escrow. A failed escrow releases the opponent slot,
+/// or drops the challenge if it was the creator's stake.
+#[callback(shortname = 0x03, zk = true)]
+pub fn rps_escrow_callback(
+    context: ContractContext,
+    callback_ctx: CallbackContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    game_id: u64,
+    player: Address,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let mut game = state.rps_games.get(&game_id).cloned().unwrap();
+    let succeeded = callback_ctx.results[0].succeeded;
+
+    if player == game.creator && game.status == (RpsStatus::AwaitingEscrow {}) {
+        if succeeded {
+            game.status = RpsStatus::WaitingForOpponent {};
+            state.rps_games.insert(game_id, game);
+        } else {
+            state.rps_games.remove(&game_id);
+        }
+    } else if game.opponent == Some(player) {
+        if succeeded {
+            game.opponent_escrowed = true;
+            game.status = RpsStatus::Throwing {};
+            let deadline = context.block_production_time + RPS_THROW_TIMEOUT_MILLIS;
+            state.rps_throw_deadlines.insert(game_id, deadline);
+        } else {
+            game.opponent = None;
+        }
+        state.rps_games.insert(game_id, game);
+    }
+
+    (state, vec![], vec![])
+}
+
+/// Submit a secret throw in a rock-paper-scissors game: 0 for rock, 1 for paper and
+/// 2 for scissors. Any other value loses against a valid throw.
+#[zk_on_secret_input(shortname = 0x45, secret_type = "RpsThrow")]
+pub fn submit_rps_throw(
+    context: ContractContext,
+    state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    game_id: u64,
+) -> (
+    CoinFlipState,
+    Vec<EventGroup>,
+    ZkInputDef<SecretVarType, RpsThrow>,
+) {
+    state.assert_not_paused();
+    let game = state
+        .rps_games
+        .get(&game_id)
+        .or_fail(GameError::NotFound, "Unknown rock-paper-scissors game.");
+    ensure!(
+        game.status == RpsStatus::Throwing {},
+        GameError::InvalidPhase,
+        "The game is not accepting throws."
+    );
+    ensure!(
+        game.is_player(&context.sender),
+        GameError::Unauthorized,
+        "Only the players can throw."
+    );
+    let already_thrown = rps_throw_variable_of(&zk_state, game_id, &context.sender).is_some()
+        || zk_state.pending_inputs.iter().any(|(_, variable)| {
+            matches!(variable.metadata, SecretVarType::RpsThrow { game_id: id, player } if id == game_id && player == context.sender)
+        });
+    ensure!(
+        !already_thrown,
+        GameError::AlreadyDone,
+        "The throw has already been submitted."
+    );
+
+    let input_def = ZkInputDef::with_metadata(
+        Some(SHORTNAME_INPUTTED_VARIABLE),
+        SecretVarType::RpsThrow {game_id, player: context.sender},
+    );
+
+    (state, vec![], input_def)
+}
+
+/// Compare the throws once both players have submitted them. Callable by either player.
+#[action(shortname = 0x1B, zk = true)]
+pub fn resolve_rps(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    game_id: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_not_paused();
+    let mut game = state
+        .rps_games
+        .get(&game_id)
+        .cloned()
+        .or_fail(GameError::NotFound, "Unknown rock-paper-scissors game.");
+    ensure!(
+        game.status == RpsStatus::Throwing {},
+        GameError::InvalidPhase,
+        "The game is not accepting throws."
+    );
+    ensure!(
+        game.is_player(&context.sender),
+        GameError::Unauthorized,
+        "Only the players can resolve the game."
+    );
+
+    let creator_throw = rps_throw_variable_of(&zk_state, game_id, &game.creator)
+        .or_fail(GameError::InvalidPhase, "The creator has not thrown yet.");
+    let opponent_throw = rps_throw_variable_of(&zk_state, game_id, &game.opponent.unwrap())
+        .or_fail(GameError::InvalidPhase, "The opponent has not thrown yet.");
+
+    game.status = RpsStatus::Computing {};
+    state.rps_games.insert(game_id, game);
+    state.rps_throw_deadlines.remove(&game_id);
+
+    (
+        state,
+        vec![],
+        vec![zk_compute::compute_rps_outcome_start(
+            creator_throw,
+            opponent_throw,
+            Some(SHORTNAME_FLIP_COMPUTE_COMPLETE),
+            &SecretVarType::RpsOutcome {game_id},
+        )],
+    )
+}
+
+/// End a rock-paper-scissors game whose throw deadline passed without both throws. The player
+/// who threw wins both stakes, and if neither did, both stakes are refunded. Callable by either
+/// player.
+#[action(shortname = 0xAC, zk = true)]
+pub fn claim_rps_timeout(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    game_id: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let mut game = state
+        .rps_games
+        .get(&game_id)
+        .cloned()
+        .or_fail(GameError::NotFound, "Unknown rock-paper-scissors game.");
+    ensure!(
+        game.status == RpsStatus::Throwing {},
+        GameError::InvalidPhase,
+        "The game is not accepting throws."
+    );
+    ensure!(
+        game.is_player(&context.sender),
+        GameError::Unauthorized,
+        "Only the players can end the game."
+    );
+    let deadline = state.rps_throw_deadlines.get(&game_id).copied().unwrap_or(i64::MAX);
+    ensure!(
+        context.block_production_time >= deadline,
+        GameError::InvalidPhase,
+        "The players can still throw."
+    );
+    let opponent = game.opponent.unwrap();
+    let creator_threw = rps_throw_variable_of(&zk_state, game_id, &game.creator).is_some();
+    let opponent_threw = rps_throw_variable_of(&zk_state, game_id, &opponent).is_some();
+    ensure!(
+        !(creator_threw && opponent_threw),
+        GameError::AlreadyDone,
+        "Both players have thrown, so the game can be resolved."
+    );
+
+    let mut event_group = EventGroup::builder();
+    let winner = if creator_threw {
+        Some(game.creator)
+    } else if opponent_threw {
+        Some(opponent)
+    } else {
+        None
+    };
+    match winner {
+        Some(winner) => token_client::transfer(
+            &mut event_group,
+            &state.gas_costs,
+            state.token_address,
+            winner,
+            game.stake * 2,
+        ),
+        None => {
+            for player in [game.creator, opponent] {
+                token_client::transfer(
+                    &mut event_group,
+                    &state.gas_costs,
+                    state.token_address,
+                    player,
+                    game.stake,
+                );
+            }
+        }
+    }
+    game.status = RpsStatus::Finished { winner };
+    state.rps_games.insert(game_id, game);
+    state.rps_throw_deadlines.remove(&game_id);
+
+    let variables_to_delete = rps_throw_variables_of(&zk_state, game_id);
+    let changes = if variables_to_delete.is_empty() {
+        vec![]
+    } else {
+        vec![ZkStateChange::DeleteVariables {
+            variables_to_delete,
+        }]
+    };
+    (state, vec![event_group.build()], changes)
+}
+
+/// Maximum jackpot rake, in basis points of the bet.
+const MAX_JACKPOT_RAKE_BPS: u32 = 1_000;
+
+/// Set the share of every bet that accrues to the jackpot, in basis points. Only callable by the
+/// owner.
+#[action(shortname = 0x1E, zk = true)]
+pub fn set_jackpot_rake(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    rake_bps: u32,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    ensure!(
+        rake_bps <= MAX_JACKPOT_RAKE_BPS,
+        GameError::InvalidArgument,
+        "The jackpot rake can be at most {} basis points.",
+        MAX_JACKPOT_RAKE_BPS
+    );
+    state.jackpot_rake_bps = rake_bps;
+    (state, vec![], vec![])
+}
+
+/// Deposit tokens into the sender's balance, from which later bets are paid without a token
+/// transfer per bet.
+#[action(shortname = 0x1F, zk = true)]
+pub fn deposit(
+    context: ContractContext,
+    state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    amount: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_not_paused();
+    ensure!(
+        amount > 0,
+        GameError::InvalidArgument,
+        "The deposit must be positive."
+    );
+
+    let mut event_group = EventGroup::builder();
+    token_client::transfer_from(
+        &mut event_group,
+        &state.gas_costs,
+        state.token_address,
+        context.sender,
+        context.contract_address,
+        amount,
+    );
+    token_client::register_callback(&mut event_group, &state.gas_costs, 0x04)
+        .argument(context.sender)
+        .argument(amount)
+        .done();
+
+    (state, vec![event_group.build()], vec![])
+}
+
+/// Credit a deposit once its token transfer succeeded.
+#[callback(shortname = 0x04, zk = true)]
+pub fn deposit_callback(
+    context: ContractContext,
+    callback_ctx: CallbackContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    player: Address,
+    amount: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    ensure!(
+        callback_ctx.results[0].succeeded,
+        GameError::TransferFailed,
+        "Token transfer failed, the deposit was not credited."
+    );
+    state.adjust_balance(player, amount);
+    (state, vec![], vec![])
+}
+
+/// Withdraw tokens from the sender's balance. The balance is only debited once the transfer
+/// succeeded, and the amount cannot be bet or withdrawn again meanwhile.
+#[action(shortname = 0x20, zk = true)]
+pub fn withdraw(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    amount: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_not_paused();
+    ensure!(
+        amount > 0,
+        GameError::InvalidArgument,
+        "The withdrawal must be positive."
+    );
+    state.assert_not_play_money(&context.sender);
+    ensure!(
+        state.available_balance(&context.sender) >= amount,
+        GameError::InsufficientFunds,
+        "Insufficient balance for the withdrawal."
+    );
+    let pending = state.pending_withdrawals.get(&context.sender).unwrap_or(0);
+    state.pending_withdrawals.insert(context.sender, pending + amount);
+    state.refresh_player_view(&context.sender);
+
+    let mut event_group = EventGroup::builder();
+    token_client::transfer(
+        &mut event_group,
+        &state.gas_costs,
+        state.token_address,
+        context.sender,
+        amount,
+    );
+    token_client::register_callback(&mut event_group, &state.gas_costs, 0x05)
+        .argument(context.sender)
+        .argument(amount)
+        .done();
+
+    (state, vec![event_group.build()], vec![])
+}
+
+/// Settle a withdrawal: debit the balance if the transfer succeeded, release the amount otherwise.
+#[callback(shortname = 0x05, zk = true)]
+pub fn withdraw_callback(
+    context: ContractContext,
+    callback_ctx: CallbackContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    player: Address,
+    amount: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let pending = state.pending_withdrawals.get(&player).unwrap_or(0);
+    if pending > amount {
+        state.pending_withdrawals.insert(player, pending - amount);
+    } else {
+        state.pending_withdrawals.remove(&player);
+    }
+    state.refresh_player_view(&player);
+
+    if callback_ctx.results[0].succeeded {
+        let balance = state.user_balances.get(&player).unwrap_or(0);
+        let remaining = balance.checked_sub(amount).or_fail(
+            GameError::InsufficientFunds,
+            "The balance no longer covers the withdrawn amount.",
+        );
+        state.set_balance(player, remaining);
+    }
+    (state, vec![], vec![])
+}
+
+/// Add tokens from the owner to the house bankroll of a registered token.
+#[action(shortname = 0x21, zk = true)]
+pub fn fund_bankroll(
+    context: ContractContext,
+    state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    amount: u64,
+    token: Address,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    ensure!(
+        amount > 0,
+        GameError::InvalidArgument,
+        "The funding must be positive."
+    );
+    ensure!(
+        state.token_registry.get(&token).is_some(),
+        GameError::NotFound,
+        "The token is not registered."
+    );
+
+    let mut event_group = EventGroup::builder();
+    token_client::transfer_from(
+        &mut event_group,
+        &state.gas_costs,
+        token,
+        context.sender,
+        context.contract_address,
+        amount,
+    );
+    token_client::register_callback(&mut event_group, &state.gas_costs, 0x06)
+        .argument(amount)
+        .argument(token)
+        .done();
+
+    (state, vec![event_group.build()], vec![])
+}
+
+/// Credit the house bankroll once the funding transfer succeeded.
+#[callback(shortname = 0x06, zk = true)]
+pub fn fund_bankroll_callback(
+    context: ContractContext,
+    callback_ctx: CallbackContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    amount: u64,
+    token: Address,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    ensure!(
+        callback_ctx.results[0].succeeded,
+        GameError::TransferFailed,
+        "Token transfer failed, the bankroll was not funded."
+    );
+    if token == state.token_address {
+        state.bankroll_stakes.fund_house(amount, state.house_bankroll);
+    }
+    state.credit_bankroll(&token, amount);
+    (state, vec![], vec![])
+}
+
+/// Whitelist the yield contract idle bankroll may be deployed to, keeping `buffer` of the
+/// bankroll in the contract. Only callable by the owner, and the contract can only be replaced
+/// once nothing is deployed to the previous one.
+#[action(shortname = 0x22, zk = true)]
+pub fn set_yield_adapter(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    yield_contract: Address,
+    buffer: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    let adapter = match state.yield_adapter.take() {
+        Some(mut adapter) if adapter.contract == yield_contract => {
+            adapter.buffer = buffer;
+            adapter
+        }
+        Some(adapter) if adapter.is_in_use() => {
+            fail!(
+                GameError::InvalidPhase,
+                "Bankroll must be recalled from the current yield contract first."
+            )
+        }
+        _ => YieldAdapter::new(yield_contract, buffer),
+    };
+    state.yield_adapter = Some(adapter);
+    (state, vec![], vec![])
+}
+
+/// Deploy all idle bankroll above the buffer to the yield contract. Only callable by a treasurer.
+#[action(shortname = 0x23, zk = true)]
+pub fn deploy_idle_bankroll(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_treasurer(&context.sender);
+    let house_bankroll = state.house_bankroll;
+    let adapter = state
+        .yield_adapter
+        .as_mut()
+        .or_fail(GameError::NotFound, "No yield contract is configured.");
+    let amount = adapter.deployable(house_bankroll);
+    ensure!(
+        amount > 0,
+        GameError::InsufficientFunds,
+        "No idle bankroll above the buffer to deploy."
+    );
+    adapter.pending_deposit += amount;
+    let yield_contract = adapter.contract;
+
+    let mut event_group = EventGroup::builder();
+    token_client::approve(
+        &mut event_group,
+        &state.gas_costs,
+        state.token_address,
+        yield_contract,
+        amount,
+    );
+    // Assuming shortname for the yield `deposit`
+    token_client::interaction(&mut event_group, &state.gas_costs, yield_contract, 0x01)
+        .argument(state.token_address)
+        .argument(amount as u128)
+        .done();
+    token_client::register_callback(&mut event_group, &state.gas_costs, 0x07)
+        .argument(amount)
+        .done();
+
+    (state, vec![event_group.build()], vec![])
+}
+
+/// Confirm a deployment to the yield contract, or return the amount to idle bankroll if it failed.
+#[callback(shortname = 0x07, zk = true)]
+pub fn deploy_bankroll_callback(
+    context: ContractContext,
+    callback_ctx: CallbackContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    amount: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let succeeded = callback_ctx.results.iter().all(|result| result.succeeded);
+    let adapter = state.yield_adapter.as_mut().unwrap();
+    adapter.pending_deposit -= amount;
+    if succeeded {
+        adapter.deployed += amount;
+    }
+    (state, vec![], vec![])
+}
+
+/// Recall deployed bankroll from the yield contract, e.g. when payouts need more liquidity than
+/// the buffer holds. Only callable by a treasurer.
+#[action(shortname = 0x24, zk = true)]
+pub fn recall_bankroll(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    amount: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_treasurer(&context.sender);
+    let adapter = state
+        .yield_adapter
+        .as_mut()
+        .or_fail(GameError::NotFound, "No yield contract is configured.");
+    ensure!(
+        amount > 0 && amount <= adapter.recallable(),
+        GameError::InvalidArgument,
+        "The recalled amount must be positive and at most the deployed bankroll."
+    );
+    adapter.pending_recall += amount;
+    let yield_contract = adapter.contract;
+
+    let mut event_group = EventGroup::builder();
+    // Assuming shortname for the yield `withdraw`
+    token_client::interaction(&mut event_group, &state.gas_costs, yield_contract, 0x02)
+        .argument(state.token_address)
+        .argument(amount as u128)
+        .done();
+    token_client::register_callback(&mut event_group, &state.gas_costs, 0x08)
+        .argument(amount)
+        .done();
+
+    (state, vec![event_group.build()], vec![])
+}
+
+/// Confirm a recall from the yield contract; a failed recall stays deployed.
+#[callback(shortname = 0x08, zk = true)]
+pub fn recall_bankroll_callback(
+    context: ContractContext,
+    callback_ctx: CallbackContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    amount: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let succeeded = callback_ctx.results[0].succeeded;
+    let adapter = state.yield_adapter.as_mut().unwrap();
+    adapter.pending_recall -= amount;
+    if succeeded {
+        adapter.deployed -= amount;
+    }
+    (state, vec![], vec![])
+}
+
+/// Register a token for bets, or update its bet limits. Only callable by the owner or an
+/// operator.
+#[action(shortname = 0x25, zk = true)]
+pub fn set_token_config(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    token: Address,
+    min_bet: u64,
+    max_bet: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_operator(&context.sender);
+    ensure!(
+        min_bet > 0 && min_bet <= max_bet,
+        GameError::InvalidArgument,
+        "The minimum bet must be positive and at most the maximum bet."
+    );
+    let config = match state.token_registry.get(&token) {
+        Some(config) => TokenConfig {
+            min_bet,
+            max_bet,
+            ..config.clone()
+        },
+        None => TokenConfig::new(min_bet, max_bet),
+    };
+    state.token_registry.insert(token, config);
+    (state, vec![], vec![])
+}
+
+/// Stop accepting new bets in a token. Only tokens without house bankroll can be removed, and
+/// never the default token. Open bets in the token are still paid out in it. Only callable by the
+/// owner.
+#[action(shortname = 0x26, zk = true)]
+pub fn remove_token(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    token: Address,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    ensure!(
+        token != state.token_address,
+        GameError::InvalidArgument,
+        "The default token cannot be removed."
+    );
+    let config = state
+        .token_registry
+        .get(&token)
+        .or_fail(GameError::NotFound, "The token is not registered.");
+    ensure!(
+        config.bankroll == 0,
+        GameError::InvalidArgument,
+        "Tokens holding house bankroll cannot be removed."
+    );
+    state.token_registry.remove(&token);
+    (state, vec![], vec![])
+}
+
+/// Set the share of collected fees allocated to the insurance fund, in basis points. Only
+/// callable by the owner.
+#[action(shortname = 0x27, zk = true)]
+pub fn set_insurance_share(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    share_bps: u32,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    ensure!(
+        share_bps <= MAX_INSURANCE_SHARE_BPS,
+        GameError::InvalidArgument,
+        "The insurance share can be at most {} basis points.",
+        MAX_INSURANCE_SHARE_BPS
+    );
+    state.insurance.share_bps = share_bps;
+    (state, vec![], vec![])
+}
+
+/// Open a compensation round paying the claims committed to by `root`, reserving `total` from the
+/// insurance fund. Only callable by the owner.
+#[action(shortname = 0x28, zk = true)]
+pub fn open_compensation_round(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    root: Hash,
+    total: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    state.insurance.open_round(root, total);
+    (state, vec![], vec![])
+}
+
+/// Claim the sender's compensation from a compensation round, proving it with the Merkle path
+/// from the claim's leaf to the round's root.
+#[action(shortname = 0x29, zk = true)]
+pub fn claim_compensation(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    round_id: u32,
+    amount: u64,
+    proof: Vec<Hash>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_not_paused();
+    state.insurance.claim(round_id, context.sender, amount, &proof);
+
+    let mut event_group = EventGroup::builder();
+    token_client::transfer(
+        &mut event_group,
+        &state.gas_costs,
+        state.token_address,
+        context.sender,
+        amount,
+    );
+    token_client::register_callback(&mut event_group, &state.gas_costs, 0x19)
+        .argument(round_id)
+        .argument(context.sender)
+        .argument(amount)
+        .done();
+    (state, vec![event_group.build()], vec![])
+}
+
+/// Restore a compensation claim if its transfer failed.
+#[callback(shortname = 0x19, zk = true)]
+pub fn claim_compensation_callback(
+    context: ContractContext,
+    callback_ctx: CallbackContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    round_id: u32,
+    claimant: Address,
+    amount: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    if !callback_ctx.results[0].succeeded {
+        state.insurance.restore_claim(round_id, &claimant, amount);
+    }
+    (state, vec![], vec![])
+}
+
+/// Play a practice round: a coin is flipped by the full zk computation, but nothing is staked or
+/// paid out and the result only counts towards the player's practice stats. Each player can
+/// practice once per `PRACTICE_COOLDOWN_MILLIS`, and not while a round of theirs is open: the
+/// practice flip folds the same contributions as the round, so it would reveal the real flip.
+#[action(shortname = 0x2A, zk = true)]
+pub fn practice_flip(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    choice: PlayerChoice,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_accepting_bets();
+    let now = context.block_production_time;
+    ensure!(
+        state.practice_choices.get(&context.sender).is_none(),
+        GameError::InvalidPhase,
+        "The previous practice round has not finished yet."
+    );
+    ensure!(
+        state
+            .game_phases
+            .get(&context.sender)
+            .map_or(true, |phase| phase.is_settled())
+            && randomness_variables_of(&zk_state, &context.sender).is_empty(),
+        GameError::InvalidPhase,
+        "Practice rounds cannot be played while a round is open."
+    );
+    let mut stats = state
+        .practice_stats
+        .get(&context.sender)
+        .unwrap_or(PracticeStats {
+            rounds: 0,
+            wins: 0,
+            last_played_at: i64::MIN,
+        });
+    ensure!(
+        stats.last_played_at == i64::MIN || now >= stats.last_played_at + PRACTICE_COOLDOWN_MILLIS,
+        GameError::Unavailable,
+        "Practice rounds are limited to one per minute."
+    );
+    // Practice rounds are not queued, so real rounds always take precedence
+    ensure!(
+        zk_state.calculation_state == CalculationStatus::Waiting && state.compute_queue.is_empty(),
+        GameError::Unavailable,
+        "The computation engine is busy, try again shortly."
+    );
+
+    stats.last_played_at = now;
+    state.practice_stats.insert(context.sender, stats);
+    state.practice_choices.insert(context.sender, choice);
+
+    let mut seed = Vec::new();
+    state.rounds_started.state_write_to(&mut seed).unwrap();
+    context.sender.state_write_to(&mut seed).unwrap();
+    now.state_write_to(&mut seed).unwrap();
+    state.rounds_started += 1;
+    let salt = salt_word(&Hash::digest(&seed));
+
+    (
+        state,
+        vec![],
+        vec![zk_compute::compute_coin_flip_start(
+            salt,
+            // Practice results are opened without other contributions, so they must not fold in
+            // the house seed
+            NO_SEED_EPOCH,
+            context.sender,
+            choice == PlayerChoice::Heads {},
+            Some(SHORTNAME_FLIP_COMPUTE_COMPLETE),
+            &SecretVarType::PracticeFlip {player: context.sender},
+        )],
+    )
+}
+
+/// Limit the potential payouts of all open bets in a token to `max_exposure_bps` basis points of
+/// the token's bankroll, or remove the limit with zero. Only callable by the owner or an operator.
+#[action(shortname = 0x2C, zk = true)]
+pub fn set_max_exposure(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    max_exposure_bps: u32,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_operator(&context.sender);
+    state.max_exposure_bps = max_exposure_bps;
+    (state, vec![], vec![])
+}
+
+/// Start the game with the stake in `stake_token` and winnings paid in `payout_token`, converted
+/// at the rate configured between them when the game is settled.
+#[action(shortname = 0x2D, zk = true)]
+pub fn start_dual_token_game(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    bet_amount: u64,
+    choice: PlayerChoice,
+    stake_token: Address,
+    payout_token: Address,
+    nonce: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    ensure!(
+        state.token_registry.get(&payout_token).is_some(),
+        GameError::NotFound,
+        "The payout token is not registered."
+    );
+    let player_bet = PlayerBet {
+        amount: bet_amount,
+        choice: Some(choice),
+        secret_amount: false,
+        multi_flip: None,
+        dice: None,
+        roulette: None,
+        beneficiary: None,
+    };
+    let event_groups =
+        place_bet(&context, &mut state, nonce, player_bet, stake_token, Some(payout_token));
+    (state, event_groups, vec![])
+}
+
+/// Configure the rate converting winnings of bets staked in `stake_token` into `payout_token`,
+/// optionally letting an oracle update it. Only callable by the owner.
+#[action(shortname = 0x2E, zk = true)]
+pub fn set_conversion_rate(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    stake_token: Address,
+    rate: ConversionRate,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    ensure!(
+        rate.numerator > 0 && rate.denominator > 0,
+        GameError::InvalidArgument,
+        "The conversion rate must be positive."
+    );
+    ensure!(
+        stake_token != rate.payout_token,
+        GameError::InvalidArgument,
+        "A token converts to itself at par."
+    );
+    let mut rates = state.conversion_rates.get(&stake_token).cloned().unwrap_or_default();
+    rates.retain(|existing| existing.payout_token != rate.payout_token);
+    rates.push(rate);
+    state.conversion_rates.insert(stake_token, rates);
+    (state, vec![], vec![])
+}
+
+/// Update a conversion rate. Only callable by the oracle configured for the rate.
+#[action(shortname = 0x2F, zk = true)]
+pub fn update_conversion_rate(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    stake_token: Address,
+    payout_token: Address,
+    numerator: u64,
+    denominator: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    ensure!(
+        numerator > 0 && denominator > 0,
+        GameError::InvalidArgument,
+        "The conversion rate must be positive."
+    );
+    let mut rates = state
+        .conversion_rates
+        .get(&stake_token)
+        .cloned()
+        .or_fail(
+            GameError::NotFound,
+            "No conversion rate between the stake and payout token.",
+        );
+    let rate = rates
+        .iter_mut()
+        .find(|rate| rate.payout_token == payout_token)
+        .or_fail(
+            GameError::NotFound,
+            "No conversion rate between the stake and payout token.",
+        );
+    ensure!(
+        rate.oracle == Some(context.sender),
+        GameError::Unauthorized,
+        "Only the oracle of the conversion rate can update it."
+    );
+    rate.numerator = numerator;
+    rate.denominator = denominator;
+    state.conversion_rates.insert(stake_token, rates);
+    (state, vec![], vec![])
+}
+
+/// Set how many completed games are kept in the game history, pruning the oldest games if it
+/// shrinks. Only callable by the owner.
+#[action(shortname = 0x30, zk = true)]
+pub fn set_history_retention(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    retention: u32,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    state.game_history.set_retention(retention);
+    (state, vec![], vec![])
+}
+
+/// Burn a share of every losing stake in the contract's default token, or stop burning when
+/// `burn` is absent. Only callable by the owner.
+#[action(shortname = 0x31, zk = true)]
+pub fn set_burn_on_loss(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    burn: Option<BurnConfig>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    if let Some(burn) = &burn {
+        ensure!(
+            burn.share_bps <= 10_000,
+            GameError::InvalidArgument,
+            "The burned share cannot exceed the stake."
+        );
+    }
+    state.burn = burn;
+    (state, vec![], vec![])
+}
+
+/// Sell streak cover as configured, or stop selling it when `config` is absent. Cover already
+/// sold is honoured. Only callable by the owner.
+#[action(shortname = 0x32, zk = true)]
+pub fn set_streak_cover(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    config: Option<StreakCoverConfig>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    if let Some(config) = &config {
+        ensure!(
+            config.min_streak > 0,
+            GameError::InvalidArgument,
+            "Only existing streaks can be covered."
+        );
+    }
+    state.streak_cover = config;
+    (state, vec![], vec![])
+}
+
+/// Move `amount` of the house bankroll into the streak cover pool. Only callable by a treasurer.
+#[action(shortname = 0x33, zk = true)]
+pub fn fund_streak_cover_pool(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    amount: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_treasurer(&context.sender);
+    ensure!(
+        amount <= state.house_bankroll,
+        GameError::InsufficientFunds,
+        "The house bankroll cannot cover the funding."
+    );
+    state.bankroll_stakes.withdraw_house(amount, state.house_bankroll);
+    state.house_bankroll -= amount;
+    state.streak_cover_pool.balance += amount;
+    (state, vec![], vec![])
+}
+
+/// Cover the current win streak of the sender against being broken by their open bet. The
+/// premium is paid from the sender's balance, and the consolation prize is credited to it if
+/// the bet loses.
+#[action(shortname = 0x34, zk = true)]
+pub fn buy_streak_cover(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_accepting_bets();
+    let config = state
+        .streak_cover
+        .clone()
+        .or_fail(GameError::Unavailable, "Streak cover is not sold.");
+    let phase = state
+        .game_phases
+        .get(&context.sender)
+        .unwrap_or(GamePhase::Start {});
+    let stake = match state.player_bets.get(&context.sender) {
+        Some(bet) if !phase.is_settled() && phase != GamePhase::Challengeable {} => bet.amount,
+        _ => fail!(
+            GameError::InvalidPhase,
+            "Streak cover can only be bought for an open bet."
+        ),
+    };
+    ensure!(
+        state.covered_bets.get(&context.sender).is_none(),
+        GameError::AlreadyDone,
+        "The bet is already covered."
+    );
+    let streak = state
+        .player_stats
+        .get(&context.sender)
+        .map_or(0, |stats| stats.current_win_streak);
+    ensure!(
+        streak >= config.min_streak,
+        GameError::Ineligible,
+        "The win streak is too short to be covered."
+    );
+
+    let cover = config.quote(stake, streak);
+    ensure!(
+        state.available_balance(&context.sender) >= cover.premium,
+        GameError::InsufficientFunds,
+        "Insufficient balance to pay the premium."
+    );
+    let balance = state.user_balances.get(&context.sender).unwrap_or(0);
+    state.set_balance(context.sender, balance - cover.premium);
+    state.streak_cover_pool.collect(cover.premium);
+    state.covered_bets.insert(context.sender, cover);
+    (state, vec![], vec![])
+}
+
+
+/// Allow or disallow `custodian` to play on behalf of end users without chain addresses. A
+/// disallowed custodian keeps its end users' sub-ledgers but cannot move them until allowed again.
+/// Only callable by the owner.
+#[action(shortname = 0x35, zk = true)]
+pub fn set_custodian(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    custodian: Address,
+    allowed: bool,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    match state.custody_accounts.get_mut(&custodian) {
+        Some(account) => account.active = allowed,
+        None if allowed => {
+            state.custody_accounts.insert(custodian, CustodyAccount::new());
+        }
+        None => {}
+    }
+    (state, vec![], vec![])
+}
+
+/// Move `amount` of the custodian's available balance into the sub-ledger of `end_user`.
+#[action(shortname = 0x36, zk = true)]
+pub fn custody_allocate(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    end_user: u128,
+    amount: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_not_paused();
+    let proxy = state.custody_proxy(&context.sender, end_user);
+    state.assert_not_play_money(&context.sender);
+    ensure!(
+        state.available_balance(&context.sender) >= amount,
+        GameError::InsufficientFunds,
+        "Insufficient balance to allocate."
+    );
+    let balance = state.user_balances.get(&context.sender).unwrap_or(0);
+    state.set_balance(context.sender, balance - amount);
+    state.adjust_balance(proxy, amount);
+    (state, vec![], vec![])
+}
+
+/// Move `amount` from the sub-ledger of `end_user` back into the custodian's balance, for
+/// example before withdrawing it for the end user.
+#[action(shortname = 0x37, zk = true)]
+pub fn custody_release(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    end_user: u128,
+    amount: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_not_paused();
+    let proxy = state.custody_proxy(&context.sender, end_user);
+    let balance = state.user_balances.get(&proxy).unwrap_or(0);
+    ensure!(
+        balance >= amount,
+        GameError::InsufficientFunds,
+        "Insufficient sub-ledger balance to release."
+    );
+    state.set_balance(proxy, balance - amount);
+    state.adjust_balance(context.sender, amount);
+    (state, vec![], vec![])
+}
+
+/// Bet on a coin flip for `end_user`, paid from their sub-ledger.
+#[action(shortname = 0x38, zk = true)]
+pub fn custodial_bet(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    end_user: u128,
+    bet_amount: u64,
+    choice: PlayerChoice,
+    nonce: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let proxy = state.custody_proxy(&context.sender, end_user);
+    ensure!(
+        state.available_balance(&proxy) >= bet_amount,
+        GameError::InsufficientFunds,
+        "Insufficient sub-ledger balance to bet."
+    );
+    let player_bet = PlayerBet {
+        amount: bet_amount,
+        choice: Some(choice),
+        secret_amount: false,
+        multi_flip: None,
+        dice: None,
+        roulette: None,
+        beneficiary: None,
+    };
+    let token = state.token_address;
+    let proxied = on_behalf_of(&context, proxy);
+    let event_groups = place_bet(&proxied, &mut state, nonce, player_bet, token, None);
+    (state, event_groups, vec![])
+}
+
+/// Flip the coin of the open bet of `end_user`.
+#[action(shortname = 0x39, zk = true)]
+pub fn custodial_flip(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    end_user: u128,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let proxy = state.custody_proxy(&context.sender, end_user);
+    flip_coin(on_behalf_of(&context, proxy), state, zk_state)
+}
+
+/// Credit everything owed to `end_user` for their completed game to their sub-ledger.
+#[action(shortname = 0x3A, zk = true)]
+pub fn custodial_claim(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    end_user: u128,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_not_paused();
+    let proxy = state.custody_proxy(&context.sender, end_user);
+    state.finalize_expired_challenge(&proxy, context.block_production_time);
+    ensure!(
+        state.game_phases.get(&proxy) == Some(GamePhase::Done {}),
+        GameError::InvalidPhase,
+        "Payout can only occur after the game has completed."
+    );
+    // Custodial bets are staked and paid out in the default token only
+    let (payout, _) = take_payout(&mut state, &proxy);
+    state.adjust_balance(proxy, payout);
+    let events = settlement_events(&mut state);
+    let changes = settlement_attestations(&mut state);
+    (state, events, changes)
+}
+
+/// The context of an action performed by a custodian or integrator, as seen by the player it acts
+/// for.
+fn on_behalf_of(context: &ContractContext, proxy: Address) -> ContractContext {
+    let mut proxied = context.clone();
+    proxied.sender = proxy;
+    proxied
+}
+
+/// Open a tournament charging `entry_fee`, running for `duration_millis` and splitting its prize
+/// pool among the best players by `prize_split_bps`, best first. Only callable by the owner, once
+/// the previous tournament has been settled.
+#[action(shortname = 0x3B, zk = true)]
+pub fn open_tournament(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    entry_fee: u64,
+    duration_millis: i64,
+    prize_split_bps: Vec<u32>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    ensure!(
+        entry_fee > 0,
+        GameError::InvalidArgument,
+        "The entry fee must be positive."
+    );
+    ensure!(
+        duration_millis > 0,
+        GameError::InvalidArgument,
+        "The tournament must last a positive duration."
+    );
+    ensure!(
+        !prize_split_bps.is_empty() && prize_split_bps.iter().sum::<u32>() <= 10_000,
+        GameError::InvalidArgument,
+        "The prize split must award between one rank and the whole pool."
+    );
+    let next_id = match &state.tournament {
+        None => 0,
+        Some(tournament) => {
+            ensure!(
+                tournament.settled,
+                GameError::InvalidPhase,
+                "The previous tournament has not been settled yet."
+            );
+            tournament.id + 1
+        }
+    };
+
+    state.tournament = Some(Tournament::new(
+        next_id,
+        entry_fee,
+        context.block_production_time,
+        context.block_production_time + duration_millis,
+        prize_split_bps,
+    ));
+    (state, vec![], vec![])
+}
+
+/// Register for the running tournament. The entry fee is escrowed through the token contract
+/// and the player only registered once the transfer succeeds.
+#[action(shortname = 0x3C, zk = true)]
+pub fn register_for_tournament(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_accepting_bets();
+    state.close_import();
+    state.assert_allowlisted(&context.sender);
+    let tournament = state
+        .tournament
+        .as_ref()
+        .or_fail(GameError::NotFound, "No tournament has been opened.");
+    ensure!(
+        tournament.is_running(context.block_production_time),
+        GameError::InvalidPhase,
+        "The tournament is not open for registration."
+    );
+    ensure!(
+        !tournament.is_registered(&context.sender),
+        GameError::AlreadyDone,
+        "Already registered for the tournament."
+    );
+
+    let mut event_group = EventGroup::builder();
+    token_client::transfer_from(
+        &mut event_group,
+        &state.gas_costs,
+        state.token_address,
+        context.sender,
+        context.contract_address,
+        tournament.entry_fee,
+    );
+    token_client::register_callback(&mut event_group, &state.gas_costs, 0x09)
+        .argument(context.sender)
+        .argument(tournament.id)
+        .done();
+
+    (state, vec![event_group.build()], vec![])
+}
+
+/// Callback registering a player whose entry fee was escrowed. Fees arriving after the
+/// tournament closed, or for an earlier tournament, are refunded instead.
+#[callback(shortname = 0x09, zk = true)]
+pub fn tournament_registration_callback(
+    context: ContractContext,
+    callback_ctx: CallbackContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    player: Address,
+    tournament_id: u32,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    ensure!(
+        callback_ctx.results[0].succeeded,
+        GameError::TransferFailed,
+        "Token transfer failed, the player was not registered."
+    );
+
+    let tournament = state.tournament.as_mut().unwrap();
+    let entry_fee = tournament.entry_fee;
+    if tournament.id == tournament_id
+        && tournament.is_running(context.block_production_time)
+        && !tournament.is_registered(&player)
+    {
+        tournament.register(player);
+        return (state, vec![], vec![]);
+    }
+
+    let mut event_group = EventGroup::builder();
+    token_client::transfer(
+        &mut event_group,
+        &state.gas_costs,
+        state.token_address,
+        player,
+        entry_fee,
+    );
+    (state, vec![event_group.build()], vec![])
+}
+
+/// Pay the prizes of the tournament once it has closed. Callable by anyone. The part of the
+/// prize pool not won by anyone goes to the house bankroll.
+#[action(shortname = 0x3D, zk = true)]
+pub fn settle_tournament(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_not_paused();
+    let tournament = state
+        .tournament
+        .as_mut()
+        .or_fail(GameError::NotFound, "No tournament has been opened.");
+    ensure!(
+        !tournament.settled,
+        GameError::InvalidPhase,
+        "The tournament has already been settled."
+    );
+    ensure!(
+        context.block_production_time >= tournament.closes_at,
+        GameError::InvalidPhase,
+        "The tournament has not closed yet."
+    );
+
+    let (prizes, unawarded) = tournament.settle();
+    state.house_bankroll += unawarded;
+    if prizes.is_empty() {
+        return (state, vec![], vec![]);
+    }
+
+    let mut event_group = EventGroup::builder();
+    for prize in prizes {
+        token_client::transfer(
+            &mut event_group,
+            &state.gas_costs,
+            state.token_address,
+            prize.player,
+            prize.amount,
+        );
+    }
+    (state, vec![event_group.build()], vec![])
+}
+
+/// Register the sender as referred by `referrer`, who then earns a commission on every bet the
+/// sender places in the contract's default token. A player can be referred only once.
+#[action(shortname = 0x3E, zk = true)]
+pub fn register_referral(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    referrer: Address,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    ensure!(
+        referrer != context.sender,
+        GameError::InvalidArgument,
+        "Players cannot refer themselves."
+    );
+    ensure!(
+        state.referrers.get(&context.sender).is_none(),
+        GameError::AlreadyDone,
+        "The player has already been referred."
+    );
+    state.referrers.insert(context.sender, referrer);
+    (state, vec![], vec![])
+}
+
+/// Set the commission paid to referrers, in basis points of their referees' bets. Only callable
+/// by the owner.
+#[action(shortname = 0x3F, zk = true)]
+pub fn set_referral_commission(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    commission_bps: u32,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    ensure!(
+        commission_bps <= MAX_REFERRAL_COMMISSION_BPS,
+        GameError::InvalidArgument,
+        "The referral commission cannot exceed 10% of the bet."
+    );
+    state.referral_commission_bps = commission_bps;
+    (state, vec![], vec![])
+}
+
+/// Transfer the sender's earned referral commissions to them.
+#[action(shortname = 0x40, zk = true)]
+pub fn claim_referral_rewards(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_not_paused();
+    let rewards = state
+        .referral_rewards
+        .get(&context.sender)
+        .filter(|rewards| *rewards > 0)
+        .or_fail(GameError::NotFound, "No referral rewards to claim.");
+    state.referral_rewards.remove(&context.sender);
+
+    let mut event_group = EventGroup::builder();
+    token_client::transfer(
+        &mut event_group,
+        &state.gas_costs,
+        state.token_address,
+        context.sender,
+        rewards,
+    );
+    token_client::register_callback(&mut event_group, &state.gas_costs, 0x0A)
+        .argument(context.sender)
+        .argument(rewards)
+        .done();
+
+    (state, vec![event_group.build()], vec![])
+}
+
+/// Restore the claimed referral rewards if their transfer failed.
+#[callback(shortname = 0x0A, zk = true)]
+pub fn claim_referral_rewards_callback(
+    context: ContractContext,
+    callback_ctx: CallbackContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    referrer: Address,
+    amount: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    if !callback_ctx.results[0].succeeded {
+        let rewards = state.referral_rewards.get(&referrer).unwrap_or(0);
+        state.referral_rewards.insert(referrer, rewards + amount);
+    }
+    (state, vec![], vec![])
+}
+
+/// Set how many loyalty points are redeemed for each token of free-bet credit, or disable
+/// redemption with zero. Only callable by the owner.
+#[action(shortname = 0x41, zk = true)]
+pub fn set_free_bet_conversion(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    points_per_credit: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    state.points_per_free_bet_credit = points_per_credit;
+    (state, vec![], vec![])
+}
+
+/// Redeem `points` of the sender's loyalty points for free-bet credits. Points that do not add up
+/// to a whole credit are kept.
+#[action(shortname = 0x42, zk = true)]
+pub fn redeem_loyalty_points(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    points: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_not_paused();
+    ensure!(
+        state.points_per_free_bet_credit > 0,
+        GameError::Unavailable,
+        "Loyalty points cannot be redeemed."
+    );
+    let balance = state.loyalty_points.get(&context.sender).unwrap_or(0);
+    ensure!(
+        points <= balance,
+        GameError::InsufficientFunds,
+        "Insufficient loyalty points."
+    );
+
+    let credits = points / state.points_per_free_bet_credit;
+    ensure!(
+        credits > 0,
+        GameError::Ineligible,
+        "Too few points for a free-bet credit."
+    );
+    let redeemed = credits * state.points_per_free_bet_credit;
+    state.loyalty_points.insert(context.sender, balance - redeemed);
+    let existing = state.free_bet_credits.get(&context.sender).unwrap_or(0);
+    state.free_bet_credits.insert(context.sender, existing + credits);
+    (state, vec![], vec![])
+}
+
+/// Set how many randomness contributions a round accepts, one of which is kept for the bettor.
+/// Only callable by the owner or an operator.
+#[action(shortname = 0x43, zk = true)]
+pub fn set_max_round_contributions(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    max_contributions: u32,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_operator(&context.sender);
+    // Strict fairness needs one contribution from the bettor and one from someone else
+    ensure!(
+        max_contributions >= 2,
+        GameError::InvalidArgument,
+        "A round must accept at least two contributions."
+    );
+    state.max_round_contributions = max_contributions;
+    (state, vec![], vec![])
+}
+
+/// Enable or disable allowlist mode, in which only allowlisted players may bet. Only callable by
+/// the owner.
+#[action(shortname = 0x44, zk = true)]
+pub fn set_allowlist_mode(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    enabled: bool,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    state.allowlist_enabled = enabled;
+    (state, vec![], vec![])
+}
+
+/// Designate the address allowed to manage the allowlist besides the owner, or remove it. Only
+/// callable by the owner.
+#[action(shortname = 0x45, zk = true)]
+pub fn set_allowlist_registrar(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    registrar: Option<Address>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    state.allowlist_registrar = registrar;
+    (state, vec![], vec![])
+}
+
+/// Add players to the allowlist. Only callable by the owner or the registrar.
+#[action(shortname = 0x46, zk = true)]
+pub fn add_to_allowlist(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    players: Vec<Address>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    assert_allowlist_manager(&state, &context.sender);
+    for player in players {
+        state.allowlist.insert(player, true);
+    }
+    (state, vec![], vec![])
+}
+
+/// Remove players from the allowlist. Open games are left to finish. Only callable by the owner
+/// or the registrar.
+#[action(shortname = 0x47, zk = true)]
+pub fn remove_from_allowlist(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    players: Vec<Address>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    assert_allowlist_manager(&state, &context.sender);
+    for player in players {
+        state.allowlist.remove(&player);
+    }
+    (state, vec![], vec![])
+}
+
+fn assert_allowlist_manager(state: &CoinFlipState, sender: &Address) {
+    ensure!(
+        *sender == state.owner || state.allowlist_registrar == Some(*sender),
+        GameError::Unauthorized,
+        "Only the owner or the registrar can manage the allowlist."
+    );
+}
+
+/// Notify `target` of the notifications matching its filter, replacing any earlier registration
+/// of the same contract. Only callable by the owner.
+#[action(shortname = 0x48, zk = true)]
+pub fn register_webhook_target(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    target: WebhookTarget,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    state
+        .webhook_targets
+        .retain(|existing| existing.target_address != target.target_address);
+    state.webhook_targets.push(target);
+    (state, vec![], vec![])
+}
+
+/// Stop notifying the target contract. Only callable by the owner.
+#[action(shortname = 0x49, zk = true)]
+pub fn remove_webhook_target(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    target_address: Address,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    state
+        .webhook_targets
+        .retain(|existing| existing.target_address != target_address);
+    (state, vec![], vec![])
+}
+
+/// Set the smallest winnings reported as a large win, or disable the notification with zero.
+/// Only callable by the owner.
+#[action(shortname = 0x4A, zk = true)]
+pub fn set_large_win_threshold(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    threshold: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    state.large_win_threshold = threshold;
+    (state, vec![], vec![])
+}
+
+/// Block addresses from betting, e.g. after exploit attempts. Only callable by the owner or an
+/// operator.
+#[action(shortname = 0x4B, zk = true)]
+pub fn block_addresses(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    addresses: Vec<Address>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_operator(&context.sender);
+    for address in addresses {
+        state.blocked_addresses.insert(address, true);
+    }
+    (state, vec![], vec![])
+}
+
+/// Lift the block of addresses. Self-exclusions are not affected. Only callable by the owner or
+/// an operator.
+#[action(shortname = 0x4C, zk = true)]
+pub fn unblock_addresses(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    addresses: Vec<Address>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_operator(&context.sender);
+    for address in addresses {
+        state.blocked_addresses.remove(&address);
+    }
+    (state, vec![], vec![])
+}
+
+/// Exclude the sender from betting for `duration_millis`. An exclusion can be extended but
+/// never shortened, not even by the owner.
+#[action(shortname = 0x4D, zk = true)]
+pub fn self_exclude(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    duration_millis: i64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    ensure!(
+        duration_millis > 0,
+        GameError::InvalidArgument,
+        "The exclusion must last a positive duration."
+    );
+    let requested_until = context.block_production_time.saturating_add(duration_millis);
+    let current_until = state.self_exclusions.get(&context.sender).unwrap_or(0);
+    state
+        .self_exclusions
+        .insert(context.sender, requested_until.max(current_until));
+    (state, vec![], vec![])
+}
+
+/// Stop accepting new bets while letting games in flight finish. Only callable by the owner or
+/// an operator.
+#[action(shortname = 0x4E, zk = true)]
+pub fn pause_new_bets(
+    context: ContractContext,
+    state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    set_pause_state(&context, state, PauseState::NewBetsPaused {})
+}
+
+/// Pause every player action. Only callable by the owner or an operator.
+#[action(shortname = 0x4F, zk = true)]
+pub fn pause_all(
+    context: ContractContext,
+    state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    set_pause_state(&context, state, PauseState::AllPaused {})
+}
+
+/// Lift any pause. Only callable by the owner or an operator.
+#[action(shortname = 0x50, zk = true)]
+pub fn unpause(
+    context: ContractContext,
+    state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    set_pause_state(&context, state, PauseState::Running {})
+}
+
+/// Change the pause state, notifying the webhook targets subscribed to the circuit breaker.
+fn set_pause_state(
+    context: &ContractContext,
+    mut state: CoinFlipState,
+    pause_state: PauseState,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_operator(&context.sender);
+    ensure!(
+        state.sunset.is_none(),
+        GameError::Unavailable,
+        "The contract is being sunset."
+    );
+    let was_running = state.pause_state == PauseState::Running {};
+    state.pause_state = pause_state;
+
+    let is_running = pause_state == PauseState::Running {};
+    if was_running == is_running {
+        return (state, vec![], vec![]);
+    }
+    let notification = Notification::CircuitBreaker {
+        tripped: !is_running,
+    };
+    let events = webhooks::notification_event(
+        &state.webhook_targets,
+        &state.gas_costs,
+        vec![notification],
+    );
+    (state, events.into_iter().collect(), vec![])
+}
+
+/// Enable instant mode as configured, or disable it when `config` is absent. Only callable by the
+/// owner.
+#[action(shortname = 0x51, zk = true)]
+pub fn set_instant_mode(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    config: Option<InstantConfig>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    if let Some(config) = &config {
+        ensure!(
+            config.max_stake > 0,
+            GameError::InvalidArgument,
+            "The maximum instant stake must be positive."
+        );
+        ensure!(
+            config.min_commitment_age_millis > 0,
+            GameError::InvalidArgument,
+            "Instant entropy must be committed before it is used."
+        );
+    }
+    state.instant_config = config;
+    (state, vec![], vec![])
+}
+
+/// Commit to future instant entropy values by their hashes. Only callable by the provider.
+#[action(shortname = 0x52, zk = true)]
+pub fn commit_instant_entropy(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    commitments: Vec<Hash>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    assert_instant_provider(&state, &context.sender);
+    for commitment in commitments {
+        state.instant_entropy.push(InstantEntropy {
+            commitment,
+            committed_at: context.block_production_time,
+            value: None,
+        });
+    }
+    (state, vec![], vec![])
+}
+
+/// Reveal the oldest instant entropy values, in commitment order, settling the bets waiting for
+/// them. Revealed values are used up, whether a bet waited for them or not. Only callable by the
+/// provider.
+#[action(shortname = 0x53, zk = true)]
+pub fn reveal_instant_entropy(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    values: Vec<Hash>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    assert_instant_provider(&state, &context.sender);
+    for value in values {
+        ensure!(
+            !state.instant_entropy.is_empty(),
+            GameError::NotFound,
+            "More values than unrevealed commitments."
+        );
+        let mut entropy = state.instant_entropy.remove(0);
+        entropy.reveal(value);
+        if !state.instant_bets.is_empty() {
+            let bet = state.instant_bets.remove(0);
+            settle_instant_bet(&mut state, &bet, entropy.value.as_ref().unwrap(), &context);
+        }
+    }
+    let events = settlement_events(&mut state);
+    let changes = settlement_attestations(&mut state);
+    (state, events, changes)
+}
+
+/// Settle the instant bet with its revealed entropy value, unless its game was voided or expired
+/// while waiting for it.
+fn settle_instant_bet(
+    state: &mut CoinFlipState,
+    bet: &InstantBet,
+    value: &Hash,
+    context: &ContractContext,
+) {
+    let player = bet.player;
+    let waiting = state.game_ids.get(&player) == Some(bet.game_id)
+        && state.game_phases.get(&player) == Some(GamePhase::FlipCoin {});
+    if !waiting {
+        return;
+    }
+    let choice = state.player_bets.get(&player).and_then(|bet| bet.choice).unwrap();
+    let now = context.block_production_time;
+    let folded = fold_randomness(state.round_salt_word(&player), [salt_word(value)]);
+    let flip_result = lands_heads(folded);
+    state.flip_results.insert(player, flip_result);
+    state.set_phase(player, GamePhase::Done {}, now);
+    let player_won = choice.matches_flip(flip_result);
+    let winner = if player_won {
+        player
+    } else {
+        context.contract_address
+    };
+    state.winners.insert(player, winner);
+    state.record_settlement(&player, player_won, now);
+}
+
+/// Bet on a coin flip settled by the oldest instant entropy value no bet waits for yet, once the
+/// provider reveals it. The stake is paid from the sender's balance, and the winnings are claimed
+/// with `payout_winner` as usual.
+#[action(shortname = 0x54, zk = true)]
+pub fn instant_flip(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    bet_amount: u64,
+    choice: PlayerChoice,
+    nonce: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let config = state
+        .instant_config
+        .clone()
+        .or_fail(GameError::Unavailable, "Instant mode is disabled.");
+    ensure!(
+        bet_amount <= config.max_stake,
+        GameError::BetRejected,
+        "The stake exceeds the maximum instant stake."
+    );
+    ensure!(
+        state.available_balance(&context.sender) >= bet_amount,
+        GameError::InsufficientFunds,
+        "Instant bets are paid from the deposited balance."
+    );
+    let now = context.block_production_time;
+    // The bet takes the value after those earlier bets wait for, which is still secret
+    let slot = state.instant_bets.len();
+    ensure!(
+        state
+            .instant_entropy
+            .get(slot)
+            .is_some_and(|entropy| entropy.is_usable(&config, now)),
+        GameError::NotFound,
+        "No instant entropy is available."
+    );
+
+    let player_bet = PlayerBet {
+        amount: bet_amount,
+        choice: Some(choice),
+        secret_amount: false,
+        multi_flip: None,
+        dice: None,
+        roulette: None,
+        beneficiary: None,
+    };
+    let token = state.token_address;
+    let events = place_bet(&context, &mut state, nonce, player_bet, token, None);
+    let game_id = state.game_ids.get(&context.sender).unwrap();
+    state.instant_bets.push(InstantBet {
+        player: context.sender,
+        game_id,
+    });
+    (state, events, vec![])
+}
+
+fn assert_instant_provider(state: &CoinFlipState, sender: &Address) {
+    let config = state
+        .instant_config
+        .as_ref()
+        .or_fail(GameError::Unavailable, "Instant mode is disabled.");
+    ensure!(
+        *sender == config.provider,
+        GameError::Unauthorized,
+        "Only the instant entropy provider can perform this action."
+    );
+}
+
+/// Nominate `new_owner` to take over the contract. Ownership only changes once they accept, so a
+/// mistyped address cannot lock the owner out. Only callable by the owner.
+#[action(shortname = 0x56, zk = true)]
+pub fn transfer_ownership(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    new_owner: Address,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    state.pending_owner = Some(new_owner);
+    (state, vec![], vec![])
+}
+
+/// Take over ownership as the nominated owner.
+#[action(shortname = 0x57, zk = true)]
+pub fn accept_ownership(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    ensure!(
+        state.pending_owner == Some(context.sender),
+        GameError::Unauthorized,
+        "Only the nominated owner can accept ownership."
+    );
+    state.pending_owner = None;
+    state.owner = context.sender;
+    (state, vec![], vec![])
+}
+
+/// Grant `role` to `address`. Only callable by the owner.
+#[action(shortname = 0x58, zk = true)]
+pub fn grant_role(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    address: Address,
+    role: Role,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    ensure!(
+        role != Role::Owner {},
+        GameError::InvalidArgument,
+        "Ownership is handed over with transfer_ownership."
+    );
+    let mut roles = state.role_grants.get(&address).cloned().unwrap_or_default();
+    if !roles.contains(&role) {
+        roles.push(role);
+    }
+    state.role_grants.insert(address, roles);
+    (state, vec![], vec![])
+}
+
+/// Revoke `role` from `address`. Only callable by the owner.
+#[action(shortname = 0x59, zk = true)]
+pub fn revoke_role(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    address: Address,
+    role: Role,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    let mut roles = state.role_grants.remove(&address).unwrap_or_default();
+    roles.retain(|granted| *granted != role);
+    if !roles.is_empty() {
+        state.role_grants.insert(address, roles);
+    }
+    (state, vec![], vec![])
+}
+
+/// Withdraw `amount` of the house bankroll in `token` to the sender. Bankroll backing the
+/// potential payouts of open bets, or deployed to the yield contract, cannot be withdrawn. Only
+/// callable by a treasurer.
+#[action(shortname = 0x5A, zk = true)]
+pub fn withdraw_house_funds(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    amount: u64,
+    token: Address,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_treasurer(&context.sender);
+    ensure!(
+        amount > 0 && amount <= state.free_bankroll(&token),
+        GameError::InsufficientFunds,
+        "The withdrawal must be positive and leave the open bets covered."
+    );
+    if token == state.token_address {
+        state.bankroll_stakes.withdraw_house(amount, state.house_bankroll);
+    }
+    state.debit_bankroll(&token, amount);
+
+    let mut event_group = EventGroup::builder();
+    token_client::transfer(
+        &mut event_group,
+        &state.gas_costs,
+        token,
+        context.sender,
+        amount,
+    );
+    token_client::register_callback(&mut event_group, &state.gas_costs, 0x0B)
+        .argument(amount)
+        .argument(token)
+        .done();
+
+    (state, vec![event_group.build()], vec![])
+}
+
+/// Return the withdrawn amount to the bankroll if its transfer failed.
+#[callback(shortname = 0x0B, zk = true)]
+pub fn withdraw_house_funds_callback(
+    context: ContractContext,
+    callback_ctx: CallbackContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    amount: u64,
+    token: Address,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    if !callback_ctx.results[0].succeeded {
+        if token == state.token_address {
+            state.bankroll_stakes.fund_house(amount, state.house_bankroll);
+        }
+        state.credit_bankroll(&token, amount);
+    }
+    (state, vec![], vec![])
+}
+
+/// Stake `amount` of the default token in the house bankroll for a pro-rata share of its profits
+/// and losses.
+#[action(shortname = 0x5B, zk = true)]
+pub fn stake_bankroll(
+    context: ContractContext,
+    state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    amount: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_not_paused();
+    ensure!(
+        amount > 0,
+        GameError::InvalidArgument,
+        "The stake must be positive."
+    );
+
+    let mut event_group = EventGroup::builder();
+    token_client::transfer_from(
+        &mut event_group,
+        &state.gas_costs,
+        state.token_address,
+        context.sender,
+        context.contract_address,
+        amount,
+    );
+    token_client::register_callback(&mut event_group, &state.gas_costs, 0x0C)
+        .argument(context.sender)
+        .argument(amount)
+        .done();
+
+    (state, vec![event_group.build()], vec![])
+}
+
+/// Mint the staker's shares once their stake has been transferred, priced at the bankroll it
+/// joins.
+#[callback(shortname = 0x0C, zk = true)]
+pub fn stake_bankroll_callback(
+    context: ContractContext,
+    callback_ctx: CallbackContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    staker: Address,
+    amount: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    ensure!(
+        callback_ctx.results[0].succeeded,
+        GameError::TransferFailed,
+        "Token transfer failed, nothing was staked."
+    );
+    state.bankroll_stakes.stake(staker, amount, state.house_bankroll);
+    state.house_bankroll += amount;
+    (state, vec![], vec![])
+}
+
+/// Redeem `shares` of the sender's stake for their part of the bankroll. Bankroll backing the
+/// potential payouts of open bets cannot be redeemed until they are settled.
+#[action(shortname = 0x5C, zk = true)]
+pub fn unstake_bankroll(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    shares: u128,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_not_paused();
+    let amount = state
+        .bankroll_stakes
+        .unstake(&context.sender, shares, state.house_bankroll);
+    ensure!(
+        amount <= state.free_bankroll(&state.token_address),
+        GameError::InsufficientFunds,
+        "The bankroll is backing open bets, try again once they are settled."
+    );
+    state.house_bankroll -= amount;
+
+    let mut event_group = EventGroup::builder();
+    token_client::transfer(
+        &mut event_group,
+        &state.gas_costs,
+        state.token_address,
+        context.sender,
+        amount,
+    );
+    token_client::register_callback(&mut event_group, &state.gas_costs, 0x0D)
+        .argument(context.sender)
+        .argument(shares)
+        .argument(amount)
+        .done();
+
+    (state, vec![event_group.build()], vec![])
+}
+
+/// Return the redeemed shares and their amount to the bankroll if the transfer failed.
+#[callback(shortname = 0x0D, zk = true)]
+pub fn unstake_bankroll_callback(
+    context: ContractContext,
+    callback_ctx: CallbackContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    staker: Address,
+    shares: u128,
+    amount: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    if !callback_ctx.results[0].succeeded {
+        state.bankroll_stakes.restore(staker, shares);
+        state.house_bankroll += amount;
+    }
+    (state, vec![], vec![])
+}
+
+/// Only the owner may upgrade the contract, and only once the upgrade time lock has passed.
+#[upgrade_is_allowed]
+pub fn is_upgrade_allowed(
+    context: ContractContext,
+    state: CoinFlipState,
+    old_contract_hashes: ContractHashes,
+    new_contract_hashes: ContractHashes,
+    new_contract_rpc: Vec<u8>,
+) -> bool {
+    context.sender == state.owner
+        && state.upgrade_schedule.is_allowed(context.block_production_time)
+}
+
+/// Carry the state over from the previous release, keeping player balances, open games and the
+/// bankroll.
+#[upgrade]
+pub fn upgrade(context: ContractContext, state: CoinFlipStateV1) -> CoinFlipState {
+    upgrade::migrate(state, context.block_production_time)
+}
+
+/// Schedule an upgrade, which becomes allowed once the upgrade time lock has passed. Only
+/// callable by the owner.
+#[action(shortname = 0x5D, zk = true)]
+pub fn schedule_upgrade(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    state.upgrade_schedule.scheduled_at = Some(context.block_production_time);
+    (state, vec![], vec![])
+}
+
+/// Cancel the scheduled upgrade. Only callable by the owner.
+#[action(shortname = 0x5E, zk = true)]
+pub fn cancel_upgrade(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    state.upgrade_schedule.scheduled_at = None;
+    (state, vec![], vec![])
+}
+
+/// Raise how long in advance upgrades must be scheduled. The time lock can never be lowered, so
+/// players can rely on the notice it gives them. Only callable by the owner.
+#[action(shortname = 0x5F, zk = true)]
+pub fn set_upgrade_timelock(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    timelock_millis: i64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    ensure!(
+        timelock_millis >= state.upgrade_schedule.timelock_millis,
+        GameError::InvalidArgument,
+        "The upgrade time lock can only be raised."
+    );
+    state.upgrade_schedule.timelock_millis = timelock_millis;
+    (state, vec![], vec![])
+}
+
+/// Start winding the contract down: new bets stop at once, and games in flight have
+/// `grace_millis` to resolve before `sweep_sunset` times them out. Cannot be undone. Only callable
+/// by the owner.
+#[action(shortname = 0x60, zk = true)]
+pub fn sunset(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    grace_millis: i64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    ensure!(
+        state.sunset.is_none(),
+        GameError::InvalidPhase,
+        "The contract is already being sunset."
+    );
+    ensure!(
+        grace_millis >= 0,
+        GameError::InvalidArgument,
+        "The grace period cannot be negative."
+    );
+    let sunset = Sunset::new(context.block_production_time, grace_millis);
+    let notification = Notification::Sunset {
+        closes_at: sunset.closes_at,
+    };
+    state.sunset = Some(sunset);
+    state.pause_state = PauseState::NewBetsPaused {};
+
+    let events = webhooks::notification_event(
+        &state.webhook_targets,
+        &state.gas_costs,
+        vec![notification],
+    );
+    (state, events.into_iter().collect(), vec![])
+}
+
+/// Return what the contract holds once every game has resolved or the grace period is over,
+/// handling at most `max_accounts` accounts per call. Callable by anyone.
+///
+/// Unresolved games are voided like in `void_game`, refunding their escrowed stakes together with
+/// the charges taken on them, and won flips still drawing a bonus multiplier are settled at their
+/// base winnings. Then unclaimed winnings, deposited balances and bankroll stakes are paid out.
+/// Once no account is left, the remaining house funds go to the owner and the sunset is complete.
+#[action(shortname = 0x61, zk = true)]
+pub fn sweep_sunset(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    max_accounts: u32,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let now = context.block_production_time;
+    ensure!(
+        max_accounts > 0,
+        GameError::InvalidArgument,
+        "A sweep must handle at least one account."
+    );
+    let sunset = state
+        .sunset
+        .as_ref()
+        .or_fail(GameError::InvalidPhase, "The contract is not being sunset.");
+    ensure!(
+        !sunset.completed,
+        GameError::InvalidPhase,
+        "The sunset is already complete."
+    );
+    let outstanding_games = state.outstanding_games(max_accounts);
+    ensure!(
+        outstanding_games.is_empty() || now >= sunset.closes_at,
+        GameError::InvalidPhase,
+        "Games are still in flight and the grace period is not over."
+    );
+
+    let mut event_group = EventGroup::builder();
+    let mut accounts = 0;
+    let mut transfers = 0;
+    let mut refunds = vec![];
+    let mut zk_changes = vec![];
+
+    for player in outstanding_games {
+        if accounts == max_accounts {
+            break;
+        }
+        // Opened results are no longer disputed once the contract closes
+        if state.game_phases.get(&player) == Some(GamePhase::Challengeable {}) {
+            state.finalize_game(&player, now);
+            accounts += 1;
+            continue;
+        }
+        // The flip was won, so only the bonus multiplier is forgone
+        if state.game_phases.get(&player) == Some(GamePhase::BonusRound {}) {
+            state.winners.insert(player, player);
+            state.finalize_game(&player, now);
+            accounts += 1;
+            continue;
+        }
+        // Only stakes that reached the FlipCoin phase were escrowed. They are voided like in
+        // `void_game`, with sponsored and session stakes going back to their budgets and
+        // sessions, which are returned below
+        if state.game_phases.get(&player) == Some(GamePhase::FlipCoin {}) {
+            let (events, changes) = refund_escrowed_game(&mut state, &zk_state, &player);
+            refunds.extend(events);
+            zk_changes.extend(changes);
+        } else {
+            state.ledger.refund(&player, 0);
+            let variables = randomness_variables_of(&zk_state, &player);
+            if !variables.is_empty() {
+                zk_changes.push(ZkStateChange::DeleteVariables {
+                    variables_to_delete: variables,
+                });
+            }
+        }
+        // A stake still being escrowed is returned by the escrow callback once it arrives
+        if let Some(escrow) = state.pending_escrows.get(&player) {
+            let token = state.bet_token_of(&player);
+            state.expired_escrows.insert(player, ExpiredEscrow { escrow, token });
+        }
+        state.clear_player_record(&player, now);
+        state.set_phase(player, GamePhase::Start {}, now);
+        accounts += 1;
+    }
+
+    let winners: Vec<Address> = state
+        .winners
+        .iter()
+        .filter(|(player, winner)| player == winner)
+        .map(|(player, _)| player)
+        .take((max_accounts - accounts) as usize)
+        .collect();
+    for player in winners {
+        if accounts == max_accounts {
+            break;
+        }
+        let payee = state.payee_of(&player);
+        let (payout, converted_payout) = take_payout(&mut state, &player);
+        state.winners.remove(&player);
+        if payout > 0 {
+            token_client::transfer(
+                &mut event_group,
+                &state.gas_costs,
+                state.bet_token_of(&player),
+                payee,
+                payout,
+            );
+            transfers += 1;
+        }
+        if converted_payout > 0 {
+            let token = state.payout_token_of(&player);
+            token_client::transfer(
+                &mut event_group,
+                &state.gas_costs,
+                token,
+                payee,
+                converted_payout,
+            );
+            transfers += 1;
+        }
+        accounts += 1;
+    }
+
+    let users: Vec<Address> = state
+        .user_balances
+        .iter()
+        .map(|(user, _)| user)
+        .filter(|user| state.available_balance(user) > 0 && !state.is_play_money_account(user))
+        .take((max_accounts - accounts) as usize)
+        .collect();
+    for user in users {
+        if accounts == max_accounts {
+            break;
+        }
+        let available = state.available_balance(&user);
+        token_client::transfer(
+            &mut event_group,
+            &state.gas_costs,
+            state.token_address,
+            user,
+            available,
+        );
+        transfers += 1;
+        // Withdrawals in flight settle through their own callbacks
+        let balance = state.user_balances.get(&user).unwrap_or(0);
+        state.set_balance(user, balance - available);
+        accounts += 1;
+    }
+
+    let stakers: Vec<(Address, u128)> = state
+        .bankroll_stakes
+        .stakes
+        .iter()
+        .map(|(staker, shares)| (*staker, *shares))
+        .collect();
+    for (staker, shares) in stakers {
+        if accounts == max_accounts {
+            break;
+        }
+        let amount = state.bankroll_stakes.unstake(&staker, shares, state.house_bankroll);
+        state.house_bankroll -= amount;
+        if amount > 0 {
+            token_client::transfer(
+                &mut event_group,
+                &state.gas_costs,
+                state.token_address,
+                staker,
+                amount,
+            );
+            transfers += 1;
+        }
+        accounts += 1;
+    }
+
+    // Only a pass that found nothing left to return completes the sunset
+    if accounts == 0 {
+        ensure!(
+            state.yield_adapter.as_ref().map_or(true, |adapter| !adapter.is_in_use()),
+            GameError::InvalidPhase,
+            "The bankroll must be recalled from the yield contract first."
+        );
+        let house_funds = state.house_bankroll
+            + state.jackpot_pool
+            + state.streak_cover_pool.balance
+            + state.insurance.balance
+            + state.side_bet_pool.balance
+            + state.promotion.budget;
+        state.house_bankroll = 0;
+        state.jackpot_pool = 0;
+        state.streak_cover_pool.balance = 0;
+        state.insurance.balance = 0;
+        state.side_bet_pool.balance = 0;
+        state.promotion.budget = 0;
+        state.bankroll_stakes = StakingPool::new();
+        if house_funds > 0 {
+            token_client::transfer(
+                &mut event_group,
+                &state.gas_costs,
+                state.token_address,
+                state.owner,
+                house_funds,
+            );
+            transfers += 1;
+        }
+        let token_bankrolls: Vec<(Address, u64)> = state
+            .token_registry
+            .iter()
+            .map(|(token, config)| (*token, config.bankroll))
+            .filter(|(_, bankroll)| *bankroll > 0)
+            .collect();
+        for (token, bankroll) in token_bankrolls {
+            token_client::transfer(
+                &mut event_group,
+                &state.gas_costs,
+                token,
+                state.owner,
+                bankroll,
+            );
+            transfers += 1;
+            state.token_registry.get_mut(&token).unwrap().bankroll = 0;
+        }
+        let sponsor_budgets: Vec<(Address, u64)> = state
+            .sponsorships
+            .iter()
+            .map(|(sponsor, sponsorship)| (*sponsor, sponsorship.budget))
+            .filter(|(_, budget)| *budget > 0)
+            .collect();
+        for (sponsor, budget) in sponsor_budgets {
+            token_client::transfer(
+                &mut event_group,
+                &state.gas_costs,
+                state.token_address,
+                sponsor,
+                budget,
+            );
+            transfers += 1;
+            state.sponsorships.get_mut(&sponsor).unwrap().budget = 0;
+        }
+        let session_balances: Vec<(Address, u64)> = state
+            .bot_sessions
+            .iter()
+            .map(|(bot, session)| (*bot, session.balance))
+            .filter(|(_, balance)| *balance > 0)
+            .collect();
+        for (bot, balance) in session_balances {
+            token_client::transfer(
+                &mut event_group,
+                &state.gas_costs,
+                state.token_address,
+                bot,
+                balance,
+            );
+            transfers += 1;
+            state.bot_sessions.get_mut(&bot).unwrap().balance = 0;
+        }
+        state.sunset.as_mut().unwrap().completed = true;
+    }
+
+    let mut events = refunds;
+    if transfers > 0 {
+        events.push(event_group.build());
+    }
+    events.extend(settlement_events(&mut state));
+    zk_changes.extend(settlement_attestations(&mut state));
+    (state, events, zk_changes)
+}
+
+/// Configure the retention window of settled games and the bounty paid for removing them.
+/// Only callable by the owner or an operator.
+#[action(shortname = 0x62, zk = true)]
+pub fn set_cleanup_config(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    config: CleanupConfig,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_operator(&context.sender);
+    ensure!(
+        config.retention_millis >= 0,
+        GameError::InvalidArgument,
+        "The retention window cannot be negative."
+    );
+    state.cleanup_config = config;
+    (state, vec![], vec![])
+}
+
+/// Remove up to `max_entries` games that settled longer than the retention window ago and hold no
+/// unclaimed winnings. Callable by anyone; the caller's balance is credited the configured bounty
+/// per removed game, as far as the free house bankroll allows.
+#[action(shortname = 0x63, zk = true)]
+pub fn cleanup(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    max_entries: u32,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let removed = state.prune_expired_games(context.block_production_time, max_entries) as u64;
+    let bounty = (state.cleanup_config.bounty_per_game.saturating_mul(removed))
+        .min(state.free_bankroll(&state.token_address));
+    if bounty > 0 {
+        state.house_bankroll -= bounty;
+        state.adjust_balance(context.sender, bounty);
+    }
+
+    (state, vec![], vec![])
+}
+
+/// Approve an address as a randomness provider. While any provider is approved, every flip needs
+/// a provider contribution. Only callable by the owner or an operator.
+#[action(shortname = 0x64, zk = true)]
+pub fn add_randomness_provider(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    provider: Address,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_operator(&context.sender);
+    state.randomness_providers.add(provider);
+    (state, vec![], vec![])
+}
+
+/// Withdraw the approval of a randomness provider. Fees it already earned remain claimable. Only
+/// callable by the owner or an operator.
+#[action(shortname = 0x65, zk = true)]
+pub fn remove_randomness_provider(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    provider: Address,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_operator(&context.sender);
+    state.randomness_providers.remove(&provider);
+    (state, vec![], vec![])
+}
+
+/// Set the fee each contributing provider earns per flip, or stop paying fees with zero. Only
+/// callable by the owner or an operator.
+#[action(shortname = 0x66, zk = true)]
+pub fn set_provider_fee(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    fee_per_flip: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_operator(&context.sender);
+    state.randomness_providers.fee_per_flip = fee_per_flip;
+    (state, vec![], vec![])
+}
+
+/// Transfer the fees the sender earned as a randomness provider to them.
+#[action(shortname = 0x67, zk = true)]
+pub fn claim_provider_fees(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_not_paused();
+    let fees = state
+        .randomness_providers
+        .earned_fees
+        .remove(&context.sender)
+        .filter(|fees| *fees > 0)
+        .or_fail(GameError::NotFound, "No provider fees to claim.");
+
+    let mut event_group = EventGroup::builder();
+    token_client::transfer(
+        &mut event_group,
+        &state.gas_costs,
+        state.token_address,
+        context.sender,
+        fees,
+    );
+    token_client::register_callback(&mut event_group, &state.gas_costs, 0x0E)
+        .argument(context.sender)
+        .argument(fees)
+        .done();
+
+    (state, vec![event_group.build()], vec![])
+}
+
+/// Restore the claimed provider fees if their transfer failed.
+#[callback(shortname = 0x0E, zk = true)]
+pub fn claim_provider_fees_callback(
+    context: ContractContext,
+    callback_ctx: CallbackContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    provider: Address,
+    amount: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    if !callback_ctx.results[0].succeeded {
+        state.randomness_providers.credit(provider, amount);
+    }
+    (state, vec![], vec![])
+}
+
+/// Enable or disable attestation of settled results by the ZK nodes. Only callable by the owner.
+#[action(shortname = 0x68, zk = true)]
+pub fn set_result_attestation(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    enabled: bool,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    state.attest_results = enabled;
+    (state, vec![], vec![])
+}
+
+/// Automatically called when the nodes have signed a result record. The signatures are stored as
+/// the player's latest attested result.
+#[zk_on_attestation_complete]
+fn result_attested(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    attestation_id: AttestationId,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let attestation = zk_state
+        .data_attestations
+        .get(&attestation_id)
+        .or_fail(GameError::NotFound, "Unknown attestation.");
+    let record = ResultRecord::decode(&attestation.data);
+    state.result_attestations.insert(
+        record.player,
+        AttestedResult {
+            record,
+            signatures: attestation.signatures.clone(),
+        },
+    );
+    (state, vec![], vec![])
+}
+
+/// Set how long opened results may be disputed before they are settled, or settle them as soon
+/// as they are opened with zero, and appoint the arbiter voiding disputed games besides the owner.
+/// Only callable by the owner.
+#[action(shortname = 0x69, zk = true)]
+pub fn set_dispute_window(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    window_millis: i64,
+    arbiter: Option<Address>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    ensure!(
+        window_millis >= 0,
+        GameError::InvalidArgument,
+        "The dispute window cannot be negative."
+    );
+    state.dispute_window_millis = window_millis;
+    state.arbiter = arbiter;
+    (state, vec![], vec![])
+}
+
+/// Void a challengeable game whose result was manipulated, returning the escrowed stake to the
+/// player instead of settling the result. Only callable by the owner or the arbiter.
+#[action(shortname = 0x6A, zk = true)]
+pub fn void_disputed_game(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    player: Address,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    ensure!(
+        context.sender == state.owner || state.arbiter == Some(context.sender),
+        GameError::Unauthorized,
+        "Only the owner or the arbiter can void a game."
+    );
+    let now = context.block_production_time;
+    ensure!(
+        state.challenge_deadlines.get(&player).is_some_and(|deadline| now < deadline),
+        GameError::InvalidPhase,
+        "Only games within their dispute window can be voided."
+    );
+
+    let (events, changes) = refund_escrowed_game(&mut state, &zk_state, &player);
+    state.challenge_deadlines.remove(&player);
+    state.clear_player_record(&player, now);
+    state.set_phase(player, GamePhase::Start {}, now);
+    (state, events, changes)
+}
+
+/// Settle a challengeable game whose dispute window has passed. Callable by anyone; payouts and
+/// new bets also settle the sender's game on their own.
+#[action(shortname = 0x6B, zk = true)]
+pub fn finalize_game(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    player: Address,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let now = context.block_production_time;
+    ensure!(
+        state.challenge_deadlines.get(&player).is_some_and(|deadline| now >= deadline),
+        GameError::InvalidPhase,
+        "The game is not past its dispute window."
+    );
+    state.finalize_game(&player, now);
+    let events = settlement_events(&mut state);
+    let changes = settlement_attestations(&mut state);
+    (state, events, changes)
+}
+
+/// Sell loss insurance as configured, or stop selling it when `config` is absent. Insurance
+/// already sold is honoured. Only callable by the owner.
+#[action(shortname = 0x6C, zk = true)]
+pub fn set_loss_insurance(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    config: Option<LossInsuranceConfig>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    if let Some(config) = &config {
+        ensure!(
+            config.refund_bps <= 10_000,
+            GameError::InvalidArgument,
+            "At most the whole stake can be refunded."
+        );
+    }
+    state.loss_insurance = config;
+    (state, vec![], vec![])
+}
+
+/// Insure the sender's open bet against losing, before its flip is computed. The premium is
+/// paid from the sender's balance, and the refund is credited to it if the bet loses.
+#[action(shortname = 0x6D, zk = true)]
+pub fn insure_bet(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_accepting_bets();
+    let config = state
+        .loss_insurance
+        .clone()
+        .or_fail(GameError::Unavailable, "Loss insurance is not sold.");
+    let phase = state
+        .game_phases
+        .get(&context.sender)
+        .unwrap_or(GamePhase::Start {});
+    let computing = state.computation_pending.get(&context.sender) == Some(true);
+    let stake = match state.player_bets.get(&context.sender) {
+        Some(bet) if !phase.is_settled() && phase != GamePhase::Challengeable {} && !computing => {
+            bet.amount
+        }
+        _ => fail!(
+            GameError::InvalidPhase,
+            "Loss insurance can only be bought for a bet that is not yet flipped."
+        ),
+    };
+    ensure!(
+        !state.is_free_bet(&context.sender),
+        GameError::Ineligible,
+        "Free bets cannot be insured."
+    );
+    // Refunds are paid from the house bankroll, which is kept in the default token
+    ensure!(
+        state.bet_token_of(&context.sender) == state.token_address,
+        GameError::Ineligible,
+        "Only bets in the default token can be insured."
+    );
+    ensure!(
+        state.insured_bets.get(&context.sender).is_none(),
+        GameError::AlreadyDone,
+        "The bet is already insured."
+    );
+
+    let insured = config.quote(stake);
+    ensure!(
+        state.available_balance(&context.sender) >= insured.premium,
+        GameError::InsufficientFunds,
+        "Insufficient balance to pay the premium."
+    );
+    let balance = state.user_balances.get(&context.sender).unwrap_or(0);
+    state.set_balance(context.sender, balance - insured.premium);
+    state.house_bankroll += insured.premium;
+    state.loss_insurance_totals.premiums_collected += insured.premium;
+    state.insured_bets.insert(context.sender, insured);
+    (state, vec![], vec![])
+}
+
+/// Move `amount` of the house bankroll into the side bet pool. Only callable by a treasurer.
+#[action(shortname = 0x6E, zk = true)]
+pub fn fund_side_bet_pool(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    amount: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_treasurer(&context.sender);
+    ensure!(
+        amount <= state.free_bankroll(&state.token_address),
+        GameError::InsufficientFunds,
+        "The house bankroll cannot cover the funding."
+    );
+    state.bankroll_stakes.withdraw_house(amount, state.house_bankroll);
+    state.house_bankroll -= amount;
+    state.side_bet_pool.balance += amount;
+    (state, vec![], vec![])
+}
+
+/// Bet `amount` on the side the coin of `player`'s open game lands on, before its flip is
+/// computed. The stake is escrowed from the sender, and winnings are credited to their balance
+/// when the game settles.
+#[action(shortname = 0x6F, zk = true)]
+pub fn place_side_bet(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    player: Address,
+    amount: u64,
+    choice: PlayerChoice,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_accepting_bets();
+    state.close_import();
+    state.assert_not_banned(&context.sender, context.block_production_time);
+    state.assert_allowlisted(&context.sender);
+    ensure!(
+        context.sender != player,
+        GameError::InvalidArgument,
+        "Players cannot side bet on their own game."
+    );
+    ensure!(
+        amount > 0,
+        GameError::InvalidArgument,
+        "The side bet must be positive."
+    );
+    ensure!(
+        state.game_phases.get(&player) == Some(GamePhase::FlipCoin {}),
+        GameError::InvalidPhase,
+        "Side bets can only be placed on games waiting for their flip."
+    );
+    ensure!(
+        state.computation_pending.get(&player) != Some(true),
+        GameError::InvalidPhase,
+        "The flip of the game is already being computed."
+    );
+    let is_coin_flip = matches!(
+        state.player_bets.get(&player),
+        Some(PlayerBet {
+            choice: Some(_),
+            secret_amount: false,
+            multi_flip: None,
+            dice: None,
+            roulette: None,
+            ..
+        })
+    );
+    ensure!(
+        is_coin_flip
+            && state.matches.get(&player).is_none()
+            && state.parlays.get(&player).is_none(),
+        GameError::Ineligible,
+        "Side bets can only be placed on single coin flips."
+    );
+
+    let mut side_bets = state.side_bets.get(&player).unwrap_or_default();
+    ensure!(
+        side_bets.len() < MAX_SIDE_BETS_PER_GAME,
+        GameError::Unavailable,
+        "The game accepts no more side bets."
+    );
+    ensure!(
+        side_bets.iter().all(|side_bet| side_bet.bettor != context.sender),
+        GameError::AlreadyDone,
+        "The sender already has a side bet on the game."
+    );
+    state.side_bet_pool.reserve(amount);
+    side_bets.push(SideBet {
+        bettor: context.sender,
+        amount,
+        choice,
+        escrowed: false,
+    });
+    state.side_bets.insert(player, side_bets);
+
+    let mut event_group = EventGroup::builder();
+    token_client::transfer_from(
+        &mut event_group,
+        &state.gas_costs,
+        state.token_address,
+        context.sender,
+        context.contract_address,
+        amount,
+    );
+    token_client::register_callback(&mut event_group, &state.gas_costs, 0x0F)
+        .argument(player)
+        .argument(context.sender)
+        .argument(amount)
+        .done();
+
+    (state, vec![event_group.build()], vec![])
+}
+
+/// Callback of the escrow of a side bet. A side bet whose game ended before its stake arrived is
+/// refunded to the bettor's balance.
+#[callback(shortname = 0x0F, zk = true)]
+pub fn side_bet_escrow_callback(
+    context: ContractContext,
+    callback_ctx: CallbackContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    player: Address,
+    bettor: Address,
+    amount: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let mut side_bets = state.side_bets.get(&player).unwrap_or_default();
+    let pending = side_bets
+        .iter()
+        .position(|side_bet| side_bet.bettor == bettor && !side_bet.escrowed);
+    match (callback_ctx.results[0].succeeded, pending) {
+        (true, Some(index)) => side_bets[index].escrowed = true,
+        (true, None) => state.adjust_balance(bettor, amount),
+        (false, Some(index)) => {
+            side_bets.remove(index);
+            state.side_bet_pool.release(amount);
+        }
+        (false, None) => {}
+    }
+    if side_bets.is_empty() {
+        state.side_bets.remove(&player);
+    } else {
+        state.side_bets.insert(player, side_bets);
+    }
+    (state, vec![], vec![])
+}
+
+/// Place a parlay of `bet_amount` on a flip per prediction in `choices`. The coin is flipped for
+/// one leg at a time, a lost leg loses the parlay at once, and winning every leg pays `2^n` times
+/// the stake minus the parlay house edge.
+#[action(shortname = 0x70, zk = true)]
+pub fn place_parlay(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    bet_amount: u64,
+    choices: Vec<PlayerChoice>,
+    nonce: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    ensure!(
+        choices.len() >= 2 && choices.len() <= MAX_PARLAY_LEGS,
+        GameError::InvalidArgument,
+        "A parlay needs between 2 and 10 legs."
+    );
+
+    let player_bet = PlayerBet {
+        amount: bet_amount,
+        choice: Some(choices[0]),
+        secret_amount: false,
+        multi_flip: None,
+        dice: None,
+        roulette: None,
+        beneficiary: None,
+    };
+    let event_groups =
+        place_bet(&context, &mut state, nonce, player_bet, state.token_address, None);
+    state.parlays.insert(
+        context.sender,
+        Parlay {
+            choices,
+            legs_won: 0,
+        },
+    );
+
+    // The bet was reserved as a single flip; reserve the payout of the whole parlay instead
+    state.reprice_bet(&context.sender, context.block_production_time);
+
+    (state, event_groups, vec![])
+}
+
+/// Set the house edge of parlays in basis points of the payout multiplier.
+/// Only callable by the owner.
+#[action(shortname = 0x71, zk = true)]
+pub fn set_parlay_house_edge(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    house_edge_bps: u32,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    ensure!(
+        house_edge_bps < 10_000,
+        GameError::InvalidArgument,
+        "The house edge must be less than one times the stake."
+    );
+    state.parlay_house_edge_bps = house_edge_bps;
+    (state, vec![], vec![])
+}
+
+/// Configure the weighted coin mode, or disable it with `None`. Only callable by the owner.
+#[action(shortname = 0x72, zk = true)]
+pub fn set_weighted_coin(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    config: Option<WeightedCoinConfig>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    if let Some(config) = &config {
+        ensure!(
+            config.denominator >= 2,
+            GameError::InvalidArgument,
+            "A weighted coin needs at least two outcomes."
+        );
+        ensure!(
+            config.house_edge_bps < 10_000,
+            GameError::InvalidArgument,
+            "The house edge must be less than one times the stake."
+        );
+    }
+    state.weighted_coin = config;
+    (state, vec![], vec![])
+}
+
+/// Bet `bet_amount` on a weighted coin landing on the sender's side, which it does for
+/// `winning_outcomes` out of the configured number of outcomes. A win pays the inverse of the
+/// odds minus the house edge, e.g. about 4x for a 1-in-4 long shot.
+#[action(shortname = 0x73, zk = true)]
+pub fn place_weighted_bet(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    bet_amount: u64,
+    winning_outcomes: u8,
+    nonce: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let config = state
+        .weighted_coin
+        .clone()
+        .or_fail(GameError::Unavailable, "Weighted coin mode is not enabled.");
+    ensure!(
+        winning_outcomes > 0 && winning_outcomes < config.denominator,
+        GameError::InvalidArgument,
+        "The bet must win some but not all outcomes."
+    );
+
+    let player_bet = PlayerBet {
+        amount: bet_amount,
+        choice: None,
+        secret_amount: false,
+        multi_flip: None,
+        dice: None,
+        roulette: None,
+        beneficiary: None,
+    };
+    let event_groups =
+        place_bet(&context, &mut state, nonce, player_bet, state.token_address, None);
+    state.weighted_bets.insert(
+        context.sender,
+        WeightedOdds {
+            winning_outcomes,
+            denominator: config.denominator,
+        },
+    );
+
+    // The bet was reserved as a coin flip; reserve the payout at its actual odds instead
+    state.reprice_bet(&context.sender, context.block_production_time);
+
+    (state, event_groups, vec![])
+}
+
+/// Set the fee kept from the stake of a cancelled bet, in basis points. Only callable by the
+/// owner.
+#[action(shortname = 0x74, zk = true)]
+pub fn set_cancellation_fee(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    fee_bps: u32,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    ensure!(
+        fee_bps <= 10_000,
+        GameError::InvalidArgument,
+        "The fee cannot exceed the stake."
+    );
+    state.cancellation_fee_bps = fee_bps;
+    (state, vec![], vec![])
+}
+
+/// Cancel the sender's bet before any randomness is contributed to its round. The stake is
+/// refunded by transfer, minus the cancellation fee, and the game is reset.
+#[action(shortname = 0x75, zk = true)]
+pub fn cancel_bet(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_not_paused();
+    let player = context.sender;
+    let now = context.block_production_time;
+    ensure!(
+        state.game_phases.get(&player) == Some(GamePhase::FlipCoin {}),
+        GameError::InvalidPhase,
+        "Only bets waiting for their flip can be cancelled."
+    );
+    ensure!(
+        state.computation_pending.get(&player) != Some(true)
+            && state.round_contributors.get(&player).is_none(),
+        GameError::InvalidPhase,
+        "Bets cannot be cancelled once randomness is contributed."
+    );
+    // Later rounds of a match or parlay would let a losing player walk away
+    ensure!(
+        state.matches.get(&player).map_or(0, |score| score.rounds_played()) == 0
+            && state.parlays.get(&player).map_or(0, |parlay| parlay.legs_won) == 0,
+        GameError::InvalidPhase,
+        "Bets cannot be cancelled once a round is played."
+    );
+    ensure!(
+        state.bet_token_of(&player) == state.token_address
+            && state.payout_tokens.get(&player).is_none(),
+        GameError::Ineligible,
+        "Only bets in the default token can be cancelled."
+    );
+
+    let stake = state.player_bets.get(&player).map_or(0, |bet| bet.amount);
+    let mut changes = vec![];
+    let secret_variables: Vec<SecretVarId> = choice_variable_of(&zk_state, &player)
+        .into_iter()
+        .chain(secret_bet_variable_of(&zk_state, &player))
+        .chain(sealed_stake_variable_of(&zk_state, &player))
+        .collect();
+    if !secret_variables.is_empty() {
+        changes.push(ZkStateChange::DeleteVariables {
+            variables_to_delete: secret_variables,
+        });
+    }
+
+    // A free bet was never escrowed, so its credits are given back instead
+    if state.is_free_bet(&player) {
+        let credits = state.free_bet_credits.get(&player).unwrap_or(0);
+        state.free_bet_credits.insert(player, credits + stake);
+        state.clear_player_record(&player, now);
+        state.set_phase(player, GamePhase::Start {}, now);
+        return (state, vec![], changes);
+    }
+    // Likewise, a sponsored or session stake goes back to its budget or session
+    if state.give_back_sponsored_stake(&player, stake)
+        || state.give_back_session_stake(&player, stake)
+    {
+        state.ledger.refund(&player, 0);
+        state.clear_player_record(&player, now);
+        state.set_phase(player, GamePhase::Start {}, now);
+        return (state, vec![], changes);
+    }
+
+    let fee = (stake as u128 * state.cancellation_fee_bps as u128 / 10_000) as u64;
+    state.collect_fee(fee);
+    let refund = stake - fee + state.take_secret_bet_refund(&player);
+    state.ledger.refund(&player, fee);
+    state.clear_player_record(&player, now);
+    state.set_phase(player, GamePhase::Start {}, now);
+
+    let mut event_group = EventGroup::builder();
+    token_client::transfer(
+        &mut event_group,
+        &state.gas_costs,
+        state.token_address,
+        player,
+        refund,
+    );
+    token_client::register_callback(&mut event_group, &state.gas_costs, 0x10)
+        .argument(player)
+        .argument(refund)
+        .done();
+    (state, vec![event_group.build()], changes)
+}
+
+/// Callback of the refund of a cancelled bet. A failed refund is credited to the player's
+/// balance instead.
+#[callback(shortname = 0x10, zk = true)]
+pub fn cancel_bet_callback(
+    context: ContractContext,
+    callback_ctx: CallbackContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    player: Address,
+    refund: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    if !callback_ctx.results[0].succeeded {
+        state.adjust_balance(player, refund);
+    }
+    (state, vec![], vec![])
+}
+
+/// Run a promotion matching bets with promotional stakes, or end it with `None`. Only callable
+/// by the owner.
+#[action(shortname = 0x76, zk = true)]
+pub fn set_promotion(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    config: Option<PromoConfig>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    if let Some(config) = &config {
+        ensure!(
+            config.starts_at < config.ends_at,
+            GameError::InvalidArgument,
+            "The promotion must end after it starts."
+        );
+    }
+    state.promotion.configure(config);
+    (state, vec![], vec![])
+}
+
+/// Move `amount` of the house bankroll into the promo budget. Only callable by a treasurer.
+#[action(shortname = 0x77, zk = true)]
+pub fn fund_promo_budget(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    amount: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_treasurer(&context.sender);
+    ensure!(
+        amount <= state.free_bankroll(&state.token_address),
+        GameError::InsufficientFunds,
+        "The house bankroll cannot cover the funding."
+    );
+    state.bankroll_stakes.withdraw_house(amount, state.house_bankroll);
+    state.house_bankroll -= amount;
+    state.promotion.budget += amount;
+    (state, vec![], vec![])
+}
+
+/// Set the bounty paid to the caller of a `keeper_tick` that did work. Only callable by the owner
+/// or an operator.
+#[action(shortname = 0x78, zk = true)]
+pub fn set_keeper_bounty(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    bounty: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_operator(&context.sender);
+    state.keeper_bounty = bounty;
+    (state, vec![], vec![])
+}
+
+/// Advance stuck games, handling up to `max_entries` games per step: expire games past the deadline
+/// of their stage, settle challengeable games past their dispute window, flip rounds whose
+/// randomness is complete, and remove expired settled games. Callable by anyone; a tick that did
+/// any work credits the keeper bounty to the caller's balance, as far as the free house bankroll
+/// allows.
+#[action(shortname = 0x79, zk = true)]
+pub fn keeper_tick(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    max_entries: u32,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let now = context.block_production_time;
+    let mut work = 0;
+
+    let mut events = vec![];
+    let mut changes = vec![];
+    let overdue: Vec<Address> = state
+        .stage_entered_at
+        .iter()
+        .map(|(player, _)| player)
+        .filter(|player| state.is_overdue(player, now))
+        .take(max_entries as usize)
+        .collect();
+    for player in overdue {
+        if let Some((expiry_events, expiry_changes)) =
+            expire_overdue_game(&mut state, &zk_state, &player, now)
+        {
+            events.extend(expiry_events);
+            changes.extend(expiry_changes);
+            work += 1;
+        }
+    }
+
+    let expired: Vec<Address> = state
+        .challenge_deadlines
+        .iter()
+        .filter(|(_, deadline)| now >= *deadline)
+        .map(|(player, _)| player)
+        .take(max_entries as usize)
+        .collect();
+    for player in expired {
+        state.finalize_game(&player, now);
+        work += 1;
+    }
+
+    if state.pause_state.allows_play() {
+        let ready: Vec<Address> = state
+            .player_bets
+            .iter()
+            .map(|(player, _)| player)
+            .filter(|player| is_ready_to_flip(&state, &zk_state, player, now))
+            .take(max_entries as usize)
+            .collect();
+        for player in ready {
+            state.computation_pending.insert(player, true);
+            state.raise_game_event(GameEvent::RandomnessComplete { player });
+            state.stage_entered_at.insert(player, now);
+            state.pay_provider_fees(&player);
+            let lane = state.compute_lane_of(&player);
+            state.compute_queue.push(player, lane);
+            work += 1;
+        }
+    }
+
+    work += state.prune_expired_games(now, max_entries);
+
+    let bounty = state.keeper_bounty.min(state.free_bankroll(&state.token_address));
+    if work > 0 && bounty > 0 {
+        state.house_bankroll -= bounty;
+        state.adjust_balance(context.sender, bounty);
+    }
+
+    // Fees are swept once they reach the threshold, not on every tick
+    let threshold = state.treasury.as_ref().map_or(0, |config| config.sweep_threshold);
+    if threshold > 0 && state.fee_sweeps.unswept >= threshold {
+        events.extend(fee_sweep(&mut state));
+    }
+
+    events.extend(settlement_events(&mut state));
+    changes.extend(next_queued_computation(&mut state, &zk_state));
+    changes.extend(settlement_attestations(&mut state));
+    (state, events, changes)
+}
+
+/// Start a game betting that the coins of `sequence.len()` flips land exactly as in `sequence`,
+/// in order. All flips are drawn from a single ZK computation and the win pays `2^k` times the
+/// stake for `k` flips.
+#[action(shortname = 0x7A, zk = true)]
+pub fn place_sequence_bet(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    bet_amount: u64,
+    sequence: Vec<PlayerChoice>,
+    nonce: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    // Exactly one outcome of the flips wins, so the bet pays the odds of matching them all
+    let flips = sequence.len().min(u8::MAX as usize) as u8;
+    let prediction = MultiFlipPrediction {
+        flips,
+        min_matches: flips,
+    };
+    validation::assert_no_violations(&validation::validate_multi_flip(&prediction));
+
+    let player_bet = PlayerBet {
+        amount: bet_amount,
+        choice: Some(sequence[0]),
+        secret_amount: false,
+        multi_flip: Some(prediction),
+        dice: None,
+        roulette: None,
+        beneficiary: None,
+    };
+    let event_groups =
+        place_bet(&context, &mut state, nonce, player_bet, state.token_address, None);
+    state.sequence_bets.insert(context.sender, encode_sequence(&sequence));
+
+    (state, event_groups, vec![])
+}
+
+/// Assert that a private balance update of the player can be started now.
+fn assert_private_update_possible(
+    state: &CoinFlipState,
+    zk_state: &ZkState<SecretVarType>,
+    player: &Address,
+) {
+    ensure!(
+        state.private_updates_pending.get(player).is_none(),
+        GameError::InvalidPhase,
+        "The private balance is already being updated."
+    );
+    ensure!(
+        zk_state.calculation_state == CalculationStatus::Waiting,
+        GameError::Unavailable,
+        "The computation engine is busy, try again later."
+    );
+}
+
+/// Claim the winnings of the sender's completed game into their private balance, a secret
+/// variable owned by them, instead of having them transferred. Only the default token can be
+/// held privately.
+#[action(shortname = 0x7B, zk = true)]
+pub fn claim_winnings_privately(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_not_paused();
+    state.finalize_expired_challenge(&context.sender, context.block_production_time);
+    ensure!(
+        state.game_phases.get(&context.sender) == Some(GamePhase::Done {}),
+        GameError::InvalidPhase,
+        "Payout can only occur after the game has completed."
+    );
+    assert_private_update_possible(&state, &zk_state, &context.sender);
+    ensure!(
+        state.bet_token_of(&context.sender) == state.token_address
+            && state.payout_tokens.get(&context.sender).is_none(),
+        GameError::Ineligible,
+        "Only winnings in the default token can be held privately."
+    );
+    ensure!(
+        state.payee_of(&context.sender) == context.sender,
+        GameError::Ineligible,
+        "Winnings owed to a beneficiary cannot be held privately."
+    );
+
+    let (payout, _) = take_payout(&mut state, &context.sender);
+    ensure!(
+        payout > 0,
+        GameError::NotFound,
+        "There are no winnings to claim."
+    );
+    state.private_updates_pending.insert(context.sender, true);
+
+    let metadata = SecretVarType::PrivateBalance {player: context.sender};
+    let computation = match private_balance_variable_of(&zk_state, &context.sender) {
+        Some(balance_variable) => zk_compute::compute_private_credit_start(
+            balance_variable,
+            payout as i64,
+            Some(SHORTNAME_PRIVATE_BALANCE_COMPUTED),
+            &metadata,
+        ),
+        None => zk_compute::compute_private_opening_start(
+            payout as i64,
+            Some(SHORTNAME_PRIVATE_BALANCE_COMPUTED),
+            &metadata,
+        ),
+    };
+    let events = settlement_events(&mut state);
+    let mut changes = settlement_attestations(&mut state);
+    changes.push(computation);
+    (state, events, changes)
+}
+
+/// Withdraw `amount` from the sender's private balance. Only whether the balance covers the
+/// amount is revealed: the withdrawable amount is opened and transferred, and the balance is
+/// debited in secret.
+#[action(shortname = 0x7C, zk = true)]
+pub fn withdraw_private_winnings(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    amount: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    ensure!(
+        amount > 0,
+        GameError::InvalidArgument,
+        "The withdrawal must be positive."
+    );
+    assert_private_update_possible(&state, &zk_state, &context.sender);
+    let balance_variable = private_balance_variable_of(&zk_state, &context.sender)
+        .or_fail(GameError::NotFound, "The sender has no private balance.");
+    state.private_updates_pending.insert(context.sender, true);
+
+    let computation = zk_compute::compute_private_withdrawable_start(
+        balance_variable,
+        amount as i64,
+        Some(SHORTNAME_FLIP_COMPUTE_COMPLETE),
+        &SecretVarType::PrivateWithdrawal {player: context.sender},
+    );
+    (state, vec![], vec![computation])
+}
+
+/// Check the allowance of escrowed bets with the token's allowance query before placing them, or
+/// stop checking with `None`. Only callable by the owner.
+#[action(shortname = 0x7D, zk = true)]
+pub fn set_allowance_precheck(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    allowance_shortname: Option<u32>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    state.allowance_shortname = allowance_shortname;
+    (state, vec![], vec![])
+}
+
+/// Callback of the allowance query of a bet. The bet is placed and escrowed only if the allowance
+/// covers it; otherwise it never enters the state.
+#[callback(shortname = 0x11, zk = true)]
+pub fn allowance_precheck_callback(
+    context: ContractContext,
+    callback_ctx: CallbackContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    player: Address,
+    bet_amount: u64,
+    choice: PlayerChoice,
+    token: Address,
+    nonce: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let result = &callback_ctx.results[0];
+    let allowance = if result.succeeded {
+        token_client::parse_allowance(&result.return_data)
+    } else {
+        0
+    };
+    if allowance < bet_amount as u128 {
+        return (state, vec![], vec![]);
+    }
+
+    let player_bet = PlayerBet {
+        amount: bet_amount,
+        choice: Some(choice),
+        secret_amount: false,
+        multi_flip: None,
+        dice: None,
+        roulette: None,
+        beneficiary: None,
+    };
+    let proxied = on_behalf_of(&context, player);
+    let event_groups = place_bet(&proxied, &mut state, nonce, player_bet, token, None);
+    (state, event_groups, vec![])
+}
+
+/// Callback of the USD price query of a bet. The quoted price is kept for the token, and the bet is
+/// placed like `start_game_and_place_bet`, within the USD bet limits at the quoted price.
+#[callback(shortname = 0x16, zk = true)]
+pub fn usd_price_callback(
+    context: ContractContext,
+    callback_ctx: CallbackContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    player: Address,
+    bet_amount: u64,
+    choice: PlayerChoice,
+    token: Address,
+    nonce: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let result = &callback_ctx.results[0];
+    let price = result
+        .succeeded
+        .then(|| price_oracle::parse_price(&result.return_data))
+        .flatten()
+        .or_fail(GameError::Unavailable, "The oracle quoted no price for the token.");
+    state.usd_prices.insert(
+        token,
+        UsdPrice {
+            price,
+            quoted_at: context.block_production_time,
+        },
+    );
+    let proxied = on_behalf_of(&context, player);
+    let event_groups = place_checked_bet(&proxied, &mut state, bet_amount, choice, token, nonce);
+    (state, event_groups, vec![])
+}
+
+/// Expire the player's game if it is past the deadline of the stage it waits in: its stake is
+/// returned and the game restarts. A computation that may be running is left to finish.
+///
+/// ### Returns:
+///
+/// The events and zk changes returning the stake, if the game expired.
+fn expire_overdue_game(
+    state: &mut CoinFlipState,
+    zk_state: &ZkState<SecretVarType>,
+    player: &Address,
+    now: i64,
+) -> Option<(Vec<EventGroup>, Vec<ZkStateChange>)> {
+    if !state.is_overdue(player, now) {
+        return None;
+    }
+    let stage = state.waiting_stage_of(player)?;
+    let computing = zk_state.calculation_state != CalculationStatus::Waiting;
+    if stage == WaitingStage::Computation && computing && !state.compute_queue.contains(player) {
+        return None;
+    }
+    state.compute_queue.remove(player);
+
+    let (events, changes) = if stage == WaitingStage::Escrow {
+        // The stake may still arrive, and is returned by the escrow callback then
+        if let Some(escrow) = state.pending_escrows.get(player) {
+            let token = state.bet_token_of(player);
+            state.expired_escrows.insert(*player, ExpiredEscrow { escrow, token });
+        }
+        (vec![], vec![])
+    } else {
+        refund_escrowed_game(state, zk_state, player)
+    };
+    state.clear_player_record(player, now);
+    state.set_phase(*player, GamePhase::Start {}, now);
+    Some((events, changes))
+}
+
+/// Refund the escrowed stake of the player's voided game, together with what was charged on it,
+/// and delete the secret inputs of its round. The game's record is left to the caller to clear.
+fn refund_escrowed_game(
+    state: &mut CoinFlipState,
+    zk_state: &ZkState<SecretVarType>,
+    player: &Address,
+) -> (Vec<EventGroup>, Vec<ZkStateChange>) {
+    let mut events = vec![];
+    let mut changes = vec![];
+    let stake = state.player_bets.get(player).map_or(0, |bet| bet.amount);
+    state.ledger.refund(player, 0);
+    let charged = state.reverse_bet_charges(player);
+    if state.is_free_bet(player) {
+        let credits = state.free_bet_credits.get(player).unwrap_or(0);
+        state.free_bet_credits.insert(*player, credits + stake);
+    } else if !state.give_back_sponsored_stake(player, stake + charged)
+        && !state.give_back_session_stake(player, stake + charged)
+    {
+        let refund = stake + charged + state.take_secret_bet_refund(player);
+        let mut event_group = EventGroup::builder();
+        token_client::transfer(
+            &mut event_group,
+            &state.gas_costs,
+            state.bet_token_of(player),
+            *player,
+            refund,
+        );
+        events.push(event_group.build());
+    }
+    let variables_to_delete: Vec<SecretVarId> = randomness_variables_of(zk_state, player)
+        .into_iter()
+        .chain(choice_variable_of(zk_state, player))
+        .chain(secret_bet_variable_of(zk_state, player))
+        .chain(sealed_stake_variable_of(zk_state, player))
+        .collect();
+    if !variables_to_delete.is_empty() {
+        changes.push(ZkStateChange::DeleteVariables {
+            variables_to_delete,
+        });
+    }
+    (events, changes)
+}
+
+/// Set the timeouts of the stages games wait in. Only callable by the owner.
+#[action(shortname = 0x7E, zk = true)]
+pub fn set_phase_timeouts(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    timeouts: PhaseTimeouts,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    ensure!(
+        timeouts.is_valid(),
+        GameError::InvalidArgument,
+        "Phase timeouts cannot be negative."
+    );
+    state.phase_timeouts = timeouts;
+    (state, vec![], vec![])
+}
+
+/// Expire a game past the deadline of the stage it waits in, returning its stake to the player.
+/// Callable by anyone.
+#[action(shortname = 0x7F, zk = true)]
+pub fn expire_game(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    player: Address,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let now = context.block_production_time;
+    let (mut events, mut changes) = expire_overdue_game(&mut state, &zk_state, &player, now)
+        .or_fail(
+            GameError::InvalidPhase,
+            "The game is not past the deadline of its current stage.",
+        );
+    changes.extend(next_queued_computation(&mut state, &zk_state));
+    (state, events, changes)
+}
+
+/// Set the gas sent with interactions and reserved for callbacks, as the costs needed differ
+/// between networks. Only callable by the owner.
+#[action(shortname = 0x80, zk = true)]
+pub fn set_gas_costs(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    costs: GasCosts,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    ensure!(
+        costs.is_valid(),
+        GameError::InvalidArgument,
+        "Callbacks must be given gas."
+    );
+    state.gas_costs = costs;
+    (state, vec![], vec![])
+}
+
+/// Allow or disallow `integrator` to bet on behalf of players. A disallowed integrator keeps its
+/// open bets and totals but cannot place new bets until allowed again. Only callable by the owner.
+#[action(shortname = 0x81, zk = true)]
+pub fn set_integrator(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    integrator: Address,
+    allowed: bool,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    match state.integrators.get_mut(&integrator) {
+        Some(account) => account.active = allowed,
+        None if allowed => {
+            state.integrators.insert(integrator, Integrator::new());
+        }
+        None => {}
+    }
+    (state, vec![], vec![])
+}
+
+/// Bet on a coin flip on behalf of `player`, as a registered integrator. The stake is escrowed
+/// from the integrator and the winnings are paid to `beneficiary` when the player claims them.
+/// The player must have no game in progress and nothing left to claim, and `nonce` must be
+/// their next bet nonce.
+#[action(shortname = 0x82, zk = true)]
+pub fn integrator_bet(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    player: Address,
+    beneficiary: Address,
+    bet_amount: u64,
+    choice: PlayerChoice,
+    token: Address,
+    nonce: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let integrator = context.sender;
+    state.record_integrator_bet(&integrator, bet_amount);
+    ensure!(
+        player != integrator,
+        GameError::InvalidArgument,
+        "Integrators cannot bet on behalf of themselves."
+    );
+    let phase = state.game_phases.get(&player).unwrap_or(GamePhase::Start {});
+    ensure!(
+        phase.is_settled()
+            && state.pending_escrows.get(&player).is_none()
+            && state.get_winner(&player) != Some(player)
+            && state.secret_bet_refunds.get(&player).is_none(),
+        GameError::InvalidPhase,
+        "Integrators can only bet for players with no game in progress or winnings to claim."
+    );
+
+    let player_bet = PlayerBet {
+        amount: bet_amount,
+        choice: Some(choice),
+        secret_amount: false,
+        multi_flip: None,
+        dice: None,
+        roulette: None,
+        beneficiary: Some(beneficiary),
+    };
+    let proxied = on_behalf_of(&context, player);
+    let source = StakeSource::Integrator(integrator);
+    let event_groups =
+        place_funded_bet(&proxied, &mut state, nonce, player_bet, token, None, source);
+    (state, event_groups, vec![])
+}
+
+/// Add `amount` from the sender to their sponsorship budget, and let each player draw at most
+/// `per_player_cap` of stakes from it. Only callable by the owner or an operator.
+#[action(shortname = 0x83, zk = true)]
+pub fn fund_sponsorship(
+    context: ContractContext,
+    state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    amount: u64,
+    per_player_cap: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_operator(&context.sender);
+    ensure!(
+        amount > 0,
+        GameError::InvalidArgument,
+        "The funding must be positive."
+    );
+
+    let mut event_group = EventGroup::builder();
+    token_client::transfer_from(
+        &mut event_group,
+        &state.gas_costs,
+        state.token_address,
+        context.sender,
+        context.contract_address,
+        amount,
+    );
+    token_client::register_callback(&mut event_group, &state.gas_costs, 0x12)
+        .argument(context.sender)
+        .argument(amount)
+        .argument(per_player_cap)
+        .done();
+
+    (state, vec![event_group.build()], vec![])
+}
+
+/// Credit the sponsorship budget once the funding transfer succeeded.
+#[callback(shortname = 0x12, zk = true)]
+pub fn fund_sponsorship_callback(
+    context: ContractContext,
+    callback_ctx: CallbackContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    sponsor: Address,
+    amount: u64,
+    per_player_cap: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    ensure!(
+        callback_ctx.results[0].succeeded,
+        GameError::TransferFailed,
+        "Token transfer failed, the sponsorship was not funded."
+    );
+    let mut sponsorship = state
+        .sponsorships
+        .get(&sponsor)
+        .cloned()
+        .unwrap_or_else(|| Sponsorship::new(per_player_cap));
+    sponsorship.per_player_cap = per_player_cap;
+    sponsorship.budget += amount;
+    state.sponsorships.insert(sponsor, sponsorship);
+    (state, vec![], vec![])
+}
+
+/// Take `amount` of the unspent sponsorship budget of the sender back. The budget is restored if
+/// the transfer fails.
+#[action(shortname = 0x84, zk = true)]
+pub fn withdraw_sponsorship(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    amount: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let sponsorship = state
+        .sponsorships
+        .get_mut(&context.sender)
+        .or_fail(GameError::NotFound, "The sender has no sponsorship.");
+    ensure!(
+        amount > 0 && amount <= sponsorship.budget,
+        GameError::InsufficientFunds,
+        "The withdrawal must be positive and at most the unspent budget."
+    );
+    sponsorship.budget -= amount;
+
+    let mut event_group = EventGroup::builder();
+    token_client::transfer(
+        &mut event_group,
+        &state.gas_costs,
+        state.token_address,
+        context.sender,
+        amount,
+    );
+    token_client::register_callback(&mut event_group, &state.gas_costs, 0x13)
+        .argument(context.sender)
+        .argument(amount)
+        .done();
+
+    (state, vec![event_group.build()], vec![])
+}
+
+/// Restore the sponsorship budget if its withdrawal failed.
+#[callback(shortname = 0x13, zk = true)]
+pub fn withdraw_sponsorship_callback(
+    context: ContractContext,
+    callback_ctx: CallbackContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    sponsor: Address,
+    amount: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    if !callback_ctx.results[0].succeeded {
+        if let Some(sponsorship) = state.sponsorships.get_mut(&sponsor) {
+            sponsorship.budget += amount;
+        }
+    }
+    (state, vec![], vec![])
+}
+
+/// Start the game with a bet on `choice` whose stake is drawn from the sponsorship budget of
+/// `sponsor`, within the sponsorship left for the sender. Winnings are paid to the sender.
+#[action(shortname = 0x85, zk = true)]
+pub fn start_sponsored_game(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    sponsor: Address,
+    bet_amount: u64,
+    choice: PlayerChoice,
+    nonce: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let player_bet = PlayerBet {
+        amount: bet_amount,
+        choice: Some(choice),
+        secret_amount: false,
+        multi_flip: None,
+        dice: None,
+        roulette: None,
+        beneficiary: None,
+    };
+    let token = state.token_address;
+    let source = StakeSource::Sponsor(sponsor);
+    let event_groups =
+        place_funded_bet(&context, &mut state, nonce, player_bet, token, None, source);
+    (state, event_groups, vec![])
+}
+
+/// Set the treasury contract the house's fees are swept to, or stop sharing fees with `None`.
+/// Fees earned while no treasury is set stay in the bankroll. Only callable by the owner.
+#[action(shortname = 0x86, zk = true)]
+pub fn set_treasury(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    config: Option<TreasuryConfig>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    if config.is_none() {
+        state.fee_sweeps.unswept = 0;
+    }
+    state.treasury = config;
+    (state, vec![], vec![])
+}
+
+/// Forward the fees earned since the last sweep to the treasury. Only callable by a treasurer.
+#[action(shortname = 0x87, zk = true)]
+pub fn sweep_fees(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_treasurer(&context.sender);
+    ensure!(
+        state.treasury.is_some(),
+        GameError::Unavailable,
+        "No treasury is configured."
+    );
+    let event_group =
+        fee_sweep(&mut state).or_fail(GameError::NotFound, "There are no fees to sweep.");
+    (state, vec![event_group], vec![])
+}
+
+/// Confirm a sweep once its transfer completed, or owe the fees again and return them to the
+/// bankroll if it failed.
+#[callback(shortname = 0x14, zk = true)]
+pub fn sweep_fees_callback(
+    context: ContractContext,
+    callback_ctx: CallbackContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    amount: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let succeeded = callback_ctx.results[0].succeeded;
+    state.fee_sweeps.settle(amount, succeeded);
+    if !succeeded {
+        state.bankroll_stakes.fund_house(amount, state.house_bankroll);
+        state.house_bankroll += amount;
+    }
+    (state, vec![], vec![])
+}
+
+/// Close the running season: archive its totals and leaderboard under its number and start the
+/// next season with empty standings. Only callable by the owner or an operator.
+#[action(shortname = 0x88, zk = true)]
+pub fn close_season(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_operator(&context.sender);
+    let now = context.block_production_time;
+    let record = state.season.archive(now);
+    state.season_archive.insert(record.season, record);
+    state.season = SeasonState::new(state.season.season + 1, now);
+    (state, vec![], vec![])
+}
+
+/// Throttle bots hammering the contract, by requiring `min_bet_interval_millis` between two bets
+/// of the same address and accepting at most `max_bets_per_block` bets in a block. Zero disables
+/// either limit. Only callable by the owner or an operator.
+#[action(shortname = 0x89, zk = true)]
+pub fn set_bet_throttle(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    config: ThrottleConfig,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_operator(&context.sender);
+    ensure!(
+        config.min_bet_interval_millis >= 0,
+        GameError::InvalidArgument,
+        "The minimum bet interval cannot be negative."
+    );
+    state.bet_throttle.config = config;
+    (state, vec![], vec![])
+}
+
+/// Set the sender's own daily wagering cap, daily loss cap and the cool-off that pauses betting
+/// once a cap is reached. Stricter limits apply immediately; looser ones only after
+/// `LIMIT_LOOSENING_DELAY_MILLIS`, and not even the owner can lift them sooner.
+#[action(shortname = 0x8A, zk = true)]
+pub fn set_gambling_limits(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    limits: GamblingLimits,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    ensure!(
+        limits.cool_off_millis >= 0,
+        GameError::InvalidArgument,
+        "The cool-off period cannot be negative."
+    );
+    state.request_gambling_limits(context.sender, limits, context.block_production_time);
+    (state, vec![], vec![])
+}
+
+/// Maximum number of players paid out by a single `payout_batch`.
+const MAX_PAYOUT_BATCH: usize = 50;
+
+/// Pay out the completed games of the given players like `payout_winner`, with every transfer in
+/// a single event group, so winnings are pushed to players instead of waiting for their claims.
+/// Players without a completed game are skipped. Only callable by the owner or an operator.
+#[action(shortname = 0x8B, zk = true)]
+pub fn payout_batch(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    players: Vec<Address>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_operator(&context.sender);
+    state.assert_not_paused();
+    ensure!(
+        players.len() <= MAX_PAYOUT_BATCH,
+        GameError::InvalidArgument,
+        "At most {MAX_PAYOUT_BATCH} players can be paid out at once."
+    );
+
+    let mut event_group = EventGroup::builder();
+    let mut transfers = 0;
+    for player in players {
+        state.finalize_expired_challenge(&player, context.block_production_time);
+        if state.game_phases.get(&player) != Some(GamePhase::Done {}) {
+            continue;
+        }
+        let payee = state.payee_of(&player);
+        let (payout, converted_payout) = take_payout(&mut state, &player);
+        if payout > 0 {
+            token_client::transfer(
+                &mut event_group,
+                &state.gas_costs,
+                state.bet_token_of(&player),
+                payee,
+                payout,
+            );
+            transfers += 1;
+        }
+        if converted_payout > 0 {
+            token_client::transfer(
+                &mut event_group,
+                &state.gas_costs,
+                state.payout_token_of(&player),
+                payee,
+                converted_payout,
+            );
+            transfers += 1;
+        }
+    }
+
+    let mut events = if transfers == 0 { vec![] } else { vec![event_group.build()] };
+    events.extend(settlement_events(&mut state));
+    let changes = settlement_attestations(&mut state);
+    (state, events, changes)
+}
+
+/// Enable or disable bonus rounds. A won coin flip whose randomness matches the bonus pattern
+/// then draws a multiplier from 1 to 10 for its winnings, provided the free bankroll covers the
+/// largest multiplier. Only callable by the owner.
+#[action(shortname = 0x8C, zk = true)]
+pub fn set_bonus_round(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    enabled: bool,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    state.bonus_round_enabled = enabled;
+    (state, vec![], vec![])
+}
+
+/// Open an elimination bracket of `2^rounds` players charging `entry_fee`, open for registration
+/// for `registration_millis` and giving the players of each round `round_duration_millis` to
+/// contribute their randomness. The winner takes the pot less `rake_bps`. Only callable by the
+/// owner.
+#[action(shortname = 0x8D, zk = true)]
+pub fn open_bracket(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    entry_fee: u64,
+    rounds: u8,
+    rake_bps: u32,
+    registration_millis: i64,
+    round_duration_millis: i64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    ensure!(
+        entry_fee > 0,
+        GameError::InvalidArgument,
+        "The entry fee must be positive."
+    );
+    ensure!(
+        (1..=MAX_BRACKET_ROUNDS).contains(&rounds),
+        GameError::InvalidArgument,
+        "A bracket has between 1 and {MAX_BRACKET_ROUNDS} rounds."
+    );
+    ensure!(
+        rake_bps <= MAX_BRACKET_RAKE_BPS,
+        GameError::InvalidArgument,
+        "The rake can be at most {MAX_BRACKET_RAKE_BPS} basis points."
+    );
+    ensure!(
+        registration_millis > 0 && round_duration_millis > 0,
+        GameError::InvalidArgument,
+        "Registration and rounds must last a positive duration."
+    );
+
+    let bracket_id = state.next_bracket_id;
+    state.next_bracket_id += 1;
+    state.brackets.insert(
+        bracket_id,
+        Bracket::new(
+            entry_fee,
+            rounds,
+            rake_bps,
+            context.block_production_time + registration_millis,
+            round_duration_millis,
+        ),
+    );
+    (state, vec![], vec![])
+}
+
+/// Register for a bracket. The entry fee is escrowed through the token contract and the player
+/// only registered once the transfer succeeds.
+#[action(shortname = 0x8E, zk = true)]
+pub fn register_for_bracket(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    bracket_id: u32,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_accepting_bets();
+    state.close_import();
+    state.assert_allowlisted(&context.sender);
+    let bracket = state
+        .brackets
+        .get(&bracket_id)
+        .or_fail(GameError::NotFound, "Unknown bracket.");
+    ensure!(
+        bracket.is_registering(context.block_production_time),
+        GameError::InvalidPhase,
+        "The bracket is not open for registration."
+    );
+    ensure!(
+        !bracket.is_registered(&context.sender),
+        GameError::AlreadyDone,
+        "Already registered for the bracket."
+    );
+
+    let mut event_group = EventGroup::builder();
+    token_client::transfer_from(
+        &mut event_group,
+        &state.gas_costs,
+        state.token_address,
+        context.sender,
+        context.contract_address,
+        bracket.entry_fee,
+    );
+    token_client::register_callback(&mut event_group, &state.gas_costs, 0x15)
+        .argument(context.sender)
+        .argument(bracket_id)
+        .done();
+
+    (state, vec![event_group.build()], vec![])
+}
+
+/// Callback registering a player whose entry fee was escrowed. Fees arriving once the bracket
+/// is full or its registration has closed are refunded instead.
+#[callback(shortname = 0x15, zk = true)]
+pub fn bracket_registration_callback(
+    context: ContractContext,
+    callback_ctx: CallbackContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    player: Address,
+    bracket_id: u32,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    ensure!(
+        callback_ctx.results[0].succeeded,
+        GameError::TransferFailed,
+        "Token transfer failed, the player was not registered."
+    );
+
+    let bracket = state.brackets.get_mut(&bracket_id).unwrap();
+    let entry_fee = bracket.entry_fee;
+    if bracket.is_registering(context.block_production_time) && !bracket.is_registered(&player) {
+        bracket.register(player, context.block_production_time);
+        return (state, vec![], vec![]);
+    }
+
+    let mut event_group = EventGroup::builder();
+    token_client::transfer(
+        &mut event_group,
+        &state.gas_costs,
+        state.token_address,
+        player,
+        entry_fee,
+    );
+    (state, vec![event_group.build()], vec![])
+}
+
+/// Cancel a bracket whose registration closed before it was full, refunding the entry fees.
+/// Callable by anyone.
+#[action(shortname = 0x8F, zk = true)]
+pub fn cancel_bracket(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    bracket_id: u32,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let bracket = state
+        .brackets
+        .get_mut(&bracket_id)
+        .or_fail(GameError::NotFound, "Unknown bracket.");
+    ensure!(
+        bracket.status == BracketStatus::Registering {}
+            && context.block_production_time >= bracket.registration_closes_at,
+        GameError::InvalidPhase,
+        "Only brackets left unfilled when registration closed can be cancelled."
+    );
+
+    bracket.status = BracketStatus::Cancelled {};
+    bracket.pot = 0;
+    let players = bracket.players.clone();
+    let entry_fee = bracket.entry_fee;
+    if players.is_empty() {
+        return (state, vec![], vec![]);
+    }
+
+    let mut event_group = EventGroup::builder();
+    for player in players {
+        token_client::transfer(
+            &mut event_group,
+            &state.gas_costs,
+            state.token_address,
+            player,
+            entry_fee,
+        );
+    }
+    (state, vec![event_group.build()], vec![])
+}
+
+/// Contribute randomness to the sender's match in the current round of a bracket. The flip of
+/// the match can be computed once both of its players have contributed.
+#[zk_on_secret_input(shortname = 0x46, secret_type = "RandomContribution")]
+pub fn add_bracket_randomness(
+    context: ContractContext,
+    state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    bracket_id: u32,
+) -> (
+    CoinFlipState,
+    Vec<EventGroup>,
+    ZkInputDef<SecretVarType, RandomContribution>,
+) {
+    state.assert_not_paused();
+    let bracket = state
+        .brackets
+        .get(&bracket_id)
+        .or_fail(GameError::NotFound, "Unknown bracket.");
+    bracket
+        .match_of(&context.sender)
+        .filter(|match_index| bracket.is_open(*match_index))
+        .or_fail(GameError::InvalidPhase, "The sender has no match to play in the bracket.");
+    ensure!(
+        context.block_production_time < bracket.round_deadline,
+        GameError::DeadlinePassed,
+        "The round of the bracket is over."
+    );
+    let round = bracket.round;
+    let already_contributed = bracket_randomness_of(&zk_state, bracket_id, round, &context.sender)
+        .is_some()
+        || zk_state.pending_inputs.iter().any(|(_, variable)| {
+            matches!(variable.metadata, SecretVarType::BracketRandomness { bracket_id: id, round: r, player } if id == bracket_id && r == round && player == context.sender)
+        });
+    ensure!(
+        !already_contributed,
+        GameError::AlreadyDone,
+        "Randomness has already been contributed to the match."
+    );
+
+    let input_def = ZkInputDef::with_metadata(
+        Some(SHORTNAME_INPUTTED_VARIABLE),
+        SecretVarType::BracketRandomness {bracket_id, round, player: context.sender},
+    );
+
+    (state, vec![], input_def)
+}
+
+/// Decide a match of the current round of a bracket. Callable by anyone.
+///
+/// Once both players have contributed randomness the match is decided by a ZK coin flip. After
+/// the round deadline, a player who contributed wins by walkover, and the higher seed advances if
+/// neither did.
+#[action(shortname = 0x90, zk = true)]
+pub fn play_bracket_match(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    bracket_id: u32,
+    match_index: u32,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_not_paused();
+    let bracket = state
+        .brackets
+        .get(&bracket_id)
+        .or_fail(GameError::NotFound, "Unknown bracket.");
+    ensure!(
+        bracket.is_open(match_index),
+        GameError::InvalidPhase,
+        "The match is not waiting to be played."
+    );
+    let round = bracket.round;
+    let round_deadline = bracket.round_deadline;
+    let (first, second) = bracket.pairing(match_index);
+    let first_randomness = bracket_randomness_of(&zk_state, bracket_id, round, &first);
+    let second_randomness = bracket_randomness_of(&zk_state, bracket_id, round, &second);
+
+    if let (Some(first_input), Some(second_input)) = (first_randomness, second_randomness) {
+        state
+            .brackets
+            .get_mut(&bracket_id)
+            .unwrap()
+            .computing
+            .push(match_index);
+        return (
+            state,
+            vec![],
+            vec![zk_compute::compute_bracket_match_start(
+                first_input,
+                second_input,
+                Some(SHORTNAME_FLIP_COMPUTE_COMPLETE),
+                &SecretVarType::BracketMatch {bracket_id, round, match_index},
+            )],
+        );
+    }
+
+    ensure!(
+        context.block_production_time >= round_deadline,
+        GameError::InvalidPhase,
+        "Both players have until the round deadline to contribute."
+    );
+    let winner = if first_randomness.is_some() || second_randomness.is_none() {
+        first
+    } else {
+        second
+    };
+    let variables_to_delete = first_randomness.into_iter().chain(second_randomness).collect();
+    let event_groups = decide_bracket_match(
+        &mut state,
+        bracket_id,
+        match_index,
+        winner,
+        context.block_production_time,
+    );
+    (
+        state,
+        event_groups,
+        vec![ZkStateChange::DeleteVariables {
+            variables_to_delete,
+        }],
+    )
+}
+
+/// Find the confirmed randomness a player contributed to a round of a bracket, if any.
+fn bracket_randomness_of(
+    zk_state: &ZkState<SecretVarType>,
+    bracket_id: u32,
+    round: u8,
+    player: &Address,
+) -> Option<SecretVarId> {
+    zk_state
+        .secret_variables
+        .iter()
+        .find(|(_, variable)| {
+            matches!(variable.metadata, SecretVarType::BracketRandomness { bracket_id: id, round: r, player: contributor } if id == bracket_id && r == round && contributor == *player)
+        })
+        .map(|(variable_id, _)| *variable_id)
+}
+
+/// Record the winner of a bracket match, paying the pot less the rake to the winner of the
+/// bracket once its final is decided.
+fn decide_bracket_match(
+    state: &mut CoinFlipState,
+    bracket_id: u32,
+    match_index: u32,
+    winner: Address,
+    now: i64,
+) -> Vec<EventGroup> {
+    let bracket = state.brackets.get_mut(&bracket_id).unwrap();
+    let Some(champion) = bracket.decide(match_index, winner, now) else {
+        return vec![];
+    };
+    let rake = bracket.rake();
+    let prize = bracket.pot - rake;
+    state.collect_fee(rake);
+
+    let mut event_group = EventGroup::builder();
+    token_client::transfer(
+        &mut event_group,
+        &state.gas_costs,
+        state.token_address,
+        champion,
+        prize,
+    );
+    vec![event_group.build()]
+}
+
+/// Commit the house to a secret seed for a future epoch by inputting it to the ZK nodes. The
+/// seed is folded into every round computed during the epoch, and is only committed once it is
+/// confirmed before the epoch starts. Only callable by the owner or an operator.
+#[zk_on_secret_input(shortname = 0x49, secret_type = "RandomContribution")]
+pub fn commit_house_seed(
+    context: ContractContext,
+    state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    epoch: u32,
+) -> (
+    CoinFlipState,
+    Vec<EventGroup>,
+    ZkInputDef<SecretVarType, RandomContribution>,
+) {
+    state.assert_operator(&context.sender);
+    ensure!(
+        epoch > state.epoch.epoch,
+        GameError::InvalidArgument,
+        "Seeds can only be committed before their epoch starts."
+    );
+    let is_seed_of_epoch = |metadata: &SecretVarType| {
+        matches!(metadata, SecretVarType::HouseSeed { epoch: other } if *other == epoch)
+    };
+    ensure!(
+        state.house_seeds.get(&epoch).is_none()
+            && !zk_state
+                .pending_inputs
+                .iter()
+                .any(|(_, variable)| is_seed_of_epoch(&variable.metadata)),
+        GameError::AlreadyDone,
+        "A seed has already been committed for the epoch."
+    );
+
+    let input_def = ZkInputDef::with_metadata(
+        Some(SHORTNAME_INPUTTED_VARIABLE),
+        SecretVarType::HouseSeed {epoch},
+    );
+    (state, vec![], input_def)
+}
+
+/// Open the house seed of an epoch that is over, sealing it together with the outcomes of the
+/// rounds settled in the epoch. Callable by anyone, so the seed is revealed even if the operator
+/// never does it.
+#[action(shortname = 0x92, zk = true)]
+pub fn reveal_house_seed(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    epoch: u32,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    ensure!(
+        epoch < state.epoch.epoch,
+        GameError::InvalidPhase,
+        "Seeds can only be revealed once their epoch is over."
+    );
+    let commitment = state
+        .house_seeds
+        .get_mut(&epoch)
+        .or_fail(GameError::NotFound, "No seed was committed for the epoch.");
+    ensure!(
+        commitment.revealed_seed.is_none() && !commitment.reveal_requested,
+        GameError::AlreadyDone,
+        "The seed has already been revealed."
+    );
+    commitment.reveal_requested = true;
+    let seed_variable = house_seed_variable_of(&zk_state, epoch)
+        .or_fail(GameError::NotFound, "The seed of the epoch is not held by the ZK nodes.");
+    (
+        state,
+        vec![],
+        vec![ZkStateChange::OpenVariables {
+            variables: vec![seed_variable],
+        }],
+    )
+}
+
+/// Push the escrow, randomness completion, opened result and payout of every player's game to
+/// the event sink, or stop pushing them with `None`. Only callable by the owner.
+#[action(shortname = 0x93, zk = true)]
+pub fn set_event_sink(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    config: Option<EventSinkConfig>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    state.event_sink = config;
+    state.game_event_outbox.clear();
+    (state, vec![], vec![])
+}
+
+/// Accept bets only while the free house bankroll can reserve their potential payout, so that
+/// however many open bets win, their winnings are covered by what was reserved for them. The
+/// reservation is released when the bet is lost or cleared. Only callable by the owner.
+#[action(shortname = 0x94, zk = true)]
+pub fn set_winnings_reserve(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    enabled: bool,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    state.reserve_winnings = enabled;
+    (state, vec![], vec![])
+}
+
+/// Set how many randomness contributions a sender may have in open rounds at once. Only callable
+/// by the owner or an operator.
+#[action(shortname = 0x95, zk = true)]
+pub fn set_max_sender_contributions(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    max_contributions: u32,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_operator(&context.sender);
+    ensure!(
+        max_contributions >= 1,
+        GameError::InvalidArgument,
+        "A sender must be allowed at least one contribution."
+    );
+    state.max_sender_contributions = max_contributions;
+    (state, vec![], vec![])
+}
+
+/// Register the public key the sender signs their bet intents with, or stop accepting signed
+/// intents of the sender with `None`.
+#[action(shortname = 0x96, zk = true)]
+pub fn register_intent_key(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    public_key: Option<PublicKey>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    match public_key {
+        Some(public_key) => state.intent_keys.insert(context.sender, public_key),
+        None => state.intent_keys.remove(&context.sender),
+    };
+    (state, vec![], vec![])
+}
+
+/// Place the bet of a signed intent on behalf of its player, as `start_game_and_place_bet` would
+/// if the player placed it. The intent must be signed with the player's registered key, name this
+/// contract and the player's next bet nonce, and not be expired. Only callable by the owner or an
+/// operator.
+#[action(shortname = 0x97, zk = true)]
+pub fn submit_signed_bet(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    intent: BetIntent,
+    signature: Signature,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_operator(&context.sender);
+    ensure!(
+        intent.contract == context.contract_address,
+        GameError::InvalidArgument,
+        "The intent is for another contract."
+    );
+    ensure!(
+        context.block_production_time < intent.expires_at,
+        GameError::DeadlinePassed,
+        "The intent has expired."
+    );
+    let public_key = state
+        .intent_keys
+        .get(&intent.player)
+        .or_fail(GameError::NotFound, "The player has no registered intent key.");
+    ensure!(
+        intent.is_signed_by(&signature, &public_key),
+        GameError::InvalidProof,
+        "The intent is not signed by the player."
+    );
+
+    let player_bet = PlayerBet {
+        amount: intent.amount,
+        choice: Some(intent.choice),
+        secret_amount: false,
+        multi_flip: None,
+        dice: None,
+        roulette: None,
+        beneficiary: None,
+    };
+    // The nonce is checked and consumed when the bet is placed
+    let proxied = on_behalf_of(&context, intent.player);
+    let event_groups =
+        place_bet(&proxied, &mut state, intent.nonce, player_bet, intent.token, None);
+    (state, event_groups, vec![])
+}
+
+/// Start the game with a bet like `start_game_and_place_bet`, whose winnings are paid to
+/// `beneficiary`, e.g. a cold wallet or the recipient of a gift, rather than to the sender. The
+/// bet still counts towards the statistics, limits and standings of the sender.
+#[action(shortname = 0x9F, zk = true)]
+pub fn start_game_with_beneficiary(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    bet_amount: u64,
+    choice: PlayerChoice,
+    token: Address,
+    beneficiary: Address,
+    nonce: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let player_bet = PlayerBet {
+        amount: bet_amount,
+        choice: Some(choice),
+        secret_amount: false,
+        multi_flip: None,
+        dice: None,
+        roulette: None,
+        beneficiary: Some(beneficiary),
+    };
+    let event_groups = place_bet(&context, &mut state, nonce, player_bet, token, None);
+    (state, event_groups, vec![])
+}
+
+/// Open a private table staking `stake` of the default token per participant, joined with the
+/// invite code hashing to `invite_code_hash` by the addresses in `participants` within
+/// `join_millis`. No house edge applies to the table.
+#[action(shortname = 0xA0, zk = true)]
+pub fn open_friends_table(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    invite_code_hash: Hash,
+    stake: u64,
+    participants: Vec<Address>,
+    join_millis: i64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_accepting_bets();
+    ensure!(
+        stake > 0 && join_millis > 0,
+        GameError::InvalidArgument,
+        "The stake and the time to join must be positive."
+    );
+    ensure!(
+        (2..=MAX_TABLE_PARTICIPANTS).contains(&participants.len()),
+        GameError::InvalidArgument,
+        "A table has between 2 and {MAX_TABLE_PARTICIPANTS} participants."
+    );
+    let mut distinct = participants.clone();
+    distinct.sort();
+    distinct.dedup();
+    ensure!(
+        distinct.len() == participants.len(),
+        GameError::InvalidArgument,
+        "Participants can only be invited once."
+    );
+    ensure!(
+        state.table_invites.get(&invite_code_hash).is_none(),
+        GameError::AlreadyDone,
+        "The invite code is already in use."
+    );
+
+    let table_id = state.next_table_id;
+    state.next_table_id += 1;
+    state.table_invites.insert(invite_code_hash, table_id);
+    state.friends_tables.insert(
+        table_id,
+        FriendsTable {
+            creator: context.sender,
+            invite_code_hash,
+            stake,
+            participants,
+            joined: SortedVecMap::new(),
+            join_deadline: context.block_production_time.saturating_add(join_millis),
+            status: TableStatus::Open {},
+        },
+    );
+    (state, vec![], vec![])
+}
+
+/// Join the friends table of `invite_code` on the side of `choice`. The sender must be invited to
+/// the table, and only joins once their stake is escrowed.
+#[action(shortname = 0xA1, zk = true)]
+pub fn join_friends_table(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    invite_code: Vec<u8>,
+    choice: PlayerChoice,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_accepting_bets();
+    state.close_import();
+    let table_id = state
+        .table_invites
+        .get(&Hash::digest(&invite_code))
+        .copied()
+        .or_fail(GameError::NotFound, "No open table has the invite code.");
+    let table = state.friends_tables.get(&table_id).unwrap();
+    ensure!(
+        table.is_invited(&context.sender),
+        GameError::Unauthorized,
+        "The sender is not invited to the table."
+    );
+    ensure!(
+        !table.has_joined(&context.sender),
+        GameError::AlreadyDone,
+        "Already joined the table."
+    );
+    ensure!(
+        table.is_joinable(context.block_production_time),
+        GameError::InvalidPhase,
+        "The table can no longer be joined."
+    );
+
+    let mut event_group = EventGroup::builder();
+    token_client::transfer_from(
+        &mut event_group,
+        &state.gas_costs,
+        state.token_address,
+        context.sender,
+        context.contract_address,
+        table.stake,
+    );
+    token_client::register_callback(&mut event_group, &state.gas_costs, 0x18)
+        .argument(table_id)
+        .argument(context.sender)
+        .argument(choice)
+        .done();
+
+    (state, vec![event_group.build()], vec![])
+}
+
+/// Callback seating a participant whose stake was escrowed. Stakes arriving once the table can no
+/// longer be joined, or twice, are refunded instead.
+#[callback(shortname = 0x18, zk = true)]
+pub fn friends_table_join_callback(
+    context: ContractContext,
+    callback_ctx: CallbackContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    table_id: u32,
+    participant: Address,
+    choice: PlayerChoice,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    ensure!(
+        callback_ctx.results[0].succeeded,
+        GameError::TransferFailed,
+        "Token transfer failed, the participant did not join."
+    );
+
+    let table = state.friends_tables.get_mut(&table_id).unwrap();
+    let stake = table.stake;
+    if table.is_joinable(context.block_production_time) && !table.has_joined(&participant) {
+        table.joined.insert(participant, choice);
+        return (state, vec![], vec![]);
+    }
+
+    let mut event_group = EventGroup::builder();
+    token_client::transfer(
+        &mut event_group,
+        &state.gas_costs,
+        state.token_address,
+        participant,
+        stake,
+    );
+    (state, vec![event_group.build()], vec![])
+}
+
+/// Contribute randomness to the flip of a friends table the sender joined.
+#[zk_on_secret_input(shortname = 0x47, secret_type = "RandomContribution")]
+pub fn add_table_randomness(
+    context: ContractContext,
+    state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    table_id: u32,
+) -> (
+    CoinFlipState,
+    Vec<EventGroup>,
+    ZkInputDef<SecretVarType, RandomContribution>,
+) {
+    state.assert_not_paused();
+    let table = state
+        .friends_tables
+        .get(&table_id)
+        .or_fail(GameError::NotFound, "Unknown table.");
+    ensure!(
+        table.status == TableStatus::Open {} && table.has_joined(&context.sender),
+        GameError::InvalidPhase,
+        "Only participants of an open table can contribute to its flip."
+    );
+    let is_own_contribution = |metadata: &SecretVarType| {
+        matches!(metadata, SecretVarType::TableRandomness { table_id: id, player } if *id == table_id && *player == context.sender)
+    };
+    let already_contributed = zk_state
+        .secret_variables
+        .iter()
+        .any(|(_, variable)| is_own_contribution(&variable.metadata))
+        || zk_state
+            .pending_inputs
+            .iter()
+            .any(|(_, variable)| is_own_contribution(&variable.metadata));
+    ensure!(
+        !already_contributed,
+        GameError::AlreadyDone,
+        "Randomness has already been contributed to the table."
+    );
+
+    let input_def = ZkInputDef::with_metadata(
+        Some(SHORTNAME_INPUTTED_VARIABLE),
+        SecretVarType::TableRandomness {table_id, player: context.sender},
+    );
+    (state, vec![], input_def)
+}
+
+/// Flip the coin of a friends table once every participant has joined and contributed
+/// randomness. Callable by any participant.
+#[action(shortname = 0xA2, zk = true)]
+pub fn flip_friends_table(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    table_id: u32,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_not_paused();
+    let table = state
+        .friends_tables
+        .get_mut(&table_id)
+        .or_fail(GameError::NotFound, "Unknown table.");
+    ensure!(
+        table.is_invited(&context.sender),
+        GameError::Unauthorized,
+        "Only participants can flip the table."
+    );
+    ensure!(
+        table.status == TableStatus::Open {} && table.is_full(),
+        GameError::InvalidPhase,
+        "The table is not waiting for its flip."
+    );
+    let contributions = table_randomness_of(&zk_state, table_id).len();
+    ensure!(
+        contributions == table.participants.len(),
+        GameError::MissingRandomness,
+        "Every participant must contribute randomness first."
+    );
+
+    table.status = TableStatus::Flipping {};
+    (
+        state,
+        vec![],
+        vec![zk_compute::compute_table_flip_start(
+            table_id,
+            Some(SHORTNAME_FLIP_COMPUTE_COMPLETE),
+            &SecretVarType::TableFlip {table_id},
+        )],
+    )
+}
+
+/// Call off an open friends table, refunding the stakes of its participants. Callable by the
+/// creator, or by anyone once the table was left unfilled past its join deadline.
+#[action(shortname = 0xA3, zk = true)]
+pub fn cancel_friends_table(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    table_id: u32,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let table = state
+        .friends_tables
+        .get_mut(&table_id)
+        .or_fail(GameError::NotFound, "Unknown table.");
+    ensure!(
+        table.status == TableStatus::Open {},
+        GameError::InvalidPhase,
+        "Only open tables can be cancelled."
+    );
+    let abandoned = !table.is_full() && context.block_production_time >= table.join_deadline;
+    ensure!(
+        context.sender == table.creator || abandoned,
+        GameError::Unauthorized,
+        "Only the creator can cancel the table before it is abandoned."
+    );
+
+    table.status = TableStatus::Cancelled {};
+    let refunds: Vec<Address> = table.joined.iter().map(|(participant, _)| *participant).collect();
+    let stake = table.stake;
+    let invite_code_hash = table.invite_code_hash;
+    state.table_invites.remove(&invite_code_hash);
+
+    let mut events = vec![];
+    if !refunds.is_empty() {
+        let mut event_group = EventGroup::builder();
+        for participant in refunds {
+            token_client::transfer(
+                &mut event_group,
+                &state.gas_costs,
+                state.token_address,
+                participant,
+                stake,
+            );
+        }
+        events.push(event_group.build());
+    }
+    let mut changes = vec![];
+    let variables_to_delete = table_randomness_of(&zk_state, table_id);
+    if !variables_to_delete.is_empty() {
+        changes.push(ZkStateChange::DeleteVariables {
+            variables_to_delete,
+        });
+    }
+    (state, events, changes)
+}
+
+/// Find the confirmed randomness contributed to the flip of a friends table.
+fn table_randomness_of(zk_state: &ZkState<SecretVarType>, table_id: u32) -> Vec<SecretVarId> {
+    zk_state
+        .secret_variables
+        .iter()
+        .filter(|(_, variable)| {
+            matches!(variable.metadata, SecretVarType::TableRandomness { table_id: id, .. } if id == table_id)
+        })
+        .map(|(variable_id, _)| *variable_id)
+        .collect()
+}
+
+/// Chain every game from its bet to its flip: once a stake is escrowed, randomness is requested
+/// from the approved providers, and the flip starts as soon as the round's inputs are confirmed.
+/// `None` goes back to flipping with `flip_coin`. Only callable by the owner.
+#[action(shortname = 0xA4, zk = true)]
+pub fn set_pipeline(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    pipeline: Option<PipelineConfig>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    state.pipeline = pipeline;
+    (state, vec![], vec![])
+}
+
+/// Credit the sender `amount` of play money in the default token, making everything the sender
+/// holds in the contract play money that cannot be withdrawn. Only available in test mode.
+#[action(shortname = 0xA5, zk = true)]
+pub fn mint_play_money(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    amount: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_test_mode();
+    ensure!(
+        amount <= MAX_FAUCET_AMOUNT,
+        GameError::InvalidArgument,
+        "The faucet credits at most {MAX_FAUCET_AMOUNT} at a time."
+    );
+    state.play_money_accounts.insert(context.sender, true);
+    state.adjust_balance(context.sender, amount);
+    (state, vec![], vec![])
+}
+
+/// Resolve the escrowed game of `player` as won or lost without flipping, deleting the randomness
+/// contributed to its round. Only callable by the owner or an operator, in test mode.
+#[action(shortname = 0xA6, zk = true)]
+pub fn force_resolve_game(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    player: Address,
+    player_won: bool,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_test_mode();
+    state.assert_operator(&context.sender);
+    ensure!(
+        state.game_phases.get(&player) == Some(GamePhase::FlipCoin {}),
+        GameError::InvalidPhase,
+        "Only a game in the FlipCoin phase can be resolved."
+    );
+    ensure!(
+        state.computation_pending.get(&player) != Some(true),
+        GameError::Unavailable,
+        "The game is already being flipped."
+    );
+    let now = context.block_production_time;
+    state.set_phase(player, GamePhase::Done {}, now);
+    state.conclude_game(player, player_won, context.contract_address, now);
+    let changes = finish_round(&mut state, &zk_state, &player, vec![]);
+    let events = settlement_events(&mut state);
+    (state, events, changes)
+}
+
+/// Move every deadline of the game of `player` `millis` into the past, as if that much time had
+/// passed. Only callable by the owner or an operator, in test mode.
+#[action(shortname = 0xA7, zk = true)]
+pub fn fast_forward_game(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    player: Address,
+    millis: i64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_test_mode();
+    state.assert_operator(&context.sender);
+    ensure!(millis > 0, GameError::InvalidArgument, "The time to skip must be positive.");
+    state.rewind_game_clock(&player, millis);
+    (state, vec![], vec![])
+}
+
+/// Void the escrowed, unsettled game of `player` after an incident, refunding its stake together
+/// with the fees, jackpot rake and commission charged on it as far as they can be reversed. Only
+/// callable by the owner or an operator.
+#[action(shortname = 0xA8, zk = true)]
+pub fn void_game(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    player: Address,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_operator(&context.sender);
+    let phase = state.game_phases.get(&player).unwrap_or(GamePhase::Start {});
+    ensure!(
+        matches!(
+            phase,
+            GamePhase::FlipCoin {} | GamePhase::BonusRound {} | GamePhase::Challengeable {}
+        ),
+        GameError::InvalidPhase,
+        "Only an escrowed game that is not settled can be voided."
+    );
+    // A started flip would still settle the game once its result is opened
+    let flipping = phase == GamePhase::FlipCoin {}
+        && state.computation_pending.get(&player) == Some(true)
+        && !state.compute_queue.contains(&player);
+    ensure!(
+        !flipping,
+        GameError::Unavailable,
+        "The game is being flipped and can only be voided once it is settled or expired."
+    );
+
+    let now = context.block_production_time;
+    state.compute_queue.remove(&player);
+    let (events, changes) = refund_escrowed_game(&mut state, &zk_state, &player);
+    state.challenge_deadlines.remove(&player);
+    state.clear_player_record(&player, now);
+    state.set_phase(player, GamePhase::Start {}, now);
+    (state, events, changes)
+}
+
+/// Pay the house's sealed take from lost sealed stakes into the house bankroll, once it totals at
+/// least `MIN_SEALED_LOSSES_PER_RELEASE` lost bets. Only the total of every stake lost since the
+/// last release is revealed. Only callable by the owner.
+#[action(shortname = 0xA9, zk = true)]
+pub fn release_sealed_house_take(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    let has_take = zk_state
+        .secret_variables
+        .iter()
+        .any(|(_, variable)| matches!(variable.metadata, SecretVarType::SealedHouseTake { .. }));
+    ensure!(
+        has_take,
+        GameError::NotFound,
+        "The house has no sealed take."
+    );
+    ensure!(
+        state.sealed_house_losses >= MIN_SEALED_LOSSES_PER_RELEASE,
+        GameError::Unavailable,
+        "The sealed take is released once it totals {MIN_SEALED_LOSSES_PER_RELEASE} lost bets."
+    );
+    let computation = sealed_release_computation(&zk_state, true, context.contract_address);
+    state.sealed_house_losses = 0;
+    (state, vec![], vec![computation])
+}
+
+/// Withdraw the sender's sealed credit from lost sealed bets, once it totals at least
+/// `MIN_SEALED_LOSSES_PER_RELEASE` of them. Only the total credited since the last withdrawal is
+/// revealed.
+#[action(shortname = 0xAA, zk = true)]
+pub fn withdraw_sealed_credit(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_not_paused();
+    ensure!(
+        sealed_credit_variable_of(&zk_state, &context.sender).is_some(),
+        GameError::NotFound,
+        "The sender has no sealed credit."
+    );
+    let losses = state.sealed_credit_losses.get(&context.sender).unwrap_or(0);
+    ensure!(
+        losses >= MIN_SEALED_LOSSES_PER_RELEASE,
+        GameError::Unavailable,
+        "Sealed credit is withdrawn once it totals {MIN_SEALED_LOSSES_PER_RELEASE} lost bets."
+    );
+    let computation = sealed_release_computation(&zk_state, false, context.sender);
+    state.sealed_credit_losses.remove(&context.sender);
+    (state, vec![], vec![computation])
+}
+
+/// Start totalling the house's sealed take, or the sealed credit of `recipient`, for release.
+/// Computations run one at a time, so no split adds to the total while it is released.
+fn sealed_release_computation(
+    zk_state: &ZkState<SecretVarType>,
+    of_house: bool,
+    recipient: Address,
+) -> ZkStateChange {
+    ensure!(
+        zk_state.calculation_state == CalculationStatus::Waiting,
+        GameError::Unavailable,
+        "The computation engine is busy, try again later."
+    );
+    zk_compute::compute_sealed_release_start(
+        of_house,
+        recipient,
+        Some(SHORTNAME_SEALED_SPLIT_COMPUTED),
+        &SecretVarType::SealedRelease {recipient},
+    )
+}
+
+/// Import the given accounts from a previous deployment of the contract, in chunks of at most
+/// `MAX_IMPORT_CHUNK`. Imported balances are added to any balance deposited meanwhile, and
+/// imported statistics replace the player's. Only possible before the first stake, and each
+/// address only once. Only callable by the owner.
+#[action(shortname = 0xAB, zk = true)]
+pub fn import_state(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    entries: Vec<ImportedAccount>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    state.assert_importable(&entries);
+    for account in entries {
+        state.adjust_balance(account.user, account.balance);
+        state.record_import(account);
+    }
+    (state, vec![], vec![])
+}
+
+/// Approve `bot` for sessions running up to `max_flips` flips each, or stop it from opening new
+/// sessions with `None`. Only callable by the owner or an operator.
+#[action(shortname = 0x9B, zk = true)]
+pub fn approve_session_bot(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    bot: Address,
+    max_flips: Option<u32>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_operator(&context.sender);
+    match max_flips {
+        Some(max_flips) => {
+            ensure!(
+                max_flips > 0,
+                GameError::InvalidArgument,
+                "A session must be allowed at least one flip."
+            );
+            state.session_bots.insert(bot, max_flips);
+        }
+        None => {
+            state.session_bots.remove(&bot);
+        }
+    }
+    (state, vec![], vec![])
+}
+
+/// Open a session of the sender, an approved bot, escrowing `deposit` of the default token in
+/// bulk. Depositing into an open session adds to its balance.
+#[action(shortname = 0x9C, zk = true)]
+pub fn open_session(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    deposit: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_not_paused();
+    state.close_import();
+    ensure!(
+        state.session_bots.get(&context.sender).is_some(),
+        GameError::Unauthorized,
+        "Only approved bots can open sessions."
+    );
+    ensure!(
+        deposit > 0,
+        GameError::InvalidArgument,
+        "The deposit must be positive."
+    );
+
+    let mut event_group = EventGroup::builder();
+    token_client::transfer_from(
+        &mut event_group,
+        &state.gas_costs,
+        state.token_address,
+        context.sender,
+        context.contract_address,
+        deposit,
+    );
+    token_client::register_callback(&mut event_group, &state.gas_costs, 0x17)
+        .argument(context.sender)
+        .argument(deposit)
+        .done();
+
+    (state, vec![event_group.build()], vec![])
+}
+
+/// Credit the deposit of a session once its transfer succeeded, opening the session if it is not
+/// open yet.
+#[callback(shortname = 0x17, zk = true)]
+pub fn open_session_callback(
+    context: ContractContext,
+    callback_ctx: CallbackContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    bot: Address,
+    deposit: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    ensure!(
+        callback_ctx.results[0].succeeded,
+        GameError::TransferFailed,
+        "Token transfer failed, the session was not funded."
+    );
+    // A bot whose approval was revoked meanwhile gets a session without flips, which it can only
+    // close
+    let max_flips = state.session_bots.get(&bot).copied().unwrap_or(0);
+    let mut session = state.bot_sessions.get(&bot).cloned().unwrap_or(BotSession {
+        deposited: 0,
+        balance: 0,
+        flips_left: max_flips,
+        opened_at: context.block_production_time,
+    });
+    session.deposited += deposit;
+    session.balance += deposit;
+    state.bot_sessions.insert(bot, session);
+    (state, vec![], vec![])
+}
+
+/// Start the sender's next flip of their session, staking `bet_amount` on `choice` from the
+/// session balance without an escrow transfer. What the previous flip of the session won is
+/// credited to the balance first.
+#[action(shortname = 0x9D, zk = true)]
+pub fn session_flip(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    bet_amount: u64,
+    choice: PlayerChoice,
+    nonce: u64,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    credit_session_payout(&mut state, &context.sender, context.block_production_time);
+    let player_bet = PlayerBet {
+        amount: bet_amount,
+        choice: Some(choice),
+        secret_amount: false,
+        multi_flip: None,
+        dice: None,
+        roulette: None,
+        beneficiary: None,
+    };
+    let token = state.token_address;
+    let source = StakeSource::Session;
+    let event_groups =
+        place_funded_bet(&context, &mut state, nonce, player_bet, token, None, source);
+    let changes = settlement_attestations(&mut state);
+    (state, event_groups, changes)
+}
+
+/// Close the sender's session once its last flip is settled, paying out the session balance: the
+/// deposits plus the net result of the session's flips.
+#[action(shortname = 0x9E, zk = true)]
+pub fn close_session(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let now = context.block_production_time;
+    credit_session_payout(&mut state, &context.sender, now);
+    ensure!(
+        state.session_bets.get(&context.sender).is_none()
+            || state.game_phases.get(&context.sender) == Some(GamePhase::Done {}),
+        GameError::InvalidPhase,
+        "The last flip of the session must be settled first."
+    );
+    let session = state
+        .bot_sessions
+        .get(&context.sender)
+        .cloned()
+        .or_fail(GameError::NotFound, "The sender has no open session.");
+    state.bot_sessions.remove(&context.sender);
+    state.session_bets.remove(&context.sender);
+
+    let mut events = if session.balance > 0 {
+        let mut event_group = EventGroup::builder();
+        token_client::transfer(
+            &mut event_group,
+            &state.gas_costs,
+            state.token_address,
+            context.sender,
+            session.balance,
+        );
+        vec![event_group.build()]
+    } else {
+        vec![]
+    };
+    events.extend(settlement_events(&mut state));
+    let changes = settlement_attestations(&mut state);
+    (state, events, changes)
+}
+
+/// Credit what the settled last flip of the bot's session owes it to the session balance.
+fn credit_session_payout(state: &mut CoinFlipState, bot: &Address, now: i64) {
+    state.finalize_expired_challenge(bot, now);
+    let settled = state.game_phases.get(bot) == Some(GamePhase::Done {});
+    if !settled || state.session_bets.get(bot).is_none() {
+        return;
+    }
+    let (payout, _) = take_payout(state, bot);
+    if let Some(session) = state.bot_sessions.get_mut(bot) {
+        session.balance += payout;
+    }
+}
+
+/// Configure the oracle quoting the USD price of each bet's token and the bet limits in USD, or
+/// remove it with `None`. Only callable by the owner.
+#[action(shortname = 0x9A, zk = true)]
+pub fn set_price_oracle(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    oracle: Option<PriceOracleConfig>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    if let Some(oracle) = &oracle {
+        ensure!(
+            oracle.min_bet_usd <= oracle.max_bet_usd,
+            GameError::InvalidArgument,
+            "The minimum bet in USD must not exceed the maximum bet."
+        );
+    }
+    state.price_oracle = oracle;
+    (state, vec![], vec![])
+}
+
+/// Configure how long won games stay claimable and where their winnings go once the claim window
+/// has passed, or stop sweeping unclaimed winnings with `None`. Only callable by the owner.
+#[action(shortname = 0x98, zk = true)]
+pub fn set_unclaimed_vault(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    vault: Option<UnclaimedVaultConfig>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    state.assert_owner(&context.sender);
+    if let Some(vault) = &vault {
+        ensure!(
+            vault.claim_window_millis > 0,
+            GameError::InvalidArgument,
+            "The claim window must be positive."
+        );
+    } else {
+        // Winnings of games settled while the vault was configured stay claimable for good
+        state.claim_deadlines = AvlTreeMap::new();
+    }
+    state.unclaimed_vault = vault;
+    (state, vec![], vec![])
+}
+
+/// Sweep the winnings the given players left unclaimed past their claim deadline into the house
+/// treasury, or to the beneficiary of the unclaimed vault. Players without winnings to sweep are
+/// skipped. Callable by anyone.
+#[action(shortname = 0x99, zk = true)]
+pub fn sweep_unclaimed(
+    context: ContractContext,
+    mut state: CoinFlipState,
+    zk_state: ZkState<SecretVarType>,
+    players: Vec<Address>,
+) -> (CoinFlipState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let vault = state
+        .unclaimed_vault
+        .clone()
+        .or_fail(GameError::Unavailable, "No unclaimed vault is configured.");
+    ensure!(
+        players.len() <= MAX_PAYOUT_BATCH,
+        GameError::InvalidArgument,
+        "At most {MAX_PAYOUT_BATCH} players can be swept at once."
+    );
+
+    let mut swept = 0;
+    for player in players {
+        let amount = take_unclaimed(&mut state, &player, context.block_production_time);
+        if amount == 0 {
+            continue;
+        }
+        match vault.beneficiary {
+            // The claim leaves the contract with the transfer below
+            Some(_) => state.ledger.pay(&player),
+            None => state.ledger.forfeit(&player),
+        }
+        swept += amount;
+    }
+
+    match vault.beneficiary {
+        Some(beneficiary) if swept > 0 => {
+            let mut event_group = EventGroup::builder();
+            token_client::transfer(
+                &mut event_group,
+                &state.gas_costs,
+                state.token_address,
+                beneficiary,
+                swept,
+            );
+            (state, vec![event_group.build()], vec![])
+        }
+        Some(_) => (state, vec![], vec![]),
+        None => {
+            state.collect_fee(swept);
+            (state, vec![], vec![])
+        }
+    }
+}
+
+#[cfg(test)]
+mod state_machine_tests;
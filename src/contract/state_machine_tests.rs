@@ -0,0 +1,2476 @@
+//! Transition table of a coin flip game, driven through the actions and zk hooks that move it.
+//!
+//! | Stage       | bet         | randomness | randomness inputted | flip      | opened | payout   |
+//! |-------------|-------------|------------|---------------------|-----------|--------|----------|
+//! | Idle        | AwaitEscrow | rejected   | deleted             | rejected  | -      | rejected |
+//! | AwaitEscrow | AwaitEscrow | rejected   | deleted             | rejected  | -      | rejected |
+//! | Flipping    | AwaitEscrow | accepted   | kept                | Computing | -      | rejected |
+//! | Computing   | AwaitEscrow | accepted   | deleted             | no-op     | Done   | rejected |
+//! | Done        | AwaitEscrow | rejected   | deleted             | rejected  | -      | paid     |
+//!
+//! A successful escrow of the pending bet moves `AwaitEscrow` to `Flipping`, and a completed
+//! computation always opens its outputs. Betting from `Flipping` or `Computing` abandons the
+//! running game. Randomness is accepted once per contributor and round; repeated contributions
+//! are rejected or deleted. A flip from `Flipping` needs randomness from someone other than the
+//! bettor.
+//!
+//! Games that leave the table early get their stake back: a bet cancelled before any randomness
+//! is refunded minus the cancellation fee, a game past the deadline of its stage is refunded in
+//! full, and a stake arriving after its escrow expired is returned when it arrives.
+
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use pbc_contract_common::address::AddressType;
+use pbc_contract_common::context::ExecutionResult;
+use pbc_contract_common::zk::ZkClosed;
+
+use super::*;
+use crate::gambling_limits::LIMIT_LOOSENING_DELAY_MILLIS;
+use crate::validation::BetViolation;
+use crate::zk_compute::MAX_DICE_FACES;
+
+const RANDOMNESS_ID: u32 = 1;
+const FLIP_RESULT_ID: u32 = 2;
+const REPEATED_RANDOMNESS_ID: u32 = 3;
+const BONUS_MULTIPLIER_ID: u32 = 4;
+const HOUSE_RANDOMNESS_ID: u32 = 9;
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+enum Stage {
+    /// The player has never bet.
+    Idle,
+    /// The bet is placed and its stake is being escrowed.
+    AwaitEscrow,
+    /// The stake is escrowed and the round accepts randomness.
+    Flipping,
+    /// The flip is being computed.
+    Computing,
+    /// The flip is settled and the winnings can be paid out.
+    Done,
+}
+
+const STAGES: [Stage; 5] = [
+    Stage::Idle,
+    Stage::AwaitEscrow,
+    Stage::Flipping,
+    Stage::Computing,
+    Stage::Done,
+];
+
+fn address(id: u8) -> Address {
+    Address {
+        address_type: AddressType::Account,
+        identifier: [id; 20],
+    }
+}
+
+fn owner() -> Address {
+    address(1)
+}
+
+fn player() -> Address {
+    address(2)
+}
+
+fn token() -> Address {
+    address(0xA0)
+}
+
+fn house() -> Address {
+    address(0xB0)
+}
+
+fn context(sender: Address, now: i64) -> ContractContext {
+    ContractContext {
+        contract_address: address(0xC0),
+        sender,
+        block_time: now,
+        block_production_time: now,
+        current_transaction: Hash { bytes: [1; 32] },
+        original_transaction: Hash { bytes: [1; 32] },
+    }
+}
+
+fn variable(id: u32, metadata: SecretVarType, data: Option<Vec<u8>>) -> ZkClosed<SecretVarType> {
+    ZkClosed {
+        variable_id: SecretVarId::new(id),
+        owner: player(),
+        is_sealed: false,
+        metadata,
+        data,
+    }
+}
+
+fn zk_state_with(variables: Vec<ZkClosed<SecretVarType>>) -> ZkState<SecretVarType> {
+    let mut secret_variables = SortedVecMap::new();
+    for variable in variables {
+        secret_variables.insert(variable.variable_id, variable);
+    }
+    ZkState {
+        calculation_state: CalculationStatus::Waiting,
+        pending_inputs: SortedVecMap::new(),
+        secret_variables,
+        data_attestations: SortedVecMap::new(),
+        external_events: SortedVecMap::new(),
+    }
+}
+
+fn idle_zk_state() -> ZkState<SecretVarType> {
+    zk_state_with(vec![])
+}
+
+fn randomness_zk_state() -> ZkState<SecretVarType> {
+    zk_state_with(vec![variable(
+        RANDOMNESS_ID,
+        SecretVarType::Randomness { player: player() },
+        None,
+    )])
+}
+
+/// Confirm a contribution of the house to the player's round, which every round needs before
+/// it can be flipped.
+fn with_house_randomness(state: CoinFlipState) -> CoinFlipState {
+    let metadata = SecretVarType::Randomness { player: player() };
+    let mut randomness = variable(HOUSE_RANDOMNESS_ID, metadata, None);
+    randomness.owner = house();
+    let zk_state = zk_state_with(vec![randomness]);
+    let variable_id = SecretVarId::new(HOUSE_RANDOMNESS_ID);
+    inputted_variable(context(house(), 25), state, zk_state, variable_id).0
+}
+
+/// An opened flip landing heads, which the player bets on and so wins.
+fn flip_result_zk_state() -> ZkState<SecretVarType> {
+    zk_state_with(vec![variable(
+        FLIP_RESULT_ID,
+        SecretVarType::FlipResult { player: player() },
+        Some(vec![1, 0, 1, 0]),
+    )])
+}
+
+/// A zk state whose computation is still running.
+fn computing_zk_state() -> ZkState<SecretVarType> {
+    let mut zk_state = idle_zk_state();
+    zk_state.calculation_state = CalculationStatus::Calculating;
+    zk_state
+}
+
+fn callback_with(succeeded: bool) -> CallbackContext {
+    CallbackContext {
+        success: succeeded,
+        results: vec![ExecutionResult {
+            succeeded,
+            return_data: vec![],
+        }],
+    }
+}
+
+fn escrowed() -> CallbackContext {
+    callback_with(true)
+}
+
+fn failed() -> CallbackContext {
+    callback_with(false)
+}
+
+/// A bet of `amount` on heads, which the flip of `flip_result_zk_state` wins.
+fn heads_bet(amount: u64) -> PlayerBet {
+    PlayerBet {
+        amount,
+        choice: Some(PlayerChoice::Heads {}),
+        secret_amount: false,
+        multi_flip: None,
+        dice: None,
+        roulette: None,
+        beneficiary: None,
+    }
+}
+
+/// Drive a game of the player to the given stage.
+fn game_at(stage: Stage) -> CoinFlipState {
+    let (state, _) = initialize(context(owner(), 0), idle_zk_state(), InitConfig::new(token()));
+    if stage == Stage::Idle {
+        return state;
+    }
+    let (state, _, _) = start_game_and_place_bet(
+        context(player(), 10),
+        state,
+        idle_zk_state(),
+        100,
+        PlayerChoice::Heads {},
+        token(),
+        0,
+    );
+    if stage == Stage::AwaitEscrow {
+        return state;
+    }
+    let (state, _, _) = transfer_success_callback(
+        context(token(), 20),
+        escrowed(),
+        state,
+        idle_zk_state(),
+        player(),
+        100,
+        0,
+    );
+    if stage == Stage::Flipping {
+        return state;
+    }
+    let state = with_house_randomness(state);
+    let (state, _, _) = flip_coin(context(player(), 30), state, idle_zk_state());
+    if stage == Stage::Computing {
+        return state;
+    }
+    let (state, _, _) = open_flip_result_variable(
+        context(player(), 40),
+        state,
+        flip_result_zk_state(),
+        vec![SecretVarId::new(FLIP_RESULT_ID)],
+    );
+    state
+}
+
+/// The completed game of `game_at(Stage::Done)` with its winnings paid out, so the player may bet
+/// again.
+fn paid_out_game() -> CoinFlipState {
+    payout_winner(context(player(), 50), game_at(Stage::Done), idle_zk_state()).0
+}
+
+fn phase_of(state: &CoinFlipState) -> GamePhase {
+    state.game_phases.get(&player()).unwrap_or(GamePhase::Start {})
+}
+
+fn rejects<T>(step: impl FnOnce() -> T) -> bool {
+    catch_unwind(AssertUnwindSafe(step)).is_err()
+}
+
+fn deletes_variables(changes: &[ZkStateChange]) -> bool {
+    changes
+        .iter()
+        .any(|change| matches!(change, ZkStateChange::DeleteVariables { .. }))
+}
+
+#[test]
+fn initialize_applies_a_valid_config() {
+    let mut config = InitConfig::new(token());
+    config.owner = Some(player());
+    config.min_bet = 10;
+    config.max_bet = 1_000;
+    config.dispute_window_millis = 5_000;
+    let (state, _) = initialize(context(owner(), 0), idle_zk_state(), config.clone());
+    assert_eq!(state.owner, player());
+    assert_eq!(state.token_registry.get(&token()).unwrap().min_bet, 10);
+    assert_eq!(state.dispute_window_millis, 5_000);
+
+    config.min_bet = 2_000;
+    assert!(rejects(|| initialize(context(owner(), 0), idle_zk_state(), config)));
+}
+
+#[test]
+fn stages_have_the_documented_phases() {
+    let phases: Vec<_> = STAGES.iter().map(|stage| phase_of(&game_at(*stage))).collect();
+    assert_eq!(
+        phases,
+        vec![
+            GamePhase::Start {},
+            GamePhase::Start {},
+            GamePhase::FlipCoin {},
+            GamePhase::FlipCoin {},
+            GamePhase::Done {},
+        ]
+    );
+    assert_eq!(game_at(Stage::Computing).computation_pending.get(&player()), Some(true));
+    assert_eq!(game_at(Stage::Done).computation_pending.get(&player()), None);
+}
+
+#[test]
+fn player_view_follows_the_game() {
+    assert!(game_at(Stage::Idle).player_views.get(&player()).is_none());
+    for stage in &STAGES[1..] {
+        let state = game_at(*stage);
+        let view = state.player_views.get(&player()).unwrap();
+        assert_eq!(view.phase, phase_of(&state), "{stage:?}");
+        assert_eq!(view.active_bet.is_some(), *stage != Stage::Done, "{stage:?}");
+        assert_eq!(view.last_won, (*stage == Stage::Done).then_some(true), "{stage:?}");
+    }
+    let state = game_at(Stage::Done);
+    let bet = state.player_bets.get(&player()).unwrap();
+    let view = state.player_views.get(&player()).unwrap();
+    assert_eq!(view.pending_payout, state.winnings_of(&player(), &bet));
+}
+
+#[test]
+fn bet_restarts_the_game_in_every_stage() {
+    for stage in STAGES {
+        let state = if stage == Stage::Done { paid_out_game() } else { game_at(stage) };
+        let nonce = state.next_bet_nonce(&player());
+        let (state, events, changes) = start_game_and_place_bet(
+            context(player(), 100),
+            state,
+            idle_zk_state(),
+            100,
+            PlayerChoice::Tails {},
+            token(),
+            nonce,
+        );
+        assert_eq!(events.len(), 1, "{stage:?}");
+        assert!(changes.is_empty(), "{stage:?}");
+        assert_eq!(phase_of(&state), GamePhase::Start {}, "{stage:?}");
+        let abandoned = state.abandonments.get(&player()).is_some();
+        let running = matches!(stage, Stage::Flipping | Stage::Computing);
+        assert_eq!(abandoned, running, "{stage:?}");
+    }
+}
+
+#[test]
+fn a_won_game_is_paid_out_before_the_next_bet() {
+    let bet = |state| {
+        let (context, choice) = (context(player(), 100), PlayerChoice::Tails {});
+        start_game_and_place_bet(context, state, idle_zk_state(), 100, choice, token(), 1)
+    };
+    assert!(rejects(|| bet(game_at(Stage::Done))));
+
+    let (state, events, _) = bet(paid_out_game());
+    assert_eq!(events.len(), 1);
+    assert_eq!(phase_of(&state), GamePhase::Start {});
+    assert_eq!(state.player_stats.get(&player()).unwrap().total_paid_out, 200);
+}
+
+#[test]
+fn bet_nonce_is_accepted_once() {
+    for stage in STAGES {
+        let replayed = rejects(|| {
+            start_game_and_place_bet(
+                context(player(), 100),
+                game_at(stage),
+                idle_zk_state(),
+                100,
+                PlayerChoice::Tails {},
+                token(),
+                0,
+            )
+        });
+        assert_eq!(replayed, stage != Stage::Idle, "{stage:?}");
+    }
+}
+
+#[test]
+fn escrow_moves_the_game_to_flipping() {
+    let (state, events, changes) = transfer_success_callback(
+        context(token(), 100),
+        escrowed(),
+        game_at(Stage::AwaitEscrow),
+        idle_zk_state(),
+        player(),
+        100,
+        0,
+    );
+    assert!(events.is_empty());
+    assert!(changes.is_empty());
+    assert_eq!(phase_of(&state), GamePhase::FlipCoin {});
+    assert_eq!(state.pending_escrows.get(&player()), None);
+}
+
+#[test]
+fn escrow_callback_must_match_the_pending_bet() {
+    for (amount, nonce) in [(99, 0), (100, 1)] {
+        assert!(rejects(|| transfer_success_callback(
+            context(token(), 100),
+            escrowed(),
+            game_at(Stage::AwaitEscrow),
+            idle_zk_state(),
+            player(),
+            amount,
+            nonce,
+        )));
+    }
+    for stage in [Stage::Flipping, Stage::Done] {
+        assert!(rejects(|| transfer_success_callback(
+            context(token(), 100),
+            escrowed(),
+            game_at(stage),
+            idle_zk_state(),
+            player(),
+            100,
+            0,
+        )));
+    }
+}
+
+#[test]
+fn allowance_precheck_places_only_covered_bets() {
+    for (allowance, placed) in [(99u128, false), (100u128, true)] {
+        let mut state = game_at(Stage::Idle);
+        state.allowance_shortname = Some(0x05);
+        let (state, events, _) = start_game_and_place_bet(
+            context(player(), 10),
+            state,
+            idle_zk_state(),
+            100,
+            PlayerChoice::Heads {},
+            token(),
+            0,
+        );
+        assert_eq!(events.len(), 1);
+        assert_eq!(state.pending_escrows.get(&player()), None);
+        let (state, events, _) = allowance_precheck_callback(
+            context(token(), 20),
+            CallbackContext {
+                success: true,
+                results: vec![ExecutionResult {
+                    succeeded: true,
+                    return_data: allowance.to_be_bytes().to_vec(),
+                }],
+            },
+            state,
+            idle_zk_state(),
+            player(),
+            100,
+            PlayerChoice::Heads {},
+            token(),
+            0,
+        );
+        assert_eq!(events.len(), placed as usize, "{allowance}");
+        assert_eq!(state.pending_escrows.get(&player()).is_some(), placed, "{allowance}");
+    }
+}
+
+#[test]
+fn games_expire_past_the_deadline_of_their_stage() {
+    let flipping = || {
+        let mut state = game_at(Stage::Flipping);
+        state.phase_timeouts.randomness_timeout_millis = 100;
+        state
+    };
+    assert!(rejects(|| {
+        expire_game(context(owner(), 119), flipping(), idle_zk_state(), player())
+    }));
+    assert!(rejects(|| {
+        add_randomness_to_flip(context(player(), 120), flipping(), idle_zk_state())
+    }));
+
+    let (state, events, _) =
+        expire_game(context(owner(), 120), flipping(), idle_zk_state(), player());
+    assert_eq!(events.len(), 1);
+    assert_eq!(phase_of(&state), GamePhase::Start {});
+    assert!(state.player_bets.get(&player()).is_none());
+}
+
+#[test]
+fn only_the_owner_sets_valid_gas_costs() {
+    let set = |sender, callback_cost| {
+        let costs = GasCosts {
+            callback_cost,
+            interaction_cost: 2_500,
+        };
+        set_gas_costs(context(sender, 0), game_at(Stage::Idle), idle_zk_state(), costs)
+    };
+    assert!(rejects(|| set(player(), 5_000)));
+    assert!(rejects(|| set(owner(), 0)));
+
+    let (state, _, _) = set(owner(), 5_000);
+    assert_eq!(state.gas_costs.callback_cost, 5_000);
+    assert_eq!(state.gas_costs.interaction_cost, 2_500);
+}
+
+#[test]
+fn registered_integrators_bet_for_idle_players() {
+    let integrator = address(3);
+    let beneficiary = address(4);
+    let bet = |state| {
+        integrator_bet(
+            context(integrator, 10),
+            state,
+            idle_zk_state(),
+            player(),
+            beneficiary,
+            100,
+            PlayerChoice::Heads {},
+            token(),
+            0,
+        )
+    };
+    let registered = |stage| {
+        let state = game_at(stage);
+        set_integrator(context(owner(), 0), state, idle_zk_state(), integrator, true).0
+    };
+    assert!(rejects(|| bet(game_at(Stage::Idle))));
+    assert!(rejects(|| bet(registered(Stage::Done))));
+
+    let (state, events, _) = bet(registered(Stage::Idle));
+    assert_eq!(events.len(), 1);
+    assert_eq!(state.bet_integrators.get(&player()), Some(integrator));
+    assert_eq!(state.payee_of(&player()), beneficiary);
+    assert_eq!(state.integrators.get(&integrator).unwrap().bets_placed, 1);
+}
+
+#[test]
+fn sponsored_stakes_are_drawn_within_the_cap() {
+    let sponsor = address(5);
+    let sponsored = || {
+        let (state, _, _) = fund_sponsorship_callback(
+            context(token(), 0),
+            escrowed(),
+            game_at(Stage::Idle),
+            idle_zk_state(),
+            sponsor,
+            1_000,
+            150,
+        );
+        state
+    };
+    let bet = |state, amount| {
+        start_sponsored_game(
+            context(player(), 10),
+            state,
+            idle_zk_state(),
+            sponsor,
+            amount,
+            PlayerChoice::Heads {},
+            0,
+        )
+    };
+    assert!(rejects(|| bet(sponsored(), 200)));
+
+    let (state, events, _) = bet(sponsored(), 100);
+    assert!(events.is_empty());
+    assert_eq!(phase_of(&state), GamePhase::FlipCoin {});
+    assert_eq!(state.sponsorships.get(&sponsor).unwrap().budget, 900);
+
+    let (state, _, _) = cancel_bet(context(player(), 20), state, idle_zk_state());
+    assert_eq!(state.sponsorships.get(&sponsor).unwrap().budget, 1_000);
+}
+
+#[test]
+fn collected_fees_are_swept_to_the_treasury() {
+    let treasurer = address(6);
+    let with_fees = || {
+        let config = TreasuryConfig {
+            treasury_address: address(7),
+            sweep_threshold: 0,
+        };
+        let state = game_at(Stage::Flipping);
+        let (state, _, _) =
+            set_treasury(context(owner(), 0), state, idle_zk_state(), Some(config));
+        let (state, _, _) =
+            set_cancellation_fee(context(owner(), 0), state, idle_zk_state(), 1_000);
+        let (state, _, _) = cancel_bet(context(player(), 40), state, idle_zk_state());
+        grant_role(context(owner(), 0), state, idle_zk_state(), treasurer, Role::Treasurer {}).0
+    };
+    assert_eq!(with_fees().fee_sweeps.unswept, 10);
+    assert!(rejects(|| sweep_fees(context(owner(), 50), with_fees(), idle_zk_state())));
+
+    let (state, events, _) = sweep_fees(context(treasurer, 50), with_fees(), idle_zk_state());
+    assert_eq!(events.len(), 1);
+    assert_eq!(state.fee_sweeps.in_flight, 10);
+    let (state, _, _) =
+        sweep_fees_callback(context(token(), 60), escrowed(), state, idle_zk_state(), 10);
+    assert_eq!(state.fee_sweeps.swept_total, 10);
+    assert!(rejects(|| sweep_fees(context(treasurer, 70), state, idle_zk_state())));
+}
+
+#[test]
+fn closing_a_season_archives_its_standings() {
+    assert!(rejects(|| close_season(context(player(), 50), game_at(Stage::Done), idle_zk_state())));
+
+    let (state, _, _) = close_season(context(owner(), 50), game_at(Stage::Done), idle_zk_state());
+    let record = state.season_archive.get(&0).unwrap();
+    assert_eq!(record.games_settled, 1);
+    assert_eq!(record.leaderboard[0].player, player());
+    assert_eq!(record.leaderboard[0].standing.wins, 1);
+    assert_eq!(state.season.season, 1);
+    assert_eq!(state.season.standings.len(), 0);
+    assert_eq!(state.player_stats.get(&player()).unwrap().wins, 1);
+}
+
+#[test]
+fn bets_are_throttled_per_player_and_per_block() {
+    let config = || ThrottleConfig {
+        min_bet_interval_millis: 100,
+        max_bets_per_block: 1,
+    };
+    let throttled = |sender| {
+        set_bet_throttle(
+            context(sender, 0),
+            game_at(Stage::Idle),
+            idle_zk_state(),
+            config(),
+        )
+    };
+    assert!(rejects(|| throttled(player())));
+    let bet = |sender, now, nonce| {
+        let (state, _, _) = throttled(owner());
+        let (state, _, _) = start_game_and_place_bet(
+            context(player(), 10),
+            state,
+            idle_zk_state(),
+            100,
+            PlayerChoice::Heads {},
+            token(),
+            0,
+        );
+        start_game_and_place_bet(
+            context(sender, now),
+            state,
+            idle_zk_state(),
+            100,
+            PlayerChoice::Tails {},
+            token(),
+            nonce,
+        )
+    };
+    assert!(rejects(|| bet(player(), 50, 1)));
+    assert!(rejects(|| bet(address(3), 10, 0)));
+
+    let (state, _, _) = bet(player(), 110, 1);
+    assert_eq!(state.bet_throttle.last_bet_at.get(&player()), Some(110));
+    let (state, _, _) = bet(address(3), 11, 0);
+    assert_eq!(state.bet_throttle.bets_in_block, 1);
+}
+
+#[test]
+fn gambling_limits_are_tightened_at_once_and_loosened_after_a_delay() {
+    let limits = |daily_wager_cap| GamblingLimits {
+        daily_wager_cap,
+        daily_loss_cap: 0,
+        cool_off_millis: 0,
+    };
+    let capped = || {
+        let (state, _, _) = set_gambling_limits(
+            context(player(), 0),
+            game_at(Stage::Idle),
+            idle_zk_state(),
+            limits(50),
+        );
+        state
+    };
+    let loosened = || {
+        let (state, _, _) =
+            set_gambling_limits(context(player(), 5), capped(), idle_zk_state(), limits(0));
+        state
+    };
+    let bet = |state, now| {
+        start_game_and_place_bet(
+            context(player(), now),
+            state,
+            idle_zk_state(),
+            100,
+            PlayerChoice::Heads {},
+            token(),
+            0,
+        )
+    };
+    assert!(rejects(|| bet(capped(), 10)));
+    assert!(rejects(|| bet(loosened(), 10)));
+
+    let (state, _, _) = bet(loosened(), 5 + LIMIT_LOOSENING_DELAY_MILLIS);
+    assert!(state.player_bets.get(&player()).is_some());
+}
+
+#[test]
+fn operators_pay_out_completed_games_in_batches() {
+    let batch = |sender, state| {
+        payout_batch(
+            context(sender, 50),
+            state,
+            idle_zk_state(),
+            vec![player(), address(3)],
+        )
+    };
+    assert!(rejects(|| batch(player(), game_at(Stage::Done))));
+
+    let (state, events, _) = batch(owner(), game_at(Stage::Done));
+    assert_eq!(events.len(), 1);
+    assert!(state.winners.get(&player()).is_none());
+
+    let (_, events, _) = batch(owner(), state);
+    assert!(events.is_empty());
+}
+
+#[test]
+fn the_ledger_follows_stakes_from_escrow_to_payout() {
+    for stage in STAGES {
+        game_at(stage).assert_ledger_balanced();
+    }
+    assert_eq!(game_at(Stage::AwaitEscrow).ledger.escrowed_total, 0);
+    assert_eq!(game_at(Stage::Computing).ledger.escrowed_total, 100);
+
+    let state = game_at(Stage::Done);
+    assert_eq!(state.ledger.escrowed_total, 0);
+    assert_eq!(state.ledger.claimable_winnings, 200);
+    assert_eq!(state.ledger.house_balance, -100);
+
+    let (state, _, _) = payout_winner(context(player(), 50), state, idle_zk_state());
+    state.assert_ledger_balanced();
+    assert_eq!(state.ledger.claimable_winnings, 0);
+}
+
+#[test]
+fn pool_sizes_are_kept_as_running_totals() {
+    let mut state = game_at(Stage::Computing);
+    state.set_balance(player(), 70);
+    state.set_balance(address(3), 50);
+    state.set_balance(player(), 20);
+    let sizes = state.pool_sizes();
+    assert_eq!(sizes.escrowed_bets, 100);
+    let balances: u64 = state.user_balances.iter().map(|(_, balance)| balance).sum();
+    assert_eq!(sizes.user_balances, balances);
+}
+
+#[test]
+fn parties_to_a_bet_cannot_contribute_its_other_side() {
+    let contribute = |contributor| {
+        add_randomness_for_player(
+            context(contributor, 30),
+            game_at(Stage::Flipping),
+            idle_zk_state(),
+            player(),
+        )
+    };
+    assert!(rejects(|| contribute(player())));
+    assert!(!rejects(|| contribute(address(3))));
+}
+
+#[test]
+fn a_bonus_pattern_multiplies_the_winnings_of_a_won_flip() {
+    let (state, _, _) = set_bonus_round(
+        context(owner(), 30),
+        game_at(Stage::Computing),
+        idle_zk_state(),
+        true,
+    );
+    let (state, _, _) = stake_bankroll_callback(
+        context(token(), 30),
+        escrowed(),
+        state,
+        idle_zk_state(),
+        address(3),
+        10_000,
+    );
+    let bonus_flip = zk_state_with(vec![variable(
+        FLIP_RESULT_ID,
+        SecretVarType::FlipResult { player: player() },
+        Some(vec![1, 0, 1, 1]),
+    )]);
+    let (state, _, changes) = open_flip_result_variable(
+        context(player(), 40),
+        state,
+        bonus_flip,
+        vec![SecretVarId::new(FLIP_RESULT_ID)],
+    );
+    assert_eq!(phase_of(&state), GamePhase::BonusRound {});
+    assert!(changes
+        .iter()
+        .any(|change| matches!(change, ZkStateChange::StartComputation { .. })));
+
+    let multiplier = zk_state_with(vec![variable(
+        BONUS_MULTIPLIER_ID,
+        SecretVarType::BonusMultiplier { player: player() },
+        Some(vec![4, 0]),
+    )]);
+    let (state, _, _) = open_flip_result_variable(
+        context(player(), 50),
+        state,
+        multiplier,
+        vec![SecretVarId::new(BONUS_MULTIPLIER_ID)],
+    );
+    assert_eq!(phase_of(&state), GamePhase::Done {});
+    assert_eq!(state.ledger.claimable_winnings, 1_000);
+}
+
+#[test]
+fn a_bracket_advances_match_winners_and_pays_the_champion() {
+    let registered = || {
+        let (mut state, _, _) = open_bracket(
+            context(owner(), 0),
+            game_at(Stage::Idle),
+            idle_zk_state(),
+            100,
+            2,
+            500,
+            1_000,
+            1_000,
+        );
+        for seed in 3..7 {
+            (state, _, _) = bracket_registration_callback(
+                context(token(), 10),
+                escrowed(),
+                state,
+                idle_zk_state(),
+                address(seed),
+                0,
+            );
+        }
+        state
+    };
+    let state = registered();
+    assert_eq!(state.brackets.get(&0).unwrap().status, BracketStatus::Playing {});
+    assert!(rejects(|| {
+        play_bracket_match(context(address(3), 20), registered(), idle_zk_state(), 0, 0)
+    }));
+
+    // Both players of the first match contributed, so its coin flip is computed
+    let contributions = zk_state_with(vec![
+        variable(
+            5,
+            SecretVarType::BracketRandomness { bracket_id: 0, round: 0, player: address(3) },
+            None,
+        ),
+        variable(
+            6,
+            SecretVarType::BracketRandomness { bracket_id: 0, round: 0, player: address(4) },
+            None,
+        ),
+    ]);
+    let (state, _, changes) =
+        play_bracket_match(context(address(3), 20), state, contributions, 0, 0);
+    assert!(changes
+        .iter()
+        .any(|change| matches!(change, ZkStateChange::StartComputation { .. })));
+    let lower_seed_advances = zk_state_with(vec![variable(
+        7,
+        SecretVarType::BracketMatch { bracket_id: 0, round: 0, match_index: 0 },
+        Some(vec![0]),
+    )]);
+    let (state, _, _) = open_flip_result_variable(
+        context(address(3), 30),
+        state,
+        lower_seed_advances,
+        vec![SecretVarId::new(7)],
+    );
+
+    // Nobody showed up to the second match, so the higher seed advances once the round is over
+    let (state, events, _) =
+        play_bracket_match(context(address(5), 2_000), state, idle_zk_state(), 0, 1);
+    assert!(events.is_empty());
+    let bracket = state.brackets.get(&0).unwrap();
+    assert_eq!(bracket.round, 1);
+    assert_eq!(bracket.players, vec![address(4), address(5)]);
+
+    // Only the lower seed contributed to the final and wins it by walkover
+    let final_contribution = zk_state_with(vec![variable(
+        8,
+        SecretVarType::BracketRandomness { bracket_id: 0, round: 1, player: address(5) },
+        None,
+    )]);
+    let bankroll = state.house_bankroll;
+    let (state, events, _) =
+        play_bracket_match(context(address(5), 4_000), state, final_contribution, 0, 0);
+    assert_eq!(events.len(), 1);
+    assert_eq!(
+        state.brackets.get(&0).unwrap().status,
+        BracketStatus::Finished { winner: address(5) }
+    );
+    assert_eq!(state.house_bankroll, bankroll + 20);
+}
+
+#[test]
+fn dice_have_at_most_the_maximum_number_of_faces() {
+    let roll = |faces| {
+        let state = game_at(Stage::Idle);
+        roll_dice(context(player(), 10), state, idle_zk_state(), 100, faces, 1, 0)
+    };
+    assert!(!rejects(|| roll(MAX_DICE_FACES)));
+    assert!(rejects(|| roll(MAX_DICE_FACES + 1)));
+}
+
+#[test]
+fn opened_outputs_must_settle_the_kind_of_game_being_played() {
+    assert_eq!(game_at(Stage::Computing).game_kind_of(&player()), GameKind::CoinFlip {});
+    let dice_roll = zk_state_with(vec![variable(
+        FLIP_RESULT_ID,
+        SecretVarType::DiceRoll { player: player() },
+        Some(vec![3, 0]),
+    )]);
+    assert!(rejects(|| {
+        open_flip_result_variable(
+            context(player(), 40),
+            game_at(Stage::Computing),
+            dice_roll,
+            vec![SecretVarId::new(FLIP_RESULT_ID)],
+        )
+    }));
+}
+
+#[test]
+fn every_opened_variable_is_settled_by_its_metadata() {
+    let outputs = zk_state_with(vec![
+        variable(
+            FLIP_RESULT_ID,
+            SecretVarType::FlipResult { player: player() },
+            Some(vec![1, 0, 1, 0]),
+        ),
+        variable(
+            5,
+            SecretVarType::PracticeFlip { player: address(3) },
+            Some(vec![0, 0, 0, 0]),
+        ),
+    ]);
+    let (state, _, changes) = open_flip_result_variable(
+        context(player(), 40),
+        game_at(Stage::Computing),
+        outputs,
+        vec![SecretVarId::new(FLIP_RESULT_ID), SecretVarId::new(5)],
+    );
+    assert_eq!(phase_of(&state), GamePhase::Done {});
+    let deleted: Vec<SecretVarId> = changes
+        .iter()
+        .flat_map(|change| match change {
+            ZkStateChange::DeleteVariables { variables_to_delete } => variables_to_delete.clone(),
+            _ => vec![],
+        })
+        .collect();
+    assert!(deleted.contains(&SecretVarId::new(FLIP_RESULT_ID)));
+    assert!(deleted.contains(&SecretVarId::new(5)));
+}
+
+#[test]
+fn house_seeds_are_committed_ahead_and_sealed_with_the_epochs_settlements() {
+    let commit = |epoch| {
+        commit_house_seed(context(owner(), 5), game_at(Stage::Idle), idle_zk_state(), epoch)
+    };
+    assert!(rejects(|| commit(0)));
+    assert!(!rejects(|| commit(1)));
+    let seed_zk_state = |data| {
+        let mut seed = variable(6, SecretVarType::HouseSeed { epoch: 1 }, data);
+        seed.owner = owner();
+        zk_state_with(vec![seed])
+    };
+    let confirm = |state| {
+        inputted_variable(context(owner(), 30), state, seed_zk_state(None), SecretVarId::new(6))
+    };
+
+    // A seed confirmed once its epoch started is deleted
+    let mut started = game_at(Stage::Computing);
+    started.epoch = EpochState::new(1, 25);
+    let (state, _, changes) = confirm(started);
+    assert!(deletes_variables(&changes));
+    assert!(state.house_seeds.get(&1).is_none());
+
+    let settled_epoch = || {
+        let (mut state, _, _) = confirm(game_at(Stage::Computing));
+        state.epoch = EpochState::new(1, 35);
+        let (mut state, _, _) = open_flip_result_variable(
+            context(player(), 40),
+            state,
+            flip_result_zk_state(),
+            vec![SecretVarId::new(FLIP_RESULT_ID)],
+        );
+        state.epoch = EpochState::new(2, 50);
+        state
+    };
+    assert_eq!(settled_epoch().house_seeds.get(&1).unwrap().settled_rounds, 1);
+
+    // Anyone can have the seed opened once the epoch is over
+    let (state, _, changes) =
+        reveal_house_seed(context(address(3), 60), settled_epoch(), seed_zk_state(None), 1);
+    assert!(matches!(changes[..], [ZkStateChange::OpenVariables { .. }]));
+    assert!(rejects(|| {
+        reveal_house_seed(context(address(3), 65), state, seed_zk_state(None), 1)
+    }));
+
+    let (state, _, _) =
+        reveal_house_seed(context(address(3), 60), settled_epoch(), seed_zk_state(None), 1);
+    let (state, _, changes) = open_flip_result_variable(
+        context(owner(), 70),
+        state,
+        seed_zk_state(Some(7u32.to_le_bytes().to_vec())),
+        vec![SecretVarId::new(6)],
+    );
+    assert!(deletes_variables(&changes));
+    let commitment = state.house_seeds.get(&1).unwrap();
+    assert_eq!(commitment.revealed_seed, Some(7));
+    assert!(commitment.seal.is_some());
+}
+
+#[test]
+fn key_transitions_are_pushed_to_the_event_sink() {
+    let sink = EventSinkConfig {
+        sink_address: address(7),
+        sink_shortname: 0x01,
+    };
+    let with_sink = |sender| {
+        let config = Some(sink.clone());
+        set_event_sink(context(sender, 15), game_at(Stage::AwaitEscrow), idle_zk_state(), config)
+    };
+    assert!(rejects(|| with_sink(player())));
+
+    let (state, _, _) = with_sink(owner());
+    let (state, events, _) = transfer_success_callback(
+        context(token(), 20),
+        escrowed(),
+        state,
+        idle_zk_state(),
+        player(),
+        100,
+        0,
+    );
+    assert_eq!(events.len(), 1);
+    let state = with_house_randomness(state);
+    let (state, events, _) = flip_coin(context(player(), 30), state, idle_zk_state());
+    assert_eq!(events.len(), 1);
+    let (state, events, _) = open_flip_result_variable(
+        context(player(), 40),
+        state,
+        flip_result_zk_state(),
+        vec![SecretVarId::new(FLIP_RESULT_ID)],
+    );
+    assert_eq!(events.len(), 1);
+    assert!(state.game_event_outbox.is_empty());
+    let (state, events, _) = payout_winner(context(player(), 50), state, idle_zk_state());
+    assert_eq!(events.len(), 2);
+    assert!(state.game_event_outbox.is_empty());
+}
+
+#[test]
+fn a_payout_settling_an_expired_dispute_window_sends_its_attestation() {
+    let state = game_at(Stage::Computing);
+    let (state, _, _) =
+        set_dispute_window(context(owner(), 35), state, idle_zk_state(), 1_000, None);
+    let (state, _, _) = set_result_attestation(context(owner(), 35), state, idle_zk_state(), true);
+    let (state, _, changes) = open_flip_result_variable(
+        context(player(), 40),
+        state,
+        flip_result_zk_state(),
+        vec![SecretVarId::new(FLIP_RESULT_ID)],
+    );
+    assert_eq!(phase_of(&state), GamePhase::Challengeable {});
+    assert!(changes.is_empty());
+
+    let (state, events, changes) = payout_winner(context(player(), 1_040), state, idle_zk_state());
+    assert_eq!(events.len(), 1);
+    assert!(matches!(changes[..], [ZkStateChange::Attest { .. }]));
+    assert!(state.attestation_outbox.is_empty());
+}
+
+#[test]
+fn quotes_match_what_the_bet_costs_and_pays() {
+    let raked = || {
+        let state = game_at(Stage::Idle);
+        set_jackpot_rake(context(owner(), 0), state, idle_zk_state(), 500).0
+    };
+    let quote = raked().quote_bet(&player(), &heads_bet(100), &token(), None, 10);
+    assert_eq!(quote.jackpot_contribution, 5);
+    assert_eq!(quote.net_stake, 95);
+    assert_eq!(quote.win_chance_bps, 5_000);
+    assert_eq!(quote.required_allowance, 100);
+
+    let (state, events, _) = start_game_and_place_bet(
+        context(player(), 10),
+        raked(),
+        idle_zk_state(),
+        100,
+        PlayerChoice::Heads {},
+        token(),
+        0,
+    );
+    assert_eq!(events.len(), 1);
+    assert_eq!(state.jackpot_pool, quote.jackpot_contribution);
+    assert_eq!(state.pending_escrows.get(&player()).unwrap().amount, quote.required_allowance);
+    let (state, _, _) = transfer_success_callback(
+        context(token(), 20),
+        escrowed(),
+        state,
+        idle_zk_state(),
+        player(),
+        100,
+        0,
+    );
+    let state = with_house_randomness(state);
+    let (state, _, _) = flip_coin(context(player(), 30), state, idle_zk_state());
+    let (state, _, _) = open_flip_result_variable(
+        context(player(), 40),
+        state,
+        flip_result_zk_state(),
+        vec![SecretVarId::new(FLIP_RESULT_ID)],
+    );
+    assert_eq!(state.ledger.claimable_winnings, quote.payout + quote.promo_payout);
+}
+
+#[test]
+fn a_failed_escrow_leaves_the_bet_awaiting_its_stake() {
+    let escrow = |callback_ctx| {
+        transfer_success_callback(
+            context(token(), 20),
+            callback_ctx,
+            game_at(Stage::AwaitEscrow),
+            idle_zk_state(),
+            player(),
+            100,
+            0,
+        )
+    };
+    assert!(rejects(|| escrow(failed())));
+
+    let (state, _, _) = escrow(escrowed());
+    assert_eq!(phase_of(&state), GamePhase::FlipCoin {});
+}
+
+#[test]
+fn a_stake_arriving_after_its_escrow_expired_is_returned() {
+    let mut state = game_at(Stage::AwaitEscrow);
+    state.phase_timeouts.bet_escrow_timeout_millis = 100;
+    let (state, events, _) =
+        expire_game(context(owner(), 110), state, idle_zk_state(), player());
+    assert!(events.is_empty());
+    assert_eq!(phase_of(&state), GamePhase::Start {});
+
+    let (state, events, _) = transfer_success_callback(
+        context(token(), 120),
+        escrowed(),
+        state,
+        idle_zk_state(),
+        player(),
+        100,
+        0,
+    );
+    assert!(events.is_empty());
+    assert_eq!(phase_of(&state), GamePhase::Start {});
+    assert_eq!(state.balance_of(&player()).available, 100);
+    assert!(state.expired_escrows.get(&player()).is_none());
+}
+
+#[test]
+fn cancelled_bets_are_refunded_minus_the_fee() {
+    let cancelled = || {
+        let state = game_at(Stage::Flipping);
+        let (state, _, _) =
+            set_cancellation_fee(context(owner(), 25), state, idle_zk_state(), 1_000);
+        cancel_bet(context(player(), 30), state, idle_zk_state())
+    };
+    let (state, events, _) = cancelled();
+    assert_eq!(events.len(), 1);
+    assert_eq!(phase_of(&state), GamePhase::Start {});
+    state.assert_ledger_balanced();
+    assert!(rejects(|| {
+        cancel_bet(context(player(), 30), game_at(Stage::Computing), idle_zk_state())
+    }));
+
+    // A refund the token contract rejects is credited to the player's balance instead
+    let (state, _, _) = cancelled();
+    let (state, _, _) =
+        cancel_bet_callback(context(token(), 40), failed(), state, idle_zk_state(), player(), 90);
+    assert_eq!(state.balance_of(&player()).available, 90);
+}
+
+#[test]
+fn computing_games_expire_only_once_their_computation_stops() {
+    let computing = || {
+        let mut state = game_at(Stage::Computing);
+        state.phase_timeouts.compute_timeout_millis = 100;
+        state
+    };
+    assert!(rejects(|| {
+        expire_game(context(owner(), 130), computing(), computing_zk_state(), player())
+    }));
+
+    let (state, events, _) =
+        expire_game(context(owner(), 130), computing(), idle_zk_state(), player());
+    assert_eq!(events.len(), 1);
+    assert_eq!(phase_of(&state), GamePhase::Start {});
+    assert_eq!(state.computation_pending.get(&player()), None);
+    state.assert_ledger_balanced();
+}
+
+#[test]
+fn flips_queue_behind_a_running_computation() {
+    let state = with_house_randomness(game_at(Stage::Flipping));
+    let (state, _, changes) = flip_coin(context(player(), 30), state, computing_zk_state());
+    assert!(changes.is_empty());
+    assert!(state.compute_queue.contains(&player()));
+    assert_eq!(state.computation_pending.get(&player()), Some(true));
+}
+
+#[test]
+fn reserved_winnings_are_covered_by_the_free_bankroll() {
+    let reserving = |house_bankroll| {
+        let (mut state, _, _) =
+            set_winnings_reserve(context(owner(), 0), game_at(Stage::Idle), idle_zk_state(), true);
+        state.house_bankroll = house_bankroll;
+        state
+    };
+    let bet = |state| {
+        let context = context(player(), 10);
+        let choice = PlayerChoice::Heads {};
+        start_game_and_place_bet(context, state, idle_zk_state(), 100, choice, token(), 0)
+    };
+    assert!(rejects(|| bet(reserving(199))));
+
+    let (state, _, _) = bet(reserving(200));
+    assert_eq!(state.free_bankroll(&token()), 0);
+    let (state, _, _) = transfer_success_callback(
+        context(token(), 20),
+        escrowed(),
+        state,
+        idle_zk_state(),
+        player(),
+        100,
+        0,
+    );
+    let state = with_house_randomness(state);
+    let (state, _, _) = flip_coin(context(player(), 30), state, idle_zk_state());
+    // The coin lands tails, so the reservation is released and the stake goes to the house
+    let lost = zk_state_with(vec![variable(
+        FLIP_RESULT_ID,
+        SecretVarType::FlipResult { player: player() },
+        Some(vec![0, 0, 0, 0]),
+    )]);
+    let (state, _, _) = open_flip_result_variable(
+        context(player(), 40),
+        state,
+        lost,
+        vec![SecretVarId::new(FLIP_RESULT_ID)],
+    );
+    assert_ne!(state.get_winner(&player()), Some(player()));
+    assert_eq!(state.free_bankroll(&token()), 300);
+}
+
+#[test]
+fn signed_intents_are_checked_before_their_bet_is_placed() {
+    let intent = |contract, expires_at| BetIntent {
+        contract,
+        player: player(),
+        amount: 100,
+        choice: PlayerChoice::Heads {},
+        token: token(),
+        nonce: 0,
+        expires_at,
+    };
+    let valid = intent(address(0xC0), 100);
+    assert_eq!(BetIntent::decode(&valid.encode()), Some(valid.clone()));
+    assert_eq!(BetIntent::decode(&[0; 4]), None);
+
+    let unsigned = Signature::rpc_read_from(&mut &[0u8; 65][..]);
+    let submit = |sender, intent| {
+        let state = game_at(Stage::Idle);
+        let signature = unsigned.clone();
+        submit_signed_bet(context(sender, 50), state, idle_zk_state(), intent, signature)
+    };
+    assert!(rejects(|| submit(player(), valid.clone())));
+    assert!(rejects(|| submit(owner(), intent(address(0xC1), 100))));
+    assert!(rejects(|| submit(owner(), intent(address(0xC0), 50))));
+    // The player registered no key, so nothing they did not submit themselves is accepted
+    assert!(rejects(|| submit(owner(), valid.clone())));
+}
+
+#[test]
+fn winnings_left_unclaimed_past_their_deadline_are_swept() {
+    let settled_with_vault = |beneficiary| {
+        let vault = UnclaimedVaultConfig {
+            claim_window_millis: 1_000,
+            beneficiary,
+        };
+        let state = game_at(Stage::Computing);
+        let (state, _, _) =
+            set_unclaimed_vault(context(owner(), 35), state, idle_zk_state(), Some(vault));
+        let (state, _, _) = open_flip_result_variable(
+            context(player(), 40),
+            state,
+            flip_result_zk_state(),
+            vec![SecretVarId::new(FLIP_RESULT_ID)],
+        );
+        state
+    };
+    let sweep = |state, now| {
+        let players = vec![player()];
+        sweep_unclaimed(context(address(3), now), state, idle_zk_state(), players)
+    };
+    assert!(rejects(|| sweep(game_at(Stage::Done), 2_000)));
+
+    let (state, events, _) = sweep(settled_with_vault(None), 1_039);
+    assert!(events.is_empty());
+    assert_eq!(state.get_winner(&player()), Some(player()));
+
+    let bankroll = state.house_bankroll;
+    let (state, events, _) = sweep(state, 1_040);
+    assert!(events.is_empty());
+    assert_eq!(state.house_bankroll, bankroll + 200);
+    assert_eq!(state.unclaimed_swept_total, 200);
+    assert_eq!(state.ledger.claimable_winnings, 0);
+    state.assert_ledger_balanced();
+    let (_, events, _) = payout_winner(context(player(), 1_050), state, idle_zk_state());
+    assert!(events.is_empty());
+
+    let (state, events, _) = sweep(settled_with_vault(Some(address(9))), 1_040);
+    assert_eq!(events.len(), 1);
+    assert_eq!(state.ledger.claimable_winnings, 0);
+    state.assert_ledger_balanced();
+}
+
+#[test]
+fn bets_are_limited_and_ranked_in_usd_once_an_oracle_quotes_them() {
+    let oracle = PriceOracleConfig {
+        oracle_address: address(9),
+        price_shortname: 0x10,
+        min_bet_usd: 500_000,
+        max_bet_usd: 2_000_000,
+    };
+    let with_oracle = || {
+        let oracle = Some(oracle.clone());
+        set_price_oracle(context(owner(), 0), game_at(Stage::Idle), idle_zk_state(), oracle).0
+    };
+    let (state, events, _) = start_game_and_place_bet(
+        context(player(), 10),
+        with_oracle(),
+        idle_zk_state(),
+        100,
+        PlayerChoice::Heads {},
+        token(),
+        0,
+    );
+    assert_eq!(events.len(), 1);
+    assert_eq!(state.player_bets.get(&player()), None);
+
+    let quoted = |state, quote: Option<u128>| {
+        let return_data = quote.map_or(vec![], |price| price.to_be_bytes().to_vec());
+        let callback_ctx = CallbackContext {
+            success: quote.is_some(),
+            results: vec![ExecutionResult {
+                succeeded: quote.is_some(),
+                return_data,
+            }],
+        };
+        let (player, choice) = (player(), PlayerChoice::Heads {});
+        let context = context(address(9), 15);
+        let zk_state = idle_zk_state();
+        usd_price_callback(context, callback_ctx, state, zk_state, player, 100, choice, token(), 0)
+    };
+    // A cent per token unit makes the bet worth one dollar
+    let (state, events, _) = quoted(state, Some(10_000_000_000_000_000));
+    assert_eq!(events.len(), 1);
+    assert!(state.pending_escrows.get(&player()).is_some());
+    assert!(rejects(|| quoted(with_oracle(), None)));
+    assert!(rejects(|| quoted(with_oracle(), Some(1_000_000_000_000_000))));
+    assert!(rejects(|| quoted(with_oracle(), Some(100_000_000_000_000_000))));
+
+    // The other bet actions are limited at the last quoted price, and rejected before any quote
+    let secret_bet = |state, amount| {
+        start_game_with_secret_choice(context(address(4), 16), state, idle_zk_state(), amount, 0)
+    };
+    let quoted_state = || quoted(with_oracle(), Some(10_000_000_000_000_000)).0;
+    assert!(rejects(|| secret_bet(with_oracle(), 100)));
+    assert!(rejects(|| secret_bet(quoted_state(), 1_000)));
+    assert_eq!(secret_bet(quoted_state(), 100).1.len(), 1);
+
+    let (state, _, _) = transfer_success_callback(
+        context(token(), 20),
+        escrowed(),
+        state,
+        idle_zk_state(),
+        player(),
+        100,
+        0,
+    );
+    let state = with_house_randomness(state);
+    let (state, _, _) = flip_coin(context(player(), 30), state, idle_zk_state());
+    let (state, _, _) = open_flip_result_variable(
+        context(player(), 40),
+        state,
+        flip_result_zk_state(),
+        vec![SecretVarId::new(FLIP_RESULT_ID)],
+    );
+    assert_eq!(state.epoch.total_wagered, 1_000_000);
+    assert_eq!(state.epoch.player_winnings.get(&player()), Some(&2_000_000));
+}
+
+#[test]
+fn bot_sessions_settle_their_flips_against_the_session_balance() {
+    let opened = || {
+        let state = game_at(Stage::Idle);
+        let (state, _, _) =
+            approve_session_bot(context(owner(), 0), state, idle_zk_state(), player(), Some(2));
+        let (state, events, _) = open_session(context(player(), 5), state, idle_zk_state(), 300);
+        assert_eq!(events.len(), 1);
+        let (bot, zk_state) = (player(), idle_zk_state());
+        open_session_callback(context(token(), 6), escrowed(), state, zk_state, bot, 300).0
+    };
+    let flip = |state, nonce| {
+        let context = context(player(), 10);
+        session_flip(context, state, idle_zk_state(), 100, PlayerChoice::Heads {}, nonce)
+    };
+    let settle = |state, data| {
+        let state = with_house_randomness(state);
+        let (state, _, _) = flip_coin(context(player(), 30), state, idle_zk_state());
+        let flip_result = zk_state_with(vec![variable(
+            FLIP_RESULT_ID,
+            SecretVarType::FlipResult { player: player() },
+            Some(data),
+        )]);
+        let opened = vec![SecretVarId::new(FLIP_RESULT_ID)];
+        open_flip_result_variable(context(player(), 40), state, flip_result, opened).0
+    };
+    assert!(rejects(|| open_session(
+        context(address(3), 5),
+        game_at(Stage::Idle),
+        idle_zk_state(),
+        300
+    )));
+
+    // The stake is drawn from the session, without an escrow transfer
+    let (state, events, _) = flip(opened(), 0);
+    assert!(events.is_empty());
+    assert_eq!(phase_of(&state), GamePhase::FlipCoin {});
+    assert_eq!(state.bot_sessions.get(&player()).unwrap().balance, 200);
+    assert!(rejects(|| close_session(context(player(), 20), flip(opened(), 0).0, idle_zk_state())));
+
+    // The won flip is credited before the next stake is drawn
+    let state = settle(state, vec![1, 0, 1, 0]);
+    let (state, _, _) = flip(state, 1);
+    let session = state.bot_sessions.get(&player()).unwrap();
+    assert_eq!((session.balance, session.flips_left), (300, 0));
+    assert_eq!(session.net_result(), 0);
+
+    let state = settle(state, vec![0, 0, 0, 0]);
+    let (state, events, _) = close_session(context(player(), 50), state, idle_zk_state());
+    assert_eq!(events.len(), 1);
+    assert!(state.bot_sessions.get(&player()).is_none());
+    state.assert_ledger_balanced();
+}
+
+#[test]
+fn winnings_of_a_bet_with_a_beneficiary_are_routed_to_it() {
+    let (state, _, _) = start_game_with_beneficiary(
+        context(player(), 10),
+        game_at(Stage::Idle),
+        idle_zk_state(),
+        100,
+        PlayerChoice::Heads {},
+        token(),
+        address(5),
+        0,
+    );
+    let (state, _, _) = transfer_success_callback(
+        context(token(), 20),
+        escrowed(),
+        state,
+        idle_zk_state(),
+        player(),
+        100,
+        0,
+    );
+    let state = with_house_randomness(state);
+    let (state, _, _) = flip_coin(context(player(), 30), state, idle_zk_state());
+    let (state, _, _) = open_flip_result_variable(
+        context(player(), 40),
+        state,
+        flip_result_zk_state(),
+        vec![SecretVarId::new(FLIP_RESULT_ID)],
+    );
+    assert_eq!(state.payee_of(&player()), address(5));
+
+    let (state, events, _) = payout_winner(context(player(), 50), state, idle_zk_state());
+    assert_eq!(events.len(), 1);
+    assert_eq!(state.player_stats.get(&player()).unwrap().total_paid_out, 200);
+    assert!(state.player_stats.get(&address(5)).is_none());
+}
+
+#[test]
+fn jackpots_are_paid_out_with_the_winnings_to_the_beneficiary() {
+    let mut state = game_at(Stage::Idle);
+    state.jackpot_pool = 30;
+    let (state, _, _) = start_game_with_beneficiary(
+        context(player(), 10),
+        state,
+        idle_zk_state(),
+        100,
+        PlayerChoice::Heads {},
+        token(),
+        address(5),
+        0,
+    );
+    let (state, _, _) = transfer_success_callback(
+        context(token(), 20),
+        escrowed(),
+        state,
+        idle_zk_state(),
+        player(),
+        100,
+        0,
+    );
+    let state = with_house_randomness(state);
+    let (state, _, _) = flip_coin(context(player(), 30), state, idle_zk_state());
+    let jackpot_flip = zk_state_with(vec![variable(
+        FLIP_RESULT_ID,
+        SecretVarType::FlipResult { player: player() },
+        Some(vec![1, 1, 1, 0]),
+    )]);
+    let (state, events, _) = open_flip_result_variable(
+        context(player(), 40),
+        state,
+        jackpot_flip,
+        vec![SecretVarId::new(FLIP_RESULT_ID)],
+    );
+    assert!(events.is_empty());
+    assert_eq!(state.jackpot_pool, 0);
+    assert_eq!(state.won_jackpots.get(&player()), Some(30));
+
+    let (state, events, _) = payout_winner(context(player(), 50), state, idle_zk_state());
+    assert_eq!(events.len(), 1);
+    assert!(state.won_jackpots.get(&player()).is_none());
+    assert_eq!(state.player_stats.get(&player()).unwrap().total_paid_out, 230);
+}
+
+#[test]
+fn friends_tables_redistribute_their_pot_without_rake() {
+    let open = || {
+        let (state, _, _) = open_friends_table(
+            context(owner(), 0),
+            game_at(Stage::Idle),
+            idle_zk_state(),
+            Hash::digest(b"invite"),
+            100,
+            vec![player(), address(3)],
+            1_000,
+        );
+        state
+    };
+    let join = |state, participant| {
+        let (code, choice) = (b"invite".to_vec(), PlayerChoice::Heads {});
+        join_friends_table(context(participant, 10), state, idle_zk_state(), code, choice)
+    };
+    let seated = |state, participant, choice| {
+        let (context, zk_state) = (context(token(), 20), idle_zk_state());
+        friends_table_join_callback(context, escrowed(), state, zk_state, 0, participant, choice).0
+    };
+    let full = || {
+        let state = seated(open(), player(), PlayerChoice::Heads {});
+        seated(state, address(3), PlayerChoice::Tails {})
+    };
+    assert!(rejects(|| join(open(), address(4))));
+    assert!(rejects(|| cancel_friends_table(context(player(), 500), open(), idle_zk_state(), 0)));
+
+    let (_, events, _) = join(open(), player());
+    assert_eq!(events.len(), 1);
+    let state = full();
+    assert_eq!(state.friends_tables.get(&0).unwrap().pot(), 200);
+
+    let contributions = || {
+        zk_state_with(vec![
+            variable(5, SecretVarType::TableRandomness { table_id: 0, player: player() }, None),
+            variable(6, SecretVarType::TableRandomness { table_id: 0, player: address(3) }, None),
+        ])
+    };
+    assert!(rejects(|| flip_friends_table(context(address(4), 30), full(), contributions(), 0)));
+    assert!(rejects(|| flip_friends_table(context(player(), 30), full(), idle_zk_state(), 0)));
+    let (state, _, changes) = flip_friends_table(context(player(), 30), state, contributions(), 0);
+    assert!(changes
+        .iter()
+        .any(|change| matches!(change, ZkStateChange::StartComputation { .. })));
+
+    let bankroll = state.house_bankroll;
+    let heads = zk_state_with(vec![variable(
+        7,
+        SecretVarType::TableFlip { table_id: 0 },
+        Some(vec![1]),
+    )]);
+    let (state, events, _) =
+        open_flip_result_variable(context(player(), 40), state, heads, vec![SecretVarId::new(7)]);
+    assert_eq!(events.len(), 1);
+    let table = state.friends_tables.get(&0).unwrap();
+    assert_eq!(table.status, TableStatus::Settled { heads: true });
+    assert_eq!(table.payouts(true), vec![(player(), 200)]);
+    assert_eq!(state.house_bankroll, bankroll);
+}
+
+#[test]
+fn the_pipeline_requests_randomness_and_flips_once_the_round_is_ready() {
+    let provider = address(0x50);
+    let awaiting_escrow = || {
+        let mut state = game_at(Stage::AwaitEscrow);
+        state.randomness_providers.add(provider);
+        state.pipeline = Some(PipelineConfig { request_shortname: 0x10 });
+        state
+    };
+    let escrow = |state| {
+        transfer_success_callback(
+            context(token(), 20),
+            escrowed(),
+            state,
+            idle_zk_state(),
+            player(),
+            100,
+            0,
+        )
+    };
+    let (_, manual_events, _) = escrow(game_at(Stage::AwaitEscrow));
+    let (state, events, _) = escrow(awaiting_escrow());
+    assert_eq!(events.len(), manual_events.len() + 1);
+    assert_eq!(phase_of(&state), GamePhase::FlipCoin {});
+
+    let starts_computation = |changes: &[ZkStateChange]| {
+        changes
+            .iter()
+            .any(|change| matches!(change, ZkStateChange::StartComputation { .. }))
+    };
+    // The player's own contribution alone leaves the round waiting for the provider
+    let (state, _, changes) = inputted_variable(
+        context(player(), 30),
+        state,
+        randomness_zk_state(),
+        SecretVarId::new(RANDOMNESS_ID),
+    );
+    assert!(!starts_computation(&changes));
+
+    let metadata = SecretVarType::Randomness { player: player() };
+    let mut provided = variable(RANDOMNESS_ID + 1, metadata, None);
+    provided.owner = provider;
+    let mut zk_state = randomness_zk_state();
+    zk_state.secret_variables.insert(provided.variable_id, provided);
+    let (state, _, changes) = inputted_variable(
+        context(provider, 40),
+        state,
+        zk_state,
+        SecretVarId::new(RANDOMNESS_ID + 1),
+    );
+    assert!(starts_computation(&changes));
+    assert_eq!(state.computation_pending.get(&player()), Some(true));
+}
+
+#[test]
+fn test_mode_actions_are_only_available_in_test_mode() {
+    let flipping = |test_mode| {
+        let mut state = game_at(Stage::Flipping);
+        state.is_test_mode = test_mode;
+        state
+    };
+    assert!(rejects(|| {
+        mint_play_money(context(player(), 30), flipping(false), idle_zk_state(), 5)
+    }));
+    assert!(rejects(|| {
+        force_resolve_game(context(owner(), 30), flipping(false), idle_zk_state(), player(), true)
+    }));
+    assert!(rejects(|| {
+        fast_forward_game(context(owner(), 30), flipping(false), idle_zk_state(), player(), 5)
+    }));
+
+    let (state, _, _) = mint_play_money(context(player(), 30), flipping(true), idle_zk_state(), 5);
+    assert_eq!(state.user_balances.get(&player()), Some(5));
+
+    let entered_at = flipping(true).stage_entered_at.get(&player()).unwrap();
+    let (state, _, _) = fast_forward_game(
+        context(owner(), 30),
+        flipping(true),
+        idle_zk_state(),
+        player(),
+        1_000,
+    );
+    assert_eq!(state.stage_entered_at.get(&player()), Some(entered_at - 1_000));
+
+    assert!(rejects(|| {
+        force_resolve_game(context(player(), 30), flipping(true), idle_zk_state(), player(), true)
+    }));
+    let (state, _, _) =
+        force_resolve_game(context(owner(), 30), flipping(true), idle_zk_state(), player(), true);
+    assert_eq!(phase_of(&state), GamePhase::Done {});
+    assert_eq!(state.winners.get(&player()), Some(player()));
+    state.assert_ledger_balanced();
+}
+
+#[test]
+fn play_money_never_leaves_the_contract() {
+    let mut state = game_at(Stage::Flipping);
+    state.is_test_mode = true;
+    let (state, _, _) = mint_play_money(context(player(), 30), state, idle_zk_state(), 5);
+    assert!(rejects(|| withdraw(context(player(), 35), state, idle_zk_state(), 5)));
+
+    let mut state = game_at(Stage::Flipping);
+    state.is_test_mode = true;
+    let (state, _, _) = mint_play_money(context(player(), 30), state, idle_zk_state(), 5);
+    let (state, _, _) =
+        force_resolve_game(context(owner(), 35), state, idle_zk_state(), player(), true);
+    let (state, events, _) = payout_winner(context(player(), 40), state, idle_zk_state());
+    assert!(events.is_empty());
+    assert!(state.user_balances.get(&player()).unwrap() > 5);
+    assert!(rejects(|| withdraw(context(player(), 45), state, idle_zk_state(), 5)));
+}
+
+#[test]
+fn every_game_is_identified_by_its_player_nonce_and_block_time() {
+    let state = game_at(Stage::AwaitEscrow);
+    let first_game = compute_game_id(&player(), 0, 10);
+    assert_eq!(state.game_ids.get(&player()), Some(first_game));
+    // The salt of the game's first round is derived from the game's id alone
+    let mut seed = Vec::new();
+    Some(first_game).state_write_to(&mut seed).unwrap();
+    None::<Hash>.state_write_to(&mut seed).unwrap();
+    assert_eq!(state.round_salts.get(&player()), Some(Hash::digest(&seed)));
+
+    let (state, _, _) = start_game_and_place_bet(
+        context(player(), 30),
+        paid_out_game(),
+        idle_zk_state(),
+        100,
+        PlayerChoice::Heads {},
+        token(),
+        1,
+    );
+    let second_game = compute_game_id(&player(), 1, 30);
+    assert_eq!(state.game_ids.get(&player()), Some(second_game));
+    assert_ne!(first_game, second_game);
+}
+
+#[test]
+fn resolved_games_are_exported_in_pages_in_resolution_order() {
+    let (state, _, _) = start_game_and_place_bet(
+        context(player(), 30),
+        paid_out_game(),
+        idle_zk_state(),
+        100,
+        PlayerChoice::Heads {},
+        token(),
+        1,
+    );
+    let (mut state, _, _) = transfer_success_callback(
+        context(token(), 40),
+        escrowed(),
+        state,
+        idle_zk_state(),
+        player(),
+        100,
+        1,
+    );
+    state.is_test_mode = true;
+    let (state, _, _) =
+        force_resolve_game(context(owner(), 50), state, idle_zk_state(), player(), false);
+    assert_eq!(state.resolved_game_count, 2);
+
+    let first_page = state.export_games(0, 1);
+    assert_eq!(first_page.games.len(), 1);
+    assert_eq!(first_page.games[0].0, 0);
+    assert_eq!(first_page.games[0].1.game_id, compute_game_id(&player(), 0, 10));
+    assert!(first_page.games[0].1.record.player_won);
+    assert_eq!(first_page.next_cursor, Some(1));
+
+    let second_page = state.export_games(1, 1);
+    assert_eq!(second_page.games[0].1.game_id, compute_game_id(&player(), 1, 30));
+    assert!(!second_page.games[0].1.record.player_won);
+    assert_eq!(second_page.next_cursor, None);
+}
+
+#[test]
+fn voiding_a_game_reverses_the_charges_taken_on_its_bet() {
+    let referrer = address(0x51);
+    let raked_game = || {
+        let (mut state, _) =
+            initialize(context(owner(), 0), idle_zk_state(), InitConfig::new(token()));
+        state.house_bankroll = 1_000;
+        state.jackpot_rake_bps = 1_000;
+        state.referral_commission_bps = 500;
+        state.referrers.insert(player(), referrer);
+        let (state, _, _) = start_game_and_place_bet(
+            context(player(), 10),
+            state,
+            idle_zk_state(),
+            100,
+            PlayerChoice::Heads {},
+            token(),
+            0,
+        );
+        let (state, _, _) = transfer_success_callback(
+            context(token(), 20),
+            escrowed(),
+            state,
+            idle_zk_state(),
+            player(),
+            100,
+            0,
+        );
+        state
+    };
+    let state = raked_game();
+    assert_eq!(state.jackpot_pool, 10);
+    assert_eq!(state.referral_rewards.get(&referrer), Some(4));
+    assert_eq!(state.house_bankroll, 996);
+
+    assert!(rejects(|| void_game(context(player(), 30), raked_game(), idle_zk_state(), player())));
+    let (state, events, _) =
+        void_game(context(owner(), 30), raked_game(), idle_zk_state(), player());
+    assert_eq!(events.len(), 1);
+    assert_eq!(phase_of(&state), GamePhase::Start {});
+    assert_eq!(state.jackpot_pool, 0);
+    assert_eq!(state.referral_rewards.get(&referrer), Some(0));
+    assert_eq!(state.house_bankroll, 1_000);
+    state.assert_ledger_balanced();
+
+    // A sunset voids the game the same way
+    let (state, _, _) = sunset(context(owner(), 30), raked_game(), idle_zk_state(), 0);
+    let (state, events, _) = sweep_sunset(context(address(3), 40), state, idle_zk_state(), 1);
+    assert_eq!(events.len(), 1);
+    assert_eq!(phase_of(&state), GamePhase::Start {});
+    assert_eq!(state.jackpot_pool, 0);
+    assert_eq!(state.referral_rewards.get(&referrer), Some(0));
+    assert_eq!(state.house_bankroll, 1_000);
+    state.assert_ledger_balanced();
+}
+
+#[test]
+fn a_lost_sealed_stake_is_split_in_secret_without_being_revealed() {
+    let sealed_game = || {
+        let mut state = game_at(Stage::Computing);
+        let mut bet = state.player_bets.get(&player()).unwrap();
+        bet.secret_amount = true;
+        state.player_bets.insert(player(), bet);
+        state
+    };
+    let stake = || variable(6, SecretVarType::SealedStake { player: player() }, None);
+    let settle = |payable: i128| {
+        let payout = variable(
+            FLIP_RESULT_ID,
+            SecretVarType::SealedPayout { player: player() },
+            Some(payable.to_le_bytes().to_vec()),
+        );
+        open_flip_result_variable(
+            context(player(), 40),
+            sealed_game(),
+            zk_state_with(vec![payout, stake()]),
+            vec![SecretVarId::new(FLIP_RESULT_ID)],
+        )
+    };
+
+    // A won stake is opened through the payable amount, as with a secret bet
+    let (state, _, _) = settle(140);
+    assert_eq!(state.player_bets.get(&player()).unwrap().amount, 40);
+    assert_eq!(state.secret_bet_refunds.get(&player()), Some(60));
+
+    let (state, _, changes) = settle(0);
+    assert_eq!(phase_of(&state), GamePhase::Done {});
+    assert_eq!(state.player_bets.get(&player()).unwrap().amount, 0);
+    assert_eq!(state.sealed_refunds.get(&player()), Some(100));
+    assert!(changes
+        .iter()
+        .any(|change| matches!(change, ZkStateChange::StartComputation { .. })));
+    state.assert_ledger_balanced();
+
+    // The house's take is credited first, then the player's credit, which ends the split
+    let house_take = || variable(7, SecretVarType::SealedHouseTake { player: player() }, None);
+    let (state, _, changes) = sealed_split_computed(
+        context(player(), 50),
+        state,
+        zk_state_with(vec![stake(), house_take()]),
+        vec![SecretVarId::new(7)],
+    );
+    assert_eq!(state.sealed_refunds.get(&player()), Some(100));
+    assert!(matches!(changes[1], ZkStateChange::StartComputation { .. }));
+    let credit = variable(8, SecretVarType::SealedCredit { player: player() }, None);
+    let (state, _, changes) = sealed_split_computed(
+        context(player(), 60),
+        state,
+        zk_state_with(vec![stake(), house_take(), credit]),
+        vec![SecretVarId::new(8)],
+    );
+    assert!(state.sealed_refunds.get(&player()).is_none());
+    assert!(matches!(&changes[0], ZkStateChange::DeleteVariables { variables_to_delete }
+        if variables_to_delete == &vec![SecretVarId::new(6)]));
+
+    // A single loss is never released, as its cover would reveal the stake
+    assert_eq!(state.sealed_credit_losses.get(&player()), Some(1));
+    assert_eq!(state.sealed_house_losses, 1);
+    let credited = || {
+        let credit = variable(8, SecretVarType::SealedCredit { player: player() }, None);
+        zk_state_with(vec![credit, house_take()])
+    };
+    assert!(rejects(|| withdraw_sealed_credit(context(player(), 70), state, credited())));
+    let aggregated = || {
+        let mut state = game_at(Stage::Idle);
+        state.sealed_credit_losses.insert(player(), MIN_SEALED_LOSSES_PER_RELEASE);
+        state.sealed_house_losses = MIN_SEALED_LOSSES_PER_RELEASE - 1;
+        state
+    };
+    assert!(rejects(|| release_sealed_house_take(context(owner(), 70), aggregated(), credited())));
+    let (state, _, changes) =
+        withdraw_sealed_credit(context(player(), 70), aggregated(), credited());
+    assert!(matches!(changes[0], ZkStateChange::StartComputation { .. }));
+    assert!(state.sealed_credit_losses.get(&player()).is_none());
+}
+
+#[test]
+fn accounts_are_imported_in_chunks_until_the_first_stake() {
+    let account = |user, balance| ImportedAccount {
+        user,
+        balance,
+        stats: None,
+    };
+    let import = |state, sender, entries| {
+        import_state(context(sender, 5), state, idle_zk_state(), entries)
+    };
+    let (state, _, _) = import(game_at(Stage::Idle), owner(), vec![account(address(3), 40)]);
+    let stats = PlayerStats {
+        games_played: 3,
+        wins: 2,
+        ..PlayerStats::default()
+    };
+    let veteran = ImportedAccount {
+        stats: Some(stats),
+        ..account(address(4), 60)
+    };
+    let (state, _, _) = import(state, owner(), vec![veteran, account(player(), 5)]);
+    assert_eq!(state.user_balances.get(&address(4)), Some(60));
+    assert_eq!(state.player_stats.get(&address(4)).unwrap().wins, 2);
+    assert_eq!(state.import.imported_accounts, 3);
+    assert_eq!(state.import.imported_total, 105);
+
+    // A balance deposited before the import is added to
+    let mut state = game_at(Stage::Idle);
+    state.adjust_balance(address(4), 10);
+    let (state, _, _) = import(state, owner(), vec![account(address(4), 60)]);
+    assert_eq!(state.user_balances.get(&address(4)), Some(70));
+
+    let imported = || import(game_at(Stage::Idle), owner(), vec![account(address(3), 40)]).0;
+    assert!(rejects(|| import(imported(), player(), vec![account(address(4), 60)])));
+    assert!(rejects(|| import(imported(), owner(), vec![account(address(3), 40)])));
+    let twice = vec![account(address(4), 1), account(address(4), 1)];
+    assert!(rejects(|| import(imported(), owner(), twice)));
+    assert!(rejects(|| import(game_at(Stage::Flipping), owner(), vec![account(address(4), 60)])));
+
+    // Stakes other than bets close the import as well
+    let (state, _, _) =
+        create_rps_challenge(context(address(5), 6), imported(), idle_zk_state(), 10);
+    assert!(rejects(|| import(state, owner(), vec![account(address(4), 60)])));
+}
+
+#[test]
+fn a_lottery_is_drawn_only_with_randomness_from_outside_its_ticket_holders() {
+    let closed_lottery = || {
+        let mut state = game_at(Stage::Idle);
+        let mut lottery = Lottery::new(0, 10, 50);
+        lottery.add_tickets(player(), 2);
+        state.lottery = Some(lottery);
+        state
+    };
+    let randomness_of = |owner| {
+        let mut randomness = variable(5, SecretVarType::LotteryRandomness { lottery_id: 0 }, None);
+        randomness.owner = owner;
+        zk_state_with(vec![randomness])
+    };
+    let draw = |zk_state| draw_lottery(context(address(3), 60), closed_lottery(), zk_state);
+
+    assert!(rejects(|| draw(idle_zk_state())));
+    assert!(rejects(|| draw(randomness_of(player()))));
+    let (state, _, changes) = draw(randomness_of(address(4)));
+    assert_eq!(state.lottery.unwrap().status, LotteryStatus::Drawing {});
+    assert!(matches!(changes[0], ZkStateChange::StartComputation { .. }));
+}
+
+#[test]
+fn a_rock_paper_scissors_game_without_both_throws_ends_at_its_deadline() {
+    let throwing_game = || {
+        let mut state = game_at(Stage::Idle);
+        let game = RpsGame {
+            creator: player(),
+            opponent: Some(address(3)),
+            opponent_escrowed: true,
+            stake: 50,
+            status: RpsStatus::Throwing {},
+        };
+        state.rps_games.insert(0, game);
+        state.rps_throw_deadlines.insert(0, 1_000);
+        state
+    };
+    let creator_throw = || {
+        zk_state_with(vec![variable(
+            5,
+            SecretVarType::RpsThrow { game_id: 0, player: player() },
+            None,
+        )])
+    };
+    let claim = |sender, now, zk_state| {
+        claim_rps_timeout(context(sender, now), throwing_game(), zk_state, 0)
+    };
+
+    assert!(rejects(|| claim(address(3), 999, creator_throw())));
+    assert!(rejects(|| claim(address(4), 1_000, creator_throw())));
+    let (state, _, changes) = claim(address(3), 1_000, creator_throw());
+    let winner = Some(player());
+    assert_eq!(state.rps_games.get(&0).unwrap().status, RpsStatus::Finished { winner });
+    assert!(deletes_variables(&changes));
+
+    let (state, _, _) = claim(player(), 1_000, idle_zk_state());
+    let winner = None;
+    assert_eq!(state.rps_games.get(&0).unwrap().status, RpsStatus::Finished { winner });
+}
+
+#[test]
+fn storage_rent_leaves_pending_withdrawals_covered() {
+    let withdrawing = |balance| {
+        let mut state = game_at(Stage::Idle);
+        state.set_balance(player(), balance);
+        state.pending_withdrawals.insert(player(), 100);
+        state.storage_rent = Some(StorageRentConfig {
+            free_bytes: 0,
+            rent_per_kib_per_day: 10,
+        });
+        state.storage_accounts.insert(
+            player(),
+            StorageAccount {
+                bytes: 0,
+                last_collected_at: 0,
+            },
+        );
+        state
+    };
+    let (state, _, _) = collect_storage_rent(
+        context(address(3), MILLIS_PER_DAY),
+        withdrawing(100),
+        idle_zk_state(),
+        vec![player()],
+    );
+    assert_eq!(state.user_balances.get(&player()), Some(100));
+    let (state, _, _) =
+        withdraw_callback(context(token(), 5), escrowed(), state, idle_zk_state(), player(), 100);
+    assert_eq!(state.user_balances.get(&player()), Some(0));
+
+    assert!(rejects(|| {
+        withdraw_callback(
+            context(token(), 5),
+            escrowed(),
+            withdrawing(50),
+            idle_zk_state(),
+            player(),
+            100,
+        )
+    }));
+}
+
+#[test]
+fn a_failed_compensation_transfer_restores_the_claim() {
+    let mut state = game_at(Stage::Idle);
+    state.insurance.balance = 100;
+    let root = crate::insurance::claim_leaf(&player(), 40);
+    state.insurance.open_round(root, 100);
+    let (state, _, _) =
+        claim_compensation(context(player(), 5), state, idle_zk_state(), 0, 40, vec![]);
+    assert_eq!(state.insurance.rounds.get(&0).unwrap().remaining, 60);
+
+    let (state, _, _) = claim_compensation_callback(
+        context(token(), 6),
+        failed(),
+        state,
+        idle_zk_state(),
+        0,
+        player(),
+        40,
+    );
+    let round = state.insurance.rounds.get(&0).unwrap();
+    assert_eq!(round.remaining, 100);
+    assert!(round.claimed.is_empty());
+    assert_eq!(state.insurance.total_paid, 0);
+
+    let (state, _, _) =
+        claim_compensation(context(player(), 7), state, idle_zk_state(), 0, 40, vec![]);
+    assert_eq!(state.insurance.rounds.get(&0).unwrap().claimed, vec![player()]);
+}
+
+#[test]
+fn the_house_bankroll_cannot_be_debited_below_zero() {
+    let mut state = game_at(Stage::Idle);
+    state.house_bankroll = 10;
+    let token = state.token_address;
+    state.debit_bankroll(&token, 10);
+    assert_eq!(state.house_bankroll, 0);
+    assert!(rejects(|| {
+        let mut state = game_at(Stage::Idle);
+        state.house_bankroll = 10;
+        state.debit_bankroll(&token, 11);
+    }));
+}
+
+#[test]
+fn practice_is_rejected_while_a_round_is_open() {
+    assert!(rejects(|| {
+        practice_flip(
+            context(player(), 40),
+            game_at(Stage::Flipping),
+            randomness_zk_state(),
+            PlayerChoice::Heads {},
+        )
+    }));
+    let (state, _, changes) = practice_flip(
+        context(player(), 40),
+        game_at(Stage::Idle),
+        idle_zk_state(),
+        PlayerChoice::Heads {},
+    );
+    assert!(state.practice_choices.get(&player()).is_some());
+    assert_eq!(changes.len(), 1);
+}
+
+#[test]
+fn games_only_move_between_phases_the_engine_allows() {
+    assert!(rejects(|| {
+        let mut state = game_at(Stage::Idle);
+        state.set_phase(player(), GamePhase::BonusRound {}, 50);
+    }));
+    let mut state = game_at(Stage::Flipping);
+    state.set_phase(player(), GamePhase::BonusRound {}, 50);
+    state.set_phase(player(), GamePhase::Done {}, 60);
+    assert_eq!(state.settled_at.get(&player()), Some(60));
+}
+
+#[test]
+fn bet_validation_covers_the_previous_game_and_the_house_exposure() {
+    let violations = |state: &CoinFlipState| {
+        state.validate_bet(&player(), &heads_bet(100), &token(), &token(), 100)
+    };
+    assert_eq!(violations(&game_at(Stage::Done)), vec![BetViolation::UnclaimedPayout {}]);
+    assert!(violations(&paid_out_game()).is_empty());
+
+    let (mut state, _, _) =
+        set_winnings_reserve(context(owner(), 0), paid_out_game(), idle_zk_state(), true);
+    state.house_bankroll = 199;
+    assert_eq!(violations(&state), vec![BetViolation::InsufficientBankroll {}]);
+    state.house_bankroll = 200;
+    assert!(violations(&state).is_empty());
+}
+
+#[test]
+fn exposure_that_would_overflow_rejects_the_bet() {
+    let mut state = game_at(Stage::Idle);
+    let token = state.token_address;
+    state.outstanding_payouts.insert(token, u64::MAX);
+    assert!(rejects(|| state.reserve_exposure(&player(), &token, 1)));
+}
+
+#[test]
+fn instant_bets_are_placed_before_their_entropy_is_revealed() {
+    let provider = address(7);
+    let config = InstantConfig {
+        provider,
+        max_stake: 10,
+        min_commitment_age_millis: 5,
+    };
+    let value = Hash::digest(b"instant entropy");
+    let placed = || {
+        let state = game_at(Stage::Idle);
+        let (state, _, _) =
+            set_instant_mode(context(owner(), 1), state, idle_zk_state(), Some(config.clone()));
+        let commitments = vec![Hash::digest(&value.bytes)];
+        let (mut state, _, _) =
+            commit_instant_entropy(context(provider, 2), state, idle_zk_state(), commitments);
+        state.set_balance(player(), 100);
+        state.set_balance(address(3), 100);
+        let bet = PlayerChoice::Heads {};
+        instant_flip(context(player(), 10), state, idle_zk_state(), 10, bet, 0).0
+    };
+
+    let state = placed();
+    assert_eq!(phase_of(&state), GamePhase::FlipCoin {});
+    assert!(state.missing_randomness(&player()).is_some());
+    // The only value is taken, so no further bet can be placed until more are committed
+    assert!(rejects(|| {
+        let bet = PlayerChoice::Tails {};
+        instant_flip(context(address(3), 11), placed(), idle_zk_state(), 10, bet, 0)
+    }));
+
+    let (state, _, _) =
+        reveal_instant_entropy(context(provider, 20), state, idle_zk_state(), vec![value]);
+    assert_eq!(phase_of(&state), GamePhase::Done {});
+    assert!(state.instant_entropy.is_empty() && state.instant_bets.is_empty());
+    let heads = (salt_word(&value) ^ state.round_salt_word(&player())) & 1 != 0;
+    assert_eq!(state.flip_results.get(&player()), Some(heads));
+}
+
+#[test]
+fn a_sunset_completes_only_after_a_pass_finds_nothing_left() {
+    let sunset_awaiting_escrow =
+        || sunset(context(owner(), 30), game_at(Stage::AwaitEscrow), idle_zk_state(), 0).0;
+    assert!(rejects(|| {
+        sweep_sunset(context(address(3), 40), sunset_awaiting_escrow(), idle_zk_state(), 0)
+    }));
+
+    let state = sunset_awaiting_escrow();
+    let (state, _, _) = sweep_sunset(context(address(3), 40), state, idle_zk_state(), 10);
+    assert!(!state.sunset.as_ref().unwrap().completed);
+    // The stake still in transit is returned when it arrives
+    assert!(state.expired_escrows.get(&player()).is_some());
+
+    let (state, _, _) = sweep_sunset(context(address(3), 50), state, idle_zk_state(), 10);
+    assert!(state.sunset.as_ref().unwrap().completed);
+}
+
+#[test]
+fn a_sunset_settles_bonus_rounds_at_their_base_winnings() {
+    let mut state = game_at(Stage::Computing);
+    state.flip_results.insert(player(), true);
+    state.set_phase(player(), GamePhase::BonusRound {}, 40);
+    let (state, _, _) = sunset(context(owner(), 50), state, idle_zk_state(), 0);
+
+    let (state, events, _) = sweep_sunset(context(address(3), 60), state, idle_zk_state(), 1);
+    assert!(events.is_empty());
+    assert_eq!(phase_of(&state), GamePhase::Done {});
+    assert_eq!(state.get_winner(&player()), Some(player()));
+
+    let (state, events, _) = sweep_sunset(context(address(3), 70), state, idle_zk_state(), 1);
+    assert_eq!(events.len(), 1);
+    assert!(state.winners.get(&player()).is_none());
+}
+
+#[test]
+fn pruning_examines_the_oldest_settlements_first() {
+    let mut state = game_at(Stage::Idle);
+    state.cleanup_config.retention_millis = 100;
+    for index in 0..4 {
+        state.set_phase(address(10 + index), GamePhase::Done {}, index as i64);
+    }
+    // Settling again leaves the first settlement stale
+    state.set_phase(address(10), GamePhase::Done {}, 50);
+
+    assert_eq!(state.prune_expired_games(120, 2), 1);
+    assert!(state.settled_at.get(&address(11)).is_none());
+    assert_eq!(state.prune_expired_games(120, 10), 2);
+    assert!(state.settled_at.get(&address(10)).is_some());
+    assert_eq!(state.prune_expired_games(151, 10), 1);
+    assert!(state.settlement_queue.front().is_none());
+}
+
+#[test]
+fn a_round_without_randomness_from_outside_the_bet_is_not_flipped() {
+    let state = game_at(Stage::Flipping);
+    assert!(rejects(|| flip_coin(context(player(), 30), state, idle_zk_state())));
+
+    let (state, _, _) = inputted_variable(
+        context(player(), 25),
+        game_at(Stage::Flipping),
+        randomness_zk_state(),
+        SecretVarId::new(RANDOMNESS_ID),
+    );
+    assert!(rejects(|| flip_coin(context(player(), 30), state, randomness_zk_state())));
+
+    // The integrator placing the bet is party to it as well
+    let integrator = address(3);
+    let mut state = game_at(Stage::Flipping);
+    state.bet_integrators.insert(player(), integrator);
+    let metadata = SecretVarType::Randomness { player: player() };
+    let mut randomness = variable(HOUSE_RANDOMNESS_ID, metadata, None);
+    randomness.owner = integrator;
+    let zk_state = zk_state_with(vec![randomness]);
+    let variable_id = SecretVarId::new(HOUSE_RANDOMNESS_ID);
+    let (state, _, _) = inputted_variable(context(integrator, 25), state, zk_state, variable_id);
+    assert!(rejects(|| flip_coin(context(player(), 30), state, idle_zk_state())));
+
+    let state = with_house_randomness(game_at(Stage::Flipping));
+    let (state, _, changes) = flip_coin(context(player(), 30), state, idle_zk_state());
+    assert_eq!(changes.len(), 1);
+    assert_eq!(state.computation_pending.get(&player()), Some(true));
+}
+
+#[test]
+fn randomness_left_over_from_an_abandoned_round_is_deleted_by_the_next_round() {
+    // The house contributed to an earlier round of the player that was abandoned
+    let metadata = SecretVarType::Randomness { player: player() };
+    let mut leftover = variable(HOUSE_RANDOMNESS_ID, metadata, None);
+    leftover.owner = house();
+    let mut zk_state = randomness_zk_state();
+    zk_state.secret_variables.insert(leftover.variable_id, leftover);
+
+    let (state, _, changes) = inputted_variable(
+        context(player(), 100),
+        game_at(Stage::Flipping),
+        zk_state,
+        SecretVarId::new(RANDOMNESS_ID),
+    );
+    assert_eq!(state.round_contributors.get(&player()), Some(vec![player()]));
+    let leftover_ids = vec![SecretVarId::new(HOUSE_RANDOMNESS_ID)];
+    assert!(matches!(
+        changes.as_slice(),
+        [ZkStateChange::DeleteVariables { variables_to_delete }]
+            if *variables_to_delete == leftover_ids
+    ));
+}
+
+#[test]
+fn randomness_input_is_accepted_only_in_the_flip_coin_phase() {
+    for stage in STAGES {
+        let rejected = rejects(|| {
+            add_randomness_to_flip(context(player(), 100), game_at(stage), idle_zk_state())
+        });
+        let expected = !matches!(stage, Stage::Flipping | Stage::Computing);
+        assert_eq!(rejected, expected, "{stage:?}");
+    }
+}
+
+#[test]
+fn inputted_randomness_is_kept_only_while_flipping() {
+    for stage in STAGES {
+        let (state, _, changes) = inputted_variable(
+            context(player(), 100),
+            game_at(stage),
+            randomness_zk_state(),
+            SecretVarId::new(RANDOMNESS_ID),
+        );
+        let kept = stage == Stage::Flipping;
+        assert_eq!(deletes_variables(&changes), !kept, "{stage:?}");
+        let contributors = state.round_contributors.get(&player()).unwrap_or_default();
+        assert_eq!(contributors.contains(&player()), kept, "{stage:?}");
+    }
+}
+
+#[test]
+fn randomness_is_accepted_once_per_contributor() {
+    let (state, _, changes) = inputted_variable(
+        context(player(), 100),
+        game_at(Stage::Flipping),
+        randomness_zk_state(),
+        SecretVarId::new(RANDOMNESS_ID),
+    );
+    assert!(!deletes_variables(&changes));
+
+    let repeated = zk_state_with(vec![
+        variable(RANDOMNESS_ID, SecretVarType::Randomness { player: player() }, None),
+        variable(REPEATED_RANDOMNESS_ID, SecretVarType::Randomness { player: player() }, None),
+    ]);
+    let (state, _, changes) = inputted_variable(
+        context(player(), 110),
+        state,
+        repeated,
+        SecretVarId::new(REPEATED_RANDOMNESS_ID),
+    );
+    assert!(deletes_variables(&changes));
+    assert_eq!(state.round_contributors.get(&player()).unwrap().len(), 1);
+
+    assert!(rejects(|| {
+        add_randomness_to_flip(context(player(), 120), state, idle_zk_state())
+    }));
+}
+
+#[test]
+fn randomness_is_capped_per_round_and_per_sender() {
+    let capped = || {
+        let state = game_at(Stage::Flipping);
+        let (mut state, _, _) =
+            set_max_sender_contributions(context(owner(), 15), state, idle_zk_state(), 1);
+        // The player's contribution to the round of address(3) was confirmed
+        state.round_contributors.insert(address(3), vec![player()]);
+        state
+    };
+    let elsewhere = || {
+        let metadata = SecretVarType::Randomness { player: address(3) };
+        variable(REPEATED_RANDOMNESS_ID, metadata, None)
+    };
+    assert!(rejects(|| {
+        add_randomness_to_flip(context(player(), 20), capped(), zk_state_with(vec![elsewhere()]))
+    }));
+    assert!(!rejects(|| add_randomness_to_flip(context(player(), 20), capped(), idle_zk_state())));
+
+    // A contribution confirmed beyond the sender's cap is deleted
+    let both = zk_state_with(vec![
+        variable(RANDOMNESS_ID, SecretVarType::Randomness { player: player() }, None),
+        elsewhere(),
+    ]);
+    let (state, _, changes) =
+        inputted_variable(context(player(), 30), capped(), both, SecretVarId::new(RANDOMNESS_ID));
+    assert!(deletes_variables(&changes));
+    assert!(state.round_contributors.get(&player()).is_none());
+
+    // Other parties fill every slot of a round but the one kept for the bettor
+    let others = |count: u8| {
+        zk_state_with(
+            (0..count)
+                .map(|index| {
+                    let metadata = SecretVarType::Randomness { player: player() };
+                    let mut contribution = variable(10 + index as u32, metadata, None);
+                    contribution.owner = address(10 + index);
+                    contribution
+                })
+                .collect(),
+        )
+    };
+    let filled = DEFAULT_MAX_ROUND_CONTRIBUTIONS as u8 - 1;
+    let contributed = || {
+        let mut state = game_at(Stage::Flipping);
+        let contributors = (0..filled).map(|index| address(10 + index)).collect();
+        state.round_contributors.insert(player(), contributors);
+        state
+    };
+    assert!(rejects(|| {
+        add_randomness_for_player(context(address(3), 20), contributed(), others(filled), player())
+    }));
+    assert!(!rejects(|| {
+        add_randomness_to_flip(context(player(), 20), contributed(), others(filled))
+    }));
+
+    // An excess contribution of another party confirmed anyway is deleted
+    let (_, _, changes) = inputted_variable(
+        context(address(10), 30),
+        contributed(),
+        others(filled + 1),
+        SecretVarId::new(10 + filled as u32),
+    );
+    assert!(deletes_variables(&changes));
+
+    // Contributions already carrying public data are malformed
+    let opened = zk_state_with(vec![variable(
+        RANDOMNESS_ID,
+        SecretVarType::Randomness { player: player() },
+        Some(vec![1, 0, 0, 0]),
+    )]);
+    let (_, _, changes) = inputted_variable(
+        context(player(), 30),
+        game_at(Stage::Flipping),
+        opened,
+        SecretVarId::new(RANDOMNESS_ID),
+    );
+    assert!(deletes_variables(&changes));
+}
+
+#[test]
+fn flip_is_accepted_only_in_the_flip_coin_phase() {
+    for stage in STAGES {
+        let state = || with_house_randomness(game_at(stage));
+        let rejected = rejects(|| flip_coin(context(player(), 100), state(), idle_zk_state()));
+        let expected = !matches!(stage, Stage::Flipping | Stage::Computing);
+        assert_eq!(rejected, expected, "{stage:?}");
+    }
+}
+
+#[test]
+fn flip_starts_one_computation_per_round() {
+    let state = with_house_randomness(game_at(Stage::Flipping));
+    let (state, _, changes) = flip_coin(context(player(), 100), state, idle_zk_state());
+    assert_eq!(changes.len(), 1);
+    assert_eq!(state.computation_pending.get(&player()), Some(true));
+
+    let (_, events, changes) =
+        flip_coin(context(player(), 110), game_at(Stage::Computing), idle_zk_state());
+    assert!(events.is_empty());
+    assert!(changes.is_empty());
+}
+
+#[test]
+fn completed_computation_opens_its_outputs() {
+    let (_, events, changes) = flip_compute_complete(
+        context(player(), 100),
+        game_at(Stage::Computing),
+        idle_zk_state(),
+        vec![SecretVarId::new(FLIP_RESULT_ID)],
+    );
+    assert!(events.is_empty());
+    assert_eq!(changes.len(), 1);
+    assert!(matches!(changes[0], ZkStateChange::OpenVariables { .. }));
+}
+
+#[test]
+fn opened_result_settles_the_computing_game() {
+    let (state, _, changes) = open_flip_result_variable(
+        context(player(), 100),
+        game_at(Stage::Computing),
+        flip_result_zk_state(),
+        vec![SecretVarId::new(FLIP_RESULT_ID)],
+    );
+    assert_eq!(phase_of(&state), GamePhase::Done {});
+    assert_eq!(state.get_winner(&player()), Some(player()));
+    assert_eq!(state.computation_pending.get(&player()), None);
+    assert!(deletes_variables(&changes));
+}
+
+#[test]
+fn payout_is_accepted_only_once_done() {
+    for stage in STAGES {
+        let rejected =
+            rejects(|| payout_winner(context(player(), 100), game_at(stage), idle_zk_state()));
+        assert_eq!(rejected, stage != Stage::Done, "{stage:?}");
+    }
+
+    let (state, events, _) =
+        payout_winner(context(player(), 100), game_at(Stage::Done), idle_zk_state());
+    assert_eq!(events.len(), 1);
+    assert_eq!(state.get_winner(&player()), None);
+}
@@ -0,0 +1,95 @@
+//! Delegated custody for custodial operators, such as exchanges, whose end users have no chain
+//! addresses.
+//!
+//! Each end user of a custodian is known by an opaque id and plays through a proxy address
+//! derived from the custodian and the id. The proxy's balance is the end user's sub-ledger
+//! inside the custodian's account: bets are placed from it and winnings are credited to it.
+//! Nobody can sign for a proxy address, so only the custodian can move its funds.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::{Address, AddressType};
+use pbc_contract_common::sorted_vec_map::SortedVecMap;
+use pbc_contract_common::Hash;
+use pbc_traits::ReadWriteState;
+use read_write_rpc_derive::ReadWriteRPC;
+use read_write_state_derive::ReadWriteState;
+
+use crate::contract::CoinFlipState;
+use crate::engine::GamePhase;
+use crate::errors::{GameError, OrFail};
+
+/// End users of a custodian.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct CustodyAccount {
+    /// Whether the custodian may currently play for its end users.
+    pub active: bool,
+    /// Proxy address of every end user the custodian has allocated funds to.
+    pub end_users: SortedVecMap<u128, Address>,
+}
+
+/// Sub-ledger of an end user as shown to the custodian.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+pub struct SubLedgerEntry {
+    pub end_user: u128,
+    pub proxy: Address,
+    pub balance: u64,
+    pub phase: GamePhase,
+}
+
+impl CustodyAccount {
+    pub fn new() -> Self {
+        CustodyAccount {
+            active: true,
+            end_users: SortedVecMap::new(),
+        }
+    }
+}
+
+/// Address the end user of a custodian plays through.
+pub fn end_user_address(custodian: &Address, end_user: u128) -> Address {
+    let mut bytes = Vec::new();
+    custodian.state_write_to(&mut bytes).unwrap();
+    bytes.extend_from_slice(&end_user.to_be_bytes());
+    let digest = Hash::digest(&bytes);
+    let mut identifier = [0u8; 20];
+    identifier.copy_from_slice(&digest.bytes[..20]);
+    Address {
+        address_type: AddressType::Account,
+        identifier,
+    }
+}
+
+impl CoinFlipState {
+    /// Proxy address of an end user of an active custodian, registering the end user if new.
+    pub(crate) fn custody_proxy(&mut self, custodian: &Address, end_user: u128) -> Address {
+        let account = self
+            .custody_accounts
+            .get_mut(custodian)
+            .filter(|account| account.active)
+            .or_fail(
+                GameError::Unauthorized,
+                "Only custodians can act on behalf of end users.",
+            );
+        let proxy = end_user_address(custodian, end_user);
+        account.end_users.insert(end_user, proxy);
+        proxy
+    }
+
+    /// Sub-ledgers of every end user of the custodian.
+    pub fn custody_sub_ledgers(&self, custodian: &Address) -> Vec<SubLedgerEntry> {
+        let account = match self.custody_accounts.get(custodian) {
+            Some(account) => account,
+            None => return vec![],
+        };
+        account
+            .end_users
+            .iter()
+            .map(|(end_user, proxy)| SubLedgerEntry {
+                end_user: *end_user,
+                proxy: *proxy,
+                balance: self.user_balances.get(proxy).unwrap_or(0),
+                phase: self.game_phases.get(proxy).unwrap_or(GamePhase::Start {}),
+            })
+            .collect()
+    }
+}
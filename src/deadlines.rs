@@ -0,0 +1,53 @@
+//! Deadlines of the stages of a game. Every transition stamps the game with the time it entered
+//! its current stage, and a game still in the stage once its timeout has passed expires: its stake
+//! is returned and it restarts, so no game lingers waiting for an escrow, randomness or a
+//! computation that never comes.
+
+use create_type_spec_derive::CreateTypeSpec;
+use read_write_rpc_derive::ReadWriteRPC;
+use read_write_state_derive::ReadWriteState;
+
+/// Stage of a game that is waiting on something else.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum WaitingStage {
+    /// The stake of the bet is being escrowed through the token contract.
+    Escrow,
+    /// The round accepts randomness and waits for its flip.
+    Randomness,
+    /// The flip is queued or being computed.
+    Computation,
+}
+
+/// Timeouts of the stages of a game. A zero timeout lets games wait in that stage indefinitely.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+pub struct PhaseTimeouts {
+    pub bet_escrow_timeout_millis: i64,
+    pub randomness_timeout_millis: i64,
+    pub compute_timeout_millis: i64,
+}
+
+impl PhaseTimeouts {
+    pub fn new() -> Self {
+        PhaseTimeouts {
+            bet_escrow_timeout_millis: 0,
+            randomness_timeout_millis: 0,
+            compute_timeout_millis: 0,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.bet_escrow_timeout_millis >= 0
+            && self.randomness_timeout_millis >= 0
+            && self.compute_timeout_millis >= 0
+    }
+
+    /// Deadline of a game that entered `stage` at `entered_at`, if the stage has a timeout.
+    pub fn deadline(&self, stage: WaitingStage, entered_at: i64) -> Option<i64> {
+        let timeout = match stage {
+            WaitingStage::Escrow => self.bet_escrow_timeout_millis,
+            WaitingStage::Randomness => self.randomness_timeout_millis,
+            WaitingStage::Computation => self.compute_timeout_millis,
+        };
+        (timeout > 0).then(|| entered_at.saturating_add(timeout))
+    }
+}
@@ -0,0 +1,286 @@
+//! The game engine shared by the contract and by other contracts reusing it as a library: bet
+//! types, the phase machine, settlement math and the folding of randomness into outcomes.
+//!
+//! The zk computations in `zk_compute` fold secret-shared contributions the same way. They are
+//! written against the secret-shared types of the zk runtime and are compiled into the
+//! computation itself, so they cannot call into this module; their tests check them against the
+//! folding here instead.
+//!
+//! Nothing here depends on the contract's actions, so with the `library` feature only this module
+//! is compiled.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use pbc_contract_common::Hash;
+use pbc_traits::ReadWriteState;
+use read_write_rpc_derive::ReadWriteRPC;
+use read_write_state_derive::ReadWriteState;
+
+/// Player choices: Heads or Tails
+#[derive(ReadWriteState, ReadWriteRPC, Debug, PartialEq, Copy, Clone, CreateTypeSpec)]
+#[repr(u8)]
+pub enum PlayerChoice {
+    #[discriminant(0)]
+    Heads {},
+    #[discriminant(1)]
+    Tails {},
+}
+
+impl PlayerChoice {
+    /// Whether the choice matches a flip, `heads` meaning the coin landed heads.
+    pub fn matches_flip(&self, heads: bool) -> bool {
+        (*self == PlayerChoice::Heads {}) == heads
+    }
+}
+
+/// Phases of a player's game.
+#[derive(ReadWriteRPC, ReadWriteState, CreateTypeSpec, Debug, PartialEq, Copy, Clone)]
+pub enum GamePhase {
+    #[discriminant(0)]
+    Start {},
+    #[discriminant(1)]
+    PlaceBets {},
+    #[discriminant(2)]
+    FlipCoin {},
+    #[discriminant(3)]
+    Done {},
+    /// The result is known but open to disputes until the dispute window passes, after which the
+    /// game is settled and moves to `Done`.
+    #[discriminant(4)]
+    Challengeable {},
+    /// The flip was won and triggered a bonus round, whose multiplier is being drawn.
+    #[discriminant(5)]
+    BonusRound {},
+}
+
+impl GamePhase {
+    /// Whether a game in this phase has no stake at risk, so a new bet may replace it.
+    pub fn is_settled(&self) -> bool {
+        matches!(self, GamePhase::Start {} | GamePhase::Done {})
+    }
+
+    /// Whether a game in this phase may move to `next`. Any game can be reset to `Start`. A bet is
+    /// flipped once escrowed from a settled game, and only a flipped game enters a bonus round. A
+    /// concluded game becomes challengeable right after it is marked `Done`.
+    pub fn can_move_to(&self, next: GamePhase) -> bool {
+        match next {
+            GamePhase::Start {} => true,
+            GamePhase::PlaceBets {} => *self == GamePhase::Start {},
+            GamePhase::FlipCoin {} => self.is_settled() || *self == GamePhase::PlaceBets {},
+            GamePhase::BonusRound {} => *self == GamePhase::FlipCoin {},
+            GamePhase::Challengeable {} => {
+                matches!(self, GamePhase::FlipCoin {} | GamePhase::Done {})
+            }
+            GamePhase::Done {} => *self != GamePhase::PlaceBets {},
+        }
+    }
+}
+
+/// Prediction of a multi-flip bet: at least `min_matches` of `flips` coins land on the chosen side.
+#[derive(ReadWriteState, ReadWriteRPC, Debug, PartialEq, Copy, Clone, CreateTypeSpec)]
+pub struct MultiFlipPrediction {
+    pub flips: u8,
+    pub min_matches: u8,
+}
+
+impl MultiFlipPrediction {
+    /// Number of flip outcomes out of `2^flips` that satisfy the prediction.
+    pub fn winning_outcomes(&self) -> u64 {
+        (self.min_matches..=self.flips)
+            .map(|matches| binomial(self.flips as u64, matches as u64))
+            .sum()
+    }
+
+    /// Whether the flips encoded in `mask` (bit set meaning heads) satisfy the prediction.
+    pub fn is_satisfied_by(&self, mask: u32, choice: PlayerChoice) -> bool {
+        let heads = mask.count_ones() as u8;
+        let matches = match choice {
+            PlayerChoice::Heads {} => heads,
+            PlayerChoice::Tails {} => self.flips - heads,
+        };
+        matches >= self.min_matches
+    }
+}
+
+/// Encode an exact sequence of flips as a multi-flip bitmask, bit `i` set meaning flip `i` lands
+/// heads.
+pub fn encode_sequence(sequence: &[PlayerChoice]) -> u32 {
+    sequence
+        .iter()
+        .enumerate()
+        .filter(|(_, choice)| **choice == PlayerChoice::Heads {})
+        .fold(0, |mask, (flip, _)| mask | 1 << flip)
+}
+
+/// Number of ways to choose `k` out of `n`.
+fn binomial(n: u64, k: u64) -> u64 {
+    (0..k).fold(1, |acc, i| acc * (n - i) / (i + 1))
+}
+
+/// Odds of a weighted coin bet: the coin lands on the player's side for `winning_outcomes` out of
+/// `denominator` equally likely outcomes.
+#[derive(ReadWriteState, ReadWriteRPC, Debug, PartialEq, Copy, Clone, CreateTypeSpec)]
+pub struct WeightedOdds {
+    pub winning_outcomes: u8,
+    pub denominator: u8,
+}
+
+impl WeightedOdds {
+    /// Amount paid out for a winning bet of `stake`: the fair multiplier
+    /// `denominator / winning_outcomes` minus the house edge.
+    pub fn winnings(&self, stake: u64, house_edge_bps: u32) -> u64 {
+        let multiplier_bps = self.denominator as u128 * (10_000 - house_edge_bps as u128)
+            / self.winning_outcomes as u128;
+        (stake as u128 * multiplier_bps / 10_000) as u64
+    }
+}
+
+/// Prediction of a dice game: the die with `faces` faces shows `predicted`, counting from 1.
+#[derive(ReadWriteState, ReadWriteRPC, Debug, PartialEq, Copy, Clone, CreateTypeSpec)]
+pub struct DicePrediction {
+    pub faces: u8,
+    pub predicted: u8,
+}
+
+/// Kinds of roulette bets.
+#[derive(ReadWriteState, ReadWriteRPC, Debug, PartialEq, Copy, Clone, CreateTypeSpec)]
+#[repr(u8)]
+pub enum BetKind {
+    #[discriminant(0)]
+    Red {},
+    #[discriminant(1)]
+    Black {},
+    #[discriminant(2)]
+    Even {},
+    #[discriminant(3)]
+    Odd {},
+    /// A single pocket from 0 to 36.
+    #[discriminant(4)]
+    Number { number: u8 },
+}
+
+/// The red pockets of the roulette wheel.
+const RED_NUMBERS: [u8; 18] = [1, 3, 5, 7, 9, 12, 14, 16, 18, 19, 21, 23, 25, 27, 30, 32, 34, 36];
+
+impl BetKind {
+    /// Whether the bet wins when the ball lands in `number`. Zero only wins a bet on zero.
+    pub fn wins_on(&self, number: u8) -> bool {
+        match self {
+            BetKind::Number { number: predicted } => *predicted == number,
+            _ if number == 0 => false,
+            BetKind::Red {} => RED_NUMBERS.contains(&number),
+            BetKind::Black {} => !RED_NUMBERS.contains(&number),
+            BetKind::Even {} => number % 2 == 0,
+            BetKind::Odd {} => number % 2 == 1,
+        }
+    }
+
+    /// Total return on a winning bet, as a multiple of the stake.
+    pub fn payout_multiplier(&self) -> u64 {
+        match self {
+            BetKind::Number { .. } => 36,
+            _ => 2,
+        }
+    }
+}
+
+/// Struct to hold player bets
+#[derive(ReadWriteState, ReadWriteRPC, Debug, Clone, CreateTypeSpec)] 
+pub struct PlayerBet {
+    pub amount: u64,
+    /// The public choice, or `None` when the choice is given as a secret input.
+    pub choice: Option<PlayerChoice>,
+    /// Whether the actual stake is a secret input. `amount` is then the escrowed cover of the
+    /// stake until the result is opened, after which it is the revealed stake.
+    pub secret_amount: bool,
+    /// Set when the bet is on several flips at once rather than a single one.
+    pub multi_flip: Option<MultiFlipPrediction>,
+    /// Set when the bet is on a dice roll rather than a coin flip; `choice` is then `None`.
+    pub dice: Option<DicePrediction>,
+    /// Set when the bet is on a roulette spin; `choice` is then `None`.
+    pub roulette: Option<BetKind>,
+    /// Receives the winnings of the bet instead of the player, as named by the player or by the
+    /// integrator that placed it. Statistics of the bet stay with the player.
+    pub beneficiary: Option<Address>,
+}
+
+impl PlayerBet {
+    /// Whether the heads/tails choice is given as a secret input.
+    pub fn has_secret_choice(&self) -> bool {
+        self.choice.is_none() && self.dice.is_none() && self.roulette.is_none()
+    }
+}
+
+/// Amount paid out to a winning player for the given bet.
+/// Multi-flip bets pay the fair odds of their prediction.
+/// Dice games pay `faces` minus the house edge.
+/// Roulette bets pay the multiplier of their kind.
+pub fn winnings_for(bet: &PlayerBet, dice_house_edge_bps: u32) -> u64 {
+    if let Some(prediction) = bet.dice {
+        let multiplier_bps = prediction.faces as u128 * 10_000 - dice_house_edge_bps as u128;
+        return (bet.amount as u128 * multiplier_bps / 10_000) as u64;
+    }
+    if let Some(kind) = bet.roulette {
+        return bet.amount * kind.payout_multiplier();
+    }
+    match bet.multi_flip {
+        None => bet.amount * 2,
+        Some(prediction) => {
+            let outcomes = 1u128 << prediction.flips;
+            (bet.amount as u128 * outcomes / prediction.winning_outcomes() as u128) as u64
+        }
+    }
+}
+
+/// Chance of the bet winning, in basis points.
+pub fn win_chance_bps(bet: &PlayerBet) -> u32 {
+    if let Some(prediction) = bet.dice {
+        return 10_000 / prediction.faces as u32;
+    }
+    if let Some(kind) = bet.roulette {
+        let winning_pockets = (0..=36).filter(|number| kind.wins_on(*number)).count() as u32;
+        return winning_pockets * 10_000 / 37;
+    }
+    match bet.multi_flip {
+        None => 5_000,
+        Some(prediction) => {
+            let outcomes = 1u128 << prediction.flips;
+            (prediction.winning_outcomes() as u128 * 10_000 / outcomes) as u32
+        }
+    }
+}
+
+/// Identifier of the game a player bets on with `nonce` in a block produced at `block_time`: the
+/// hash of the three in state serialization. A player's bet nonces are never reused, so the id is
+/// unique and can be computed off-chain from the bet transaction.
+pub fn compute_game_id(player: &Address, nonce: u64, block_time: i64) -> Hash {
+    let mut seed = Vec::new();
+    player.state_write_to(&mut seed).unwrap();
+    nonce.state_write_to(&mut seed).unwrap();
+    block_time.state_write_to(&mut seed).unwrap();
+    Hash::digest(&seed)
+}
+
+/// XOR-fold randomness contributions into a single word, starting from the public salt of the
+/// round. Every bit of the word is uniform as long as one contribution is.
+pub fn fold_randomness(salt: i32, contributions: impl IntoIterator<Item = i32>) -> i32 {
+    contributions.into_iter().fold(salt, |folded, contribution| folded ^ contribution)
+}
+
+/// Whether a coin flip on the folded randomness lands heads: the low bit of the word is set.
+pub fn lands_heads(folded: i32) -> bool {
+    folded & 1 != 0
+}
+
+/// Draw a number from 0 to `modulus - 1` from the low 31 bits of the folded randomness, as dice
+/// rolls, roulette spins and weighted flips do.
+pub fn uniform_below(modulus: u8, folded: i32) -> u8 {
+    ((folded & 0x7FFF_FFFF) % i32::from(modulus)) as u8
+}
+
+/// Amount paid out for a parlay of `stake` whose `legs` predictions all hit: `2^legs` times the
+/// stake minus the house edge.
+pub fn parlay_winnings(stake: u64, legs: u32, house_edge_bps: u32) -> u64 {
+    let multiplier_bps = (1u128 << legs) * (10_000 - house_edge_bps as u128);
+    (stake as u128 * multiplier_bps / 10_000) as u64
+}
@@ -0,0 +1,128 @@
+//! Per-epoch statistics and the compact summaries pushed to the archive contract when an
+//! epoch closes.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use pbc_contract_common::sorted_vec_map::SortedVecMap;
+use read_write_rpc_derive::ReadWriteRPC;
+use read_write_state_derive::ReadWriteState;
+
+/// Number of players included in the leaderboard of an epoch summary.
+pub const LEADERBOARD_SIZE: usize = 10;
+
+/// Where and how often epoch summaries are archived.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+pub struct EpochArchiveConfig {
+    /// Contract receiving the epoch summaries.
+    pub archive_address: Address,
+    /// Shortname of the archive contract's action accepting an `EpochSummary`.
+    pub archive_shortname: u32,
+    /// Minimum duration of an epoch.
+    pub epoch_length_millis: i64,
+}
+
+/// Detailed statistics of the running epoch.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct EpochState {
+    pub epoch: u32,
+    pub started_at: i64,
+    pub games_settled: u64,
+    pub player_wins: u64,
+    pub total_wagered: u64,
+    pub total_won_by_players: u64,
+    /// Losing stakes burned during the epoch.
+    pub total_burned: u64,
+    /// Winnings per player during the epoch, used to build the leaderboard.
+    pub player_winnings: SortedVecMap<Address, u64>,
+}
+
+/// A single leaderboard row.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+pub struct LeaderboardEntry {
+    pub player: Address,
+    pub winnings: u64,
+}
+
+/// Token amounts held by the contract when the epoch closed.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+pub struct PoolSizes {
+    /// Stakes escrowed in the default token by games that are not yet resolved.
+    pub escrowed_bets: u64,
+    /// Sum of all internal player balances.
+    pub user_balances: u64,
+}
+
+/// Compact record of a closed epoch, sent to the archive contract.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+pub struct EpochSummary {
+    pub epoch: u32,
+    pub started_at: i64,
+    pub ended_at: i64,
+    pub games_settled: u64,
+    pub player_wins: u64,
+    pub total_wagered: u64,
+    pub total_won_by_players: u64,
+    pub total_burned: u64,
+    pub leaderboard: Vec<LeaderboardEntry>,
+    pub pools: PoolSizes,
+}
+
+impl EpochState {
+    /// Start a fresh epoch.
+    pub fn new(epoch: u32, started_at: i64) -> Self {
+        EpochState {
+            epoch,
+            started_at,
+            games_settled: 0,
+            player_wins: 0,
+            total_wagered: 0,
+            total_won_by_players: 0,
+            total_burned: 0,
+            player_winnings: SortedVecMap::new(),
+        }
+    }
+
+    /// Whether the epoch has lasted long enough to be closed.
+    pub fn is_over(&self, config: &EpochArchiveConfig, now: i64) -> bool {
+        now >= self.started_at + config.epoch_length_millis
+    }
+
+    /// Record a settled game.
+    pub fn record_settlement(&mut self, player: Address, amount: u64, winnings: Option<u64>) {
+        self.games_settled += 1;
+        self.total_wagered += amount;
+        if let Some(winnings) = winnings {
+            self.player_wins += 1;
+            self.total_won_by_players += winnings;
+            let total = self.player_winnings.get(&player).copied().unwrap_or(0);
+            self.player_winnings.insert(player, total + winnings);
+        }
+    }
+
+    /// Summarize the epoch into the record archived off-contract.
+    pub fn summarize(&self, ended_at: i64, pools: PoolSizes) -> EpochSummary {
+        let mut leaderboard: Vec<LeaderboardEntry> = self
+            .player_winnings
+            .iter()
+            .map(|(player, winnings)| LeaderboardEntry {
+                player: *player,
+                winnings: *winnings,
+            })
+            .collect();
+        leaderboard.sort_by(|a, b| b.winnings.cmp(&a.winnings));
+        leaderboard.truncate(LEADERBOARD_SIZE);
+
+        EpochSummary {
+            epoch: self.epoch,
+            started_at: self.started_at,
+            ended_at,
+            games_settled: self.games_settled,
+            player_wins: self.player_wins,
+            total_wagered: self.total_wagered,
+            total_won_by_players: self.total_won_by_players,
+            total_burned: self.total_burned,
+            leaderboard,
+            pools,
+        }
+    }
+}
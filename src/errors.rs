@@ -0,0 +1,100 @@
+//! Errors of the contract. Every failed action panics with `[code] message`, where the code names
+//! the kind of failure and stays stable across versions, so clients can map failures to messages
+//! of their own while the message remains readable in explorers.
+
+use std::fmt;
+
+/// Kind of failure, identified by a stable code.
+#[derive(Debug, PartialEq, Copy, Clone)]
+#[repr(u16)]
+pub enum GameError {
+    /// The sender lacks the role or relation to the game the action needs.
+    Unauthorized = 1,
+    /// An argument or configuration is out of range.
+    InvalidArgument = 2,
+    /// The game, or the lottery, tournament or sunset acted on, is not in the phase the action
+    /// needs.
+    InvalidPhase = 3,
+    /// The action has already been performed and cannot be repeated.
+    AlreadyDone = 4,
+    /// The game, configuration or claim acted on does not exist.
+    NotFound = 5,
+    /// The feature is disabled, paused, busy or at capacity.
+    Unavailable = 6,
+    /// A balance, bankroll or pool cannot cover the amount.
+    InsufficientFunds = 7,
+    /// The bet breaks one of the betting rules.
+    BetRejected = 8,
+    /// The player's bet does not qualify for the action.
+    Ineligible = 9,
+    /// The bet nonce is not the player's next nonce.
+    InvalidNonce = 10,
+    /// A token transfer failed, or does not match the bet it escrows.
+    TransferFailed = 11,
+    /// The round is missing a randomness contribution it requires.
+    MissingRandomness = 12,
+    /// A proof or revealed value does not verify.
+    InvalidProof = 13,
+    /// The computation or opened variables do not have the expected outputs.
+    UnexpectedOutput = 14,
+    /// The game is past the deadline of the stage it waits in, and can only expire.
+    DeadlinePassed = 15,
+}
+
+impl GameError {
+    pub fn code(self) -> u16 {
+        self as u16
+    }
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "E{:03}", self.code())
+    }
+}
+
+/// Fail with the error and a message formatted like `format!`.
+macro_rules! fail {
+    ($error:expr, $($message:tt)+) => {
+        panic!("[{}] {}", $error, format_args!($($message)+))
+    };
+}
+
+/// Fail with the error and message unless the condition holds.
+macro_rules! ensure {
+    ($condition:expr, $error:expr, $($message:tt)+) => {
+        if !$condition {
+            $crate::errors::fail!($error, $($message)+);
+        }
+    };
+}
+
+pub(crate) use {ensure, fail};
+
+/// Failing counterpart of `Option::expect`.
+pub trait OrFail<T> {
+    fn or_fail(self, error: GameError, message: &str) -> T;
+}
+
+impl<T> OrFail<T> for Option<T> {
+    #[track_caller]
+    fn or_fail(self, error: GameError, message: &str) -> T {
+        match self {
+            Some(value) => value,
+            None => fail!(error, "{message}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic::catch_unwind;
+
+    #[test]
+    fn failures_carry_their_code() {
+        let failure = catch_unwind(|| fail!(GameError::InvalidNonce, "Nonce {} is used.", 3))
+            .unwrap_err();
+        assert_eq!(failure.downcast_ref::<String>().unwrap(), "[E010] Nonce 3 is used.");
+    }
+}
@@ -0,0 +1,67 @@
+//! Push notifications of game transitions to an event sink contract.
+//!
+//! Off-chain indexers that cannot poll the contract state can instead follow an event sink: a
+//! contract receiving one interaction per key transition of a player's game. Events raised while
+//! a sink is configured are queued and sent with the events of the transaction raising them, or,
+//! for transitions of actions that do not send them, with the next transaction that does.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use pbc_contract_common::events::EventGroup;
+use read_write_rpc_derive::ReadWriteRPC;
+use read_write_state_derive::ReadWriteState;
+
+use crate::contract::CoinFlipState;
+use crate::token_client;
+
+/// A contract receiving game events.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+pub struct EventSinkConfig {
+    pub sink_address: Address,
+    /// Shortname of the sink's action accepting a single `GameEvent`.
+    pub sink_shortname: u32,
+}
+
+/// Structured payload of a transition of a player's game.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, PartialEq, Clone)]
+#[repr(u8)]
+pub enum GameEvent {
+    /// The stake of the player's bet reached the contract and the coin can be flipped.
+    #[discriminant(0)]
+    BetEscrowed { player: Address, amount: u64 },
+    /// The randomness of the player's round is complete and its computation was started.
+    #[discriminant(1)]
+    RandomnessComplete { player: Address },
+    /// The result of the player's round was opened and settled.
+    #[discriminant(2)]
+    ResultOpened { player: Address, player_won: bool },
+    /// What the player's game owed them was paid out.
+    #[discriminant(3)]
+    PaidOut { player: Address, amount: u64 },
+}
+
+impl CoinFlipState {
+    /// Queue a game event for the event sink, if one is configured.
+    pub(crate) fn raise_game_event(&mut self, event: GameEvent) {
+        if self.event_sink.is_some() {
+            self.game_event_outbox.push(event);
+        }
+    }
+
+    /// Build the event group sending the queued game events to the event sink, if there are any.
+    pub(crate) fn take_game_events(&mut self) -> Option<EventGroup> {
+        let game_events = std::mem::take(&mut self.game_event_outbox);
+        let config = self.event_sink.as_ref()?;
+        if game_events.is_empty() {
+            return None;
+        }
+        let mut event_group = EventGroup::builder();
+        for game_event in game_events {
+            let (contract, shortname) = (config.sink_address, config.sink_shortname);
+            token_client::interaction(&mut event_group, &self.gas_costs, contract, shortname)
+                .argument(game_event)
+                .done();
+        }
+        Some(event_group.build())
+    }
+}
@@ -0,0 +1,63 @@
+//! Paged export of resolved games for analytics.
+//!
+//! Every resolved game is indexed under its resolution sequence number, which orders the index by
+//! resolution time. Analytics tooling walks the index in pages with `export_games`, reading only
+//! the tree entries of a page instead of downloading the whole contract state each time. The
+//! oldest games are dropped from the index once it holds `EXPORT_RETENTION` of them.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::Hash;
+use read_write_state_derive::ReadWriteState;
+
+use crate::contract::CoinFlipState;
+use crate::history::GameRecord;
+
+/// Number of resolved games kept in the export index.
+pub const EXPORT_RETENTION: u64 = 100_000;
+
+/// Largest page returned by `export_games`.
+pub const MAX_EXPORT_PAGE: u32 = 500;
+
+/// A resolved game in the export index.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct ExportedGame {
+    /// Id of the game, see `compute_game_id`.
+    pub game_id: Hash,
+    pub record: GameRecord,
+}
+
+/// A page of resolved games.
+#[derive(Debug, Clone)]
+pub struct GameExportPage {
+    /// Resolved games in resolution order, each with its sequence number.
+    pub games: Vec<(u64, ExportedGame)>,
+    /// Cursor of the next page, `None` once every resolved game was exported.
+    pub next_cursor: Option<u64>,
+}
+
+impl CoinFlipState {
+    /// Index a resolved game, dropping the oldest one beyond the retention.
+    pub(crate) fn index_resolved_game(&mut self, game: ExportedGame) {
+        self.resolved_games.insert(self.resolved_game_count, game);
+        self.resolved_game_count += 1;
+        if let Some(dropped) = self.resolved_game_count.checked_sub(EXPORT_RETENTION + 1) {
+            self.resolved_games.remove(&dropped);
+        }
+    }
+
+    /// Up to `limit` resolved games from sequence number `cursor` on, oldest first. Games dropped
+    /// from the index are skipped, so a walk starting at zero resumes at the oldest kept game.
+    pub fn export_games(&self, cursor: u64, limit: u32) -> GameExportPage {
+        let first = cursor.max(self.resolved_game_count.saturating_sub(EXPORT_RETENTION));
+        let end = self
+            .resolved_game_count
+            .min(first.saturating_add(limit.min(MAX_EXPORT_PAGE) as u64));
+        let games = (first..end)
+            .filter_map(|index| self.resolved_games.get(&index).map(|game| (index, game)))
+            .collect();
+        GameExportPage {
+            games,
+            next_cursor: (end < self.resolved_game_count).then_some(end),
+        }
+    }
+}
@@ -0,0 +1,109 @@
+//! Rake-free private tables among friends.
+//!
+//! The creator of a table sets its stake and the addresses invited to it, and shares an invite
+//! code with them off-chain. The table is found by the hash of the code, and only invited
+//! addresses can join, each escrowing the stake on a side of the coin. Once everyone has joined
+//! and contributed randomness, a ZK coin flip decides the table and the contract redistributes
+//! the pot among the participants on the winning side. No house edge or fee applies.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use pbc_contract_common::sorted_vec_map::SortedVecMap;
+use pbc_contract_common::Hash;
+use read_write_rpc_derive::ReadWriteRPC;
+use read_write_state_derive::ReadWriteState;
+
+use crate::engine::PlayerChoice;
+
+/// Maximum number of addresses invited to a table.
+pub const MAX_TABLE_PARTICIPANTS: usize = 16;
+
+/// Progress of a friends table.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, PartialEq, Copy, Clone)]
+#[repr(u8)]
+pub enum TableStatus {
+    /// Invited addresses join and contribute randomness.
+    #[discriminant(0)]
+    Open {},
+    /// The coin of the table is being flipped.
+    #[discriminant(1)]
+    Flipping {},
+    /// The coin landed on `heads` or tails and the pot was redistributed.
+    #[discriminant(2)]
+    Settled { heads: bool },
+    /// The table was called off and the stakes refunded.
+    #[discriminant(3)]
+    Cancelled {},
+}
+
+/// A private table.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct FriendsTable {
+    pub creator: Address,
+    /// Hash of the invite code the table is joined with.
+    pub invite_code_hash: Hash,
+    /// Stake of every participant, in the default token.
+    pub stake: u64,
+    /// Addresses invited to the table, the only ones that can join it.
+    pub participants: Vec<Address>,
+    /// Side taken by each participant whose stake was escrowed.
+    pub joined: SortedVecMap<Address, PlayerChoice>,
+    /// Time after which a table not everyone joined can be cancelled by anyone.
+    pub join_deadline: i64,
+    pub status: TableStatus,
+}
+
+impl FriendsTable {
+    pub fn is_invited(&self, address: &Address) -> bool {
+        self.participants.contains(address)
+    }
+
+    pub fn has_joined(&self, address: &Address) -> bool {
+        self.joined.get(address).is_some()
+    }
+
+    /// Whether invited addresses can join at the given time.
+    pub fn is_joinable(&self, now: i64) -> bool {
+        self.status == TableStatus::Open {} && now < self.join_deadline
+    }
+
+    /// Whether every invited address has joined.
+    pub fn is_full(&self) -> bool {
+        self.joined.len() == self.participants.len()
+    }
+
+    /// Stakes escrowed for the table.
+    pub fn pot(&self) -> u64 {
+        self.stake * self.joined.len() as u64
+    }
+
+    /// Split the pot among the participants on the side the coin landed on, or return every
+    /// stake if all or none of them took that side. What the split leaves over goes to the first
+    /// winner.
+    ///
+    /// ### Returns:
+    ///
+    /// The amount owed to each participant that is owed anything.
+    pub fn payouts(&self, heads: bool) -> Vec<(Address, u64)> {
+        let winners: Vec<Address> = self
+            .joined
+            .iter()
+            .filter(|(_, choice)| choice.matches_flip(heads))
+            .map(|(participant, _)| *participant)
+            .collect();
+        if winners.is_empty() || winners.len() == self.joined.len() {
+            return self
+                .joined
+                .iter()
+                .map(|(participant, _)| (*participant, self.stake))
+                .collect();
+        }
+        let share = self.pot() / winners.len() as u64;
+        let left_over = self.pot() % winners.len() as u64;
+        winners
+            .iter()
+            .enumerate()
+            .map(|(index, winner)| (*winner, if index == 0 { share + left_over } else { share }))
+            .collect()
+    }
+}
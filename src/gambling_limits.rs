@@ -0,0 +1,218 @@
+//! Self-service responsible-gambling limits.
+//!
+//! Players cap how much they wager and lose per day, and how long betting pauses once a cap is
+//! reached. Days are counted from the first bet after the previous day ended. Stricter limits take
+//! effect immediately, while looser ones only take effect after `LIMIT_LOOSENING_DELAY_MILLIS`,
+//! so a player cannot lift their limits on impulse.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use read_write_rpc_derive::ReadWriteRPC;
+use read_write_state_derive::ReadWriteState;
+
+use crate::contract::CoinFlipState;
+
+/// Length of the period wagers and losses are capped over.
+const LIMIT_PERIOD_MILLIS: i64 = 24 * 60 * 60 * 1000;
+
+/// Delay before looser limits take effect (7 days).
+pub const LIMIT_LOOSENING_DELAY_MILLIS: i64 = 7 * LIMIT_PERIOD_MILLIS;
+
+/// Limits a player sets on their own betting. Zero disables a limit.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, PartialEq, Clone)]
+pub struct GamblingLimits {
+    /// Stakes the player may wager per day.
+    pub daily_wager_cap: u64,
+    /// Net losses the player may incur per day.
+    pub daily_loss_cap: u64,
+    /// How long betting pauses once a cap is reached, even past the end of the day.
+    pub cool_off_millis: i64,
+}
+
+impl GamblingLimits {
+    /// The stricter of two limits, limit by limit.
+    fn stricter(&self, other: &GamblingLimits) -> GamblingLimits {
+        GamblingLimits {
+            daily_wager_cap: stricter_cap(self.daily_wager_cap, other.daily_wager_cap),
+            daily_loss_cap: stricter_cap(self.daily_loss_cap, other.daily_loss_cap),
+            cool_off_millis: self.cool_off_millis.max(other.cool_off_millis),
+        }
+    }
+}
+
+fn stricter_cap(a: u64, b: u64) -> u64 {
+    match (a, b) {
+        (0, cap) | (cap, 0) => cap,
+        (a, b) => a.min(b),
+    }
+}
+
+/// Looser limits waiting out the loosening delay.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct PendingLimits {
+    pub limits: GamblingLimits,
+    pub effective_at: i64,
+}
+
+/// A player's limits and their betting in the current day.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct PlayerLimits {
+    pub limits: GamblingLimits,
+    pub pending: Option<PendingLimits>,
+    pub day_started_at: i64,
+    pub wagered: u64,
+    /// Stakes of the bets lost in the day.
+    pub lost: u64,
+    /// Profits of the bets won in the day.
+    pub won: u64,
+    pub cooling_off_until: i64,
+}
+
+impl PlayerLimits {
+    fn new() -> Self {
+        PlayerLimits {
+            limits: GamblingLimits {
+                daily_wager_cap: 0,
+                daily_loss_cap: 0,
+                cool_off_millis: 0,
+            },
+            pending: None,
+            day_started_at: 0,
+            wagered: 0,
+            lost: 0,
+            won: 0,
+            cooling_off_until: 0,
+        }
+    }
+
+    /// Request new limits at `now`: what they tighten applies immediately, the rest after the
+    /// loosening delay.
+    fn request(&mut self, requested: GamblingLimits, now: i64) {
+        self.apply_pending(now);
+        self.limits = self.limits.stricter(&requested);
+        self.pending = if requested == self.limits {
+            None
+        } else {
+            Some(PendingLimits {
+                limits: requested,
+                effective_at: now.saturating_add(LIMIT_LOOSENING_DELAY_MILLIS),
+            })
+        };
+    }
+
+    /// Limits in force at `now`.
+    fn effective(&self, now: i64) -> &GamblingLimits {
+        match &self.pending {
+            Some(pending) if now >= pending.effective_at => &pending.limits,
+            _ => &self.limits,
+        }
+    }
+
+    fn apply_pending(&mut self, now: i64) {
+        if self.pending.as_ref().is_some_and(|pending| now >= pending.effective_at) {
+            self.limits = self.pending.take().unwrap().limits;
+        }
+    }
+
+    fn is_new_day(&self, now: i64) -> bool {
+        now >= self.day_started_at.saturating_add(LIMIT_PERIOD_MILLIS)
+    }
+
+    /// Whether the limits in force at `now` let the player wager `amount`.
+    fn permits(&self, amount: u64, now: i64) -> bool {
+        if now < self.cooling_off_until {
+            return false;
+        }
+        let (wagered, net_loss) = if self.is_new_day(now) {
+            (0, 0)
+        } else {
+            (self.wagered, self.net_loss())
+        };
+        let limits = self.effective(now);
+        let within_wager_cap =
+            limits.daily_wager_cap == 0 || wagered + amount <= limits.daily_wager_cap;
+        let within_loss_cap = limits.daily_loss_cap == 0 || net_loss < limits.daily_loss_cap;
+        within_wager_cap && within_loss_cap
+    }
+
+    fn net_loss(&self) -> u64 {
+        self.lost.saturating_sub(self.won)
+    }
+
+    fn record_wager(&mut self, amount: u64, now: i64) {
+        self.apply_pending(now);
+        if self.is_new_day(now) {
+            self.day_started_at = now;
+            self.wagered = 0;
+            self.lost = 0;
+            self.won = 0;
+        }
+        self.wagered += amount;
+        self.start_cool_off_at_cap(now);
+    }
+
+    fn record_result(&mut self, amount: u64, winnings: Option<u64>, now: i64) {
+        self.apply_pending(now);
+        match winnings {
+            Some(winnings) => self.won += winnings.saturating_sub(amount),
+            None => self.lost += amount,
+        }
+        self.start_cool_off_at_cap(now);
+    }
+
+    fn start_cool_off_at_cap(&mut self, now: i64) {
+        let wager_cap = self.limits.daily_wager_cap;
+        let loss_cap = self.limits.daily_loss_cap;
+        let at_cap = (wager_cap > 0 && self.wagered >= wager_cap)
+            || (loss_cap > 0 && self.net_loss() >= loss_cap);
+        if at_cap && self.limits.cool_off_millis > 0 {
+            self.cooling_off_until = now.saturating_add(self.limits.cool_off_millis);
+        }
+    }
+}
+
+impl CoinFlipState {
+    /// Set the limits `player` requested at `now`.
+    pub(crate) fn request_gambling_limits(
+        &mut self,
+        player: Address,
+        requested: GamblingLimits,
+        now: i64,
+    ) {
+        let mut player_limits = self
+            .gambling_limits
+            .get(&player)
+            .unwrap_or_else(PlayerLimits::new);
+        player_limits.request(requested, now);
+        self.gambling_limits.insert(player, player_limits);
+    }
+
+    /// Whether the player's own limits let them wager `amount` at `now`.
+    pub(crate) fn within_gambling_limits(&self, player: &Address, amount: u64, now: i64) -> bool {
+        self.gambling_limits
+            .get(player)
+            .map_or(true, |player_limits| player_limits.permits(amount, now))
+    }
+
+    /// Count a wager of `amount` against the player's limits, if they set any.
+    pub(crate) fn record_limited_wager(&mut self, player: Address, amount: u64, now: i64) {
+        if let Some(mut player_limits) = self.gambling_limits.get(&player) {
+            player_limits.record_wager(amount, now);
+            self.gambling_limits.insert(player, player_limits);
+        }
+    }
+
+    /// Count the result of a settled bet against the player's limits, if they set any.
+    pub(crate) fn record_limited_result(
+        &mut self,
+        player: Address,
+        amount: u64,
+        winnings: Option<u64>,
+        now: i64,
+    ) {
+        if let Some(mut player_limits) = self.gambling_limits.get(&player) {
+            player_limits.record_result(amount, winnings, now);
+            self.gambling_limits.insert(player, player_limits);
+        }
+    }
+}
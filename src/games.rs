@@ -0,0 +1,91 @@
+//! Registry of the kinds of games a player's round can be.
+//!
+//! The kind of a round decides which zk computation decides it and which settlement routine its
+//! opened output goes to. Rounds are routed by the kind of the player's current bet when their
+//! computation is built, and opened outputs by their metadata, so every computation's output
+//! reaches the settlement of the game that started it.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use read_write_rpc_derive::ReadWriteRPC;
+use read_write_state_derive::ReadWriteState;
+
+use crate::contract::{CoinFlipState, SecretVarType};
+
+/// Kind of game of a player's round.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, PartialEq, Copy, Clone)]
+#[repr(u8)]
+pub enum GameKind {
+    /// A single coin flip on a public choice.
+    #[discriminant(0)]
+    CoinFlip {},
+    /// A round of a best-of-N match.
+    #[discriminant(1)]
+    BestOfMatch {},
+    /// A leg of a parlay.
+    #[discriminant(2)]
+    Parlay {},
+    /// A coin flip on a secret choice.
+    #[discriminant(3)]
+    SecretChoice {},
+    /// A coin flip with a secret stake.
+    #[discriminant(4)]
+    SecretBet {},
+    #[discriminant(5)]
+    MultiFlip {},
+    #[discriminant(6)]
+    Dice {},
+    #[discriminant(7)]
+    Roulette {},
+    /// A coin flip with configurable odds.
+    #[discriminant(8)]
+    WeightedFlip {},
+}
+
+impl SecretVarType {
+    /// The player and kind of the round an opened output settles, if it settles a player's round.
+    pub(crate) fn settled_round(&self) -> Option<(Address, GameKind)> {
+        match *self {
+            SecretVarType::FlipResult { player } => Some((player, GameKind::CoinFlip {})),
+            SecretVarType::MatchRound { player, .. } => Some((player, GameKind::BestOfMatch {})),
+            SecretVarType::ParlayLeg { player, .. } => Some((player, GameKind::Parlay {})),
+            SecretVarType::Outcome { player } => Some((player, GameKind::SecretChoice {})),
+            SecretVarType::SecretPayout { player } => Some((player, GameKind::SecretBet {})),
+            SecretVarType::SealedPayout { player } => Some((player, GameKind::SecretBet {})),
+            SecretVarType::MultiFlipResult { player } => Some((player, GameKind::MultiFlip {})),
+            SecretVarType::DiceRoll { player } => Some((player, GameKind::Dice {})),
+            SecretVarType::RouletteSpin { player } => Some((player, GameKind::Roulette {})),
+            SecretVarType::WeightedFlip { player } => Some((player, GameKind::WeightedFlip {})),
+            _ => None,
+        }
+    }
+}
+
+impl CoinFlipState {
+    /// Kind of the player's current round.
+    pub(crate) fn game_kind_of(&self, player: &Address) -> GameKind {
+        if self.weighted_bets.get(player).is_some() {
+            return GameKind::WeightedFlip {};
+        }
+        let Some(bet) = self.player_bets.get(player) else {
+            return GameKind::CoinFlip {};
+        };
+        if bet.roulette.is_some() {
+            GameKind::Roulette {}
+        } else if bet.dice.is_some() {
+            GameKind::Dice {}
+        } else if bet.multi_flip.is_some() {
+            GameKind::MultiFlip {}
+        } else if bet.secret_amount && bet.choice.is_some() {
+            GameKind::SecretBet {}
+        } else if bet.has_secret_choice() {
+            GameKind::SecretChoice {}
+        } else if self.parlays.get(player).is_some() {
+            GameKind::Parlay {}
+        } else if self.matches.get(player).is_some() {
+            GameKind::BestOfMatch {}
+        } else {
+            GameKind::CoinFlip {}
+        }
+    }
+}
@@ -0,0 +1,79 @@
+//! Bounded log of completed games, so frontends and auditors can reconstruct past games from
+//! contract state alone.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use read_write_rpc_derive::ReadWriteRPC;
+use read_write_state_derive::ReadWriteState;
+
+use crate::contract::CoinFlipState;
+use crate::engine::PlayerChoice;
+
+/// Number of games kept unless the owner configures otherwise.
+pub const DEFAULT_HISTORY_RETENTION: u32 = 1000;
+
+/// A completed game, stamped with the block production time it was settled at.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+pub struct GameRecord {
+    pub time: i64,
+    pub player: Address,
+    pub amount: u64,
+    /// Public choice of the player; absent for secret choices and games without one.
+    pub choice: Option<PlayerChoice>,
+    /// Outcome of the coin flip, true for heads; absent for other games.
+    pub flip_result: Option<bool>,
+    pub player_won: bool,
+    /// Amount won by the player, in the payout token of the bet.
+    pub payout: u64,
+}
+
+/// Append-only log of completed games, pruned from the oldest entry once `retention` is reached.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct GameHistory {
+    records: Vec<GameRecord>,
+    /// Maximum number of games kept.
+    pub retention: u32,
+    /// Number of games recorded since deployment, including pruned ones.
+    pub total_recorded: u64,
+}
+
+impl GameHistory {
+    pub fn new(retention: u32) -> Self {
+        GameHistory {
+            records: vec![],
+            retention,
+            total_recorded: 0,
+        }
+    }
+
+    /// Append a completed game, pruning the oldest games beyond the retention cap.
+    pub fn push(&mut self, record: GameRecord) {
+        self.records.push(record);
+        self.total_recorded += 1;
+        self.prune();
+    }
+
+    /// Change the retention cap, pruning immediately if it shrinks.
+    pub fn set_retention(&mut self, retention: u32) {
+        self.retention = retention;
+        self.prune();
+    }
+
+    fn prune(&mut self) {
+        let excess = self.records.len().saturating_sub(self.retention as usize);
+        self.records.drain(..excess);
+    }
+}
+
+impl CoinFlipState {
+    /// Kept games settled after `block_time`, oldest first, optionally only those of `player`.
+    pub fn get_game_history(&self, player: Option<Address>, block_time: i64) -> Vec<GameRecord> {
+        self.game_history
+            .records
+            .iter()
+            .filter(|record| record.time > block_time)
+            .filter(|record| player.map_or(true, |player| record.player == player))
+            .cloned()
+            .collect()
+    }
+}
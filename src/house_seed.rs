@@ -0,0 +1,74 @@
+//! House seeds pre-committed per epoch.
+//!
+//! Before an epoch starts, an operator inputs a secret seed for it to the ZK nodes, which commits
+//! the house to the seed without revealing it. Every round computed during the epoch folds the
+//! seed into its randomness together with the round's contributions, and every round settled
+//! during the epoch folds its opened outcome into a running digest of the commitment. Once the
+//! epoch is over anyone can have the seed opened, so its reveal does not depend on the operator,
+//! and it is sealed together with the digest. Players can then audit that the house's seed was
+//! fixed before any of their bets in the epoch, and that it covers every settled round.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use pbc_contract_common::Hash;
+use pbc_traits::ReadWriteState;
+use read_write_state_derive::ReadWriteState;
+
+use crate::contract::CoinFlipState;
+
+/// Epoch for which no seed can be committed, as seeds are only committed for epochs after the
+/// running one and the first epoch is numbered zero.
+pub const NO_SEED_EPOCH: u32 = 0;
+
+/// A house seed committed for an epoch.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct SeedCommitment {
+    /// When the ZK nodes confirmed the secret seed.
+    pub committed_at: i64,
+    /// Running hash of the outcomes of the rounds settled in the epoch.
+    pub settlements_digest: Hash,
+    pub settled_rounds: u64,
+    /// Whether the seed is being opened.
+    pub reveal_requested: bool,
+    /// The seed, once opened after the epoch.
+    pub revealed_seed: Option<u32>,
+    /// Hash of the revealed seed and the final settlements digest.
+    pub seal: Option<Hash>,
+}
+
+impl SeedCommitment {
+    pub fn new(committed_at: i64) -> Self {
+        SeedCommitment {
+            committed_at,
+            settlements_digest: Hash { bytes: [0; 32] },
+            settled_rounds: 0,
+            reveal_requested: false,
+            revealed_seed: None,
+            seal: None,
+        }
+    }
+
+    /// Record the opened seed and seal it together with the digest of the epoch's settlements.
+    pub fn reveal(&mut self, seed: u32) {
+        let mut data = seed.to_le_bytes().to_vec();
+        data.extend_from_slice(&self.settlements_digest.bytes);
+        self.seal = Some(Hash::digest(&data));
+        self.revealed_seed = Some(seed);
+    }
+}
+
+impl CoinFlipState {
+    /// Fold the opened outcome of the player's settled round into the seed commitment of the
+    /// running epoch, if there is one.
+    pub(crate) fn fold_into_house_seed(&mut self, player: &Address, player_won: bool) {
+        let Some(commitment) = self.house_seeds.get_mut(&self.epoch.epoch) else {
+            return;
+        };
+        let mut data = commitment.settlements_digest.bytes.to_vec();
+        self.round_salts.get(player).state_write_to(&mut data).unwrap();
+        self.flip_results.get(player).state_write_to(&mut data).unwrap();
+        player_won.state_write_to(&mut data).unwrap();
+        commitment.settlements_digest = Hash::digest(&data);
+        commitment.settled_rounds += 1;
+    }
+}
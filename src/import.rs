@@ -0,0 +1,96 @@
+//! One-shot import of balances and statistics from a previous deployment.
+//!
+//! A redeployed contract starts out empty, which would strand the balances players held in the
+//! contract it replaces. Until the first stake is placed, the owner can seed those balances and
+//! the players' statistics with `import_state`, in as many calls as the transaction size limit
+//! requires. An imported balance is added to whatever the player deposited meanwhile. The import
+//! statistics let the owner reconcile the imported total with the tokens moved to the new
+//! contract. The first stake of any kind, be it a bet, side bet, lottery ticket,
+//! rock-paper-scissors challenge, table, bracket, tournament or session, closes the import for
+//! good, and a migrated contract never opens it.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use read_write_rpc_derive::ReadWriteRPC;
+use read_write_state_derive::ReadWriteState;
+
+use crate::contract::{CoinFlipState, PlayerStats};
+use crate::errors::{ensure, GameError};
+
+/// Largest number of balances imported by a single call.
+pub const MAX_IMPORT_CHUNK: usize = 500;
+
+/// An account of the previous deployment, as imported by `import_state`.
+#[derive(ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+pub struct ImportedAccount {
+    pub user: Address,
+    pub balance: u64,
+    /// Statistics of the player's settled games, if they played any.
+    pub stats: Option<PlayerStats>,
+}
+
+/// Progress of the balance import.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct ImportStats {
+    /// Whether balances can still be imported, until the first stake is placed.
+    pub is_open: bool,
+    /// Number of balances imported.
+    pub imported_accounts: u64,
+    /// Sum of the balances imported.
+    pub imported_total: u64,
+}
+
+impl ImportStats {
+    pub fn new(is_open: bool) -> Self {
+        ImportStats {
+            is_open,
+            imported_accounts: 0,
+            imported_total: 0,
+        }
+    }
+}
+
+impl CoinFlipState {
+    /// Close the import for good, as a stake is placed.
+    pub(crate) fn close_import(&mut self) {
+        self.import.is_open = false;
+    }
+
+    /// Assert that the given chunk of accounts can be imported: the import is open, the chunk
+    /// fits a call and none of its accounts was imported before, so a chunk sent twice is
+    /// rejected instead of crediting its balances twice.
+    pub(crate) fn assert_importable(&self, entries: &[ImportedAccount]) {
+        ensure!(
+            self.import.is_open,
+            GameError::Unavailable,
+            "Balances can only be imported before the first stake."
+        );
+        ensure!(
+            !entries.is_empty() && entries.len() <= MAX_IMPORT_CHUNK,
+            GameError::InvalidArgument,
+            "An import must hold between 1 and {} balances.",
+            MAX_IMPORT_CHUNK
+        );
+        for (index, account) in entries.iter().enumerate() {
+            let user = &account.user;
+            ensure!(
+                self.imported_users.get(user).is_none()
+                    && !entries[..index].iter().any(|earlier| earlier.user == *user),
+                GameError::AlreadyDone,
+                "The account {:?} has already been imported.",
+                user
+            );
+        }
+    }
+
+    /// Record an imported account, whose balance has been credited, carrying its statistics over
+    /// and adding it to the import statistics.
+    pub(crate) fn record_import(&mut self, account: ImportedAccount) {
+        if let Some(stats) = account.stats {
+            self.player_stats.insert(account.user, stats);
+        }
+        self.imported_users.insert(account.user, true);
+        self.import.imported_accounts += 1;
+        self.import.imported_total += account.balance;
+    }
+}
@@ -0,0 +1,64 @@
+//! Instant mode: tiny bets settled without a zk computation, by entropy values the entropy
+//! provider committed to before the bets were placed.
+//!
+//! Every bet is placed on the oldest committed value no bet waits for yet, while that value is
+//! still secret, and is settled when the provider reveals it. The commitment keeps the provider
+//! from tailoring the value to the bet, and the bet's choice is fixed before anyone but the
+//! provider knows the value. A value that is never revealed leaves its game to expire like any
+//! other, which returns the stake. The provider does learn outcomes early, so the mode is capped
+//! to a small maximum stake.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use pbc_contract_common::Hash;
+use read_write_rpc_derive::ReadWriteRPC;
+use read_write_state_derive::ReadWriteState;
+
+use crate::errors::{ensure, GameError};
+
+/// Configuration of instant mode.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+pub struct InstantConfig {
+    /// Address committing to and revealing the entropy.
+    pub provider: Address,
+    /// Largest stake of an instant bet.
+    pub max_stake: u64,
+    /// Minimum age of a commitment before its value can settle a bet.
+    pub min_commitment_age_millis: i64,
+}
+
+/// A committed entropy value, in the order it will be consumed.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct InstantEntropy {
+    /// Hash of the value.
+    pub commitment: Hash,
+    pub committed_at: i64,
+    /// The value, once revealed.
+    pub value: Option<Hash>,
+}
+
+impl InstantEntropy {
+    /// Whether a bet can be placed on the value at the given time: the value is still secret
+    /// and its commitment has aged.
+    pub fn is_usable(&self, config: &InstantConfig, now: i64) -> bool {
+        self.value.is_none() && now >= self.committed_at + config.min_commitment_age_millis
+    }
+
+    /// Reveal the value, checking it against the commitment.
+    pub fn reveal(&mut self, value: Hash) {
+        ensure!(
+            Hash::digest(&value.bytes) == self.commitment,
+            GameError::InvalidProof,
+            "The value does not match its commitment."
+        );
+        self.value = Some(value);
+    }
+}
+
+/// An instant bet waiting for its entropy value to be revealed.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct InstantBet {
+    pub player: Address,
+    /// Id of the player's game, so a game voided or expired meanwhile is not settled.
+    pub game_id: Hash,
+}
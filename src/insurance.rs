@@ -0,0 +1,171 @@
+//! Segregated insurance fund fed by a share of the contract's fees. Compensation after a voided
+//! game or incident is paid from the fund through Merkle claims, never from the bankroll.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use pbc_contract_common::sorted_vec_map::SortedVecMap;
+use pbc_contract_common::Hash;
+use pbc_traits::ReadWriteState;
+use read_write_rpc_derive::ReadWriteRPC;
+use read_write_state_derive::ReadWriteState;
+
+use crate::errors::{ensure, GameError, OrFail};
+
+/// Maximum share of fees allocated to the insurance fund, in basis points.
+pub const MAX_INSURANCE_SHARE_BPS: u32 = 2_000;
+
+/// A set of compensation claims, committed to by the Merkle root of its leaves.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct CompensationRound {
+    /// Root of the Merkle tree over `claim_leaf(claimant, amount)` of every claim.
+    pub root: Hash,
+    /// Amount reserved from the fund for the round.
+    pub total: u64,
+    /// Reserved amount not yet claimed.
+    pub remaining: u64,
+    /// Claimants that have been paid.
+    pub claimed: Vec<Address>,
+}
+
+/// The insurance fund and its accounting.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct InsuranceFund {
+    /// Share of every collected fee allocated to the fund, in basis points.
+    pub share_bps: u32,
+    /// Allocated amount not reserved for any compensation round.
+    pub balance: u64,
+    /// Total ever allocated to the fund.
+    pub total_allocated: u64,
+    /// Total ever paid out as compensation.
+    pub total_paid: u64,
+    /// Compensation rounds by id.
+    pub rounds: SortedVecMap<u32, CompensationRound>,
+}
+
+/// Accounting of the insurance fund as shown to clients.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+pub struct InsuranceSummary {
+    pub balance: u64,
+    /// Amount reserved for open compensation rounds and not yet claimed.
+    pub reserved: u64,
+    pub total_allocated: u64,
+    pub total_paid: u64,
+}
+
+impl InsuranceFund {
+    pub fn new() -> Self {
+        InsuranceFund {
+            share_bps: 0,
+            balance: 0,
+            total_allocated: 0,
+            total_paid: 0,
+            rounds: SortedVecMap::new(),
+        }
+    }
+
+    /// Allocate the fund's share of a fee.
+    ///
+    /// ### Returns:
+    ///
+    /// The part of the fee left to the operator.
+    pub fn allocate(&mut self, fee: u64) -> u64 {
+        let share = (fee as u128 * self.share_bps as u128 / 10_000) as u64;
+        self.balance += share;
+        self.total_allocated += share;
+        fee - share
+    }
+
+    /// Reserve `total` of the balance for a new compensation round.
+    ///
+    /// ### Returns:
+    ///
+    /// The id of the round.
+    pub fn open_round(&mut self, root: Hash, total: u64) -> u32 {
+        ensure!(
+            total <= self.balance,
+            GameError::InsufficientFunds,
+            "The insurance fund cannot cover the compensation round."
+        );
+        self.balance -= total;
+        let id = self.rounds.len() as u32;
+        self.rounds.insert(
+            id,
+            CompensationRound {
+                root,
+                total,
+                remaining: total,
+                claimed: vec![],
+            },
+        );
+        id
+    }
+
+    /// Verify and record a compensation claim.
+    pub fn claim(&mut self, round_id: u32, claimant: Address, amount: u64, proof: &[Hash]) {
+        let round = self
+            .rounds
+            .get_mut(&round_id)
+            .or_fail(GameError::NotFound, "Unknown compensation round.");
+        ensure!(
+            !round.claimed.contains(&claimant),
+            GameError::AlreadyDone,
+            "The compensation has already been claimed."
+        );
+        ensure!(
+            verify_merkle_proof(&round.root, claim_leaf(&claimant, amount), proof),
+            GameError::InvalidProof,
+            "Invalid compensation claim proof."
+        );
+        ensure!(
+            amount <= round.remaining,
+            GameError::InsufficientFunds,
+            "The compensation round has insufficient funds left."
+        );
+        round.remaining -= amount;
+        round.claimed.push(claimant);
+        self.total_paid += amount;
+    }
+
+    /// Undo a recorded claim whose payout failed, so the claimant can claim again.
+    pub fn restore_claim(&mut self, round_id: u32, claimant: &Address, amount: u64) {
+        if let Some(round) = self.rounds.get_mut(&round_id) {
+            round.claimed.retain(|paid| paid != claimant);
+            round.remaining += amount;
+            self.total_paid -= amount;
+        }
+    }
+
+    pub fn summary(&self) -> InsuranceSummary {
+        InsuranceSummary {
+            balance: self.balance,
+            reserved: self.rounds.iter().map(|(_, round)| round.remaining).sum(),
+            total_allocated: self.total_allocated,
+            total_paid: self.total_paid,
+        }
+    }
+}
+
+/// Leaf of a compensation claim: the hash of the serialized claimant followed by the amount as
+/// big-endian bytes.
+pub fn claim_leaf(claimant: &Address, amount: u64) -> Hash {
+    let mut bytes = Vec::new();
+    claimant.state_write_to(&mut bytes).unwrap();
+    bytes.extend_from_slice(&amount.to_be_bytes());
+    Hash::digest(&bytes)
+}
+
+/// Check a Merkle proof, where each parent is the hash of its two children in ascending order.
+pub fn verify_merkle_proof(root: &Hash, leaf: Hash, proof: &[Hash]) -> bool {
+    let mut node = leaf;
+    for sibling in proof {
+        let (low, high) = if node.bytes <= sibling.bytes {
+            (node.bytes, sibling.bytes)
+        } else {
+            (sibling.bytes, node.bytes)
+        };
+        let mut bytes = low.to_vec();
+        bytes.extend_from_slice(&high);
+        node = Hash::digest(&bytes);
+    }
+    node.bytes == root.bytes
+}
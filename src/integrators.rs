@@ -0,0 +1,57 @@
+//! Third-party integrators, such as casino aggregators, betting on behalf of their users from
+//! another contract.
+//!
+//! Only integrators registered by the owner may place bets. An integrator pays the stake of every
+//! bet it places and names the beneficiary its winnings are paid to, and the bet stays attributed
+//! to the integrator until it is cleared.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use read_write_state_derive::ReadWriteState;
+
+use crate::contract::CoinFlipState;
+use crate::errors::{GameError, OrFail};
+
+/// A registered integrator and the bets it placed.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct Integrator {
+    /// Whether the integrator may currently place bets.
+    pub active: bool,
+    pub bets_placed: u64,
+    /// Stakes of the bets placed, summed over every token.
+    pub volume: u128,
+}
+
+impl Integrator {
+    pub fn new() -> Self {
+        Integrator {
+            active: true,
+            bets_placed: 0,
+            volume: 0,
+        }
+    }
+}
+
+impl CoinFlipState {
+    /// Count a bet of `amount` placed by `integrator`, which must be active.
+    pub(crate) fn record_integrator_bet(&mut self, integrator: &Address, amount: u64) {
+        let account = self
+            .integrators
+            .get_mut(integrator)
+            .filter(|account| account.active)
+            .or_fail(
+                GameError::Unauthorized,
+                "Only registered integrators can bet on behalf of players.",
+            );
+        account.bets_placed += 1;
+        account.volume += amount as u128;
+    }
+
+    /// Address the winnings of the player's current bet are paid to.
+    pub(crate) fn payee_of(&self, player: &Address) -> Address {
+        self.player_bets
+            .get(player)
+            .and_then(|bet| bet.beneficiary)
+            .unwrap_or(*player)
+    }
+}
@@ -0,0 +1,65 @@
+//! Signed off-chain bet intents.
+//!
+//! A player signs a `BetIntent` off-chain and an operator submits it on their behalf with
+//! `submit_signed_bet`, so the player needs no transaction of their own to bet. The signed bytes
+//! are the intent in state serialization behind a domain separator, which binds the signature to
+//! this kind of message, and the intent itself names the contract, player and bet nonce, so a
+//! signature can neither be replayed on another contract nor reused for a second bet. Players
+//! register the public key their intents are signed with, as an address cannot be derived from a
+//! recovered key on chain.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use pbc_contract_common::signature::Signature;
+use pbc_contract_common::{Hash, PublicKey};
+use pbc_traits::ReadWriteState;
+use read_write_rpc_derive::ReadWriteRPC;
+use read_write_state_derive::ReadWriteState;
+
+use crate::engine::PlayerChoice;
+
+/// Prefix of the signed encoding of a bet intent.
+pub const BET_INTENT_DOMAIN: &[u8] = b"flip-coin:BetIntent:v1";
+
+/// A bet a player authorizes off-chain.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, PartialEq, Clone)]
+pub struct BetIntent {
+    /// The contract the bet is placed with.
+    pub contract: Address,
+    /// The player placing the bet, who signed the intent.
+    pub player: Address,
+    pub amount: u64,
+    pub choice: PlayerChoice,
+    pub token: Address,
+    /// The player's next bet nonce, so the intent is used at most once.
+    pub nonce: u64,
+    /// Time after which the intent can no longer be submitted.
+    pub expires_at: i64,
+}
+
+impl BetIntent {
+    /// The signed bytes: the domain separator followed by the intent in state serialization.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = BET_INTENT_DOMAIN.to_vec();
+        self.state_write_to(&mut data).unwrap();
+        data
+    }
+
+    /// Decode signed bytes back into the intent, if they carry the domain separator.
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let mut intent = data.strip_prefix(BET_INTENT_DOMAIN)?;
+        Some(BetIntent::state_read_from(&mut intent))
+    }
+
+    /// Hash of the signed bytes.
+    pub fn digest(&self) -> Hash {
+        Hash::digest(&self.encode())
+    }
+
+    /// Whether `signature` is a signature of the intent by the holder of `public_key`.
+    pub fn is_signed_by(&self, signature: &Signature, public_key: &PublicKey) -> bool {
+        signature
+            .recover_public_key(&self.digest())
+            .is_some_and(|signer| signer == *public_key)
+    }
+}
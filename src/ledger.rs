@@ -0,0 +1,131 @@
+//! Accounting of the default token held for games, kept apart from deposited balances so the
+//! contract can reconcile its token holdings.
+//!
+//! Stakes of bets placed and paid out in the default token are escrowed once they reach the
+//! contract, move to the claimable winnings of the player or to the house when the game is
+//! resolved, and leave the ledger when they are refunded or paid out. Free bets never escrow a
+//! stake, and bets in other tokens are not tracked.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use pbc_contract_common::avl_tree_map::AvlTreeMap;
+use read_write_state_derive::ReadWriteState;
+
+use crate::contract::CoinFlipState;
+
+/// Default token held for games, in total and by player.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct EscrowLedger {
+    /// Stakes of games that are not resolved yet.
+    pub escrowed_total: u64,
+    /// Net result of resolved games for the house: stakes lost and winnings forfeited by players,
+    /// less the winnings paid beyond the stakes. Negative while players are ahead.
+    pub house_balance: i128,
+    /// Winnings of resolved games that are not paid out yet.
+    pub claimable_winnings: u64,
+    pub stakes: AvlTreeMap<Address, u64>,
+    pub claims: AvlTreeMap<Address, u64>,
+}
+
+impl EscrowLedger {
+    pub fn new() -> Self {
+        EscrowLedger {
+            escrowed_total: 0,
+            house_balance: 0,
+            claimable_winnings: 0,
+            stakes: AvlTreeMap::new(),
+            claims: AvlTreeMap::new(),
+        }
+    }
+
+    pub fn escrow(&mut self, player: Address, stake: u64) {
+        self.escrowed_total += stake;
+        self.stakes.insert(player, stake);
+    }
+
+    /// Resolve the game of the player: the stake goes to the house, or the winnings become
+    /// claimable.
+    pub fn resolve(&mut self, player: Address, winnings: Option<u64>) {
+        let Some(stake) = self.take_stake(&player) else {
+            return;
+        };
+        self.house_balance += stake as i128;
+        if let Some(winnings) = winnings {
+            self.house_balance -= winnings as i128;
+            self.claimable_winnings += winnings;
+            self.claims.insert(player, winnings);
+        }
+    }
+
+    /// Re-stake the claimable winnings of the player on a new game.
+    pub fn ride(&mut self, player: Address) {
+        if let Some(winnings) = self.take_claim(&player) {
+            self.escrow(player, winnings);
+        }
+    }
+
+    /// Refund the stake of an unresolved game, except `fee`, which the house keeps.
+    pub fn refund(&mut self, player: &Address, fee: u64) {
+        if self.take_stake(player).is_some() {
+            self.house_balance += fee as i128;
+        }
+    }
+
+    /// Pay out the claimable winnings of the player.
+    pub fn pay(&mut self, player: &Address) {
+        self.take_claim(player);
+    }
+
+    /// Give the house whatever the player's cleared game still holds.
+    pub fn forfeit(&mut self, player: &Address) {
+        let stake = self.take_stake(player).unwrap_or(0);
+        let winnings = self.take_claim(player).unwrap_or(0);
+        self.house_balance += stake as i128 + winnings as i128;
+    }
+
+    fn take_stake(&mut self, player: &Address) -> Option<u64> {
+        let stake = self.stakes.get(player)?;
+        self.stakes.remove(player);
+        self.escrowed_total -= stake;
+        Some(stake)
+    }
+
+    fn take_claim(&mut self, player: &Address) -> Option<u64> {
+        let winnings = self.claims.get(player)?;
+        self.claims.remove(player);
+        self.claimable_winnings -= winnings;
+        Some(winnings)
+    }
+}
+
+impl CoinFlipState {
+    /// Escrow the stake of the player's current bet, if it is tracked by the ledger.
+    pub(crate) fn escrow_stake(&mut self, player: &Address) {
+        let tracked = self.bet_tokens.get(player).is_none()
+            && self.payout_tokens.get(player).is_none()
+            && self.free_bets.get(player).is_none();
+        if let (true, Some(bet)) = (tracked, self.player_bets.get(player)) {
+            self.ledger.escrow(*player, bet.amount);
+        }
+    }
+
+    /// Panic unless the ledger totals add up, and every stake and claim it holds belongs to a
+    /// game that is unresolved or won and unclaimed, respectively.
+    #[cfg(test)]
+    pub(crate) fn assert_ledger_balanced(&self) {
+        use crate::engine::GamePhase;
+
+        let stakes: u64 = self.ledger.stakes.iter().map(|(_, stake)| stake).sum();
+        let claims: u64 = self.ledger.claims.iter().map(|(_, winnings)| winnings).sum();
+        assert_eq!(self.ledger.escrowed_total, stakes);
+        assert_eq!(self.ledger.claimable_winnings, claims);
+        for (player, stake) in self.ledger.stakes.iter() {
+            assert_eq!(self.player_bets.get(&player).map(|bet| bet.amount), Some(stake));
+            assert_ne!(self.game_phases.get(&player), Some(GamePhase::Done {}));
+        }
+        for (player, _) in self.ledger.claims.iter() {
+            assert_eq!(self.game_phases.get(&player), Some(GamePhase::Done {}));
+            assert_eq!(self.winners.get(&player), Some(player));
+        }
+    }
+}
@@ -0,0 +1,49 @@
+//! Optional insurance bought alongside a bet that refunds part of the stake if the bet loses.
+//! Premiums are taken into the house bankroll, which also pays the refunds.
+
+use create_type_spec_derive::CreateTypeSpec;
+use read_write_rpc_derive::ReadWriteRPC;
+use read_write_state_derive::ReadWriteState;
+
+/// Pricing of loss insurance.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+pub struct LossInsuranceConfig {
+    /// Price of the insurance, in basis points of the stake of the insured bet.
+    pub premium_bps: u32,
+    /// Part of a losing stake refunded, in basis points.
+    pub refund_bps: u32,
+}
+
+/// Insurance bought for a player's current bet.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct InsuredBet {
+    pub premium: u64,
+    /// Refunded if the bet loses.
+    pub refund: u64,
+}
+
+/// Running totals of loss insurance, showing what it earned the house.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct LossInsuranceTotals {
+    pub premiums_collected: u64,
+    pub refunds_paid: u64,
+}
+
+impl LossInsuranceConfig {
+    /// Price and refund of insurance on a bet of `stake`.
+    pub fn quote(&self, stake: u64) -> InsuredBet {
+        InsuredBet {
+            premium: (stake as u128 * self.premium_bps as u128 / 10_000) as u64,
+            refund: (stake as u128 * self.refund_bps as u128 / 10_000) as u64,
+        }
+    }
+}
+
+impl LossInsuranceTotals {
+    pub fn new() -> Self {
+        LossInsuranceTotals {
+            premiums_collected: 0,
+            refunds_paid: 0,
+        }
+    }
+}
@@ -0,0 +1,75 @@
+//! Lottery rounds: players buy tickets during an open window and a single ZK computation over
+//! the lottery randomness picks the winning ticket once the window has closed.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use read_write_rpc_derive::ReadWriteRPC;
+use read_write_state_derive::ReadWriteState;
+
+/// Maximum number of tickets in a single lottery.
+pub const MAX_LOTTERY_TICKETS: u32 = 10_000;
+
+/// Progress of a lottery.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, PartialEq, Copy, Clone)]
+#[repr(u8)]
+pub enum LotteryStatus {
+    /// Tickets can be bought until the lottery closes.
+    #[discriminant(0)]
+    Open {},
+    /// The winning ticket is being computed.
+    #[discriminant(1)]
+    Drawing {},
+    /// The winner has been drawn and paid the pot.
+    #[discriminant(2)]
+    Drawn { winner: Option<Address> },
+}
+
+/// A lottery round.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct Lottery {
+    pub id: u32,
+    pub ticket_price: u64,
+    /// Block production time after which no more tickets are sold and the draw may start.
+    pub closes_at: i64,
+    /// Holder of each ticket, indexed by ticket number.
+    pub tickets: Vec<Address>,
+    pub pot: u64,
+    pub status: LotteryStatus,
+}
+
+impl Lottery {
+    /// Open a new lottery.
+    pub fn new(id: u32, ticket_price: u64, closes_at: i64) -> Self {
+        Lottery {
+            id,
+            ticket_price,
+            closes_at,
+            tickets: vec![],
+            pot: 0,
+            status: LotteryStatus::Open {},
+        }
+    }
+
+    /// Whether tickets can be bought at the given time.
+    pub fn is_selling(&self, now: i64) -> bool {
+        self.status == LotteryStatus::Open {} && now < self.closes_at
+    }
+
+    /// Whether the lottery is finished and a new one may be opened.
+    pub fn is_finished(&self) -> bool {
+        matches!(self.status, LotteryStatus::Drawn { .. })
+    }
+
+    /// Add `count` escrowed tickets for the buyer.
+    pub fn add_tickets(&mut self, buyer: Address, count: u32) {
+        for _ in 0..count {
+            self.tickets.push(buyer);
+        }
+        self.pot += self.ticket_price * count as u64;
+    }
+
+    /// Number of tickets sold so far.
+    pub fn ticket_count(&self) -> u32 {
+        self.tickets.len() as u32
+    }
+}
@@ -0,0 +1,36 @@
+//! Notarization of settled rounds to a public log contract, so a result can be proven to have
+//! existed at a given time even after this contract's records are pruned.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use pbc_contract_common::events::EventGroup;
+use pbc_contract_common::Hash;
+use read_write_rpc_derive::ReadWriteRPC;
+use read_write_state_derive::ReadWriteState;
+
+use crate::token_client::{self, GasCosts};
+
+/// The log contract settled rounds are notarized to.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+pub struct NotaryConfig {
+    /// Contract receiving the round hashes.
+    pub notary_address: Address,
+    /// Shortname of the notary contract's action accepting a single `Hash`.
+    pub notary_shortname: u32,
+}
+
+/// Build the event group forwarding the given round hashes to the notary, one call per hash.
+pub fn notarization_event(
+    config: &NotaryConfig,
+    costs: &GasCosts,
+    record_hashes: Vec<Hash>,
+) -> EventGroup {
+    let mut event_group = EventGroup::builder();
+    for record_hash in record_hashes {
+        let (notary, shortname) = (config.notary_address, config.notary_shortname);
+        token_client::interaction(&mut event_group, costs, notary, shortname)
+            .argument(record_hash)
+            .done();
+    }
+    event_group.build()
+}
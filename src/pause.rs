@@ -0,0 +1,33 @@
+//! Emergency pause. Pausing new bets still lets games in flight receive randomness, be computed
+//! and be paid out, so the pause is checked per action rather than globally.
+
+use create_type_spec_derive::CreateTypeSpec;
+use read_write_rpc_derive::ReadWriteRPC;
+use read_write_state_derive::ReadWriteState;
+
+/// Which actions the operator has paused.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, PartialEq, Copy, Clone)]
+#[repr(u8)]
+pub enum PauseState {
+    /// Nothing is paused.
+    #[discriminant(0)]
+    Running {},
+    /// No new bets are accepted, but games in flight can finish.
+    #[discriminant(1)]
+    NewBetsPaused {},
+    /// Every player action is paused; only administrators can act.
+    #[discriminant(2)]
+    AllPaused {},
+}
+
+impl PauseState {
+    /// Whether new bets are accepted.
+    pub fn accepts_bets(&self) -> bool {
+        *self == PauseState::Running {}
+    }
+
+    /// Whether games in flight can progress.
+    pub fn allows_play(&self) -> bool {
+        *self != PauseState::AllPaused {}
+    }
+}
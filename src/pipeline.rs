@@ -0,0 +1,45 @@
+//! Chained flip pipeline.
+//!
+//! Without the pipeline a game takes a transaction for the bet, one for each randomness
+//! contribution and one for `flip_coin`. While the pipeline is configured, the escrow callback of
+//! a bet asks every approved randomness provider to contribute to the player's round, and the
+//! flip starts by itself as soon as the confirmed inputs make the round ready, so a player whose
+//! round needs no randomness of their own only sends the bet. The flip's outputs are then opened
+//! and settled as usual.
+//!
+//! A provider's request action takes the address of the player whose round needs randomness.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use pbc_contract_common::events::EventGroup;
+use read_write_rpc_derive::ReadWriteRPC;
+use read_write_state_derive::ReadWriteState;
+
+use crate::token_client::{self, GasCosts};
+
+/// How randomness is requested from the approved providers.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+pub struct PipelineConfig {
+    /// Shortname of the providers' action requesting randomness for a round.
+    pub request_shortname: u32,
+}
+
+/// Build the event group requesting randomness for the player's round from every provider, if
+/// any is approved.
+pub fn randomness_request_event(
+    providers: &[Address],
+    costs: &GasCosts,
+    config: &PipelineConfig,
+    player: Address,
+) -> Option<EventGroup> {
+    if providers.is_empty() {
+        return None;
+    }
+    let mut event_group = EventGroup::builder();
+    for provider in providers {
+        token_client::interaction(&mut event_group, costs, *provider, config.request_shortname)
+            .argument(player)
+            .done();
+    }
+    Some(event_group.build())
+}
@@ -0,0 +1,98 @@
+//! USD prices of bet tokens from a price oracle contract.
+//!
+//! While an oracle is configured, every bet must be within the USD bet limits at the last quoted
+//! price of its token, and bets in tokens the oracle never quoted are rejected.
+//! `start_game_and_place_bet` first queries the USD price of the bet's token and only places the
+//! bet from the query's callback, so its bets are limited at a fresh price and quote their token
+//! for the other bet actions. The price of each token is kept as last quoted, and settlements are
+//! recorded in epochs and seasons at their USD value, so leaderboards rank players across tokens.
+//!
+//! The oracle's price action takes the token address and returns the price as a big-endian
+//! `u128`: USD per base unit of the token, with `USD_PRICE_DECIMALS` decimals. USD amounts are
+//! in micro-USD.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use pbc_contract_common::events::EventGroupBuilder;
+use read_write_rpc_derive::ReadWriteRPC;
+use read_write_state_derive::ReadWriteState;
+
+use crate::contract::CoinFlipState;
+use crate::token_client::{self, GasCosts};
+use crate::validation::{self, BetViolation};
+
+/// Decimals of the prices returned by the oracle.
+pub const USD_PRICE_DECIMALS: u32 = 18;
+
+/// Decimals of USD amounts.
+const USD_AMOUNT_DECIMALS: u32 = 6;
+
+/// The price oracle and the bet limits evaluated with its prices.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+pub struct PriceOracleConfig {
+    pub oracle_address: Address,
+    /// Shortname of the oracle's action returning the USD price of a token.
+    pub price_shortname: u32,
+    /// Smallest accepted bet, in micro-USD.
+    pub min_bet_usd: u64,
+    /// Largest accepted bet, in micro-USD.
+    pub max_bet_usd: u64,
+}
+
+/// The last price quoted for a token.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct UsdPrice {
+    /// USD per base unit of the token, with `USD_PRICE_DECIMALS` decimals.
+    pub price: u128,
+    pub quoted_at: i64,
+}
+
+/// Value of `amount` base units of a token at `price`, in micro-USD, rounding down.
+pub fn usd_value(amount: u64, price: u128) -> u64 {
+    let scale = 10u128.pow(USD_PRICE_DECIMALS - USD_AMOUNT_DECIMALS);
+    (amount as u128 * price / scale).min(u64::MAX as u128) as u64
+}
+
+/// Query the USD price of `token` from the oracle.
+pub fn query_price(
+    event_group: &mut EventGroupBuilder,
+    costs: &GasCosts,
+    config: &PriceOracleConfig,
+    token: Address,
+) {
+    token_client::interaction(event_group, costs, config.oracle_address, config.price_shortname)
+        .argument(token)
+        .done();
+}
+
+/// Parse the price returned by `query_price`, `None` if nothing was returned.
+pub fn parse_price(return_data: &[u8]) -> Option<u128> {
+    let bytes = return_data.get(..16)?;
+    Some(u128::from_be_bytes(bytes.try_into().unwrap()))
+}
+
+impl CoinFlipState {
+    /// Value of `amount` of `token` as settlements are recorded: in micro-USD at the token's last
+    /// quoted price while an oracle is configured, zero if the token was never quoted, and as is
+    /// otherwise.
+    pub(crate) fn recorded_value(&self, token: &Address, amount: u64) -> u64 {
+        if self.price_oracle.is_none() {
+            return amount;
+        }
+        self.usd_prices
+            .get(token)
+            .map_or(0, |quote| usd_value(amount, quote.price))
+    }
+
+    /// USD bet limits broken by a bet of `amount` of `token` at the token's last quoted price.
+    /// Nothing is checked without an oracle.
+    pub(crate) fn usd_limit_violations(&self, token: &Address, amount: u64) -> Vec<BetViolation> {
+        let Some(oracle) = &self.price_oracle else {
+            return vec![];
+        };
+        match self.usd_prices.get(token) {
+            None => vec![BetViolation::UnquotedToken {}],
+            Some(quote) => validation::validate_usd_limits(oracle, usd_value(amount, quote.price)),
+        }
+    }
+}
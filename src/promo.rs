@@ -0,0 +1,140 @@
+//! Promotional matching of bets. While a promotion runs, every bet in the default token gets an
+//! extra promotional stake of a share of its own stake, up to a cap per player and a global cap.
+//!
+//! The promotional stake is funded by a dedicated promo budget. When the bet is placed, the
+//! winnings of the promotional stake are reserved in the budget. They are only taken from the
+//! budget when the winnings are paid out; otherwise the reservation is released.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use pbc_contract_common::avl_tree_map::AvlTreeMap;
+use read_write_rpc_derive::ReadWriteRPC;
+use read_write_state_derive::ReadWriteState;
+
+/// Window and terms of a promotion.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+pub struct PromoConfig {
+    /// Promotional stake added to a bet, in basis points of its stake.
+    pub match_bps: u32,
+    pub starts_at: i64,
+    pub ends_at: i64,
+    /// Promotional stake a single player may receive during the promotion.
+    pub per_player_cap: u64,
+    /// Promotional stake given out in total during the promotion.
+    pub global_cap: u64,
+}
+
+/// Promotional stake added to a player's current bet.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct PromoStake {
+    pub stake: u64,
+    /// Winnings of the promotional stake if the bet wins, reserved in the budget.
+    pub payout: u64,
+}
+
+/// The running promotion and its budget.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct Promotion {
+    pub config: Option<PromoConfig>,
+    pub budget: u64,
+    /// Part of the budget reserved for the winnings of open bets.
+    pub reserved: u64,
+    /// Promotional stake given out during the current promotion.
+    pub granted_total: u64,
+    pub granted_by_player: AvlTreeMap<Address, u64>,
+    pub stakes: AvlTreeMap<Address, PromoStake>,
+}
+
+impl Promotion {
+    pub fn new() -> Self {
+        Promotion {
+            config: None,
+            budget: 0,
+            reserved: 0,
+            granted_total: 0,
+            granted_by_player: AvlTreeMap::new(),
+            stakes: AvlTreeMap::new(),
+        }
+    }
+
+    /// Start a new promotion, or end the running one with `None`. Caps count from the start of
+    /// the new promotion.
+    pub fn configure(&mut self, config: Option<PromoConfig>) {
+        let players: Vec<Address> = self
+            .granted_by_player
+            .iter()
+            .map(|(player, _)| player)
+            .collect();
+        for player in players {
+            self.granted_by_player.remove(&player);
+        }
+        self.granted_total = 0;
+        self.config = config;
+    }
+
+    /// Promotional stake offered to a bet of `stake` placed by `player` at `now`, zero outside of
+    /// a promotion or once a cap is reached.
+    pub fn offer(&self, player: &Address, stake: u64, now: i64) -> u64 {
+        let Some(config) = &self.config else {
+            return 0;
+        };
+        if now < config.starts_at || now >= config.ends_at {
+            return 0;
+        }
+        let granted = self.granted_by_player.get(player).unwrap_or(0);
+        let matched = (stake as u128 * config.match_bps as u128 / 10_000) as u64;
+        matched
+            .min(config.per_player_cap.saturating_sub(granted))
+            .min(config.global_cap.saturating_sub(self.granted_total))
+    }
+
+    /// Grant `stake` to the player's bet, reserving its `payout`. Nothing is granted if the budget
+    /// cannot cover the payout.
+    pub fn grant(&mut self, player: Address, stake: u64, payout: u64) {
+        if stake == 0 || payout > self.budget - self.reserved {
+            return;
+        }
+        self.reserved += payout;
+        self.granted_total += stake;
+        let granted = self.granted_by_player.get(&player).unwrap_or(0);
+        self.granted_by_player.insert(player, granted + stake);
+        self.stakes.insert(player, PromoStake { stake, payout });
+    }
+
+    /// Take back the stake granted to the player's bet, e.g. to grant it again once the bet is
+    /// repriced.
+    pub fn revoke(&mut self, player: &Address) {
+        if let Some(promo) = self.stakes.get(player) {
+            self.stakes.remove(player);
+            self.reserved -= promo.payout;
+            self.granted_total -= promo.stake;
+            let granted = self.granted_by_player.get(player).unwrap_or(0);
+            self.granted_by_player.insert(*player, granted.saturating_sub(promo.stake));
+        }
+    }
+
+    /// Release the reservation of a bet that did not win.
+    pub fn release(&mut self, player: &Address) {
+        if let Some(promo) = self.stakes.get(player) {
+            self.stakes.remove(player);
+            self.reserved -= promo.payout;
+        }
+    }
+
+    /// Debit the winnings of the promotional stake of a won bet from the budget.
+    ///
+    /// ### Returns:
+    ///
+    /// The winnings owed to the player.
+    pub fn pay(&mut self, player: &Address) -> u64 {
+        match self.stakes.get(player) {
+            Some(promo) => {
+                self.stakes.remove(player);
+                self.reserved -= promo.payout;
+                self.budget -= promo.payout;
+                promo.payout
+            }
+            None => 0,
+        }
+    }
+}
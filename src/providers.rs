@@ -0,0 +1,65 @@
+//! Randomness providers approved by the operator, e.g. bots run by the operator or the community.
+//!
+//! While any provider is registered, every flip needs a contribution from one of them besides the
+//! player's own. Each provider contributing to a flip earns `fee_per_flip` from the house bankroll,
+//! which it claims with `claim_provider_fees`.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use pbc_contract_common::sorted_vec_map::SortedVecMap;
+use read_write_state_derive::ReadWriteState;
+
+use crate::errors::{ensure, GameError};
+
+/// The approved providers and the fees they earned.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct ProviderRegistry {
+    pub providers: Vec<Address>,
+    /// Fee paid to each contributing provider per flip.
+    pub fee_per_flip: u64,
+    /// Fees earned by each provider and not yet claimed, kept when a provider is removed.
+    pub earned_fees: SortedVecMap<Address, u64>,
+}
+
+impl ProviderRegistry {
+    pub fn new() -> Self {
+        ProviderRegistry {
+            providers: vec![],
+            fee_per_flip: 0,
+            earned_fees: SortedVecMap::new(),
+        }
+    }
+
+    pub fn is_provider(&self, address: &Address) -> bool {
+        self.providers.contains(address)
+    }
+
+    /// Whether flips need a provider contribution, which is the case once any provider is
+    /// registered.
+    pub fn is_required(&self) -> bool {
+        !self.providers.is_empty()
+    }
+
+    pub fn add(&mut self, provider: Address) {
+        ensure!(
+            !self.is_provider(&provider),
+            GameError::AlreadyDone,
+            "The address is already a provider."
+        );
+        self.providers.push(provider);
+    }
+
+    pub fn remove(&mut self, provider: &Address) {
+        ensure!(
+            self.is_provider(provider),
+            GameError::NotFound,
+            "The address is not a provider."
+        );
+        self.providers.retain(|registered| registered != provider);
+    }
+
+    pub fn credit(&mut self, provider: Address, amount: u64) {
+        let earned = self.earned_fees.get(&provider).copied().unwrap_or(0);
+        self.earned_fees.insert(provider, earned + amount);
+    }
+}
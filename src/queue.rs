@@ -0,0 +1,95 @@
+//! Queue of rounds waiting for the zk computation engine, which runs one computation at a time.
+//!
+//! Rounds of VIP players are scheduled ahead of standard rounds, but at most
+//! `max_vip_streak` VIP rounds in a row are started while standard rounds are waiting.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use read_write_rpc_derive::ReadWriteRPC;
+use read_write_state_derive::ReadWriteState;
+
+/// Default number of consecutive VIP rounds started ahead of a waiting standard round.
+pub const DEFAULT_MAX_VIP_STREAK: u32 = 3;
+
+/// Lane of the computation queue a round waits in.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, PartialEq, Copy, Clone)]
+#[repr(u8)]
+pub enum ComputeLane {
+    #[discriminant(0)]
+    Standard {},
+    #[discriminant(1)]
+    Vip {},
+}
+
+/// Rounds waiting to be computed, by lane, along with per-lane fairness counters.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct ComputeQueue {
+    vip: Vec<Address>,
+    standard: Vec<Address>,
+    /// Maximum number of VIP rounds started in a row while standard rounds are waiting.
+    pub max_vip_streak: u32,
+    /// VIP rounds started in a row while standard rounds were waiting.
+    pub vip_streak: u32,
+    /// Number of VIP rounds started from the queue.
+    pub vip_served: u64,
+    /// Number of standard rounds started from the queue.
+    pub standard_served: u64,
+}
+
+impl ComputeQueue {
+    pub fn new(max_vip_streak: u32) -> Self {
+        ComputeQueue {
+            vip: vec![],
+            standard: vec![],
+            max_vip_streak,
+            vip_streak: 0,
+            vip_served: 0,
+            standard_served: 0,
+        }
+    }
+
+    /// Whether no round is waiting in either lane.
+    pub fn is_empty(&self) -> bool {
+        self.vip.is_empty() && self.standard.is_empty()
+    }
+
+    /// Whether the player's round is waiting in either lane.
+    pub fn contains(&self, player: &Address) -> bool {
+        self.vip.contains(player) || self.standard.contains(player)
+    }
+
+    /// Drop the player's round from the lane it waits in.
+    pub fn remove(&mut self, player: &Address) {
+        self.vip.retain(|queued| queued != player);
+        self.standard.retain(|queued| queued != player);
+    }
+
+    /// Queue the player's round at the back of the given lane.
+    pub fn push(&mut self, player: Address, lane: ComputeLane) {
+        match lane {
+            ComputeLane::Vip {} => self.vip.push(player),
+            ComputeLane::Standard {} => self.standard.push(player),
+        }
+    }
+
+    /// Take the next round to compute. VIP rounds go first unless the streak bound is reached
+    /// while standard rounds are waiting.
+    pub fn pop(&mut self) -> Option<Address> {
+        let standard_starved = !self.standard.is_empty() && self.vip_streak >= self.max_vip_streak;
+        if !self.vip.is_empty() && !standard_starved {
+            self.vip_streak = if self.standard.is_empty() {
+                0
+            } else {
+                self.vip_streak + 1
+            };
+            self.vip_served += 1;
+            Some(self.vip.remove(0))
+        } else if !self.standard.is_empty() {
+            self.vip_streak = 0;
+            self.standard_served += 1;
+            Some(self.standard.remove(0))
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,103 @@
+//! Dry-run quotes of bets under the current configuration.
+//!
+//! Wallets show a player what a bet would cost and pay before they place it. A quote follows the
+//! bet through the same fees, rake and payout math as `start_game_and_place_bet`, without
+//! changing the state, so the numbers shown match what the contract does with the bet.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use read_write_rpc_derive::ReadWriteRPC;
+use read_write_state_derive::ReadWriteState;
+
+use crate::contract::CoinFlipState;
+use crate::engine::{win_chance_bps, winnings_for, PlayerBet};
+
+/// What a bet would cost and pay if the player placed it now.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, PartialEq, Clone)]
+pub struct BetQuote {
+    /// Whether the bet would be paid from the player's free-bet credits.
+    pub free_bet: bool,
+    /// Penalty fee of a player who abandoned too many games.
+    pub penalty_fee: u64,
+    /// Part of the stake accruing to the jackpot.
+    pub jackpot_contribution: u64,
+    /// Stake left at risk once the fee and rake are taken.
+    pub net_stake: u64,
+    /// Chance of the bet winning, in basis points.
+    pub win_chance_bps: u32,
+    /// Paid out if the bet wins, in the payout token.
+    pub payout: u64,
+    /// Paid out from the promo budget on top of `payout` if the bet wins.
+    pub promo_payout: u64,
+    /// Allowance of the contract on the player's tokens the bet needs, zero when it is paid from
+    /// free-bet credits or the deposited balance.
+    pub required_allowance: u64,
+}
+
+impl CoinFlipState {
+    /// Quote `player_bet` placed by `player` at `now` with `start_game_and_place_bet`, staked in
+    /// `token` and paid out in `payout_token`, or the stake token if `None`.
+    pub fn quote_bet(
+        &self,
+        player: &Address,
+        player_bet: &PlayerBet,
+        token: &Address,
+        payout_token: Option<Address>,
+        now: i64,
+    ) -> BetQuote {
+        let amount = player_bet.amount;
+        let default_token = *token == self.token_address;
+        let free_bet_credits = self.free_bet_credits.get(player).unwrap_or(0);
+        let free_bet = default_token && payout_token.is_none() && free_bet_credits >= amount;
+
+        let penalty_fee = if free_bet {
+            0
+        } else {
+            self.abandonment_fee(player, amount)
+        };
+        let jackpot_contribution = if free_bet || !default_token {
+            0
+        } else {
+            ((amount - penalty_fee) as u128 * self.jackpot_rake_bps as u128 / 10_000) as u64
+        };
+        let mut net_bet = player_bet.clone();
+        net_bet.amount = amount - penalty_fee - jackpot_contribution;
+
+        let winnings = winnings_for(&net_bet, self.dice_house_edge_bps);
+        let payout_token = payout_token.unwrap_or(*token);
+        let payout = if free_bet {
+            // The stake of a free bet was never escrowed, so only the profit is paid
+            winnings - net_bet.amount
+        } else {
+            self.convert_payout(token, &payout_token, winnings)
+        };
+
+        let promo_stake = if !free_bet && default_token && payout_token == *token {
+            self.promotion.offer(player, net_bet.amount, now)
+        } else {
+            0
+        };
+        let promo_payout = match promo_stake {
+            0 => 0,
+            stake => (winnings as u128 * stake as u128 / net_bet.amount as u128) as u64,
+        };
+        // The promotion grants nothing it cannot cover from its budget
+        let promo_payout = if promo_payout <= self.promotion.budget - self.promotion.reserved {
+            promo_payout
+        } else {
+            0
+        };
+
+        let paid_from_balance = default_token && self.available_balance(player) >= amount;
+        BetQuote {
+            free_bet,
+            penalty_fee,
+            jackpot_contribution,
+            net_stake: net_bet.amount,
+            win_chance_bps: win_chance_bps(&net_bet),
+            payout,
+            promo_payout,
+            required_allowance: if free_bet || paid_from_balance { 0 } else { amount },
+        }
+    }
+}
@@ -0,0 +1,71 @@
+//! Administrative roles. The owner grants and revokes the other roles, operators manage limits and
+//! pauses, and only treasurers move house funds out of the bankroll.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use read_write_rpc_derive::ReadWriteRPC;
+use read_write_state_derive::ReadWriteState;
+
+use crate::contract::CoinFlipState;
+use crate::errors::{ensure, GameError};
+
+/// An administrative role.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, PartialEq, Copy, Clone)]
+#[repr(u8)]
+pub enum Role {
+    /// Holds every right not delegated to another role, and is the only one granting roles. There
+    /// is exactly one owner, replaced through `transfer_ownership` and `accept_ownership`.
+    #[discriminant(0)]
+    Owner {},
+    /// Manages bet limits, blocked addresses and pauses.
+    #[discriminant(1)]
+    Operator {},
+    /// Withdraws and deploys house funds.
+    #[discriminant(2)]
+    Treasurer {},
+}
+
+impl CoinFlipState {
+    /// Whether `address` holds `role`.
+    pub fn has_role(&self, address: &Address, role: Role) -> bool {
+        match role {
+            Role::Owner {} => *address == self.owner,
+            _ => self
+                .role_grants
+                .get(address)
+                .map(|roles| roles.contains(&role))
+                .unwrap_or(false),
+        }
+    }
+
+    /// Every role held by `address`.
+    pub fn roles_of(&self, address: &Address) -> Vec<Role> {
+        let mut roles = vec![];
+        if *address == self.owner {
+            roles.push(Role::Owner {});
+        }
+        if let Some(granted) = self.role_grants.get(address) {
+            roles.extend(granted.iter().copied());
+        }
+        roles
+    }
+
+    /// The owner can do everything an operator can.
+    pub(crate) fn assert_operator(&self, address: &Address) {
+        ensure!(
+            *address == self.owner || self.has_role(address, Role::Operator {}),
+            GameError::Unauthorized,
+            "Only the owner or an operator can perform this action."
+        );
+    }
+
+    /// Unlike operator rights, treasurer rights are not implied by ownership; an owner moving
+    /// house funds must grant themselves the role first.
+    pub(crate) fn assert_treasurer(&self, address: &Address) {
+        ensure!(
+            self.has_role(address, Role::Treasurer {}),
+            GameError::Unauthorized,
+            "Only a treasurer can perform this action."
+        );
+    }
+}
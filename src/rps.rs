@@ -0,0 +1,61 @@
+//! Rock-paper-scissors between two players. Both throws are secret inputs and only the
+//! outcome of comparing them is opened. A player who does not throw within
+//! `RPS_THROW_TIMEOUT_MILLIS` of both stakes being escrowed forfeits the game to the player who
+//! did, and a game neither player threw in is refunded.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use read_write_rpc_derive::ReadWriteRPC;
+use read_write_state_derive::ReadWriteState;
+
+/// Opened outcome of a game where the throws were equal, or both invalid.
+pub const RPS_DRAW: u8 = 0;
+/// Opened outcome of a game won by the creator.
+pub const RPS_CREATOR_WINS: u8 = 1;
+/// Opened outcome of a game won by the opponent.
+pub const RPS_OPPONENT_WINS: u8 = 2;
+
+/// Time the players have to throw once both stakes are escrowed.
+pub const RPS_THROW_TIMEOUT_MILLIS: i64 = 24 * 60 * 60 * 1000;
+
+/// Progress of a rock-paper-scissors game.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, PartialEq, Copy, Clone)]
+#[repr(u8)]
+pub enum RpsStatus {
+    /// Waiting for the creator's stake to be escrowed.
+    #[discriminant(0)]
+    AwaitingEscrow {},
+    /// Open for an opponent to join.
+    #[discriminant(1)]
+    WaitingForOpponent {},
+    /// Both stakes are escrowed and the players submit their throws.
+    #[discriminant(2)]
+    Throwing {},
+    /// The throws are being compared.
+    #[discriminant(3)]
+    Computing {},
+    /// Settled; `winner` is `None` for a draw.
+    #[discriminant(4)]
+    Finished { winner: Option<Address> },
+    /// Cancelled by the creator before an opponent joined.
+    #[discriminant(5)]
+    Cancelled {},
+}
+
+/// A rock-paper-scissors game between two players staking the same amount.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct RpsGame {
+    pub creator: Address,
+    /// The joined opponent; set as soon as they join, before their stake is escrowed.
+    pub opponent: Option<Address>,
+    pub opponent_escrowed: bool,
+    pub stake: u64,
+    pub status: RpsStatus,
+}
+
+impl RpsGame {
+    /// Whether the address is one of the two players of the game.
+    pub fn is_player(&self, address: &Address) -> bool {
+        self.creator == *address || self.opponent == Some(*address)
+    }
+}
@@ -0,0 +1,103 @@
+//! Seasons of competition. Settled games count towards the standings of the running season, and
+//! closing a season archives its totals and leaderboard under the season number before the
+//! standings start over, so operators can run recurring competitions without losing their
+//! history. Lifetime statistics of players are kept across seasons.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use pbc_contract_common::sorted_vec_map::SortedVecMap;
+use read_write_rpc_derive::ReadWriteRPC;
+use read_write_state_derive::ReadWriteState;
+
+/// Number of players kept in the archived leaderboard of a season.
+pub const SEASON_LEADERBOARD_SIZE: usize = 25;
+
+/// A player's standing in a season.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone, Default)]
+pub struct SeasonStanding {
+    pub games_played: u64,
+    pub wins: u64,
+    pub total_wagered: u64,
+    pub total_winnings: u64,
+}
+
+/// A single row of a season's leaderboard.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+pub struct SeasonLeaderboardEntry {
+    pub player: Address,
+    pub standing: SeasonStanding,
+}
+
+/// Archived record of a closed season.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+pub struct SeasonRecord {
+    pub season: u32,
+    pub started_at: i64,
+    pub ended_at: i64,
+    pub games_settled: u64,
+    pub total_wagered: u64,
+    pub players: u32,
+    /// Best players by winnings, best first.
+    pub leaderboard: Vec<SeasonLeaderboardEntry>,
+}
+
+/// Live standings of the running season.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct SeasonState {
+    pub season: u32,
+    pub started_at: i64,
+    pub games_settled: u64,
+    pub total_wagered: u64,
+    pub standings: SortedVecMap<Address, SeasonStanding>,
+}
+
+impl SeasonState {
+    /// Start a fresh season.
+    pub fn new(season: u32, started_at: i64) -> Self {
+        SeasonState {
+            season,
+            started_at,
+            games_settled: 0,
+            total_wagered: 0,
+            standings: SortedVecMap::new(),
+        }
+    }
+
+    /// Record a settled game.
+    pub fn record_settlement(&mut self, player: Address, amount: u64, winnings: Option<u64>) {
+        self.games_settled += 1;
+        self.total_wagered += amount;
+        let mut standing = self.standings.get(&player).cloned().unwrap_or_default();
+        standing.games_played += 1;
+        standing.total_wagered += amount;
+        if let Some(winnings) = winnings {
+            standing.wins += 1;
+            standing.total_winnings += winnings;
+        }
+        self.standings.insert(player, standing);
+    }
+
+    /// Snapshot the season into its archived record.
+    pub fn archive(&self, ended_at: i64) -> SeasonRecord {
+        let mut leaderboard: Vec<SeasonLeaderboardEntry> = self
+            .standings
+            .iter()
+            .map(|(player, standing)| SeasonLeaderboardEntry {
+                player: *player,
+                standing: standing.clone(),
+            })
+            .collect();
+        leaderboard.sort_by(|a, b| b.standing.total_winnings.cmp(&a.standing.total_winnings));
+        leaderboard.truncate(SEASON_LEADERBOARD_SIZE);
+
+        SeasonRecord {
+            season: self.season,
+            started_at: self.started_at,
+            ended_at,
+            games_settled: self.games_settled,
+            total_wagered: self.total_wagered,
+            players: self.standings.len() as u32,
+            leaderboard,
+        }
+    }
+}
@@ -0,0 +1,72 @@
+//! Sessions of approved bots flipping continuously.
+//!
+//! A market-maker bot betting at high frequency would need an escrow transfer and its callback
+//! for every bet, and a payout for every win. Instead, an operator approves the bot for sessions
+//! of up to a number of flips. The bot opens a session with a single bulk escrow, stakes its flips
+//! from the session balance with `session_flip`, and what the flips win is credited back to the
+//! balance. Closing the session pays out the balance, settling the net result of all its flips
+//! in one transfer.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use read_write_state_derive::ReadWriteState;
+
+use crate::contract::CoinFlipState;
+use crate::errors::{ensure, GameError, OrFail};
+
+/// An open session of a bot, in the default token.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct BotSession {
+    /// Escrowed into the session in total.
+    pub deposited: u64,
+    /// Available for stakes: the deposits and winnings, less the stakes of the flips.
+    pub balance: u64,
+    /// Flips the session may still run.
+    pub flips_left: u32,
+    pub opened_at: i64,
+}
+
+impl BotSession {
+    /// Net result of the session's flips so far, not counting the stake of a flip in play.
+    pub fn net_result(&self) -> i128 {
+        self.balance as i128 - self.deposited as i128
+    }
+}
+
+impl CoinFlipState {
+    /// Fund the stake of a bet of `bot` from its open session.
+    pub(crate) fn draw_session_stake(&mut self, bot: Address, stake: u64) {
+        let session = self
+            .bot_sessions
+            .get_mut(&bot)
+            .or_fail(GameError::NotFound, "The bot has no open session.");
+        ensure!(
+            session.flips_left > 0,
+            GameError::Ineligible,
+            "The session has run all its flips."
+        );
+        ensure!(
+            stake <= session.balance,
+            GameError::InsufficientFunds,
+            "The session balance cannot cover the bet."
+        );
+        session.balance -= stake;
+        session.flips_left -= 1;
+        self.session_bets.insert(bot, true);
+    }
+
+    /// Return `stake` to the session that funded the player's current bet, if any.
+    ///
+    /// ### Returns:
+    ///
+    /// Whether the bet was funded by a session.
+    pub(crate) fn give_back_session_stake(&mut self, player: &Address, stake: u64) -> bool {
+        match self.bot_sessions.get_mut(player) {
+            Some(session) if self.session_bets.get(player).is_some() => {
+                session.balance += stake;
+                true
+            }
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,107 @@
+//! Side bets of spectators on the coin flip of another player's open game.
+//!
+//! Side bets pay even money from a pool of their own, apart from the bankroll. Every side bet
+//! reserves its potential winnings in the pool when placed, so the pool always covers the side
+//! bets it has accepted. Lost stakes stay in the pool.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use read_write_state_derive::ReadWriteState;
+
+use crate::contract::CoinFlipState;
+use crate::engine::PlayerChoice;
+use crate::errors::{ensure, GameError};
+
+/// Side bets accepted on a single game.
+pub const MAX_SIDE_BETS_PER_GAME: usize = 16;
+
+/// A spectator's bet on the side the coin of a game lands on.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct SideBet {
+    pub bettor: Address,
+    pub amount: u64,
+    pub choice: PlayerChoice,
+    /// Whether the stake has arrived; only escrowed side bets are settled.
+    pub escrowed: bool,
+}
+
+/// Pool paying the winnings of side bets.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct SideBetPool {
+    pub balance: u64,
+    /// Part of the balance reserved for the winnings of open side bets.
+    pub reserved: u64,
+}
+
+impl SideBetPool {
+    pub fn new() -> Self {
+        SideBetPool {
+            balance: 0,
+            reserved: 0,
+        }
+    }
+
+    /// Reserve the winnings of a new side bet of `amount`.
+    pub fn reserve(&mut self, amount: u64) {
+        ensure!(
+            amount <= self.balance - self.reserved,
+            GameError::InsufficientFunds,
+            "The side bet pool cannot cover the side bet."
+        );
+        self.reserved += amount;
+    }
+
+    /// Release the reservation of a side bet that ended without being settled.
+    pub fn release(&mut self, amount: u64) {
+        self.reserved -= amount;
+    }
+
+    /// Settle an escrowed side bet of `amount`.
+    ///
+    /// ### Returns:
+    ///
+    /// The amount owed to the bettor: the stake and the winnings on a win, nothing on a loss.
+    pub fn settle(&mut self, amount: u64, won: bool) -> u64 {
+        self.reserved -= amount;
+        if won {
+            self.balance -= amount;
+            amount * 2
+        } else {
+            self.balance += amount;
+            0
+        }
+    }
+}
+
+impl CoinFlipState {
+    /// Settle the side bets on the flip of the player's game, crediting what they won to the
+    /// bettors' balances.
+    pub(crate) fn settle_side_bets(&mut self, player: &Address) {
+        let Some(heads) = self.flip_results.get(player) else {
+            self.refund_side_bets(player);
+            return;
+        };
+        for side_bet in self.side_bets.get(player).unwrap_or_default() {
+            if side_bet.escrowed {
+                let won = side_bet.choice.matches_flip(heads);
+                let owed = self.side_bet_pool.settle(side_bet.amount, won);
+                self.adjust_balance(side_bet.bettor, owed);
+            } else {
+                // The stake is refunded by the escrow callback once it arrives
+                self.side_bet_pool.release(side_bet.amount);
+            }
+        }
+        self.side_bets.remove(player);
+    }
+
+    /// Return the side bets on a game that ends without a flip.
+    pub(crate) fn refund_side_bets(&mut self, player: &Address) {
+        for side_bet in self.side_bets.get(player).unwrap_or_default() {
+            self.side_bet_pool.release(side_bet.amount);
+            if side_bet.escrowed {
+                self.adjust_balance(side_bet.bettor, side_bet.amount);
+            }
+        }
+        self.side_bets.remove(player);
+    }
+}
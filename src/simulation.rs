@@ -0,0 +1,85 @@
+//! Host-side replay of settled rounds under a proposed configuration, so operators can see the
+//! projected effect of a change before making it. Only compiled with the `simulation` feature.
+
+use crate::contract::CoinFlipState;
+use crate::engine::{winnings_for, GamePhase, PlayerBet};
+
+/// The configurable parameters affecting settlement.
+#[derive(Debug, Clone)]
+pub struct ProposedConfig {
+    pub dice_house_edge_bps: u32,
+}
+
+/// A settled round to replay.
+#[derive(Debug, Clone)]
+pub struct SimulatedRound {
+    pub bet: PlayerBet,
+    pub player_won: bool,
+}
+
+/// Totals of the replayed rounds under the current and the proposed configuration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimulationReport {
+    pub rounds: u64,
+    pub house_pnl_current: i128,
+    pub house_pnl_proposed: i128,
+    pub player_payouts_current: u128,
+    pub player_payouts_proposed: u128,
+}
+
+impl SimulationReport {
+    /// Projected change in house profit and loss if the proposed configuration is applied.
+    pub fn house_pnl_change(&self) -> i128 {
+        self.house_pnl_proposed - self.house_pnl_current
+    }
+}
+
+/// Collect the settled rounds currently stored in the state.
+pub fn stored_rounds(state: &CoinFlipState) -> Vec<SimulatedRound> {
+    state
+        .player_bets
+        .iter()
+        .filter(|(player, _)| state.game_phases.get(player) == Some(GamePhase::Done {}))
+        .filter_map(|(player, bet)| {
+            state.winners.get(&player).map(|winner| SimulatedRound {
+                bet,
+                player_won: winner == player,
+            })
+        })
+        .collect()
+}
+
+/// Replay `historical_rounds` under the state's current configuration and under `config`.
+pub fn simulate_config(
+    state: &CoinFlipState,
+    config: &ProposedConfig,
+    historical_rounds: &[SimulatedRound],
+) -> SimulationReport {
+    let (house_pnl_current, player_payouts_current) =
+        replay(historical_rounds, state.dice_house_edge_bps);
+    let (house_pnl_proposed, player_payouts_proposed) =
+        replay(historical_rounds, config.dice_house_edge_bps);
+
+    SimulationReport {
+        rounds: historical_rounds.len() as u64,
+        house_pnl_current,
+        house_pnl_proposed,
+        player_payouts_current,
+        player_payouts_proposed,
+    }
+}
+
+/// House profit and total player payouts of the rounds under the given parameters.
+fn replay(rounds: &[SimulatedRound], dice_house_edge_bps: u32) -> (i128, u128) {
+    let mut house_pnl = 0i128;
+    let mut player_payouts = 0u128;
+    for round in rounds {
+        house_pnl += round.bet.amount as i128;
+        if round.player_won {
+            let winnings = winnings_for(&round.bet, dice_house_edge_bps);
+            house_pnl -= winnings as i128;
+            player_payouts += winnings as u128;
+        }
+    }
+    (house_pnl, player_payouts)
+}
@@ -0,0 +1,84 @@
+//! Sponsored bets onboarding new players.
+//!
+//! An operator pre-funds a sponsorship budget in the default token, from which players draw the
+//! stakes of their bets through `start_sponsored_game`, up to a cap per player. Winnings are paid
+//! to the player as for any other bet, and lost stakes go to the house. Stakes of sponsored bets
+//! that end without being settled return to the budget.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use pbc_contract_common::sorted_vec_map::SortedVecMap;
+use read_write_state_derive::ReadWriteState;
+
+use crate::contract::CoinFlipState;
+use crate::errors::{ensure, GameError, OrFail};
+
+/// Budget of a sponsor and the stakes drawn from it.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct Sponsorship {
+    /// Unspent budget, in the default token.
+    pub budget: u64,
+    /// Stake a single player may draw from the budget in total.
+    pub per_player_cap: u64,
+    pub drawn_by_player: SortedVecMap<Address, u64>,
+}
+
+impl Sponsorship {
+    pub fn new(per_player_cap: u64) -> Self {
+        Sponsorship {
+            budget: 0,
+            per_player_cap,
+            drawn_by_player: SortedVecMap::new(),
+        }
+    }
+
+    /// Draw the stake of a bet of `player` from the budget.
+    pub fn draw(&mut self, player: Address, stake: u64) {
+        let drawn = self.drawn_by_player.get(&player).copied().unwrap_or(0);
+        ensure!(
+            drawn + stake <= self.per_player_cap,
+            GameError::Ineligible,
+            "The bet exceeds the sponsorship left for the player."
+        );
+        ensure!(
+            stake <= self.budget,
+            GameError::InsufficientFunds,
+            "The sponsorship budget cannot cover the bet."
+        );
+        self.budget -= stake;
+        self.drawn_by_player.insert(player, drawn + stake);
+    }
+
+    /// Return the stake of a sponsored bet of `player` that ended without being settled.
+    pub fn give_back(&mut self, player: Address, stake: u64) {
+        self.budget += stake;
+        let drawn = self.drawn_by_player.get(&player).copied().unwrap_or(0);
+        self.drawn_by_player.insert(player, drawn.saturating_sub(stake));
+    }
+}
+
+impl CoinFlipState {
+    /// Fund the stake of a bet of `player` from the budget of `sponsor`.
+    pub(crate) fn draw_sponsored_stake(&mut self, sponsor: Address, player: Address, stake: u64) {
+        self.sponsorships
+            .get_mut(&sponsor)
+            .or_fail(GameError::NotFound, "The sponsor has no sponsorship.")
+            .draw(player, stake);
+        self.sponsored_bets.insert(player, sponsor);
+    }
+
+    /// Return `stake` to the budget that sponsored the player's current bet, if any.
+    ///
+    /// ### Returns:
+    ///
+    /// Whether the bet was sponsored.
+    pub(crate) fn give_back_sponsored_stake(&mut self, player: &Address, stake: u64) -> bool {
+        let Some(sponsor) = self.sponsored_bets.get(player) else {
+            return false;
+        };
+        if let Some(sponsorship) = self.sponsorships.get_mut(&sponsor) {
+            sponsorship.give_back(*player, stake);
+        }
+        true
+    }
+}
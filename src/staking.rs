@@ -0,0 +1,137 @@
+//! Shares in the house bankroll of the default token.
+//!
+//! Stakers and the house own the bankroll through shares, so whatever the bankroll wins or loses,
+//! including fees credited to it, is split pro rata. The house's own capital is held as
+//! `house_shares`, minted when the bankroll is funded and burned when house funds are withdrawn.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use pbc_contract_common::sorted_vec_map::SortedVecMap;
+use read_write_rpc_derive::ReadWriteRPC;
+use read_write_state_derive::ReadWriteState;
+
+use crate::errors::{ensure, GameError};
+
+/// Shares of the house bankroll.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct StakingPool {
+    /// Shares of every holder, the house included.
+    pub total_shares: u128,
+    /// Shares backing the house's own capital.
+    pub house_shares: u128,
+    /// Shares of each staker.
+    pub stakes: SortedVecMap<Address, u128>,
+}
+
+/// A staker's position as shown to clients.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+pub struct StakeSummary {
+    pub shares: u128,
+    /// Amount of the bankroll the shares can currently be redeemed for.
+    pub value: u64,
+}
+
+impl StakingPool {
+    pub fn new() -> Self {
+        StakingPool {
+            total_shares: 0,
+            house_shares: 0,
+            stakes: SortedVecMap::new(),
+        }
+    }
+
+    /// Shares worth `amount` of a bankroll of `bankroll`. Bankroll without any shares, funded
+    /// before staking existed, is first attributed to the house.
+    fn shares_for(&mut self, amount: u64, bankroll: u64) -> u128 {
+        if bankroll == 0 {
+            // A depleted bankroll leaves every share worthless, so pricing starts over
+            *self = StakingPool::new();
+        }
+        if self.total_shares == 0 {
+            self.house_shares = bankroll as u128;
+            self.total_shares = bankroll as u128;
+        }
+        if self.total_shares == 0 {
+            return amount as u128;
+        }
+        amount as u128 * self.total_shares / bankroll as u128
+    }
+
+    /// Amount of a bankroll of `bankroll` that `shares` are worth.
+    pub fn value_of(&self, shares: u128, bankroll: u64) -> u64 {
+        if self.total_shares == 0 {
+            return 0;
+        }
+        (shares * bankroll as u128 / self.total_shares) as u64
+    }
+
+    /// Mint shares to `staker` for `amount` added to a bankroll of `bankroll`.
+    pub fn stake(&mut self, staker: Address, amount: u64, bankroll: u64) -> u128 {
+        let shares = self.shares_for(amount, bankroll);
+        let held = self.stakes.get(&staker).copied().unwrap_or(0);
+        self.stakes.insert(staker, held + shares);
+        self.total_shares += shares;
+        shares
+    }
+
+    /// Burn `shares` of `staker`.
+    ///
+    /// ### Returns:
+    ///
+    /// The amount of a bankroll of `bankroll` they were worth.
+    pub fn unstake(&mut self, staker: &Address, shares: u128, bankroll: u64) -> u64 {
+        let held = self.stakes.get(staker).copied().unwrap_or(0);
+        ensure!(
+            shares > 0 && shares <= held,
+            GameError::InsufficientFunds,
+            "Insufficient shares."
+        );
+        let value = self.value_of(shares, bankroll);
+        if held == shares {
+            self.stakes.remove(staker);
+        } else {
+            self.stakes.insert(*staker, held - shares);
+        }
+        self.total_shares -= shares;
+        value
+    }
+
+    /// Give back the shares of a redemption that could not be paid out.
+    pub fn restore(&mut self, staker: Address, shares: u128) {
+        let held = self.stakes.get(&staker).copied().unwrap_or(0);
+        self.stakes.insert(staker, held + shares);
+        self.total_shares += shares;
+    }
+
+    /// Mint house shares for `amount` added to a bankroll of `bankroll`.
+    pub fn fund_house(&mut self, amount: u64, bankroll: u64) {
+        let shares = self.shares_for(amount, bankroll);
+        self.house_shares += shares;
+        self.total_shares += shares;
+    }
+
+    /// Burn the house shares worth `amount` taken from a bankroll of `bankroll`, which may not
+    /// exceed the house's part of it.
+    pub fn withdraw_house(&mut self, amount: u64, bankroll: u64) {
+        if self.total_shares == 0 || amount == 0 {
+            return;
+        }
+        ensure!(
+            amount <= self.value_of(self.house_shares, bankroll),
+            GameError::InsufficientFunds,
+            "The house's part of the bankroll cannot cover the withdrawal."
+        );
+        let shares = (amount as u128 * self.total_shares).div_ceil(bankroll as u128);
+        let shares = shares.min(self.house_shares);
+        self.house_shares -= shares;
+        self.total_shares -= shares;
+    }
+
+    pub fn summary(&self, staker: &Address, bankroll: u64) -> StakeSummary {
+        let shares = self.stakes.get(staker).copied().unwrap_or(0);
+        StakeSummary {
+            shares,
+            value: self.value_of(shares, bankroll),
+        }
+    }
+}
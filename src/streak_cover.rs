@@ -0,0 +1,87 @@
+//! Optional cover sold alongside a bet that pays a consolation prize when a long enough win
+//! streak is broken. Premiums and prizes go through a pool of their own, apart from the bankroll.
+
+use create_type_spec_derive::CreateTypeSpec;
+use read_write_rpc_derive::ReadWriteRPC;
+use read_write_state_derive::ReadWriteState;
+
+/// Pricing of streak cover.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+pub struct StreakCoverConfig {
+    /// Shortest win streak that can be covered.
+    pub min_streak: u32,
+    /// Price of the cover, in basis points of the stake of the covered bet.
+    pub premium_bps: u32,
+    /// Consolation prize for each win of the broken streak, in basis points of the stake.
+    pub consolation_bps_per_win: u32,
+}
+
+/// Cover bought for a player's current bet.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct StreakCover {
+    pub premium: u64,
+    /// Prize paid if the bet loses, breaking the streak.
+    pub consolation: u64,
+}
+
+/// Pool funding the consolation prizes.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct StreakCoverPool {
+    pub balance: u64,
+    /// Total ever collected as premiums.
+    pub premiums_collected: u64,
+    /// Total ever paid as consolation prizes.
+    pub consolations_paid: u64,
+}
+
+impl StreakCoverConfig {
+    /// Price and prize of cover on a bet of `stake` extending a streak of `streak` wins.
+    pub fn quote(&self, stake: u64, streak: u32) -> StreakCover {
+        let premium = stake as u128 * self.premium_bps as u128 / 10_000;
+        let consolation = stake as u128 * self.consolation_bps_per_win as u128 * streak as u128 / 10_000;
+        StreakCover {
+            premium: premium as u64,
+            consolation: consolation.min(u64::MAX as u128) as u64,
+        }
+    }
+}
+
+impl StreakCoverPool {
+    pub fn new() -> Self {
+        StreakCoverPool {
+            balance: 0,
+            premiums_collected: 0,
+            consolations_paid: 0,
+        }
+    }
+
+    /// Collect the premium of sold cover.
+    pub fn collect(&mut self, premium: u64) {
+        self.balance += premium;
+        self.premiums_collected += premium;
+    }
+
+    /// Refund the premium of cover whose bet was discarded, as far as the pool allows.
+    ///
+    /// ### Returns:
+    ///
+    /// The amount refunded.
+    pub fn refund(&mut self, premium: u64) -> u64 {
+        let refunded = premium.min(self.balance);
+        self.balance -= refunded;
+        self.premiums_collected -= refunded;
+        refunded
+    }
+
+    /// Pay the consolation prize of broken cover, as far as the pool allows.
+    ///
+    /// ### Returns:
+    ///
+    /// The amount paid.
+    pub fn pay(&mut self, consolation: u64) -> u64 {
+        let paid = consolation.min(self.balance);
+        self.balance -= paid;
+        self.consolations_paid += paid;
+        paid
+    }
+}
@@ -0,0 +1,26 @@
+//! Graceful shutdown. A sunset stops new bets and gives games in flight until `closes_at` to
+//! resolve; afterwards the sweep returns every balance, stake and unresolved escrow to its owner
+//! and the remaining house funds to the contract owner.
+
+use create_type_spec_derive::CreateTypeSpec;
+use read_write_state_derive::ReadWriteState;
+
+/// Progress of the sunset.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct Sunset {
+    pub announced_at: i64,
+    /// When games still in flight are timed out and their stakes returned.
+    pub closes_at: i64,
+    /// Whether everything has been returned and the contract is done.
+    pub completed: bool,
+}
+
+impl Sunset {
+    pub fn new(now: i64, grace_millis: i64) -> Self {
+        Sunset {
+            announced_at: now,
+            closes_at: now.saturating_add(grace_millis),
+            completed: false,
+        }
+    }
+}
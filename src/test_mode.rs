@@ -0,0 +1,57 @@
+//! Test mode of testnet deployments.
+//!
+//! A contract initialized with `is_test_mode` accepts extra actions easing QA and frontend
+//! development: a faucet crediting play money, resolving a game with a chosen outcome instead of
+//! a flip, and fast-forwarding the deadlines of a game. Every one of them is rejected by a
+//! contract initialized without the flag, which cannot be turned on afterwards.
+//!
+//! Play money must not become real tokens. An account the faucet credited cannot withdraw or move
+//! its balance, its winnings and jackpots are credited to its balance rather than transferred, and
+//! a sunset leaves its balance behind.
+
+use pbc_contract_common::address::Address;
+
+use crate::contract::CoinFlipState;
+use crate::errors::{ensure, GameError};
+
+/// Largest amount credited by a single faucet call.
+pub const MAX_FAUCET_AMOUNT: u64 = 1_000_000_000;
+
+impl CoinFlipState {
+    pub(crate) fn assert_test_mode(&self) {
+        ensure!(
+            self.is_test_mode,
+            GameError::Unavailable,
+            "The action is only available in test mode."
+        );
+    }
+
+    pub(crate) fn is_play_money_account(&self, player: &Address) -> bool {
+        self.play_money_accounts.contains_key(player)
+    }
+
+    pub(crate) fn assert_not_play_money(&self, player: &Address) {
+        ensure!(
+            !self.is_play_money_account(player),
+            GameError::Ineligible,
+            "Play money from the faucet cannot be withdrawn."
+        );
+    }
+
+    /// Move every deadline and timestamp of the player's game `millis` into the past, as if that
+    /// much time had passed.
+    pub(crate) fn rewind_game_clock(&mut self, player: &Address, millis: i64) {
+        if let Some(entered_at) = self.stage_entered_at.get(player) {
+            self.stage_entered_at.insert(*player, entered_at.saturating_sub(millis));
+        }
+        if let Some(deadline) = self.challenge_deadlines.get(player) {
+            self.challenge_deadlines.insert(*player, deadline.saturating_sub(millis));
+        }
+        if let Some(deadline) = self.claim_deadlines.get(player) {
+            self.claim_deadlines.insert(*player, deadline.saturating_sub(millis));
+        }
+        if let Some(settled_at) = self.settled_at.get(player) {
+            self.settled_at.insert(*player, settled_at.saturating_sub(millis));
+        }
+    }
+}
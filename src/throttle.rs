@@ -0,0 +1,68 @@
+//! Throttling of bets against bots hammering the contract: a minimum interval between consecutive
+//! bets of an address, and a cap on the bets accepted within a single block.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use pbc_contract_common::avl_tree_map::AvlTreeMap;
+use read_write_rpc_derive::ReadWriteRPC;
+use read_write_state_derive::ReadWriteState;
+
+/// Limits on how often bets are accepted.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+pub struct ThrottleConfig {
+    /// Minimum block production time between two bets of the same address. Zero disables it.
+    pub min_bet_interval_millis: i64,
+    /// Bets accepted within a single block. Zero disables the cap.
+    pub max_bets_per_block: u32,
+}
+
+/// Throttle limits and the bets they are checked against.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct BetThrottle {
+    pub config: ThrottleConfig,
+    pub last_bet_at: AvlTreeMap<Address, i64>,
+    /// Block production time of the latest block a bet was placed in.
+    pub block_time: i64,
+    pub bets_in_block: u32,
+}
+
+impl BetThrottle {
+    pub fn new() -> Self {
+        BetThrottle {
+            config: ThrottleConfig {
+                min_bet_interval_millis: 0,
+                max_bets_per_block: 0,
+            },
+            last_bet_at: AvlTreeMap::new(),
+            block_time: 0,
+            bets_in_block: 0,
+        }
+    }
+
+    /// Whether a bet of `player` at `now` follows their previous bet too closely.
+    pub fn is_too_soon(&self, player: &Address, now: i64) -> bool {
+        let interval = self.config.min_bet_interval_millis;
+        interval > 0
+            && self
+                .last_bet_at
+                .get(player)
+                .is_some_and(|last_bet_at| now < last_bet_at.saturating_add(interval))
+    }
+
+    /// Whether the block at `now` has accepted as many bets as it may.
+    pub fn is_block_full(&self, now: i64) -> bool {
+        let cap = self.config.max_bets_per_block;
+        cap > 0 && self.block_time == now && self.bets_in_block >= cap
+    }
+
+    /// Record a bet of `player` placed at `now`.
+    pub fn record(&mut self, player: Address, now: i64) {
+        self.last_bet_at.insert(player, now);
+        if self.block_time == now {
+            self.bets_in_block += 1;
+        } else {
+            self.block_time = now;
+            self.bets_in_block = 1;
+        }
+    }
+}
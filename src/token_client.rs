@@ -0,0 +1,156 @@
+//! Typed client for the MPC-20 token contracts the game escrows and pays out in.
+//!
+//! Every interaction is added to an event group under construction, so several token calls and a
+//! callback can be combined into one group. Interactions and callbacks are sent with the gas
+//! costs the owner configured, as the costs needed differ between networks.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::{Address, ShortnameCallback};
+use pbc_contract_common::events::{CallbackBuilder, EventGroupBuilder, InteractionBuilder};
+use pbc_contract_common::shortname::Shortname;
+use read_write_rpc_derive::ReadWriteRPC;
+use read_write_state_derive::ReadWriteState;
+
+/// Shortname of the MPC-20 `transfer` action.
+const TRANSFER: u32 = 0x01;
+/// Shortname of the MPC-20 `transfer_from` action.
+const TRANSFER_FROM: u32 = 0x03;
+/// Shortname of the MPC-20 `approve` action.
+const APPROVE: u32 = 0x05;
+
+/// Gas reserved for each callback unless the owner configures otherwise.
+const DEFAULT_CALLBACK_COST: u64 = 1000;
+
+/// Gas sent with interactions and reserved for callbacks.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+pub struct GasCosts {
+    /// Gas reserved for each callback registered on interactions.
+    pub callback_cost: u64,
+    /// Gas sent with each interaction. Zero lets the interaction share the gas left over by the
+    /// transaction.
+    pub interaction_cost: u64,
+}
+
+impl GasCosts {
+    pub fn new() -> Self {
+        GasCosts {
+            callback_cost: DEFAULT_CALLBACK_COST,
+            interaction_cost: 0,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.callback_cost > 0
+    }
+}
+
+/// Start an interaction with the action of `contract` with the given shortname, sent with the
+/// configured interaction cost. Its arguments are added to the returned builder.
+pub fn interaction<'a>(
+    event_group: &'a mut EventGroupBuilder,
+    costs: &GasCosts,
+    contract: Address,
+    shortname: u32,
+) -> InteractionBuilder<'a> {
+    let interaction = event_group.call(contract, Shortname::from_u32(shortname));
+    if costs.interaction_cost > 0 {
+        interaction.with_cost(costs.interaction_cost)
+    } else {
+        interaction
+    }
+}
+
+/// Transfer `amount` of the contract's own tokens to `to`.
+pub fn transfer(
+    event_group: &mut EventGroupBuilder,
+    costs: &GasCosts,
+    token: Address,
+    to: Address,
+    amount: u64,
+) {
+    interaction(event_group, costs, token, TRANSFER)
+        .argument(to)
+        .argument(amount as u128)
+        .done();
+}
+
+/// Transfer `amount` tokens from `from` to `to`, using the allowance `from` gave the contract.
+pub fn transfer_from(
+    event_group: &mut EventGroupBuilder,
+    costs: &GasCosts,
+    token: Address,
+    from: Address,
+    to: Address,
+    amount: u64,
+) {
+    interaction(event_group, costs, token, TRANSFER_FROM)
+        .argument(from)
+        .argument(to)
+        .argument(amount as u128)
+        .done();
+}
+
+/// Allow `spender` to transfer up to `amount` of the contract's own tokens.
+pub fn approve(
+    event_group: &mut EventGroupBuilder,
+    costs: &GasCosts,
+    token: Address,
+    spender: Address,
+    amount: u64,
+) {
+    interaction(event_group, costs, token, APPROVE)
+        .argument(spender)
+        .argument(amount as u128)
+        .done();
+}
+
+/// Burn `amount` of the contract's own tokens through the token's non-standard burn action with
+/// the given shortname.
+pub fn burn(
+    event_group: &mut EventGroupBuilder,
+    costs: &GasCosts,
+    token: Address,
+    burn_shortname: u32,
+    amount: u64,
+) {
+    interaction(event_group, costs, token, burn_shortname)
+        .argument(amount as u128)
+        .done();
+}
+
+/// Query the allowance `owner` gave `spender` through the token's non-standard allowance action
+/// with the given shortname, which returns the allowance as a `u128`. Allowance queries are not
+/// part of MPC-20, so the operator only enables them for tokens known to support them.
+pub fn query_allowance(
+    event_group: &mut EventGroupBuilder,
+    costs: &GasCosts,
+    token: Address,
+    allowance_shortname: u32,
+    owner: Address,
+    spender: Address,
+) {
+    interaction(event_group, costs, token, allowance_shortname)
+        .argument(owner)
+        .argument(spender)
+        .done();
+}
+
+/// Parse the allowance returned by `query_allowance`, zero if nothing was returned.
+pub fn parse_allowance(return_data: &[u8]) -> u128 {
+    match return_data.get(..16) {
+        Some(bytes) => u128::from_be_bytes(bytes.try_into().unwrap()),
+        None => 0,
+    }
+}
+
+/// Register the contract's callback with the given shortname to run once the interactions of the
+/// event group have completed. The callback's arguments are added to the returned builder.
+pub fn register_callback<'a>(
+    event_group: &'a mut EventGroupBuilder,
+    costs: &GasCosts,
+    callback: u32,
+) -> CallbackBuilder<'a> {
+    event_group
+        .with_callback(ShortnameCallback::new(Shortname::from_u32(callback)))
+        .with_cost(costs.callback_cost)
+}
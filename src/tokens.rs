@@ -0,0 +1,56 @@
+//! Registry of tokens accepted for bets, each with its own limits and bankroll.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use read_write_rpc_derive::ReadWriteRPC;
+use read_write_state_derive::ReadWriteState;
+
+/// Configuration of a token accepted for bets.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+pub struct TokenConfig {
+    /// Smallest accepted bet.
+    pub min_bet: u64,
+    /// Largest accepted bet.
+    pub max_bet: u64,
+    /// House capital in this token. The bankroll of the contract's default token is kept in
+    /// `house_bankroll` instead.
+    pub bankroll: u64,
+}
+
+impl TokenConfig {
+    pub fn new(min_bet: u64, max_bet: u64) -> Self {
+        TokenConfig {
+            min_bet,
+            max_bet,
+            bankroll: 0,
+        }
+    }
+}
+
+/// Burning of a share of the losing stakes in the contract's default token.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+pub struct BurnConfig {
+    /// Share of each losing stake that is burned, in basis points.
+    pub share_bps: u32,
+    /// Shortname of the token contract's burn action, taking the amount to burn. Burning is not
+    /// part of MPC-20, so the operator only enables it for tokens known to support it.
+    pub burn_shortname: u32,
+}
+
+/// Rate at which winnings of bets staked in one token are converted into their payout token.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+pub struct ConversionRate {
+    pub payout_token: Address,
+    /// Payout token units per `denominator` stake token units.
+    pub numerator: u64,
+    pub denominator: u64,
+    /// Oracle allowed to update the rate, if it is not fixed.
+    pub oracle: Option<Address>,
+}
+
+impl ConversionRate {
+    /// Convert an amount of the stake token into the payout token, rounding down.
+    pub fn convert(&self, amount: u64) -> u64 {
+        (amount as u128 * self.numerator as u128 / self.denominator as u128) as u64
+    }
+}
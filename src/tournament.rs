@@ -0,0 +1,105 @@
+//! Tournaments: players pay an entry fee to register, every game they win while the tournament
+//! runs scores a point, and the pooled entry fees are split among the best scorers at the close.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use pbc_contract_common::sorted_vec_map::SortedVecMap;
+use read_write_rpc_derive::ReadWriteRPC;
+use read_write_state_derive::ReadWriteState;
+
+/// A tournament.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct Tournament {
+    pub id: u32,
+    pub entry_fee: u64,
+    /// Block production time from which wins are scored.
+    pub starts_at: i64,
+    /// Block production time after which registration and scoring stop and prizes can be paid.
+    pub closes_at: i64,
+    /// Share of the prize pool won by each rank, best first, in basis points.
+    pub prize_split_bps: Vec<u32>,
+    /// Points of every registered player.
+    pub scores: SortedVecMap<Address, u64>,
+    pub prize_pool: u64,
+    pub settled: bool,
+}
+
+/// A prize paid at the close of a tournament.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+pub struct TournamentPrize {
+    pub player: Address,
+    pub amount: u64,
+}
+
+impl Tournament {
+    /// Open a new tournament.
+    pub fn new(
+        id: u32,
+        entry_fee: u64,
+        starts_at: i64,
+        closes_at: i64,
+        prize_split_bps: Vec<u32>,
+    ) -> Self {
+        Tournament {
+            id,
+            entry_fee,
+            starts_at,
+            closes_at,
+            prize_split_bps,
+            scores: SortedVecMap::new(),
+            prize_pool: 0,
+            settled: false,
+        }
+    }
+
+    /// Whether the tournament is running at the given time.
+    pub fn is_running(&self, now: i64) -> bool {
+        !self.settled && self.starts_at <= now && now < self.closes_at
+    }
+
+    /// Whether the player has registered.
+    pub fn is_registered(&self, player: &Address) -> bool {
+        self.scores.contains_key(player)
+    }
+
+    /// Register a player whose entry fee has been escrowed.
+    pub fn register(&mut self, player: Address) {
+        self.scores.insert(player, 0);
+        self.prize_pool += self.entry_fee;
+    }
+
+    /// Score a win of a registered player.
+    pub fn record_win(&mut self, player: &Address) {
+        if let Some(points) = self.scores.get_mut(player) {
+            *points += 1;
+        }
+    }
+
+    /// Rank the players and split the prize pool among the best of them. Players with equal
+    /// points are ranked by address.
+    ///
+    /// ### Returns:
+    ///
+    /// The prizes, and the part of the pool not won by anyone.
+    pub fn settle(&mut self) -> (Vec<TournamentPrize>, u64) {
+        let mut ranking: Vec<(Address, u64)> = self
+            .scores
+            .iter()
+            .map(|(player, points)| (*player, *points))
+            .collect();
+        ranking.sort_by(|a, b| b.1.cmp(&a.1));
+
+        let prizes: Vec<TournamentPrize> = ranking
+            .iter()
+            .zip(self.prize_split_bps.iter())
+            .map(|((player, _), share_bps)| TournamentPrize {
+                player: *player,
+                amount: (self.prize_pool as u128 * *share_bps as u128 / 10_000) as u64,
+            })
+            .filter(|prize| prize.amount > 0)
+            .collect();
+        let paid: u64 = prizes.iter().map(|prize| prize.amount).sum();
+        self.settled = true;
+        (prizes, self.prize_pool - paid)
+    }
+}
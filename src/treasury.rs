@@ -0,0 +1,49 @@
+//! Revenue share with an external treasury contract.
+//!
+//! Fees the house earns in the default token, namely abandonment and cancellation fees and storage
+//! rent, are tallied as they accrue to the bankroll. Sweeping forwards the tallied fees from the
+//! bankroll to the treasury, when a treasurer asks for it or from `keeper_tick` once they reach
+//! the configured threshold.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use read_write_rpc_derive::ReadWriteRPC;
+use read_write_state_derive::ReadWriteState;
+
+/// The treasury contract fees are swept to.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+pub struct TreasuryConfig {
+    pub treasury_address: Address,
+    /// Unswept fees at which `keeper_tick` sweeps them. Zero leaves sweeping to treasurers.
+    pub sweep_threshold: u64,
+}
+
+/// Running totals of the fees owed to the treasury.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct FeeSweeps {
+    /// Fees earned since they were last swept, still held in the bankroll.
+    pub unswept: u64,
+    /// Fees being transferred to the treasury.
+    pub in_flight: u64,
+    pub swept_total: u64,
+}
+
+impl FeeSweeps {
+    pub fn new() -> Self {
+        FeeSweeps {
+            unswept: 0,
+            in_flight: 0,
+            swept_total: 0,
+        }
+    }
+
+    /// Settle a sweep of `amount` once its transfer completed. A failed sweep is owed again.
+    pub fn settle(&mut self, amount: u64, succeeded: bool) {
+        self.in_flight -= amount;
+        if succeeded {
+            self.swept_total += amount;
+        } else {
+            self.unswept += amount;
+        }
+    }
+}
@@ -0,0 +1,40 @@
+//! Vault for winnings that are never claimed.
+//!
+//! While a vault is configured, every won game gets a claim deadline when it is settled. The
+//! winner can claim as usual until then; afterwards anyone can sweep what the game still owes
+//! with `sweep_unclaimed`, moving it into the house treasury or to a charity address, so
+//! forgotten winnings do not stay held on behalf of players forever.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use read_write_rpc_derive::ReadWriteRPC;
+use read_write_state_derive::ReadWriteState;
+
+use crate::contract::CoinFlipState;
+
+/// How long winnings stay claimable and where they go afterwards.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+pub struct UnclaimedVaultConfig {
+    /// Time after settlement a winner has to claim their winnings.
+    pub claim_window_millis: i64,
+    /// Address the swept winnings are transferred to, or `None` to collect them as house fees,
+    /// owed to the treasury while one is set.
+    pub beneficiary: Option<Address>,
+}
+
+impl CoinFlipState {
+    /// Start the claim window of the player's won game, if a vault is configured.
+    pub(crate) fn start_claim_window(&mut self, player: &Address, now: i64) {
+        if let Some(vault) = &self.unclaimed_vault {
+            let deadline = now.saturating_add(vault.claim_window_millis);
+            self.claim_deadlines.insert(*player, deadline);
+        }
+    }
+
+    /// Whether the claim window of the player's won game has passed.
+    pub(crate) fn claim_window_passed(&self, player: &Address, now: i64) -> bool {
+        self.claim_deadlines
+            .get(player)
+            .is_some_and(|deadline| now >= deadline)
+    }
+}
@@ -0,0 +1,417 @@
+//! Upgrades of the contract code. Only the owner may upgrade, and with a time lock set the upgrade
+//! must be scheduled at least the time lock in advance, giving players time to withdraw their
+//! balances if they disagree with it.
+//!
+//! The state is carried over by `migrate`. When a release changes the layout of `CoinFlipState`,
+//! the layout it replaces is kept here under a versioned name as the input of the migration.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use pbc_contract_common::avl_tree_map::AvlTreeMap;
+use pbc_contract_common::sorted_vec_map::SortedVecMap;
+use pbc_contract_common::Hash;
+use pbc_traits::ReadWriteState;
+use read_write_state_derive::ReadWriteState;
+
+use crate::bankroll::YieldAdapter;
+use crate::changes::ChangeLog;
+use crate::cleanup::{CleanupConfig, SettlementQueue};
+use crate::contract::{
+    AbandonPenaltyConfig, AbandonRecord, CoinFlipState, MatchScore, PlayerStats, PracticeStats,
+    RideStreak, StorageAccount, StorageRentConfig, Tombstone, DEFAULT_MAX_SENDER_CONTRIBUTIONS,
+};
+use crate::custody::CustodyAccount;
+use crate::deadlines::PhaseTimeouts;
+use crate::engine::{
+    BetKind, DicePrediction, GamePhase, MultiFlipPrediction, PlayerBet, PlayerChoice,
+};
+use crate::epoch::{EpochArchiveConfig, EpochState};
+use crate::gambling_limits::PlayerLimits;
+use crate::history::GameHistory;
+use crate::import::ImportStats;
+use crate::instant::{InstantConfig, InstantEntropy};
+use crate::ledger::EscrowLedger;
+use crate::insurance::InsuranceFund;
+use crate::lottery::Lottery;
+use crate::loss_insurance::LossInsuranceTotals;
+use crate::notary::NotaryConfig;
+use crate::pause::PauseState;
+use crate::promo::Promotion;
+use crate::providers::ProviderRegistry;
+use crate::queue::ComputeQueue;
+use crate::roles::Role;
+use crate::rps::{RpsGame, RpsStatus, RPS_THROW_TIMEOUT_MILLIS};
+use crate::seasons::SeasonState;
+use crate::side_bets::SideBetPool;
+use crate::staking::StakingPool;
+use crate::streak_cover::{StreakCover, StreakCoverConfig, StreakCoverPool};
+use crate::sunset::Sunset;
+use crate::throttle::BetThrottle;
+use crate::token_client::GasCosts;
+use crate::tokens::{BurnConfig, ConversionRate, TokenConfig};
+use crate::tournament::Tournament;
+use crate::treasury::FeeSweeps;
+use crate::webhooks::{Notification, WebhookTarget};
+
+/// Version of the state layout defined by this release.
+pub const STATE_VERSION: u32 = 2;
+
+/// Time lock of upgrades and the upgrade scheduled under it.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone)]
+pub struct UpgradeSchedule {
+    /// How long in advance an upgrade must be scheduled; zero allows upgrades at any time.
+    pub timelock_millis: i64,
+    /// When the scheduled upgrade was scheduled.
+    pub scheduled_at: Option<i64>,
+}
+
+impl UpgradeSchedule {
+    pub fn new() -> Self {
+        UpgradeSchedule {
+            timelock_millis: 0,
+            scheduled_at: None,
+        }
+    }
+
+    /// Whether an upgrade may happen at `now`.
+    pub fn is_allowed(&self, now: i64) -> bool {
+        if self.timelock_millis == 0 {
+            return true;
+        }
+        self.scheduled_at
+            .is_some_and(|scheduled_at| now >= scheduled_at.saturating_add(self.timelock_millis))
+    }
+}
+
+/// Bet layout of version 1, before bets could name a beneficiary.
+#[derive(ReadWriteState)]
+pub struct PlayerBetV1 {
+    pub amount: u64,
+    pub choice: Option<PlayerChoice>,
+    pub secret_amount: bool,
+    pub multi_flip: Option<MultiFlipPrediction>,
+    pub dice: Option<DicePrediction>,
+    pub roulette: Option<BetKind>,
+}
+
+impl PlayerBetV1 {
+    /// The bet in the current layout, paying its winnings to the player.
+    fn migrate(self) -> PlayerBet {
+        PlayerBet {
+            amount: self.amount,
+            choice: self.choice,
+            secret_amount: self.secret_amount,
+            multi_flip: self.multi_flip,
+            dice: self.dice,
+            roulette: self.roulette,
+            beneficiary: None,
+        }
+    }
+}
+
+/// State layout of version 1, which kept the per-player maps in `SortedVecMap`s.
+#[derive(ReadWriteState)]
+pub struct CoinFlipStateV1 {
+    pub player_bets: SortedVecMap<Address, PlayerBetV1>,
+    pub flip_results: SortedVecMap<Address, bool>,
+    pub winners: SortedVecMap<Address, Address>,
+    pub user_balances: SortedVecMap<Address, u64>,
+    pub game_phases: SortedVecMap<Address, GamePhase>,
+    pub token_address: Address,
+    pub tombstones: SortedVecMap<Address, Tombstone>,
+    pub owner: Address,
+    pub pending_owner: Option<Address>,
+    pub role_grants: SortedVecMap<Address, Vec<Role>>,
+    pub storage_rent: Option<StorageRentConfig>,
+    pub storage_accounts: SortedVecMap<Address, StorageAccount>,
+    pub collected_storage_rent: u64,
+    pub epoch: EpochState,
+    pub epoch_archive: Option<EpochArchiveConfig>,
+    pub secret_bet_refunds: SortedVecMap<Address, u64>,
+    pub multi_flip_results: SortedVecMap<Address, u32>,
+    pub dice_rolls: SortedVecMap<Address, u8>,
+    pub roulette_spins: SortedVecMap<Address, u8>,
+    pub dice_house_edge_bps: u32,
+    pub strict_fairness: bool,
+    pub round_contributors: SortedVecMap<Address, Vec<Address>>,
+    pub lottery: Option<Lottery>,
+    pub ride_streaks: SortedVecMap<Address, RideStreak>,
+    pub max_consecutive_rides: u32,
+    pub rounds_started: u64,
+    pub round_salts: SortedVecMap<Address, Hash>,
+    pub matches: SortedVecMap<Address, MatchScore>,
+    pub abandon_penalty: Option<AbandonPenaltyConfig>,
+    pub abandonments: SortedVecMap<Address, AbandonRecord>,
+    pub collected_penalty_fees: u64,
+    pub rps_games: SortedVecMap<u64, RpsGame>,
+    pub next_rps_game_id: u64,
+    pub vip_players: Vec<Address>,
+    pub compute_queue: ComputeQueue,
+    pub jackpot_rake_bps: u32,
+    pub jackpot_pool: u64,
+    pub recent_changes: ChangeLog,
+    pub pending_withdrawals: SortedVecMap<Address, u64>,
+    pub computation_pending: SortedVecMap<Address, bool>,
+    pub house_bankroll: u64,
+    pub yield_adapter: Option<YieldAdapter>,
+    pub token_registry: SortedVecMap<Address, TokenConfig>,
+    pub bet_tokens: SortedVecMap<Address, Address>,
+    pub insurance: InsuranceFund,
+    pub practice_choices: SortedVecMap<Address, PlayerChoice>,
+    pub practice_stats: SortedVecMap<Address, PracticeStats>,
+    pub notary: Option<NotaryConfig>,
+    pub notarization_outbox: Vec<Hash>,
+    pub max_exposure_bps: u32,
+    pub outstanding_payouts: SortedVecMap<Address, u64>,
+    pub bet_exposures: SortedVecMap<Address, u64>,
+    pub conversion_rates: SortedVecMap<Address, Vec<ConversionRate>>,
+    pub payout_tokens: SortedVecMap<Address, Address>,
+    pub converted_winnings: SortedVecMap<Address, u64>,
+    pub game_history: GameHistory,
+    pub burn: Option<BurnConfig>,
+    pub pending_burn: u64,
+    pub player_stats: SortedVecMap<Address, PlayerStats>,
+    pub streak_cover: Option<StreakCoverConfig>,
+    pub streak_cover_pool: StreakCoverPool,
+    pub covered_bets: SortedVecMap<Address, StreakCover>,
+    pub custody_accounts: SortedVecMap<Address, CustodyAccount>,
+    pub tournament: Option<Tournament>,
+    pub referrers: SortedVecMap<Address, Address>,
+    pub referral_commission_bps: u32,
+    pub referral_rewards: SortedVecMap<Address, u64>,
+    pub loyalty_points: SortedVecMap<Address, u64>,
+    pub points_per_free_bet_credit: u64,
+    pub free_bet_credits: SortedVecMap<Address, u64>,
+    pub free_bets: SortedVecMap<Address, bool>,
+    pub max_round_contributions: u32,
+    pub allowlist_enabled: bool,
+    pub allowlist: SortedVecMap<Address, bool>,
+    pub allowlist_registrar: Option<Address>,
+    pub webhook_targets: Vec<WebhookTarget>,
+    pub large_win_threshold: u64,
+    pub notification_outbox: Vec<Notification>,
+    pub blocked_addresses: SortedVecMap<Address, bool>,
+    pub self_exclusions: SortedVecMap<Address, i64>,
+    pub pause_state: PauseState,
+    pub instant_config: Option<InstantConfig>,
+    pub instant_entropy: Vec<InstantEntropy>,
+    /// Dropped when migrating, as only the removed quick flips read it.
+    pub last_contribution_epochs: SortedVecMap<Address, u32>,
+    pub bankroll_stakes: StakingPool,
+    pub state_version: u32,
+    pub upgrade_schedule: UpgradeSchedule,
+    pub sunset: Option<Sunset>,
+}
+
+/// Carry the state of the previous release over to this one, moving the per-player maps into
+/// `AvlTreeMap`s so actions only load the entries they touch. Games already settled count as
+/// settled at `now` for the retention window of `cleanup`, and rock-paper-scissors games waiting
+/// for throws get the full throw timeout from `now`.
+pub fn migrate(old: CoinFlipStateV1, now: i64) -> CoinFlipState {
+    let mut player_bets = AvlTreeMap::new();
+    for (player, bet) in old.player_bets {
+        player_bets.insert(player, bet.migrate());
+    }
+    let mut settled_at = AvlTreeMap::new();
+    let mut settlement_queue = SettlementQueue::new();
+    for (player, phase) in old.game_phases.iter() {
+        if *phase == (GamePhase::Done {}) {
+            settled_at.insert(*player, now);
+            settlement_queue.push(*player, now);
+        }
+    }
+    let mut rps_throw_deadlines = SortedVecMap::new();
+    for (game_id, game) in old.rps_games.iter() {
+        if game.status == (RpsStatus::Throwing {}) {
+            rps_throw_deadlines.insert(*game_id, now + RPS_THROW_TIMEOUT_MILLIS);
+        }
+    }
+    let user_balance_total = old.user_balances.iter().map(|(_, balance)| *balance).sum();
+    CoinFlipState {
+        player_bets,
+        flip_results: into_avl_tree_map(old.flip_results),
+        winners: into_avl_tree_map(old.winners),
+        user_balances: into_avl_tree_map(old.user_balances),
+        game_phases: into_avl_tree_map(old.game_phases),
+        token_address: old.token_address,
+        tombstones: into_avl_tree_map(old.tombstones),
+        owner: old.owner,
+        pending_owner: old.pending_owner,
+        role_grants: old.role_grants,
+        storage_rent: old.storage_rent,
+        storage_accounts: into_avl_tree_map(old.storage_accounts),
+        collected_storage_rent: old.collected_storage_rent,
+        epoch: old.epoch,
+        epoch_archive: old.epoch_archive,
+        secret_bet_refunds: into_avl_tree_map(old.secret_bet_refunds),
+        multi_flip_results: into_avl_tree_map(old.multi_flip_results),
+        dice_rolls: into_avl_tree_map(old.dice_rolls),
+        roulette_spins: into_avl_tree_map(old.roulette_spins),
+        dice_house_edge_bps: old.dice_house_edge_bps,
+        strict_fairness: old.strict_fairness,
+        round_contributors: into_avl_tree_map(old.round_contributors),
+        lottery: old.lottery,
+        ride_streaks: into_avl_tree_map(old.ride_streaks),
+        max_consecutive_rides: old.max_consecutive_rides,
+        rounds_started: old.rounds_started,
+        round_salts: into_avl_tree_map(old.round_salts),
+        matches: into_avl_tree_map(old.matches),
+        abandon_penalty: old.abandon_penalty,
+        abandonments: into_avl_tree_map(old.abandonments),
+        collected_penalty_fees: old.collected_penalty_fees,
+        rps_games: old.rps_games,
+        next_rps_game_id: old.next_rps_game_id,
+        vip_players: old.vip_players,
+        compute_queue: old.compute_queue,
+        jackpot_rake_bps: old.jackpot_rake_bps,
+        jackpot_pool: old.jackpot_pool,
+        recent_changes: old.recent_changes,
+        pending_withdrawals: into_avl_tree_map(old.pending_withdrawals),
+        computation_pending: into_avl_tree_map(old.computation_pending),
+        house_bankroll: old.house_bankroll,
+        yield_adapter: old.yield_adapter,
+        token_registry: old.token_registry,
+        bet_tokens: into_avl_tree_map(old.bet_tokens),
+        insurance: old.insurance,
+        practice_choices: into_avl_tree_map(old.practice_choices),
+        practice_stats: into_avl_tree_map(old.practice_stats),
+        notary: old.notary,
+        notarization_outbox: old.notarization_outbox,
+        max_exposure_bps: old.max_exposure_bps,
+        outstanding_payouts: old.outstanding_payouts,
+        bet_exposures: into_avl_tree_map(old.bet_exposures),
+        conversion_rates: old.conversion_rates,
+        payout_tokens: into_avl_tree_map(old.payout_tokens),
+        converted_winnings: into_avl_tree_map(old.converted_winnings),
+        game_history: old.game_history,
+        burn: old.burn,
+        pending_burn: old.pending_burn,
+        player_stats: into_avl_tree_map(old.player_stats),
+        streak_cover: old.streak_cover,
+        streak_cover_pool: old.streak_cover_pool,
+        covered_bets: into_avl_tree_map(old.covered_bets),
+        custody_accounts: old.custody_accounts,
+        tournament: old.tournament,
+        referrers: into_avl_tree_map(old.referrers),
+        referral_commission_bps: old.referral_commission_bps,
+        referral_rewards: into_avl_tree_map(old.referral_rewards),
+        loyalty_points: into_avl_tree_map(old.loyalty_points),
+        points_per_free_bet_credit: old.points_per_free_bet_credit,
+        free_bet_credits: into_avl_tree_map(old.free_bet_credits),
+        free_bets: into_avl_tree_map(old.free_bets),
+        max_round_contributions: old.max_round_contributions,
+        allowlist_enabled: old.allowlist_enabled,
+        allowlist: into_avl_tree_map(old.allowlist),
+        allowlist_registrar: old.allowlist_registrar,
+        webhook_targets: old.webhook_targets,
+        large_win_threshold: old.large_win_threshold,
+        notification_outbox: old.notification_outbox,
+        blocked_addresses: into_avl_tree_map(old.blocked_addresses),
+        self_exclusions: into_avl_tree_map(old.self_exclusions),
+        pause_state: old.pause_state,
+        instant_config: old.instant_config,
+        // Values revealed before bets had to wait for them can no longer be bet on
+        instant_entropy: old
+            .instant_entropy
+            .into_iter()
+            .filter(|entropy| entropy.value.is_none())
+            .collect(),
+        bankroll_stakes: old.bankroll_stakes,
+        state_version: STATE_VERSION,
+        upgrade_schedule: UpgradeSchedule {
+            scheduled_at: None,
+            ..old.upgrade_schedule
+        },
+        sunset: old.sunset,
+        settled_at,
+        cleanup_config: CleanupConfig::new(),
+        randomness_providers: ProviderRegistry::new(),
+        attest_results: false,
+        attestation_outbox: vec![],
+        result_attestations: AvlTreeMap::new(),
+        dispute_window_millis: 0,
+        arbiter: None,
+        challenge_deadlines: AvlTreeMap::new(),
+        loss_insurance: None,
+        insured_bets: AvlTreeMap::new(),
+        loss_insurance_totals: LossInsuranceTotals::new(),
+        side_bets: AvlTreeMap::new(),
+        side_bet_pool: SideBetPool::new(),
+        parlays: AvlTreeMap::new(),
+        parlay_house_edge_bps: 0,
+        weighted_coin: None,
+        weighted_bets: AvlTreeMap::new(),
+        bet_nonces: AvlTreeMap::new(),
+        pending_escrows: AvlTreeMap::new(),
+        cancellation_fee_bps: 0,
+        promotion: Promotion::new(),
+        keeper_bounty: 0,
+        sequence_bets: AvlTreeMap::new(),
+        private_updates_pending: AvlTreeMap::new(),
+        allowance_shortname: None,
+        player_views: AvlTreeMap::new(),
+        phase_timeouts: PhaseTimeouts::new(),
+        stage_entered_at: AvlTreeMap::new(),
+        expired_escrows: AvlTreeMap::new(),
+        gas_costs: GasCosts::new(),
+        integrators: SortedVecMap::new(),
+        bet_integrators: AvlTreeMap::new(),
+        sponsorships: SortedVecMap::new(),
+        sponsored_bets: AvlTreeMap::new(),
+        treasury: None,
+        fee_sweeps: FeeSweeps::new(),
+        season: SeasonState::new(0, now),
+        season_archive: AvlTreeMap::new(),
+        bet_throttle: BetThrottle::new(),
+        gambling_limits: AvlTreeMap::new(),
+        ledger: EscrowLedger::new(),
+        bonus_round_enabled: false,
+        bonus_multipliers: AvlTreeMap::new(),
+        brackets: SortedVecMap::new(),
+        next_bracket_id: 0,
+        house_seeds: SortedVecMap::new(),
+        event_sink: None,
+        game_event_outbox: vec![],
+        reserve_winnings: false,
+        max_sender_contributions: DEFAULT_MAX_SENDER_CONTRIBUTIONS,
+        intent_keys: AvlTreeMap::new(),
+        unclaimed_vault: None,
+        claim_deadlines: AvlTreeMap::new(),
+        unclaimed_swept_total: 0,
+        price_oracle: None,
+        usd_prices: SortedVecMap::new(),
+        session_bots: SortedVecMap::new(),
+        bot_sessions: SortedVecMap::new(),
+        session_bets: SortedVecMap::new(),
+        friends_tables: SortedVecMap::new(),
+        next_table_id: 0,
+        table_invites: SortedVecMap::new(),
+        pipeline: None,
+        is_test_mode: false,
+        game_ids: AvlTreeMap::new(),
+        resolved_games: AvlTreeMap::new(),
+        resolved_game_count: 0,
+        bet_charges: AvlTreeMap::new(),
+        sealed_refunds: AvlTreeMap::new(),
+        import: ImportStats::new(false),
+        rps_throw_deadlines,
+        instant_bets: vec![],
+        settlement_queue,
+        play_money_accounts: AvlTreeMap::new(),
+        sealed_credit_losses: AvlTreeMap::new(),
+        sealed_house_losses: 0,
+        imported_users: AvlTreeMap::new(),
+        won_jackpots: AvlTreeMap::new(),
+        user_balance_total,
+    }
+}
+
+fn into_avl_tree_map<K: ReadWriteState + Ord, V: ReadWriteState>(
+    map: SortedVecMap<K, V>,
+) -> AvlTreeMap<K, V> {
+    let mut tree = AvlTreeMap::new();
+    for (key, value) in map {
+        tree.insert(key, value);
+    }
+    tree
+}
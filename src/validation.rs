@@ -0,0 +1,313 @@
+//! Dry-run validation of bets, shared by the bet actions and clients that want to know why a
+//! bet would be rejected before submitting it.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use read_write_rpc_derive::ReadWriteRPC;
+use read_write_state_derive::ReadWriteState;
+
+use crate::contract::CoinFlipState;
+use crate::engine::{
+    winnings_for, BetKind, DicePrediction, GamePhase, MultiFlipPrediction, PlayerBet,
+};
+use crate::errors::{fail, GameError};
+use crate::price_oracle::PriceOracleConfig;
+use crate::zk_compute::{MAX_DICE_FACES, MAX_MULTI_FLIPS};
+
+/// A rule that a bet violates.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, PartialEq, Copy, Clone)]
+#[repr(u8)]
+pub enum BetViolation {
+    /// The bet is not made in the token accepted by the contract.
+    #[discriminant(0)]
+    UnsupportedToken {},
+    /// The bet amount is zero.
+    #[discriminant(1)]
+    ZeroAmount {},
+    /// The multi-flip prediction has too many flips or an unsatisfiable number of matches.
+    #[discriminant(2)]
+    InvalidMultiFlip {},
+    /// The die has fewer than two or more than `MAX_DICE_FACES` faces, the prediction is not one
+    /// of them, or the house edge leaves no payout.
+    #[discriminant(3)]
+    InvalidDice {},
+    /// The roulette bet is on a pocket that does not exist.
+    #[discriminant(4)]
+    InvalidRouletteNumber {},
+    /// The player is cooling down after abandoning too many games.
+    #[discriminant(5)]
+    AbandonCooldown {},
+    /// The bet is smaller than the minimum bet of its token.
+    #[discriminant(6)]
+    BelowMinimumBet {},
+    /// The bet is larger than the maximum bet of its token.
+    #[discriminant(7)]
+    AboveMaximumBet {},
+    /// Allowlist mode is enabled and the player is not on the allowlist.
+    #[discriminant(8)]
+    NotAllowlisted {},
+    /// The player is blocked by the operator or has excluded themselves from betting.
+    #[discriminant(9)]
+    Banned {},
+    /// The operator has paused new bets.
+    #[discriminant(10)]
+    BettingPaused {},
+    /// The player's previous bet was placed less than the minimum bet interval ago.
+    #[discriminant(11)]
+    BetTooSoon {},
+    /// The block has already accepted as many bets as it may.
+    #[discriminant(12)]
+    BlockBetCapReached {},
+    /// The bet breaks a gambling limit the player set on themselves, or they are cooling off.
+    #[discriminant(13)]
+    GamblingLimitReached {},
+    /// The USD value of the bet is below the minimum bet of the price oracle configuration.
+    #[discriminant(14)]
+    BelowMinimumUsdBet {},
+    /// The USD value of the bet is above the maximum bet of the price oracle configuration.
+    #[discriminant(15)]
+    AboveMaximumUsdBet {},
+    /// A price oracle is configured and has never quoted the token, so the bet's USD value is
+    /// unknown.
+    #[discriminant(16)]
+    UnquotedToken {},
+    /// The player's previous game is open to disputes until its dispute window passes.
+    #[discriminant(17)]
+    PreviousGameDisputable {},
+    /// The player's previous game owes them a payout that is not claimed yet.
+    #[discriminant(18)]
+    UnclaimedPayout {},
+    /// The potential payout of the bet would exceed the house's exposure limit.
+    #[discriminant(19)]
+    ExposureLimitReached {},
+    /// The house bankroll cannot reserve the potential payout of the bet.
+    #[discriminant(20)]
+    InsufficientBankroll {},
+}
+
+impl BetViolation {
+    /// Whether the violated rule is a USD bet limit of the price oracle configuration.
+    pub fn is_usd_limit(&self) -> bool {
+        matches!(
+            self,
+            BetViolation::BelowMinimumUsdBet {}
+                | BetViolation::AboveMaximumUsdBet {}
+                | BetViolation::UnquotedToken {}
+        )
+    }
+
+    /// Human readable description of the violated rule.
+    pub fn describe(&self) -> String {
+        let description = match self {
+            BetViolation::UnsupportedToken {} => "The token is not accepted for bets.",
+            BetViolation::ZeroAmount {} => "The bet amount must be positive.",
+            BetViolation::InvalidMultiFlip {} => {
+                return format!(
+                    "A multi-flip bet needs 1 to {MAX_MULTI_FLIPS} flips and 1 to that many \
+                     matches."
+                );
+            }
+            BetViolation::InvalidDice {} => {
+                return format!(
+                    "A dice bet needs 2 to {MAX_DICE_FACES} faces and a prediction between 1 and \
+                     the faces."
+                );
+            }
+            BetViolation::InvalidRouletteNumber {} => "Roulette numbers range from 0 to 36.",
+            BetViolation::AbandonCooldown {} => {
+                "Betting is paused after abandoning too many games. Try again later."
+            }
+            BetViolation::BelowMinimumBet {} => "The bet is below the minimum bet of the token.",
+            BetViolation::AboveMaximumBet {} => "The bet is above the maximum bet of the token.",
+            BetViolation::NotAllowlisted {} => "The player is not on the allowlist.",
+            BetViolation::Banned {} => "The player is banned from betting.",
+            BetViolation::BettingPaused {} => "New bets are paused.",
+            BetViolation::BetTooSoon {} => "Bets are throttled. Wait before betting again.",
+            BetViolation::BlockBetCapReached {} => {
+                "The block has reached its bet limit. Try again in a later block."
+            }
+            BetViolation::GamblingLimitReached {} => {
+                "The bet exceeds the gambling limits set by the player."
+            }
+            BetViolation::BelowMinimumUsdBet {} => "The bet is below the minimum bet in USD.",
+            BetViolation::AboveMaximumUsdBet {} => "The bet is above the maximum bet in USD.",
+            BetViolation::UnquotedToken {} => {
+                "The token has no USD price yet. Bet with start_game_and_place_bet to quote it."
+            }
+            BetViolation::PreviousGameDisputable {} => {
+                "The previous game is open to disputes until its dispute window passes."
+            }
+            BetViolation::UnclaimedPayout {} => {
+                "The payout of the previous game must be claimed before placing a new bet."
+            }
+            BetViolation::ExposureLimitReached {} => {
+                "The bet would exceed the house's exposure limit."
+            }
+            BetViolation::InsufficientBankroll {} => {
+                "The house bankroll cannot reserve the potential payout of the bet."
+            }
+        };
+        description.to_string()
+    }
+}
+
+impl CoinFlipState {
+    /// Check a hypothetical bet staked in `token` and paid out in `payout_token` against every
+    /// rule the bet actions enforce, without mutating anything. The exposure of a bet paid out in
+    /// another token than its stake is only checked once the bet is placed, at the conversion
+    /// rate of that time.
+    ///
+    /// ### Returns:
+    ///
+    /// All violated rules; the bet would be accepted if the list is empty.
+    pub fn validate_bet(
+        &self,
+        player: &Address,
+        bet: &PlayerBet,
+        token: &Address,
+        payout_token: &Address,
+        now: i64,
+    ) -> Vec<BetViolation> {
+        let amount = bet.amount;
+        let mut violations = vec![];
+        if !self.pause_state.accepts_bets() {
+            violations.push(BetViolation::BettingPaused {});
+        }
+        if amount == 0 {
+            violations.push(BetViolation::ZeroAmount {});
+        }
+        match self.token_registry.get(token) {
+            None => violations.push(BetViolation::UnsupportedToken {}),
+            Some(config) => {
+                if amount > 0 && amount < config.min_bet {
+                    violations.push(BetViolation::BelowMinimumBet {});
+                }
+                if amount > config.max_bet {
+                    violations.push(BetViolation::AboveMaximumBet {});
+                }
+            }
+        }
+        violations.extend(self.usd_limit_violations(token, amount));
+        if !self.is_allowlisted(player) {
+            violations.push(BetViolation::NotAllowlisted {});
+        }
+        if self.is_banned(player, now) {
+            violations.push(BetViolation::Banned {});
+        }
+        if let Some(record) = self.abandonments.get(player) {
+            if now < record.cooldown_until {
+                violations.push(BetViolation::AbandonCooldown {});
+            }
+        }
+        if self.bet_throttle.is_too_soon(player, now) {
+            violations.push(BetViolation::BetTooSoon {});
+        }
+        if self.bet_throttle.is_block_full(now) {
+            violations.push(BetViolation::BlockBetCapReached {});
+        }
+        if !self.within_gambling_limits(player, amount, now) {
+            violations.push(BetViolation::GamblingLimitReached {});
+        }
+        violations.extend(self.previous_game_violations(player, now));
+        if payout_token == token {
+            let potential_payout = winnings_for(bet, self.dice_house_edge_bps);
+            violations.extend(self.exposure_violations(player, token, potential_payout));
+        }
+        violations
+    }
+
+    /// Check that the player's previous game can be cleared for a new bet: it is past its dispute
+    /// window, and its payout has been claimed.
+    fn previous_game_violations(&self, player: &Address, now: i64) -> Vec<BetViolation> {
+        let phase = self.game_phases.get(player).unwrap_or(GamePhase::Start {});
+        let disputable = phase == GamePhase::Challengeable {}
+            && !self
+                .challenge_deadlines
+                .get(player)
+                .is_some_and(|deadline| now >= deadline);
+        let mut violations = vec![];
+        if disputable {
+            violations.push(BetViolation::PreviousGameDisputable {});
+        }
+        if self.has_unclaimed_payout(player) {
+            violations.push(BetViolation::UnclaimedPayout {});
+        }
+        violations
+    }
+
+    /// Check that the house can take on `potential_payout` in `token`, as `reserve_exposure` will
+    /// once the bet is placed. The exposure of the player's previous game is released by then.
+    fn exposure_violations(
+        &self,
+        player: &Address,
+        token: &Address,
+        potential_payout: u64,
+    ) -> Vec<BetViolation> {
+        let released = if self.payout_token_of(player) == *token {
+            self.bet_exposures.get(player).unwrap_or(0)
+        } else {
+            0
+        };
+        let outstanding = self.outstanding_payouts.get(token).copied().unwrap_or(0);
+        let exposure = outstanding.saturating_sub(released) as u128 + potential_payout as u128;
+        let mut violations = vec![];
+        if self.max_exposure_bps > 0 {
+            let limit = self.bankroll_of(token) as u128 * self.max_exposure_bps as u128 / 10_000;
+            if exposure > limit {
+                violations.push(BetViolation::ExposureLimitReached {});
+            }
+        }
+        if self.reserve_winnings && exposure > self.idle_bankroll(token) as u128 {
+            violations.push(BetViolation::InsufficientBankroll {});
+        }
+        violations
+    }
+}
+
+/// Check the USD value of a bet, in micro-USD, against the USD bet limits.
+pub fn validate_usd_limits(config: &PriceOracleConfig, usd_amount: u64) -> Vec<BetViolation> {
+    if usd_amount < config.min_bet_usd {
+        vec![BetViolation::BelowMinimumUsdBet {}]
+    } else if usd_amount > config.max_bet_usd {
+        vec![BetViolation::AboveMaximumUsdBet {}]
+    } else {
+        vec![]
+    }
+}
+
+/// Check that a multi-flip prediction can be computed and won.
+pub fn validate_multi_flip(prediction: &MultiFlipPrediction) -> Vec<BetViolation> {
+    let valid_flips = (1..=MAX_MULTI_FLIPS).contains(&prediction.flips);
+    let valid_matches = (1..=prediction.flips).contains(&prediction.min_matches);
+    if valid_flips && valid_matches {
+        vec![]
+    } else {
+        vec![BetViolation::InvalidMultiFlip {}]
+    }
+}
+
+/// Check that a dice prediction can be rolled and won.
+pub fn validate_dice(prediction: &DicePrediction, house_edge_bps: u32) -> Vec<BetViolation> {
+    let valid_faces = (2..=MAX_DICE_FACES).contains(&prediction.faces);
+    let valid_prediction = (1..=prediction.faces).contains(&prediction.predicted);
+    if valid_faces && valid_prediction && house_edge_bps < 10_000 {
+        vec![]
+    } else {
+        vec![BetViolation::InvalidDice {}]
+    }
+}
+
+/// Check that a roulette bet is on an existing pocket.
+pub fn validate_roulette(kind: &BetKind) -> Vec<BetViolation> {
+    match kind {
+        BetKind::Number { number } if *number > 36 => vec![BetViolation::InvalidRouletteNumber {}],
+        _ => vec![],
+    }
+}
+
+/// Panic with the description of the first violated rule, if any.
+pub fn assert_no_violations(violations: &[BetViolation]) {
+    if let Some(violation) = violations.first() {
+        fail!(GameError::BetRejected, "{}", violation.describe());
+    }
+}
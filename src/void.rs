@@ -0,0 +1,81 @@
+//! Charges taken when a bet is placed, and their reversal when its game is voided.
+//!
+//! Placing a bet may take an abandonment fee from the stake, split between the insurance fund
+//! and the house, and rake a share of it into the jackpot; escrowing it may pay the player's
+//! referrer a commission from the house. A voided game, whether it expired or was called off by
+//! an operator, reverses each of these movements instead of refunding only the stake left after
+//! them. What was already spent, e.g. a jackpot paid out or a commission claimed, can no longer be
+//! reversed, so the refund may fall short of the full bet.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use read_write_state_derive::ReadWriteState;
+
+use crate::contract::CoinFlipState;
+
+/// Amounts moved out of a bet's stake, or on its account, when it was placed and escrowed.
+#[derive(ReadWriteState, CreateTypeSpec, Debug, Clone, Default)]
+pub struct BetCharges {
+    /// Share of the abandonment fee allocated to the insurance fund.
+    pub insurance_share: u64,
+    /// Rest of the abandonment fee, kept by the house in the bet's token.
+    pub house_fee: u64,
+    /// Share of the stake raked into the jackpot.
+    pub jackpot_rake: u64,
+    /// Commission paid from the house bankroll to the player's referrer.
+    pub referral_commission: u64,
+}
+
+impl CoinFlipState {
+    /// Record the commission paid to the referrer of the player's current bet.
+    pub(crate) fn record_referral_commission(&mut self, player: &Address, commission: u64) {
+        if let Some(mut charges) = self.bet_charges.get(player) {
+            charges.referral_commission += commission;
+            self.bet_charges.insert(*player, charges);
+        }
+    }
+
+    /// Reverse the charges of the player's current bet as far as they can be.
+    ///
+    /// ### Returns:
+    ///
+    /// The amount taken from the stake that was recovered and is owed back to the player.
+    pub(crate) fn reverse_bet_charges(&mut self, player: &Address) -> u64 {
+        let Some(charges) = self.bet_charges.get(player) else {
+            return 0;
+        };
+        self.bet_charges.remove(player);
+
+        let insurance_share = charges.insurance_share.min(self.insurance.balance);
+        self.insurance.balance -= insurance_share;
+        self.insurance.total_allocated -= insurance_share;
+
+        let token = self.bet_tokens.get(player).unwrap_or(self.token_address);
+        let house_fee = if token == self.token_address {
+            let house_fee = charges.house_fee.min(self.house_bankroll);
+            self.house_bankroll -= house_fee;
+            self.collected_penalty_fees = self.collected_penalty_fees.saturating_sub(house_fee);
+            self.fee_sweeps.unswept = self.fee_sweeps.unswept.saturating_sub(house_fee);
+            house_fee
+        } else if let Some(config) = self.token_registry.get_mut(&token) {
+            let house_fee = charges.house_fee.min(config.bankroll);
+            config.bankroll -= house_fee;
+            house_fee
+        } else {
+            0
+        };
+
+        let jackpot_rake = charges.jackpot_rake.min(self.jackpot_pool);
+        self.jackpot_pool -= jackpot_rake;
+
+        // The commission came from the house, so what the referrer has not claimed returns to it
+        if let Some(referrer) = self.referrers.get(player) {
+            let rewards = self.referral_rewards.get(&referrer).unwrap_or(0);
+            let reclaimed = charges.referral_commission.min(rewards);
+            self.referral_rewards.insert(referrer, rewards - reclaimed);
+            self.house_bankroll += reclaimed;
+        }
+
+        insurance_share + house_fee + jackpot_rake
+    }
+}
@@ -0,0 +1,81 @@
+//! Registry of outbound notification targets, such as oracle or relay contracts feeding
+//! off-chain alerting. Each target subscribes to kinds of notifications with a filter bitmask,
+//! so alerting can be reconfigured without redeploying the contract.
+
+use create_type_spec_derive::CreateTypeSpec;
+use pbc_contract_common::address::Address;
+use pbc_contract_common::events::EventGroup;
+use read_write_rpc_derive::ReadWriteRPC;
+use read_write_state_derive::ReadWriteState;
+
+use crate::token_client::{self, GasCosts};
+
+/// Filter bit of `Notification::LargeWin`.
+pub const NOTIFY_LARGE_WIN: u32 = 1 << 0;
+/// Filter bit of `Notification::CircuitBreaker`.
+pub const NOTIFY_CIRCUIT_BREAKER: u32 = 1 << 1;
+/// Filter bit of `Notification::Sunset`.
+pub const NOTIFY_SUNSET: u32 = 1 << 2;
+
+/// A contract receiving notifications.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+pub struct WebhookTarget {
+    pub target_address: Address,
+    /// Shortname of the target's action accepting a single `Notification`.
+    pub target_shortname: u32,
+    /// Kinds of notifications forwarded to the target, as `NOTIFY_*` bits.
+    pub event_filter: u32,
+}
+
+/// Structured payload forwarded to matching targets.
+#[derive(ReadWriteState, ReadWriteRPC, CreateTypeSpec, Debug, Clone)]
+#[repr(u8)]
+pub enum Notification {
+    /// A player won at least the configured large-win threshold.
+    #[discriminant(0)]
+    LargeWin { player: Address, winnings: u64 },
+    /// Betting was halted or resumed by the operator.
+    #[discriminant(1)]
+    CircuitBreaker { tripped: bool },
+    /// The contract stopped taking bets and is wound down at the given time, after which
+    /// everything it holds is returned.
+    #[discriminant(2)]
+    Sunset { closes_at: i64 },
+}
+
+impl Notification {
+    /// Filter bit targets subscribe to this notification with.
+    pub fn filter_bit(&self) -> u32 {
+        match self {
+            Notification::LargeWin { .. } => NOTIFY_LARGE_WIN,
+            Notification::CircuitBreaker { .. } => NOTIFY_CIRCUIT_BREAKER,
+            Notification::Sunset { .. } => NOTIFY_SUNSET,
+        }
+    }
+}
+
+/// Build the event group forwarding each notification to every target subscribed to it, if any.
+pub fn notification_event(
+    targets: &[WebhookTarget],
+    costs: &GasCosts,
+    notifications: Vec<Notification>,
+) -> Option<EventGroup> {
+    let mut event_group = EventGroup::builder();
+    let mut calls = 0;
+    for notification in notifications {
+        for target in targets
+            .iter()
+            .filter(|target| target.event_filter & notification.filter_bit() != 0)
+        {
+            let (contract, shortname) = (target.target_address, target.target_shortname);
+            token_client::interaction(&mut event_group, costs, contract, shortname)
+                .argument(notification.clone())
+                .done();
+            calls += 1;
+        }
+    }
+    if calls == 0 {
+        return None;
+    }
+    Some(event_group.build())
+}
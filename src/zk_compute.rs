@@ -1,51 +1,789 @@
+use pbc_contract_common::address::Address;
+use pbc_traits::ReadWriteState;
 use pbc_zk::*;
+use read_write_state_derive::ReadWriteState;
 
-/// Output variable type
+/// A randomness contribution, also used to parse the opened flip.
 #[derive(pbc_zk::SecretBinary, Clone)]
 pub struct RandomnessInput {
-    /// Coin flip result.
+    /// The contributed word, or the coin flip result once opened.
+    pub result: Sbi32,
+}
+
+
+/// Output of the coin flip computation.
+#[derive(pbc_zk::SecretBinary, Clone)]
+pub struct CoinFlipOutput {
+    /// Coin flip result, 0 or 1.
     pub result: Sbi8,
+    /// 1 if the folded randomness hit the jackpot pattern, 0 otherwise.
+    pub jackpot: Sbi8,
+    /// 1 if the flip matches the choice committed to the computation, 0 otherwise.
+    pub player_won: Sbi8,
+    /// 1 if the folded randomness triggers a bonus round, 0 otherwise.
+    pub bonus: Sbi8,
 }
 
+/// Lowest byte of the folded randomness that wins the jackpot, a one in 256 chance.
+const JACKPOT_PATTERN: i32 = 0x5A;
+
+/// Second lowest byte of the folded randomness that triggers a bonus round, a one in 256 chance.
+const BONUS_PATTERN: i32 = 0xA5;
+
+/// Largest multiplier drawn in a bonus round.
+pub const BONUS_MAX_MULTIPLIER: u8 = 10;
 
 /// Perform a zk computation on secret-shared randomness to make a random coin flip.
 ///
+/// Contributions are XOR-folded as whole 32-bit words, so the flip is unbiased as long as a single
+/// contribution is uniformly random, whatever the other contributions are.
+///
+/// ### Parameters:
+///
+/// * `salt`: The public salt of the round, see `xor_folded_randomness`. It is unique to the round
+///   and so identifies the game the output belongs to.
+/// * `seed_epoch`: The epoch whose house seed is folded into the round, see `house_seed`.
+/// * `player`: The player whose round is flipped; only contributions to that round are folded.
+/// * `choice_is_heads`: The player's choice, committed to the computation so the output settles
+///   this specific bet.
+///
 /// ### Returns:
 ///
-/// The sum of the randomness contributions variables, reduced to 0 or 1.
+/// The low bit of the XOR of the randomness contributions, whether the lowest folded byte
+/// equals `JACKPOT_PATTERN`, whether the flip matches the choice, and whether the second lowest
+/// folded byte equals `BONUS_PATTERN`.
 #[zk_compute(shortname = 0x61)]
-pub fn compute_coin_flip() -> RandomnessInput 
-{
-    let mut flip = RandomnessInput {
-        result: Sbi8::from(0),
-    };
+pub fn compute_coin_flip(
+    salt: i32,
+    seed_epoch: u32,
+    player: Address,
+    choice_is_heads: bool,
+) -> CoinFlipOutput {
+    let folded = xor_folded_randomness(salt, seed_epoch, player);
+    let is_heads = low_bit(folded);
+    let mut result = Sbi8::from(0);
+    if is_heads {
+        result = Sbi8::from(1);
+    }
+    let mut jackpot = Sbi8::from(0);
+    if (folded & Sbi32::from(0xFF)) == Sbi32::from(JACKPOT_PATTERN) {
+        jackpot = Sbi8::from(1);
+    }
+    let mut player_won = Sbi8::from(0);
+    if is_heads == Sbi1::from(choice_is_heads) {
+        player_won = Sbi8::from(1);
+    }
+    let mut bonus = Sbi8::from(0);
+    if (folded & Sbi32::from(0xFF00)) == Sbi32::from(BONUS_PATTERN << 8) {
+        bonus = Sbi8::from(1);
+    }
+    CoinFlipOutput {
+        result,
+        jackpot,
+        player_won,
+        bonus,
+    }
+}
+
+
+/// Perform a zk computation comparing a player's secret choice with a random coin flip.
+///
+/// ### Parameters:
+///
+/// * `choice_variable`: The secret variable holding the player's `SecretChoice`.
+/// * `salt`: The public salt of the round.
+/// * `seed_epoch`: The epoch whose house seed is folded into the round.
+/// * `player`: The player whose round is computed.
+///
+/// ### Returns:
+///
+/// Whether the player guessed the flip correctly. The flip itself is never revealed.
+#[zk_compute(shortname = 0x62)]
+pub fn compute_secret_choice_outcome(
+    choice_variable: SecretVarId,
+    salt: i32,
+    seed_epoch: u32,
+    player: Address,
+) -> Sbi1 {
+    let choice_is_heads = load_sbi::<Sbi1>(choice_variable);
+    flip_is_heads(salt, seed_epoch, player) == choice_is_heads
+}
+
+/// Perform a zk computation settling a bet with a secret stake.
+///
+/// ### Parameters:
+///
+/// * `bet_variable`: The secret variable holding the player's `SecretBetAmount`.
+/// * `cover`: The publicly escrowed amount the stake is clamped to.
+/// * `choice_is_heads`: The player's public choice.
+/// * `salt`: The public salt of the round.
+/// * `seed_epoch`: The epoch whose house seed is folded into the round.
+/// * `player`: The player whose round is computed.
+///
+/// ### Returns:
+///
+/// The amount to return to the player: the cover plus the stake on a win, or the cover minus
+/// the stake on a loss.
+#[zk_compute(shortname = 0x63)]
+pub fn compute_secret_bet_payout(
+    bet_variable: SecretVarId,
+    cover: i64,
+    choice_is_heads: bool,
+    salt: i32,
+    seed_epoch: u32,
+    player: Address,
+) -> Sbi64 {
+    let cover = Sbi64::from(cover);
+    let mut stake = load_sbi::<Sbi64>(bet_variable);
+    if stake > cover {
+        stake = cover;
+    }
+    if stake < Sbi64::from(0) {
+        stake = Sbi64::from(0);
+    }
+
+    let player_won = flip_is_heads(salt, seed_epoch, player) == Sbi1::from(choice_is_heads);
+    let mut returned = cover - stake;
+    if player_won {
+        returned = returned + stake + stake;
+    }
+    returned
+}
+
+/// Perform a zk computation settling a bet with a sealed stake, revealing nothing of a lost
+/// stake.
+///
+/// ### Parameters:
+///
+/// * `stake_variable`: The secret variable holding the player's `SealedBetAmount`.
+/// * `cover`: The publicly escrowed amount the stake is clamped to.
+/// * `choice_is_heads`: The player's public choice.
+/// * `salt`: The public salt of the round.
+/// * `seed_epoch`: The epoch whose house seed is folded into the round.
+/// * `player`: The player whose round is computed.
+///
+/// ### Returns:
+///
+/// The amount payable to the player: the stake multiplied by the win bit, on top of the cover
+/// returned on a win, so a lost game opens as zero.
+#[zk_compute(shortname = 0x70)]
+pub fn compute_sealed_payout(
+    stake_variable: SecretVarId,
+    cover: i64,
+    choice_is_heads: bool,
+    salt: i32,
+    seed_epoch: u32,
+    player: Address,
+) -> Sbi128 {
+    let cover = Sbi128::from(cover as i128);
+    let stake = clamped_sealed_stake(stake_variable, cover);
+    let mut win_bit = Sbi128::from(0);
+    if flip_is_heads(salt, seed_epoch, player) == Sbi1::from(choice_is_heads) {
+        win_bit = Sbi128::from(1);
+    }
+    (cover + stake) * win_bit
+}
+
+/// Perform a zk computation adding the lost sealed stake to the house's sealed take.
+///
+/// ### Parameters:
+///
+/// * `stake_variable`: The secret variable holding the lost `SealedBetAmount`.
+/// * `cover`: The publicly escrowed amount the stake is clamped to.
+///
+/// ### Returns:
+///
+/// The new sealed take of the house: every earlier take plus the stake.
+#[zk_compute(shortname = 0x71)]
+pub fn compute_sealed_house_take(stake_variable: SecretVarId, cover: i64) -> Sbi128 {
+    let mut take = clamped_sealed_stake(stake_variable, Sbi128::from(cover as i128));
+    for variable_id in secret_variable_ids().filter(|id| is_sealed_house_take(*id)) {
+        take = take + load_sbi::<Sbi128>(variable_id);
+    }
+    take
+}
+
+/// Perform a zk computation crediting the unstaked part of a lost sealed bet's cover to the
+/// player's sealed credit.
+///
+/// ### Parameters:
+///
+/// * `stake_variable`: The secret variable holding the lost `SealedBetAmount`.
+/// * `cover`: The publicly escrowed amount the stake is clamped to.
+/// * `player`: The player credited.
+///
+/// ### Returns:
+///
+/// The new sealed credit of the player: every earlier credit plus the cover less the stake.
+#[zk_compute(shortname = 0x72)]
+pub fn compute_sealed_credit(stake_variable: SecretVarId, cover: i64, player: Address) -> Sbi128 {
+    let cover = Sbi128::from(cover as i128);
+    let mut credit = cover - clamped_sealed_stake(stake_variable, cover);
+    for variable_id in secret_variable_ids().filter(|id| is_sealed_credit_of(*id, player)) {
+        credit = credit + load_sbi::<Sbi128>(variable_id);
+    }
+    credit
+}
+
+/// Perform a zk computation totalling the sealed take of the house, or the sealed credit of a
+/// player, so the total can be opened on its own and paid out.
+///
+/// ### Parameters:
+///
+/// * `of_house`: Whether the house's sealed take is totalled, or else the player's credit.
+/// * `player`: The player whose sealed credit is totalled.
+///
+/// ### Returns:
+///
+/// The released amount.
+#[zk_compute(shortname = 0x73)]
+pub fn compute_sealed_release(of_house: bool, player: Address) -> Sbi128 {
+    let mut total = Sbi128::from(0);
+    for variable_id in secret_variable_ids().filter(|id| {
+        if of_house {
+            is_sealed_house_take(*id)
+        } else {
+            is_sealed_credit_of(*id, player)
+        }
+    }) {
+        total = total + load_sbi::<Sbi128>(variable_id);
+    }
+    total
+}
+
+/// The sealed stake held by the variable, clamped to between zero and `cover`.
+fn clamped_sealed_stake(stake_variable: SecretVarId, cover: Sbi128) -> Sbi128 {
+    let mut stake = load_sbi::<Sbi128>(stake_variable);
+    if stake > cover {
+        stake = cover;
+    }
+    if stake < Sbi128::from(0) {
+        stake = Sbi128::from(0);
+    }
+    stake
+}
+
+/// Maximum number of flips in a single multi-flip computation. Flip `i` lands heads if bit `i` of
+/// the folded randomness is set; the cap bounds a winning bet at `2^MAX_MULTI_FLIPS` times its
+/// stake.
+pub const MAX_MULTI_FLIPS: u8 = 8;
+
+/// Perform a zk computation producing several independent coin flips at once.
+///
+/// Contributions are XOR-folded so every bit is uniform as long as one contribution is.
+///
+/// ### Parameters:
+///
+/// * `flips`: The number of flips, at most `MAX_MULTI_FLIPS`.
+/// * `salt`: The public salt of the round.
+/// * `seed_epoch`: The epoch whose house seed is folded into the round.
+/// * `player`: The player whose round is computed.
+///
+/// ### Returns:
+///
+/// A bitmask where bit `i` is set if flip `i` landed heads. Bits from `flips` up are zero.
+#[zk_compute(shortname = 0x64)]
+pub fn compute_multi_flip(flips: u8, salt: i32, seed_epoch: u32, player: Address) -> Sbi32 {
+    let folded = xor_folded_randomness(salt, seed_epoch, player);
+
+    let mut mask = Sbi32::from(0);
+    for bit in 0..flips {
+        if (folded & Sbi32::from(1 << bit)) != Sbi32::from(0) {
+            mask = mask | Sbi32::from(1 << bit);
+        }
+    }
+    mask
+}
+
+/// Maximum number of faces of a die.
+pub const MAX_DICE_FACES: u8 = 100;
+
+/// Perform a zk computation rolling a die with the given number of faces.
+///
+/// ### Parameters:
+///
+/// * `faces`: The number of faces of the die, from 2 to `MAX_DICE_FACES`.
+/// * `salt`: The public salt of the round.
+/// * `seed_epoch`: The epoch whose house seed is folded into the round.
+/// * `player`: The player whose round is computed.
+///
+/// ### Returns:
+///
+/// The roll, from 0 to `faces - 1`.
+#[zk_compute(shortname = 0x65)]
+pub fn compute_dice_roll(faces: u8, salt: i32, seed_epoch: u32, player: Address) -> Sbi16 {
+    uniform_below(faces, salt, seed_epoch, player)
+}
+
+/// Output of the roulette computation.
+#[derive(pbc_zk::SecretBinary, Clone)]
+pub struct RouletteSpin {
+    /// The pocket the ball landed in, from 0 to 36.
+    pub number: Sbi16,
+}
+
+/// Number of pockets on the roulette wheel.
+const ROULETTE_POCKETS: u8 = 37;
+
+/// Perform a zk computation spinning a single-zero roulette wheel.
+///
+/// ### Parameters:
+///
+/// * `salt`: The public salt of the round.
+/// * `seed_epoch`: The epoch whose house seed is folded into the round.
+/// * `player`: The player whose round is computed.
+///
+/// ### Returns:
+///
+/// The pocket the ball landed in, from 0 to 36.
+#[zk_compute(shortname = 0x66)]
+pub fn compute_roulette_spin(salt: i32, seed_epoch: u32, player: Address) -> RouletteSpin {
+    RouletteSpin {
+        number: uniform_below(ROULETTE_POCKETS, salt, seed_epoch, player),
+    }
+}
+
+/// Perform a zk computation flipping a weighted coin, which lands on the player's side for
+/// `winning_outcomes` out of `denominator` outcomes.
+///
+/// ### Parameters:
+///
+/// * `denominator`: The number of equally likely outcomes, at least 2.
+/// * `winning_outcomes`: The number of outcomes won by the player, less than `denominator`.
+/// * `salt`: The public salt of the round.
+/// * `seed_epoch`: The epoch whose house seed is folded into the round.
+/// * `player`: The player whose round is computed.
+///
+/// ### Returns:
+///
+/// 1 if the player won, 0 otherwise.
+#[zk_compute(shortname = 0x69)]
+pub fn compute_weighted_flip(
+    denominator: u8,
+    winning_outcomes: u8,
+    salt: i32,
+    seed_epoch: u32,
+    player: Address,
+) -> Sbi8 {
+    let mut player_won = Sbi8::from(0);
+    let outcome = uniform_below(denominator, salt, seed_epoch, player);
+    if outcome < Sbi16::from(winning_outcomes as i16) {
+        player_won = Sbi8::from(1);
+    }
+    player_won
+}
+
+/// Perform a zk computation drawing the multiplier of a bonus round.
+///
+/// The multiplier is drawn from the upper 16 bits of the folded randomness, which neither the
+/// opened coin flip nor its jackpot and bonus flags reveal anything about.
+///
+/// ### Parameters:
+///
+/// * `salt`: The public salt of the round the bonus was triggered in.
+/// * `seed_epoch`: The epoch whose house seed is folded into the round.
+/// * `player`: The player whose round is computed.
+///
+/// ### Returns:
+///
+/// The multiplier less one, from 0 to `BONUS_MAX_MULTIPLIER - 1`.
+#[zk_compute(shortname = 0x6D)]
+pub fn compute_bonus_multiplier(salt: i32, seed_epoch: u32, player: Address) -> Sbi16 {
+    bits_below(BONUS_MAX_MULTIPLIER, xor_folded_randomness(salt, seed_epoch, player), 16, 16)
+}
+
+/// Perform a zk computation flipping the coin of a bracket match.
+///
+/// ### Parameters:
+///
+/// * `first_randomness`: The randomness contributed by the higher seed of the match.
+/// * `second_randomness`: The randomness contributed by the lower seed of the match.
+///
+/// ### Returns:
+///
+/// 1 if the higher seed advances, 0 if the lower seed does. As for `compute_coin_flip`, the flip
+/// is unbiased as long as either player contributed uniform randomness.
+#[zk_compute(shortname = 0x6E)]
+pub fn compute_bracket_match(
+    first_randomness: SecretVarId,
+    second_randomness: SecretVarId,
+) -> Sbi8 {
+    let first = load_sbi::<RandomnessInput>(first_randomness);
+    let second = load_sbi::<RandomnessInput>(second_randomness);
+    let mut first_advances = Sbi8::from(0);
+    if low_bit(first.result ^ second.result) {
+        first_advances = Sbi8::from(1);
+    }
+    first_advances
+}
+
+/// Perform a zk computation flipping the coin of a friends table.
+///
+/// ### Parameters:
+///
+/// * `table_id`: The table whose coin is flipped; only randomness contributed to it is folded.
+///
+/// ### Returns:
+///
+/// 1 if the coin lands heads, 0 if tails. As for `compute_coin_flip`, the flip is unbiased as long
+/// as a single participant contributed uniform randomness.
+#[zk_compute(shortname = 0x6F)]
+pub fn compute_table_flip(table_id: u32) -> Sbi8 {
+    let mut folded = Sbi32::from(table_id as i32);
+    for variable_id in secret_variable_ids().filter(|id| is_randomness_of_table(*id, table_id)) {
+        let contribution = load_sbi::<RandomnessInput>(variable_id);
+        folded = folded ^ contribution.result;
+    }
+    let mut is_heads = Sbi8::from(0);
+    if low_bit(folded) {
+        is_heads = Sbi8::from(1);
+    }
+    is_heads
+}
+
+/// Draw a number from 0 to `modulus - 1` from the low 31 bits of the XOR-folded randomness.
+fn uniform_below(modulus: u8, salt: i32, seed_epoch: u32, player: Address) -> Sbi16 {
+    bits_below(modulus, xor_folded_randomness(salt, seed_epoch, player), 0, 31)
+}
+
+/// Reduce the `width` bits of `folded` starting at bit `shift` modulo `modulus`.
+///
+/// Unless `modulus` divides `2^width`, some remainders are hit once more than others, so each
+/// number is drawn with a probability within `2^-width` of `1 / modulus`. Reducing a single byte
+/// would favour the low numbers by up to a factor of two.
+fn bits_below(modulus: u8, folded: Sbi32, shift: i32, width: i32) -> Sbi16 {
+    let mut value = Sbi32::from(0);
+    for bit in 0..width {
+        if (folded & Sbi32::from(1 << (shift + bit))) != Sbi32::from(0) {
+            value = value | Sbi32::from(1 << bit);
+        }
+    }
+
+    // Reduce modulo `modulus` by subtracting the largest shifted multiples first, skipping those
+    // the value cannot reach.
+    for step in (0..width).rev() {
+        let multiple = (modulus as i64) << step;
+        if multiple < 1i64 << width {
+            let multiple = Sbi32::from(multiple as i32);
+            if value >= multiple {
+                value = value - multiple;
+            }
+        }
+    }
+
+    // The remainder is below `modulus`, so it fits in a byte.
+    let mut remainder = Sbi16::from(0);
+    for bit in 0..8 {
+        if (value & Sbi32::from(1 << bit)) != Sbi32::from(0) {
+            remainder = remainder | Sbi16::from(1 << bit);
+        }
+    }
+    remainder
+}
+
+/// Metadata discriminant of lottery randomness, see `SecretVarType::LotteryRandomness`.
+const LOTTERY_RANDOMNESS_METADATA: u8 = 9;
 
+/// Perform a zk computation drawing the winning ticket of a lottery.
+///
+/// Lottery contributions are only accepted for the open lottery and deleted after its draw, so
+/// all present lottery randomness belongs to the lottery being drawn.
+///
+/// ### Parameters:
+///
+/// * `ticket_count`: The number of tickets sold.
+///
+/// ### Returns:
+///
+/// The index of the winning ticket, from 0 to `ticket_count - 1`.
+#[zk_compute(shortname = 0x67)]
+pub fn compute_lottery_draw(ticket_count: u32) -> Sbi64 {
+    let mut folded = Sbi64::from(0);
     for variable_id in secret_variable_ids() {
-        let raw_contribution: RandomnessInput = load_sbi::<RandomnessInput>(variable_id);
-        let result_reduced = reduce_contribution(raw_contribution.result);
-        flip.result = flip.result + result_reduced;
+        if load_metadata::<u8>(variable_id) == LOTTERY_RANDOMNESS_METADATA {
+            folded = folded ^ load_sbi::<Sbi64>(variable_id);
+        }
     }
 
-   
-    // Perform the modulo operation directly on the Sbi8 value
-    flip.result = flip.result & Sbi8::from(1); // Reduce the sum to 0 or 1
-    flip
+    // Keep 31 bits so the value is non-negative, then reduce modulo the ticket count by
+    // subtracting the largest shifted multiples first.
+    let mut ticket = folded & Sbi64::from(0x7fff_ffff);
+    for step in 0..31 {
+        let multiple = Sbi64::from((ticket_count as i64) << (30 - step));
+        if ticket >= multiple {
+            ticket = ticket - multiple;
+        }
+    }
+    ticket
 }
 
+/// Perform a zk computation comparing two rock-paper-scissors throws.
+///
+/// ### Parameters:
+///
+/// * `creator_throw`: The secret throw of the game's creator.
+/// * `opponent_throw`: The secret throw of the opponent.
+///
+/// ### Returns:
+///
+/// 0 for a draw, 1 if the creator won and 2 if the opponent won. An invalid throw loses against
+/// a valid one, and two invalid throws draw.
+#[zk_compute(shortname = 0x68)]
+pub fn compute_rps_outcome(creator_throw: SecretVarId, opponent_throw: SecretVarId) -> Sbi8 {
+    let creator = load_sbi::<Sbi8>(creator_throw);
+    let opponent = load_sbi::<Sbi8>(opponent_throw);
+    let creator_valid = (creator >= Sbi8::from(0)) & (creator <= Sbi8::from(2));
+    let opponent_valid = (opponent >= Sbi8::from(0)) & (opponent <= Sbi8::from(2));
+
+    // Each throw beats the one before it, so the difference modulo 3 is the outcome.
+    let mut outcome = creator - opponent;
+    if outcome < Sbi8::from(0) {
+        outcome = outcome + Sbi8::from(3);
+    }
 
-/// Reduce the contribution to 0 or 1.
-fn reduce_contribution(value: Sbi8) -> Sbi8 {
-    let reduced = value & Sbi8::from(0b111);
-    if reduced >= Sbi8::from(2) {
-        reduced - Sbi8::from(2)
-    } else {
-        reduced
+    if !creator_valid {
+        outcome = Sbi8::from(2);
+        if !opponent_valid {
+            outcome = Sbi8::from(0);
+        }
+    } else if !opponent_valid {
+        outcome = Sbi8::from(1);
     }
+    outcome
+}
+
+/// Perform a zk computation opening a private balance of `amount`.
+///
+/// ### Returns:
+///
+/// The new private balance.
+#[zk_compute(shortname = 0x6A)]
+pub fn compute_private_opening(amount: i64) -> Sbi64 {
+    Sbi64::from(amount)
+}
+
+/// Perform a zk computation adding `amount`, which may be negative, to a private balance.
+///
+/// ### Parameters:
+///
+/// * `balance_variable`: The secret variable holding the private balance.
+/// * `amount`: The public amount credited, or debited if negative.
+///
+/// ### Returns:
+///
+/// The new private balance.
+#[zk_compute(shortname = 0x6B)]
+pub fn compute_private_credit(balance_variable: SecretVarId, amount: i64) -> Sbi64 {
+    load_sbi::<Sbi64>(balance_variable) + Sbi64::from(amount)
+}
+
+/// Perform a zk computation deciding how much of a requested withdrawal a private balance covers,
+/// without revealing the balance.
+///
+/// ### Parameters:
+///
+/// * `balance_variable`: The secret variable holding the private balance.
+/// * `amount`: The public amount requested.
+///
+/// ### Returns:
+///
+/// `amount` if the balance covers it, zero otherwise.
+#[zk_compute(shortname = 0x6C)]
+pub fn compute_private_withdrawable(balance_variable: SecretVarId, amount: i64) -> Sbi64 {
+    let mut withdrawable = Sbi64::from(0);
+    if load_sbi::<Sbi64>(balance_variable) >= Sbi64::from(amount) {
+        withdrawable = Sbi64::from(amount);
+    }
+    withdrawable
+}
+
+/// XOR-fold the randomness contributions to the round of `player` into a single word, starting
+/// from the public salt of the round. The salt is known to everyone, so it only offsets the
+/// result by a known word and ties the computation to its round: the fold is only as random as
+/// the contributions, which is why a round is only flipped once someone other than its bettor
+/// contributed. The house seed committed for `seed_epoch`, if any, is folded in as well.
+/// Contributions to other players' rounds are left alone.
+fn xor_folded_randomness(salt: i32, seed_epoch: u32, player: Address) -> Sbi32 {
+    let mut folded = Sbi32::from(salt);
+    let folded_ids = secret_variable_ids()
+        .filter(|id| is_randomness_of(*id, player) || is_house_seed_of(*id, seed_epoch));
+    for variable_id in folded_ids {
+        let raw_contribution: RandomnessInput = load_sbi::<RandomnessInput>(variable_id);
+        folded = folded ^ raw_contribution.result;
+    }
+    folded
+}
+
+/// Fold the randomness contributions to the round of `player` into a single flip, `true` meaning
+/// heads.
+fn flip_is_heads(salt: i32, seed_epoch: u32, player: Address) -> Sbi1 {
+    low_bit(xor_folded_randomness(salt, seed_epoch, player))
+}
+
+/// Whether the lowest bit of the value is set.
+fn low_bit(value: Sbi32) -> Sbi1 {
+    (value & Sbi32::from(1)) != Sbi32::from(0)
+}
+
+/// Metadata discriminant of randomness contributions, see `SecretVarType::Randomness`.
+const RANDOMNESS_METADATA: u8 = 0;
+
+/// Metadata of a randomness contribution, laid out as `SecretVarType::Randomness`.
+#[derive(ReadWriteState)]
+struct RandomnessMetadata {
+    discriminant: u8,
+    player: Address,
+}
+
+/// Whether the given variable is a randomness contribution to the round of `player`.
+fn is_randomness_of(variable_id: SecretVarId, player: Address) -> bool {
+    // The discriminant is checked first, as other metadata need not have room for an address
+    load_metadata::<u8>(variable_id) == RANDOMNESS_METADATA
+        && load_metadata::<RandomnessMetadata>(variable_id).player == player
+}
+
+/// Metadata discriminant of house seeds, see `SecretVarType::HouseSeed`.
+const HOUSE_SEED_METADATA: u8 = 29;
+
+/// Metadata of a house seed, laid out as `SecretVarType::HouseSeed`.
+#[derive(ReadWriteState)]
+struct HouseSeedMetadata {
+    discriminant: u8,
+    epoch: u32,
+}
+
+/// Whether the given variable is the house seed committed for `epoch`.
+fn is_house_seed_of(variable_id: SecretVarId, epoch: u32) -> bool {
+    load_metadata::<u8>(variable_id) == HOUSE_SEED_METADATA
+        && load_metadata::<HouseSeedMetadata>(variable_id).epoch == epoch
+}
+
+/// Metadata discriminant of randomness contributed to a friends table, see
+/// `SecretVarType::TableRandomness`.
+const TABLE_RANDOMNESS_METADATA: u8 = 22;
+
+/// Metadata of randomness contributed to a friends table, laid out as
+/// `SecretVarType::TableRandomness`.
+#[derive(ReadWriteState)]
+struct TableRandomnessMetadata {
+    discriminant: u8,
+    table_id: u32,
+    player: Address,
+}
+
+/// Whether the given variable is randomness contributed to the friends table `table_id`.
+fn is_randomness_of_table(variable_id: SecretVarId, table_id: u32) -> bool {
+    load_metadata::<u8>(variable_id) == TABLE_RANDOMNESS_METADATA
+        && load_metadata::<TableRandomnessMetadata>(variable_id).table_id == table_id
+}
+
+/// Metadata discriminant of the house's sealed take, see `SecretVarType::SealedHouseTake`.
+const SEALED_HOUSE_TAKE_METADATA: u8 = 26;
+
+/// Metadata discriminant of sealed credits, see `SecretVarType::SealedCredit`.
+const SEALED_CREDIT_METADATA: u8 = 27;
+
+/// Metadata of a sealed credit, laid out as `SecretVarType::SealedCredit`.
+#[derive(ReadWriteState)]
+struct SealedCreditMetadata {
+    discriminant: u8,
+    player: Address,
+}
+
+/// Whether the given variable is a sealed take of the house.
+fn is_sealed_house_take(variable_id: SecretVarId) -> bool {
+    load_metadata::<u8>(variable_id) == SEALED_HOUSE_TAKE_METADATA
+}
+
+/// Whether the given variable is a sealed credit of `player`.
+fn is_sealed_credit_of(variable_id: SecretVarId, player: Address) -> bool {
+    load_metadata::<u8>(variable_id) == SEALED_CREDIT_METADATA
+        && load_metadata::<SealedCreditMetadata>(variable_id).player == player
+}
+
+/// Opened output of `compute_coin_flip`, laid out as `CoinFlipOutput`.
+#[derive(ReadWriteState, Debug)]
+pub struct OpenedCoinFlip {
+    pub is_heads: bool,
+    pub jackpot: bool,
+    pub player_won: bool,
+    pub bonus: bool,
 }
 
-// Parse the output of the zk computation.
-pub fn parse_compute_output(output: RandomnessInput) -> Sbi1 {
-    output.result != Sbi8::from(0)
+/// Decode the data of an opened output variable as the opened form of its secret type.
+pub fn decode_output<T: ReadWriteState>(data: &[u8]) -> T {
+    T::state_read_from(&mut &data[..])
+}
+
+/// Parse an opened `compute_coin_flip` output.
+pub fn parse_compute_output_coin_flip(data: &[u8]) -> OpenedCoinFlip {
+    decode_output(data)
+}
+
+/// Parse the opened output of `compute_bonus_multiplier` into a multiplier from 1.
+pub fn parse_compute_output_bonus_multiplier(data: &[u8]) -> u8 {
+    decode_output::<u16>(data) as u8 + 1
+}
+
+/// Parse whether the higher seed advances from an opened `compute_bracket_match` output.
+pub fn parse_compute_output_bracket_match(data: &[u8]) -> bool {
+    decode_output::<u8>(data) != 0
+}
+
+/// Parse whether the coin of a friends table landed heads from an opened `compute_table_flip`
+/// output.
+pub fn parse_compute_output_table_flip(data: &[u8]) -> bool {
+    decode_output::<u8>(data) != 0
+}
+
+/// Parse the opened output of `compute_multi_flip` into a bitmask of `flips` flips.
+pub fn parse_compute_output_multi(data: &[u8], flips: u8) -> u32 {
+    decode_output::<u32>(data) & ((1u32 << flips) - 1)
+}
+
+/// Parse the opened output of `compute_dice_roll` into a face numbered from 1.
+pub fn parse_compute_output_dice(data: &[u8]) -> u8 {
+    decode_output::<u16>(data) as u8 + 1
+}
+
+/// Parse whether the player won from an opened `compute_weighted_flip` output.
+pub fn parse_compute_output_weighted(data: &[u8]) -> bool {
+    decode_output::<u8>(data) != 0
+}
+
+/// Parse whether the player guessed right from an opened `compute_secret_choice_outcome` output.
+pub fn parse_compute_output_secret_choice(data: &[u8]) -> bool {
+    decode_output::<u8>(data) != 0
+}
+
+/// Parse the opened output of `compute_secret_bet_payout` into the amount returned.
+pub fn parse_compute_output_secret_payout(data: &[u8]) -> u64 {
+    decode_output::<i64>(data) as u64
+}
+
+/// Parse an opened `compute_sealed_payout` or `compute_sealed_release` output into an amount.
+pub fn parse_compute_output_sealed_amount(data: &[u8]) -> u64 {
+    decode_output::<i128>(data) as u64
+}
+
+/// Parse the opened output of `compute_private_withdrawable` into the withdrawn amount.
+pub fn parse_compute_output_withdrawable(data: &[u8]) -> u64 {
+    decode_output::<i64>(data) as u64
+}
+
+/// Parse the opened output of `compute_roulette_spin` into the winning pocket.
+pub fn parse_compute_output_roulette(data: &[u8]) -> u8 {
+    decode_output::<u16>(data) as u8
+}
+
+/// Parse the opened output of `compute_lottery_draw` into the winning ticket index.
+pub fn parse_compute_output_lottery(data: &[u8]) -> u32 {
+    decode_output::<i64>(data) as u32
+}
+
+/// Parse the opened output of `compute_rps_outcome`, see `rps::RPS_DRAW`.
+pub fn parse_compute_output_rps(data: &[u8]) -> u8 {
+    decode_output(data)
 }
 
 #[allow(dead_code)]
@@ -53,4 +791,278 @@ pub fn parse_compute_output(output: RandomnessInput) -> Sbi1 {
 pub struct CoinFlipResult {
     pub id: u64,
     pub result: bool,
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use std::sync::{Mutex, MutexGuard};
+
+    use pbc_contract_common::address::AddressType;
+    use pbc_zk::api::{set_secrets, SecretVar};
+
+    use super::*;
+    use crate::engine;
+
+    fn address(id: u8) -> Address {
+        Address {
+            address_type: AddressType::Account,
+            identifier: [id; 20],
+        }
+    }
+
+    /// Deterministic pseudo-random words, so the sampled contributions are reproducible.
+    fn sample_words(seed: u32, count: usize) -> Vec<i32> {
+        let mut state = seed | 1;
+        (0..count)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as i32
+            })
+            .collect()
+    }
+
+    fn contribution(player: Address, word: i32) -> SecretVar {
+        SecretVar {
+            metadata: Box::new(RandomnessMetadata {
+                discriminant: RANDOMNESS_METADATA,
+                player,
+            }),
+            value: Box::new(RandomnessInput {
+                result: Sbi32::from(word),
+            }),
+        }
+    }
+
+    fn house_seed(epoch: u32, word: i32) -> SecretVar {
+        SecretVar {
+            metadata: Box::new(HouseSeedMetadata {
+                discriminant: HOUSE_SEED_METADATA,
+                epoch,
+            }),
+            value: Box::new(RandomnessInput {
+                result: Sbi32::from(word),
+            }),
+        }
+    }
+
+    /// The secret variables of the host mock are shared, so tests setting them run one at a time.
+    static SECRETS: Mutex<()> = Mutex::new(());
+
+    fn lock_secrets() -> MutexGuard<'static, ()> {
+        SECRETS
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Make the given contributions to the round of `address(2)` the only secret variables.
+    fn contribute(words: &[i32]) {
+        let inputs = words
+            .iter()
+            .map(|word| contribution(address(2), *word))
+            .collect();
+        unsafe { set_secrets(inputs) };
+    }
+
+    /// Only the round's own contributions and the house seed of the given epoch are folded.
+    #[test]
+    fn the_fold_covers_the_rounds_contributions_and_the_epochs_house_seed() {
+        let _secrets = lock_secrets();
+        let inputs = vec![
+            contribution(address(2), 0x0F0F_0000),
+            contribution(address(3), 0x7777_7777),
+            contribution(address(2), 0x00F0_0F00),
+            house_seed(1, 0x0000_00F0),
+            house_seed(2, 0x1111_1111),
+        ];
+        unsafe { set_secrets(inputs) };
+        let folded = xor_folded_randomness(0x0000_000F, 1, address(2));
+        assert!(folded == Sbi32::from(0x0FFF_0FFF));
+    }
+
+    /// The secret-shared fold and flip agree with the public ones of the engine.
+    #[test]
+    fn the_fold_matches_the_engine() {
+        let _secrets = lock_secrets();
+        for seed in 1..=16 {
+            let words = sample_words(seed, 4);
+            contribute(&words[1..]);
+            let folded = engine::fold_randomness(words[0], words[1..].iter().copied());
+            assert!(xor_folded_randomness(words[0], 0, address(2)) == Sbi32::from(folded));
+            let heads = engine::lands_heads(folded);
+            assert!(flip_is_heads(words[0], 0, address(2)) == Sbi1::from(heads), "seed {seed}");
+        }
+    }
+
+    /// Without contributions the fold is the public salt, so anyone can tell the outcome ahead of
+    /// time. The contract never flips such a round, see `missing_randomness`.
+    #[test]
+    fn a_round_without_contributions_folds_to_its_public_salt() {
+        let _secrets = lock_secrets();
+        for salt in sample_words(7, 16) {
+            contribute(&[]);
+            assert!(xor_folded_randomness(salt, 0, address(2)) == Sbi32::from(salt));
+            assert!(flip_is_heads(salt, 0, address(2)) == Sbi1::from(salt & 1 == 1));
+        }
+    }
+
+    /// Whatever a dishonest party contributes, an honest uniform contribution gives heads for
+    /// exactly half of its values.
+    #[test]
+    fn the_flip_is_unbiased_with_one_uniform_contribution() {
+        let _secrets = lock_secrets();
+        for fixed in [i32::MIN, -1, 0, 1, 0x5A5A_5A5A, i32::MAX] {
+            let heads = (0..=255i32)
+                .filter(|uniform| {
+                    contribute(&[fixed, *uniform]);
+                    flip_is_heads(0, 0, address(2))
+                })
+                .count();
+            assert_eq!(heads, 128);
+        }
+    }
+
+    /// Every bit of the folded word, not only the lowest, is unbiased.
+    #[test]
+    fn folded_bits_are_unbiased_with_one_uniform_contribution() {
+        let _secrets = lock_secrets();
+        let fixed = 0x5A5A_5A5A;
+        for bit in 0..32 {
+            let mask = Sbi32::from(1i32 << bit);
+            // Rotating every byte value places each of its bits at `bit` for half of them
+            let set = (0..=255i32)
+                .filter(|uniform| {
+                    contribute(&[fixed, uniform.rotate_left(bit)]);
+                    (xor_folded_randomness(0, 0, address(2)) & mask) != Sbi32::from(0)
+                })
+                .count();
+            assert_eq!(set, 128);
+        }
+    }
+
+    /// However many contributions the other parties fix, and whatever the salt, a single honest
+    /// contribution gives heads for exactly half of its values.
+    #[test]
+    fn fixed_contributions_of_any_count_leave_the_flip_unbiased() {
+        let _secrets = lock_secrets();
+        for count in 1..=8 {
+            let words = sample_words(count as u32, count + 1);
+            let (salt, fixed) = (words[0], &words[1..]);
+            let heads = (0..=255i32)
+                .filter(|uniform| {
+                    contribute(&[fixed, &[*uniform]].concat());
+                    flip_is_heads(salt, 0, address(2))
+                })
+                .count();
+            assert_eq!(heads, 128, "{count} fixed contributions");
+        }
+    }
+
+    /// No choice of the other contributions fixes the outcome of `compute_coin_flip`: the honest
+    /// contribution still reaches both sides of the coin and wins and loses the bet, and hits the
+    /// jackpot and bonus patterns for exactly one value of their byte.
+    #[test]
+    fn fixed_contributions_cannot_force_an_outcome() {
+        let _secrets = lock_secrets();
+        for seed in 1..=32 {
+            let words = sample_words(seed, 4);
+            let outputs: Vec<CoinFlipOutput> = (0..=255i32)
+                .map(|byte| {
+                    contribute(&[words[1], words[2], words[3], byte | byte << 8]);
+                    compute_coin_flip(words[0], 0, address(2), true)
+                })
+                .collect();
+            let count = |field: fn(&CoinFlipOutput) -> &Sbi8| {
+                outputs
+                    .iter()
+                    .filter(|output| *field(output) == Sbi8::from(1))
+                    .count()
+            };
+            assert_eq!(count(|output| &output.result), 128, "seed {seed}");
+            assert_eq!(count(|output| &output.player_won), 128, "seed {seed}");
+            assert_eq!(count(|output| &output.jackpot), 1, "seed {seed}");
+            assert_eq!(count(|output| &output.bonus), 1, "seed {seed}");
+        }
+    }
+
+    /// Every combination of output bits decodes to its fields and encodes back to the same bytes.
+    #[test]
+    fn every_coin_flip_output_round_trips() {
+        for bits in 0..16u8 {
+            let data: Vec<u8> = (0..4).map(|field| bits >> field & 1).collect();
+            let flip = parse_compute_output_coin_flip(&data);
+            assert_eq!(
+                [flip.is_heads, flip.jackpot, flip.player_won, flip.bonus],
+                [data[0] == 1, data[1] == 1, data[2] == 1, data[3] == 1]
+            );
+            let mut encoded = vec![];
+            flip.state_write_to(&mut encoded).unwrap();
+            assert_eq!(encoded, data);
+        }
+    }
+
+    /// A die shows the low 31 bits of the folded word modulo its faces, so no face is favoured by
+    /// more than one value in `2^31`.
+    #[test]
+    fn dice_rolls_reduce_31_bits_of_the_fold() {
+        let _secrets = lock_secrets();
+        for word in sample_words(11, 64).into_iter().chain([-1, 0, i32::MAX, i32::MIN]) {
+            contribute(&[word]);
+            for faces in [2, 6, 7, 20, 37, MAX_DICE_FACES] {
+                let expected = engine::uniform_below(faces, word);
+                let roll = compute_dice_roll(faces, 0, 0, address(2));
+                assert!(roll == Sbi16::from(i16::from(expected)), "{word} on {faces} faces");
+            }
+        }
+    }
+
+    /// Every pocket is hit by the same number of values of the low 31 bits, give or take one, so
+    /// the wheel pays out at the odds `win_chance_bps` quotes.
+    #[test]
+    fn roulette_spins_reduce_31_bits_of_the_fold() {
+        let _secrets = lock_secrets();
+        for word in sample_words(13, 64).into_iter().chain([-1, 0, i32::MAX, i32::MIN]) {
+            contribute(&[word]);
+            let expected = engine::uniform_below(ROULETTE_POCKETS, word);
+            let spin = compute_roulette_spin(0, 0, address(2));
+            assert!(spin.number == Sbi16::from(i16::from(expected)), "{word}");
+        }
+    }
+
+    /// The weighted coin lands on the player's side for the low 31 bits of the folded word whose
+    /// remainder is one of the winning outcomes.
+    #[test]
+    fn weighted_flips_reduce_31_bits_of_the_fold() {
+        let _secrets = lock_secrets();
+        for word in sample_words(17, 64).into_iter().chain([-1, 0, i32::MAX, i32::MIN]) {
+            contribute(&[word]);
+            for (denominator, winning) in [(3u8, 1u8), (3, 2), (10, 7), (100, 1), (255, 254)] {
+                let won = engine::uniform_below(denominator, word) < winning;
+                let flip = compute_weighted_flip(denominator, winning, 0, 0, address(2));
+                assert!(flip == Sbi8::from(won as i8), "{word} at {winning}/{denominator}");
+            }
+        }
+    }
+
+    /// The multiplier reduces bits 16 to 31 of the folded word, none of which the opened flip
+    /// reveals, so every multiplier is drawn with a probability within `2^-16` of the others.
+    #[test]
+    fn bonus_multipliers_reduce_the_upper_half_of_the_fold() {
+        let _secrets = lock_secrets();
+        for word in sample_words(19, 64).into_iter().chain([-1, 0, i32::MAX, i32::MIN]) {
+            contribute(&[word]);
+            let expected = (word >> 16 & 0xFFFF) % i32::from(BONUS_MAX_MULTIPLIER);
+            let multiplier = compute_bonus_multiplier(0, 0, address(2));
+            assert!(multiplier == Sbi16::from(expected as i16), "{word}");
+        }
+    }
+
+    #[test]
+    fn opened_coin_flips_are_decoded_field_by_field() {
+        let flip = parse_compute_output_coin_flip(&[1, 0, 1, 0]);
+        assert!(flip.is_heads && flip.player_won);
+        assert!(!flip.jackpot && !flip.bonus);
+        assert_eq!(parse_compute_output_dice(&[3, 0]), 4);
+        assert_eq!(parse_compute_output_secret_payout(&300i64.to_le_bytes()), 300);
+    }
+}
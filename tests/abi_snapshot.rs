@@ -0,0 +1,71 @@
+//! Guards the contract's interface against accidental changes.
+//!
+//! The entry points, with their kinds and shortnames, and the fields of the contract state are
+//! read from the sources and compared against the snapshot committed in
+//! `tests/snapshots/flip_coin_interface.txt`. The test fails if the snapshot is missing or any
+//! entry point, shortname or state field changed. After an intended change, rerun the test with
+//! `UPDATE_ABI_SNAPSHOT=1` and commit the new snapshot so client SDKs can be updated.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+const SNAPSHOT: &str = "tests/snapshots/flip_coin_interface.txt";
+
+/// Sources declaring the contract's entry points.
+const SOURCES: [&str; 2] = ["src/contract.rs", "src/zk_compute.rs"];
+
+/// Attributes of the contract's entry points, each on the line before its function.
+const ENTRY_POINTS: [&str; 5] = ["#[action", "#[callback", "#[init(", "#[zk_on_", "#[zk_compute"];
+
+fn manifest_path(relative: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(relative)
+}
+
+/// One line per entry point, giving its attribute and function, followed by one line per field of
+/// the contract state in declaration order.
+fn interface() -> String {
+    let mut interface = String::new();
+    for source in SOURCES {
+        let text = fs::read_to_string(manifest_path(source)).unwrap();
+        let lines: Vec<&str> = text.lines().map(str::trim).collect();
+        for (attribute, function) in lines.iter().zip(lines.iter().skip(1)) {
+            if ENTRY_POINTS.iter().any(|prefix| attribute.starts_with(prefix)) {
+                let name = function.trim_start_matches("pub ").trim_start_matches("fn ");
+                let name = name.split('(').next().unwrap();
+                interface += &format!("{attribute} {name}\n");
+            }
+        }
+    }
+
+    let contract = fs::read_to_string(manifest_path("src/contract.rs")).unwrap();
+    let fields = contract
+        .lines()
+        .map(str::trim)
+        .skip_while(|line| *line != "pub struct CoinFlipState {")
+        .skip(1)
+        .take_while(|line| *line != "}")
+        .filter(|line| line.starts_with("pub"));
+    for field in fields {
+        let field = field.split("//").next().unwrap().trim();
+        interface += &format!("state {field}\n");
+    }
+    interface
+}
+
+#[test]
+fn interface_matches_snapshot() {
+    let interface = interface();
+    let snapshot_path = manifest_path(SNAPSHOT);
+    if env::var_os("UPDATE_ABI_SNAPSHOT").is_some() {
+        fs::write(&snapshot_path, &interface).unwrap();
+    }
+
+    let snapshot = fs::read_to_string(&snapshot_path)
+        .unwrap_or_else(|_| panic!("No interface snapshot at {SNAPSHOT}."));
+    assert!(
+        interface == snapshot,
+        "The interface differs from {SNAPSHOT}. If the change is intended, rerun with \
+         UPDATE_ABI_SNAPSHOT=1 and commit the new snapshot so client SDKs can be updated."
+    );
+}
@@ -0,0 +1,120 @@
+//! Golden test of the canonical journey of a player: a bet escrowed through the token contract,
+//! the coin flip requested, and every phase change visible to clients polling the change log.
+
+use flip_coin::*;
+use pbc_contract_common::address::{Address, AddressType};
+use pbc_contract_common::context::{CallbackContext, ContractContext, ExecutionResult};
+use pbc_contract_common::sorted_vec_map::SortedVecMap;
+use pbc_contract_common::zk::{CalculationStatus, SecretVarId, ZkClosed, ZkState};
+use pbc_contract_common::Hash;
+
+fn address(id: u8) -> Address {
+    Address {
+        address_type: AddressType::Account,
+        identifier: [id; 20],
+    }
+}
+
+fn context(sender: Address, now: i64) -> ContractContext {
+    ContractContext {
+        contract_address: address(0xC0),
+        sender,
+        block_time: now,
+        block_production_time: now,
+        current_transaction: Hash { bytes: [1; 32] },
+        original_transaction: Hash { bytes: [1; 32] },
+    }
+}
+
+fn idle_zk_state() -> ZkState<SecretVarType> {
+    ZkState {
+        calculation_state: CalculationStatus::Waiting,
+        pending_inputs: SortedVecMap::new(),
+        secret_variables: SortedVecMap::new(),
+        data_attestations: SortedVecMap::new(),
+        external_events: SortedVecMap::new(),
+    }
+}
+
+fn successful_callback() -> CallbackContext {
+    CallbackContext {
+        success: true,
+        results: vec![ExecutionResult {
+            succeeded: true,
+            return_data: vec![],
+        }],
+    }
+}
+
+#[test]
+fn bet_escrow_and_flip() {
+    let owner = address(1);
+    let player = address(2);
+    let token = address(0xA0);
+
+    let (state, events) = initialize(context(owner, 0), idle_zk_state(), InitConfig::new(token));
+    assert!(events.is_empty());
+
+    // The stake is escrowed through the token contract before the game can proceed
+    let (state, events, changes) = start_game_and_place_bet(
+        context(player, 10),
+        state,
+        idle_zk_state(),
+        100,
+        PlayerChoice::Heads {},
+        token,
+        0,
+    );
+    assert_eq!(events.len(), 1);
+    assert!(changes.is_empty());
+
+    let (state, events, changes) = transfer_success_callback(
+        context(token, 20),
+        successful_callback(),
+        state,
+        idle_zk_state(),
+        player,
+        100,
+        0,
+    );
+    assert!(events.is_empty());
+    assert!(changes.is_empty());
+
+    // The house contributes randomness, without which the bettor alone would decide the flip
+    let house = address(0xB0);
+    let mut zk_state = idle_zk_state();
+    let randomness = ZkClosed {
+        variable_id: SecretVarId::new(1),
+        owner: house,
+        is_sealed: false,
+        metadata: SecretVarType::Randomness { player },
+        data: None,
+    };
+    zk_state.secret_variables.insert(randomness.variable_id, randomness);
+    let (state, _, changes) =
+        inputted_variable(context(house, 25), state, zk_state, SecretVarId::new(1));
+    assert!(changes.is_empty());
+
+    // With the engine idle the flip is computed straight away
+    let (state, events, changes) = flip_coin(context(player, 30), state, idle_zk_state());
+    assert!(events.is_empty());
+    assert_eq!(changes.len(), 1);
+
+    // Flipping again while the round is being computed is a no-op
+    let (state, _, changes) = flip_coin(context(player, 40), state, idle_zk_state());
+    assert!(changes.is_empty());
+
+    let phases: Vec<_> = state
+        .get_changes_since(0)
+        .into_iter()
+        .filter(|record| record.player == player)
+        .map(|record| record.kind)
+        .collect();
+    assert_eq!(
+        phases,
+        vec![ChangeKind::PhaseChanged {
+            phase: GamePhase::FlipCoin {},
+        }]
+    );
+    assert_eq!(state.balance_of(&player).available, 0);
+}
@@ -0,0 +1,122 @@
+//! Benchmarks of the storage layout of per-player state.
+//!
+//! Every action reads the whole serialized state and writes it back, except for `AvlTreeMap`
+//! entries, which live in the tree storage and are only touched when looked up. These benchmarks
+//! measure the serialized size of a per-player map of bets and the cost of an action placing one
+//! more bet in it, with 10, 1k and 100k players, for the `SortedVecMap` and `AvlTreeMap` layouts.
+//!
+//! The timings are a host approximation only. Off chain `AvlTreeMap` is an in-memory mock of the
+//! tree storage, so they show how the serialization work grows with the number of players, not
+//! the gas an action costs on chain.
+//!
+//! The measurements are ignored by default as they are slow; run them with
+//! `cargo test --release --test state_size -- --ignored --nocapture`. The layout checks run with
+//! every test run and catch per-player state growing unexpectedly.
+
+use std::time::{Duration, Instant};
+
+use flip_coin::engine::{PlayerBet, PlayerChoice};
+use pbc_contract_common::address::{Address, AddressType};
+use pbc_contract_common::avl_tree_map::AvlTreeMap;
+use pbc_contract_common::sorted_vec_map::SortedVecMap;
+use pbc_traits::ReadWriteState;
+
+const PLAYER_COUNTS: [u32; 3] = [10, 1_000, 100_000];
+
+/// Actions timed per measurement.
+const ACTIONS_PER_MEASUREMENT: u32 = 20;
+
+/// Serialized size of a player's entry: the address and a coin flip bet.
+const SERIALIZED_ENTRY_BYTES: usize = 21 + 15;
+
+fn player(index: u32) -> Address {
+    let mut identifier = [0; 20];
+    identifier[16..].copy_from_slice(&index.to_be_bytes());
+    Address {
+        address_type: AddressType::Account,
+        identifier,
+    }
+}
+
+fn bet(index: u32) -> PlayerBet {
+    PlayerBet {
+        amount: 100 + index as u64,
+        choice: Some(PlayerChoice::Heads {}),
+        secret_amount: false,
+        multi_flip: None,
+        dice: None,
+        roulette: None,
+        beneficiary: None,
+    }
+}
+
+fn sorted_layout(players: u32) -> SortedVecMap<Address, PlayerBet> {
+    let mut bets = SortedVecMap::new();
+    for index in 0..players {
+        bets.insert(player(index), bet(index));
+    }
+    bets
+}
+
+fn avl_layout(players: u32) -> AvlTreeMap<Address, PlayerBet> {
+    let mut bets = AvlTreeMap::new();
+    for index in 0..players {
+        bets.insert(player(index), bet(index));
+    }
+    bets
+}
+
+fn serialized<S: ReadWriteState>(state: &S) -> Vec<u8> {
+    let mut bytes = vec![];
+    state.state_write_to(&mut bytes).unwrap();
+    bytes
+}
+
+/// Average time of an action placing a bet: the state is read, the bet of a new player
+/// inserted, and the state written back.
+fn action_cost<S: ReadWriteState>(state: &S, place_bet: impl Fn(&mut S, u32)) -> Duration {
+    let bytes = serialized(state);
+    let started = Instant::now();
+    for action in 0..ACTIONS_PER_MEASUREMENT {
+        let mut state = S::state_read_from(&mut &bytes[..]);
+        place_bet(&mut state, u32::MAX - action);
+        serialized(&state);
+    }
+    started.elapsed() / ACTIONS_PER_MEASUREMENT
+}
+
+#[test]
+#[ignore]
+fn state_size_and_action_cost_by_layout() {
+    println!("Host approximation: timings measure the in-memory AvlTreeMap mock, not gas.");
+    println!("players  layout        state bytes  place bet (host)");
+    for players in PLAYER_COUNTS {
+        let sorted = sorted_layout(players);
+        let cost = action_cost(&sorted, |bets, index| {
+            bets.insert(player(index), bet(index));
+        });
+        let size = serialized(&sorted).len();
+        println!("{players:>7}  SortedVecMap  {size:>11}  {cost:?}");
+
+        let avl = avl_layout(players);
+        let cost = action_cost(&avl, |bets, index| {
+            bets.insert(player(index), bet(index));
+        });
+        let size = serialized(&avl).len();
+        println!("{players:>7}  AvlTreeMap    {size:>11}  {cost:?}");
+    }
+}
+
+#[test]
+fn sorted_layout_grows_by_one_entry_per_player() {
+    for players in [0, 1, 10, 1_000] {
+        let size = serialized(&sorted_layout(players)).len();
+        assert_eq!(size, 4 + players as usize * SERIALIZED_ENTRY_BYTES, "{players} players");
+    }
+}
+
+#[test]
+fn avl_layout_keeps_players_out_of_the_serialized_state() {
+    let empty = serialized(&avl_layout(0)).len();
+    assert_eq!(serialized(&avl_layout(1_000)).len(), empty);
+}